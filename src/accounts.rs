@@ -0,0 +1,84 @@
+//! Named Gmail accounts
+//!
+//! Every account stores its credentials under its own keyring username and its
+//! message cache in its own SQLite file next to the default `rmail.db`. The
+//! "default" account keeps the original names, so existing installs show up as
+//! the default account without any migration. Accounts are discovered from the
+//! database files present in the working directory.
+
+use std::path::Path;
+
+use crate::gmail_api::KEYRING_USERNAME;
+
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+const DATABASE_PREFIX: &str = "rmail";
+const DATABASE_EXTENSION: &str = "db";
+
+// Keyring username that holds the credentials of an account
+pub fn keyring_username(account: &str) -> String {
+    if account == DEFAULT_ACCOUNT {
+        KEYRING_USERNAME.to_string()
+    } else {
+        format!("account:{}", account)
+    }
+}
+
+// File name of the SQLite cache of an account
+pub fn database_file(account: &str) -> String {
+    if account == DEFAULT_ACCOUNT {
+        format!("{}.{}", DATABASE_PREFIX, DATABASE_EXTENSION)
+    } else {
+        format!("{}-{}.{}", DATABASE_PREFIX, account, DATABASE_EXTENSION)
+    }
+}
+
+pub fn database_url(account: &str) -> String {
+    format!("sqlite:{}", database_file(account))
+}
+
+// Account names end up in file names, so only allow a conservative character set
+pub fn validate_account_name(account: &str) -> Result<(), String> {
+    if account.is_empty() {
+        return Err("Account name cannot be empty".to_string());
+    }
+    if !account
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "Invalid account name '{}': use letters, digits, '-' and '_' only",
+            account
+        ));
+    }
+    Ok(())
+}
+
+// List the accounts that have a database in `dir`, default account first
+pub fn discover_accounts(dir: &Path) -> Vec<String> {
+    let mut accounts: Vec<String> = std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| account_for_file(&entry.file_name().to_string_lossy()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    accounts.sort_by(|a, b| {
+        (a != DEFAULT_ACCOUNT, a.as_str()).cmp(&(b != DEFAULT_ACCOUNT, b.as_str()))
+    });
+    accounts.dedup();
+    accounts
+}
+
+// Map a database file name back to its account name
+fn account_for_file(file_name: &str) -> Option<String> {
+    let stem = file_name.strip_suffix(&format!(".{}", DATABASE_EXTENSION))?;
+    if stem == DATABASE_PREFIX {
+        return Some(DEFAULT_ACCOUNT.to_string());
+    }
+    let account = stem.strip_prefix(&format!("{}-", DATABASE_PREFIX))?;
+    validate_account_name(account).ok()?;
+    Some(account.to_string())
+}
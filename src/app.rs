@@ -1,37 +1,60 @@
+use crate::accounts::{database_url, discover_accounts};
 use crate::background_tasks::{spawn_background_fetch, spawn_message_fetch_with_cache};
 use crate::database::Database;
 use crate::gmail_api::{fetch_labels, try_authenticate};
 use crate::notifications::{
     self, setup_real_time_notifications, NotificationConfig, NotificationEvent,
 };
-use crate::state::AppState;
+use crate::state::{is_outbox_label, is_unified_inbox, AppState};
 use crate::types::LoadingStage;
-use crate::ui::{draw_compose_ui, draw_loading_screen, draw_main_ui};
+use crate::ui::{draw_compose_ui, draw_error_popup, draw_loading_screen, draw_main_ui};
 use ratatui::Terminal;
+use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::{mpsc::Receiver, RwLock};
 
-pub async fn initialize_app(
-) -> Result<(Arc<RwLock<AppState>>, Receiver<NotificationEvent>), Box<dyn std::error::Error>> {
+// Authenticate an account and open its database, returning a fresh state for it
+pub async fn open_account(
+    account: &str,
+    api_base_url: &str,
+) -> Result<AppState, Box<dyn std::error::Error>> {
     // Initialize database
-    let db = Arc::new(Database::new("sqlite:rmail.db").await?);
+    let db = Arc::new(Database::new(&database_url(account)).await?);
 
     // Create initial state
     let client = reqwest::Client::new();
     let mut state = AppState::new(client, "".to_string());
+    state.api_base_url = api_base_url.to_string();
+    state.account = account.to_string();
+    state.accounts = discover_accounts(Path::new("."));
+    if !state.accounts.iter().any(|known| known == account) {
+        state.accounts.push(account.to_string());
+    }
 
     // Set up database integration
     state.set_database(db.clone());
+    state.load_restorable_compose().await;
+    state.load_outbox().await;
 
     // Authenticate
-    let auth_result = try_authenticate().await?;
+    let auth_result = try_authenticate(account, false).await?;
     state.token = auth_result.token;
+    state.token_refresher = auth_result.token_refresher;
 
     // If client secret was loaded from file, set flag for TUI prompt
     if auth_result.client_secret_loaded_from_file {
         state.client_secret_deletion_prompt = true;
     }
 
+    Ok(state)
+}
+
+pub async fn initialize_app(
+    account: &str,
+    api_base_url: &str,
+) -> Result<(Arc<RwLock<AppState>>, Receiver<NotificationEvent>), Box<dyn std::error::Error>> {
+    let state = open_account(account, api_base_url).await?;
+
     // Initialize notification system
     let state_arc = Arc::new(RwLock::new(state));
     let notification_config = NotificationConfig::default();
@@ -45,6 +68,33 @@ pub async fn initialize_app(
             rx
         });
 
+    load_labels(state_arc.clone()).await;
+
+    Ok((state_arc, notification_rx))
+}
+
+// Switch the running app to another account, keeping the view settings
+pub async fn switch_account(
+    state_arc: Arc<RwLock<AppState>>,
+    account: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let api_base_url = state_arc.read().await.api_base_url.clone();
+    let mut new_state = open_account(account, &api_base_url).await?;
+    {
+        let mut state_guard = state_arc.write().await;
+        new_state.show_help = state_guard.show_help;
+        new_state.screen_height = state_guard.screen_height;
+        new_state.messages_per_screen = state_guard.messages_per_screen;
+        new_state.download_dir = state_guard.download_dir.clone();
+        *state_guard = new_state;
+    }
+
+    load_labels(state_arc).await;
+    Ok(())
+}
+
+// Load labels for the active account and start loading the first label's messages
+pub async fn load_labels(state_arc: Arc<RwLock<AppState>>) {
     // Try to load labels from cache first, fallback to API
     {
         let mut state_guard = state_arc.write().await;
@@ -62,7 +112,10 @@ pub async fn initialize_app(
                     // Save labels to cache for future use
                     if let Some(db) = &state_guard.database {
                         for label in &state_guard.labels {
-                            if let (Some(_id), Some(_name)) = (&label.id, &label.name) {
+                            if let (Some(id), Some(_name)) = (&label.id, &label.name) {
+                                if is_unified_inbox(id) || is_outbox_label(id) {
+                                    continue; // Virtual, not a label of this mailbox
+                                }
                                 let _ = db.upsert_label(label).await;
                             }
                         }
@@ -113,8 +166,6 @@ pub async fn initialize_app(
             }
         }
     }
-
-    Ok((state_arc, notification_rx))
 }
 
 pub async fn run_app_loop(
@@ -142,10 +193,21 @@ pub async fn run_app_loop(
         // Draw UI
         {
             let mut state_guard = state_arc.write().await;
+            let keyring_error = state_guard
+                .token_refresher
+                .as_ref()
+                .and_then(|refresher| refresher.take_keyring_error());
+            if let Some(error) = keyring_error {
+                state_guard.set_error_message(error);
+            }
             terminal.draw(|f| {
                 if state_guard.composing {
                     draw_main_ui(f, &mut state_guard);
                     draw_compose_ui(f, &mut state_guard);
+                    // Errors of compose actions show above the compose window
+                    if state_guard.error_message.is_some() {
+                        draw_error_popup(f, &mut state_guard);
+                    }
                 } else {
                     draw_main_ui(f, &mut state_guard);
                 }
@@ -160,6 +222,9 @@ pub async fn run_app_loop(
                 }
             }
         }
+
+        // Keep a local copy of the message being composed in case the app dies
+        state_arc.write().await.autosave_compose().await;
     }
 
     Ok(())
@@ -1,6 +1,15 @@
-use crate::gmail_api::fetch_messages_for_label;
-use crate::state::AppState;
+use crate::gmail_api::attachments::{
+    decode_attachment_data, expected_response_size, read_attachment, request_attachment,
+    save_attachment,
+};
+use crate::gmail_api::refresh_current_label;
+use crate::outbox::replay_outbox_in_background;
+use crate::state::{AppState, AttachmentDownload};
+use crate::sync::sync_mailbox_in_background;
+use crate::types::Attachment;
 use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use tokio::sync::{Mutex, RwLock};
 
@@ -45,10 +54,7 @@ pub fn spawn_message_fetch_with_cache(state_arc: Arc<RwLock<AppState>>) {
                 }
 
                 // Fetch from API in background without blocking UI
-                {
-                    let mut state_guard = state_arc.write().await;
-                    fetch_messages_for_label(&mut state_guard).await;
-                }
+                refresh_current_label(&state_arc).await;
 
                 // Remove from ongoing fetches
                 {
@@ -80,10 +86,17 @@ pub fn spawn_background_fetch(state_arc: Arc<RwLock<AppState>>) {
                 ongoing.insert(label_id.clone());
             }
 
-            // Fetch in background without affecting UI state
-            {
-                let mut state_guard = state_arc.write().await;
-                fetch_messages_for_label(&mut state_guard).await;
+            // Actions queued while offline reach Gmail before the sync looks at
+            // it. Both only lock the state to apply what their requests returned.
+            let has_pending_outbox = state_arc.read().await.has_pending_outbox();
+            if has_pending_outbox {
+                replay_outbox_in_background(&state_arc).await;
+            }
+            if let Err(e) = sync_mailbox_in_background(&state_arc).await {
+                state_arc
+                    .write()
+                    .await
+                    .set_error_message(format!("Failed to sync mailbox: {}", e));
             }
 
             // Remove from ongoing fetches
@@ -98,8 +111,69 @@ pub fn spawn_background_fetch(state_arc: Arc<RwLock<AppState>>) {
 // Helper function for user-initiated fetching (shows loading state)
 pub fn spawn_message_fetch(state_arc: Arc<RwLock<AppState>>) {
     tokio::spawn(async move {
+        refresh_current_label(&state_arc).await;
+        state_arc.write().await.set_loading_messages(false);
+    });
+}
+
+// Download an attachment into the download directory. The state lock is only
+// taken to start the request, so the UI keeps drawing the progress while the
+// body streams in.
+pub fn spawn_attachment_download(
+    state_arc: Arc<RwLock<AppState>>,
+    message_id: String,
+    attachment: Attachment,
+) {
+    tokio::spawn(async move {
+        let received = Arc::new(AtomicU64::new(0));
+        let download_dir = {
+            let mut state_guard = state_arc.write().await;
+            state_guard.attachment_download = Some(AttachmentDownload {
+                filename: attachment.filename.clone(),
+                total: expected_response_size(&attachment),
+                received: received.clone(),
+            });
+            state_guard.download_dir.clone()
+        };
+
+        let result =
+            download_to(&state_arc, &message_id, &attachment, download_dir, received).await;
+
         let mut state_guard = state_arc.write().await;
-        fetch_messages_for_label(&mut state_guard).await;
-        state_guard.set_loading_messages(false);
+        state_guard.attachment_download = None;
+        match result {
+            Ok(path) => state_guard.set_error_message(format!(
+                "✅ Saved {} to {}",
+                attachment.filename,
+                path.display()
+            )),
+            Err(e) => state_guard
+                .set_error_message(format!("Failed to download {}: {}", attachment.filename, e)),
+        }
     });
 }
+
+async fn download_to(
+    state_arc: &Arc<RwLock<AppState>>,
+    message_id: &str,
+    attachment: &Attachment,
+    download_dir: PathBuf,
+    received: Arc<AtomicU64>,
+) -> Result<PathBuf, String> {
+    let content = match (&attachment.data, &attachment.attachment_id) {
+        (Some(data), _) => decode_attachment_data(data).map_err(|e| e.to_string())?,
+        (None, Some(attachment_id)) => {
+            let response = {
+                let state_guard = state_arc.read().await;
+                request_attachment(&state_guard, message_id, attachment_id)
+                    .await
+                    .map_err(|e| e.to_string())?
+            };
+            read_attachment(response, |bytes| received.store(bytes, Ordering::Relaxed))
+                .await
+                .map_err(|e| e.to_string())?
+        }
+        (None, None) => return Err("the attachment has no content".to_string()),
+    };
+    save_attachment(&download_dir, &attachment.filename, &content).map_err(|e| e.to_string())
+}
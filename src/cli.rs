@@ -1,6 +1,9 @@
-use crate::gmail_api::{KEYRING_SERVICE_NAME, KEYRING_USERNAME};
+use crate::accounts::{keyring_username, DEFAULT_ACCOUNT};
+use crate::gmail_api::client::DEFAULT_API_BASE_URL;
+use crate::gmail_api::KEYRING_SERVICE_NAME;
 use clap::Parser;
 use keyring::Entry;
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -8,10 +11,24 @@ pub struct Cli {
     /// Clear the stored credentials from the system keyring and exit.
     #[clap(long)]
     pub clear_keyring: bool,
+
+    /// Name of the Gmail account to use. Each account has its own credentials
+    /// and cache; a new name signs in to a new account.
+    #[clap(long, default_value = DEFAULT_ACCOUNT)]
+    pub account: String,
+
+    /// Base URL of the Gmail API, e.g. a local fake server for testing.
+    #[clap(long, default_value = DEFAULT_API_BASE_URL)]
+    pub api_base_url: String,
+
+    /// Directory attachments are saved to. Defaults to ~/Downloads if it
+    /// exists, else the current directory.
+    #[clap(long)]
+    pub download_dir: Option<PathBuf>,
 }
 
-pub fn handle_keyring_clear() -> Result<(), Box<dyn std::error::Error>> {
-    let credentials_keyring = Entry::new(KEYRING_SERVICE_NAME, KEYRING_USERNAME)?;
+pub fn handle_keyring_clear(account: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let credentials_keyring = Entry::new(KEYRING_SERVICE_NAME, &keyring_username(account))?;
 
     if let Err(e) = credentials_keyring.delete_password() {
         // Cannot use app_state here as it's not initialized yet.
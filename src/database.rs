@@ -1,7 +1,9 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::{Row, SqlitePool};
+use sqlx::{Row, SqliteConnection, SqlitePool};
 
+use crate::mime::OutgoingAttachment;
+use crate::outbox::OutboxAction;
 use crate::types::Label;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,12 +25,88 @@ pub struct CachedMessage {
     pub cache_timestamp: DateTime<Utc>,
 }
 
+impl CachedMessage {
+    // Label ids with UNREAD and STARRED taken from the flag columns, which
+    // are kept current even when those labels are not stored for the message
+    pub fn label_ids_with_flags(&self) -> Vec<String> {
+        let mut label_ids: Vec<String> = self
+            .label_ids
+            .iter()
+            .filter(|id| *id != "UNREAD" && *id != "STARRED")
+            .cloned()
+            .collect();
+        if self.is_unread {
+            label_ids.push("UNREAD".to_string());
+        }
+        if self.is_starred {
+            label_ids.push("STARRED".to_string());
+        }
+        label_ids
+    }
+}
+
+// Markers placed around matched terms in search snippets
+pub const SEARCH_HIGHLIGHT_START: &str = "\u{2}";
+pub const SEARCH_HIGHLIGHT_END: &str = "\u{3}";
+
+// A cached message matching a full-text search
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub message: CachedMessage,
+    // Excerpt around the best match, with matched terms wrapped in the highlight markers
+    pub highlighted_snippet: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct CachedLabel {
     pub id: String,
     pub name: String,
 }
 
+// A message being composed, autosaved so that it survives a crash or a lost terminal.
+// Attachments are stored separately as they rarely change while typing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SavedCompose {
+    pub to: String,
+    pub to_cursor_position: usize,
+    pub cc: String,
+    pub cc_cursor_position: usize,
+    pub bcc: String,
+    pub bcc_cursor_position: usize,
+    pub subject: String,
+    pub subject_cursor_position: usize,
+    pub body: String,
+    pub body_cursor_position: usize,
+    pub focused_field: String,
+    pub show_bcc: bool,
+    pub thread_id: Option<String>,
+    pub in_reply_to: Option<String>,
+    pub references: Vec<String>,
+    pub draft_id: Option<String>,
+    pub draft_message_id: Option<String>,
+}
+
+// An autosaved message found when the app starts
+#[derive(Debug, Clone)]
+pub struct RestorableCompose {
+    pub compose: SavedCompose,
+    pub attachments: Vec<OutgoingAttachment>,
+    pub saved_at: DateTime<Utc>,
+}
+
+// An action waiting in the outbox until Gmail can be reached
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutboxEntry {
+    pub id: i64,
+    pub action: OutboxAction,
+    // What the Outbox folder lists, e.g. "Send to bob@example.com" and the subject
+    pub description: String,
+    pub subject: String,
+    // Why Gmail refused the last attempt; such entries wait for a retry
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
 pub struct Database {
     pool: SqlitePool,
 }
@@ -119,6 +197,7 @@ impl Database {
                 history_id TEXT,
                 last_sync DATETIME DEFAULT CURRENT_TIMESTAMP,
                 message_count INTEGER DEFAULT 0,
+                page_token TEXT,
                 FOREIGN KEY (label_id) REFERENCES labels(id) ON DELETE CASCADE
             )
             "#,
@@ -126,6 +205,86 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
+        // The mailbox-wide history id, kept apart from the per-label sync state
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS mailbox_state (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                history_id TEXT NOT NULL,
+                last_sync DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Add page_token column to existing sync_state tables if it doesn't exist
+        let _ = sqlx::query("ALTER TABLE sync_state ADD COLUMN page_token TEXT")
+            .execute(&self.pool)
+            .await; // Ignore error if column already exists
+
+        // The message being composed, a single row that is replaced on every autosave
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS compose_autosave (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                to_addr TEXT NOT NULL,
+                to_cursor INTEGER NOT NULL,
+                cc TEXT NOT NULL,
+                cc_cursor INTEGER NOT NULL,
+                bcc TEXT NOT NULL,
+                bcc_cursor INTEGER NOT NULL,
+                subject TEXT NOT NULL,
+                subject_cursor INTEGER NOT NULL,
+                body TEXT NOT NULL,
+                body_cursor INTEGER NOT NULL,
+                focused_field TEXT NOT NULL,
+                show_bcc BOOLEAN NOT NULL,
+                thread_id TEXT,
+                in_reply_to TEXT,
+                reference_ids TEXT NOT NULL,
+                draft_id TEXT,
+                draft_message_id TEXT,
+                saved_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS compose_autosave_attachments (
+                position INTEGER PRIMARY KEY,
+                filename TEXT NOT NULL,
+                content_type TEXT NOT NULL,
+                content BLOB NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Actions taken while Gmail could not be reached, replayed in id order
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS outbox (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                action TEXT NOT NULL,
+                message_ids TEXT NOT NULL,
+                thread_id TEXT,
+                raw TEXT,
+                draft_id TEXT,
+                description TEXT NOT NULL,
+                subject TEXT NOT NULL,
+                error TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
         // Create indexes for performance
         sqlx::query(
             "CREATE INDEX IF NOT EXISTS idx_messages_received_date ON messages(received_date DESC)",
@@ -149,6 +308,38 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
+        // Create full-text index over the searchable message fields
+        sqlx::query(
+            r#"
+            CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
+                message_id UNINDEXED,
+                subject,
+                from_addr,
+                to_addr,
+                snippet,
+                body_text,
+                tokenize = 'unicode61 remove_diacritics 2'
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Index messages cached before the full-text index existed. Index rows
+        // share the rowid of their message, message_id is only carried along.
+        sqlx::query(
+            r#"
+            INSERT INTO messages_fts (
+                rowid, message_id, subject, from_addr, to_addr, snippet, body_text
+            )
+            SELECT m.rowid, m.id, m.subject, m.from_addr, m.to_addr, m.snippet, m.body_text
+            FROM messages m
+            WHERE NOT EXISTS (SELECT 1 FROM messages_fts f WHERE f.rowid = m.rowid)
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
         Ok(())
     }
 
@@ -174,6 +365,38 @@ impl Database {
         Ok(())
     }
 
+    pub async fn rename_label(&self, label_id: &str, name: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE labels SET name = ? WHERE id = ?")
+            .bind(name)
+            .bind(label_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // Drop a label together with its message assignments and sync state
+    pub async fn delete_label(&self, label_id: &str) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM message_labels WHERE label_id = ?")
+            .bind(label_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM sync_state WHERE label_id = ?")
+            .bind(label_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM labels WHERE id = ?")
+            .bind(label_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await
+    }
+
     pub async fn get_labels(&self) -> Result<Vec<CachedLabel>, sqlx::Error> {
         let rows = sqlx::query(
             r#"
@@ -198,6 +421,8 @@ impl Database {
 
     // Message operations
     pub async fn upsert_message(&self, message: &CachedMessage) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
         // Insert/update message
         sqlx::query(
             r#"
@@ -212,10 +437,10 @@ impl Database {
                 snippet = excluded.snippet,
                 subject = excluded.subject,
                 from_addr = excluded.from_addr,
-                to_addr = excluded.to_addr,
+                to_addr = COALESCE(excluded.to_addr, messages.to_addr),
                 date_str = excluded.date_str,
-                body_text = excluded.body_text,
-                body_html = excluded.body_html,
+                body_text = COALESCE(excluded.body_text, messages.body_text),
+                body_html = COALESCE(excluded.body_html, messages.body_html),
                 received_date = excluded.received_date,
                 internal_date = excluded.internal_date,
                 is_unread = excluded.is_unread,
@@ -232,18 +457,39 @@ impl Database {
         .bind(&message.date_str)
         .bind(&message.body_text)
         .bind(&message.body_html)
-        .bind(&message.received_date)
-        .bind(&message.internal_date)
+        .bind(message.received_date)
+        .bind(message.internal_date)
         .bind(message.is_unread)
         .bind(message.is_starred)
-        .bind(&message.cache_timestamp)
-        .execute(&self.pool)
+        .bind(message.cache_timestamp)
+        .execute(&mut *tx)
+        .await?;
+
+        // Re-index the stored row, which may keep a body from an earlier full fetch
+        sqlx::query(
+            "DELETE FROM messages_fts WHERE rowid = (SELECT rowid FROM messages WHERE id = ?)",
+        )
+        .bind(&message.id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            r#"
+            INSERT INTO messages_fts (
+                rowid, message_id, subject, from_addr, to_addr, snippet, body_text
+            )
+            SELECT rowid, id, subject, from_addr, to_addr, snippet, body_text
+            FROM messages
+            WHERE id = ?
+            "#,
+        )
+        .bind(&message.id)
+        .execute(&mut *tx)
         .await?;
 
         // Clear existing label associations
         sqlx::query("DELETE FROM message_labels WHERE message_id = ?")
             .bind(&message.id)
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await?;
 
         // Insert new label associations
@@ -253,11 +499,11 @@ impl Database {
             )
             .bind(&message.id)
             .bind(label_id)
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await?;
         }
 
-        Ok(())
+        tx.commit().await
     }
 
     pub async fn get_messages_for_label(
@@ -305,39 +551,86 @@ impl Database {
 
         let mut messages = Vec::new();
         for row in rows {
-            let message_id: String = row.get("id");
-
-            // Get label IDs for this message
-            let label_rows =
-                sqlx::query("SELECT label_id FROM message_labels WHERE message_id = ?")
-                    .bind(&message_id)
-                    .fetch_all(&self.pool)
-                    .await?;
-
-            let label_ids: Vec<String> = label_rows.iter().map(|r| r.get("label_id")).collect();
-
-            messages.push(CachedMessage {
-                id: message_id,
-                thread_id: row.get("thread_id"),
-                label_ids,
-                snippet: row.get("snippet"),
-                subject: row.get("subject"),
-                from_addr: row.get("from_addr"),
-                to_addr: row.get("to_addr"),
-                date_str: row.get("date_str"),
-                body_text: row.get("body_text"),
-                body_html: row.get("body_html"),
-                received_date: row.get("received_date"),
-                internal_date: row.get("internal_date"),
-                is_unread: row.get("is_unread"),
-                is_starred: row.get("is_starred"),
-                cache_timestamp: row.get("cache_timestamp"),
-            });
+            messages.push(self.message_from_row(&row).await?);
         }
 
         Ok(messages)
     }
 
+    // Build a CachedMessage from a `messages` row, looking up its labels
+    async fn message_from_row(
+        &self,
+        row: &sqlx::sqlite::SqliteRow,
+    ) -> Result<CachedMessage, sqlx::Error> {
+        let message_id: String = row.get("id");
+
+        // Get label IDs for this message
+        let label_rows = sqlx::query("SELECT label_id FROM message_labels WHERE message_id = ?")
+            .bind(&message_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let label_ids: Vec<String> = label_rows.iter().map(|r| r.get("label_id")).collect();
+
+        Ok(CachedMessage {
+            id: message_id,
+            thread_id: row.get("thread_id"),
+            label_ids,
+            snippet: row.get("snippet"),
+            subject: row.get("subject"),
+            from_addr: row.get("from_addr"),
+            to_addr: row.get("to_addr"),
+            date_str: row.get("date_str"),
+            body_text: row.get("body_text"),
+            body_html: row.get("body_html"),
+            received_date: row.get("received_date"),
+            internal_date: row.get("internal_date"),
+            is_unread: row.get("is_unread"),
+            is_starred: row.get("is_starred"),
+            cache_timestamp: row.get("cache_timestamp"),
+        })
+    }
+
+    // Full-text search over the cached messages, best matches first. The query
+    // uses the Gmail search syntax; see `fts_query` for what is understood.
+    pub async fn search(&self, query: &str, limit: i64) -> Result<Vec<SearchHit>, sqlx::Error> {
+        let Some(fts_query) = fts_query(query) else {
+            return Ok(Vec::new());
+        };
+
+        // Matches in the subject and sender weigh more than matches in the body
+        let rows = sqlx::query(
+            r#"
+            SELECT m.id, m.thread_id, m.snippet, m.subject, m.from_addr, m.to_addr, m.date_str,
+                   m.body_text, m.body_html, m.received_date, m.internal_date,
+                   m.is_unread, m.is_starred, m.cache_timestamp,
+                   snippet(messages_fts, -1, ?, ?, '…', 12) AS highlighted_snippet,
+                   bm25(messages_fts, 0.0, 10.0, 5.0, 3.0, 2.0, 1.0) AS rank
+            FROM messages_fts
+            JOIN messages m ON m.rowid = messages_fts.rowid
+            WHERE messages_fts MATCH ?
+            ORDER BY rank, m.internal_date DESC
+            LIMIT ?
+            "#,
+        )
+        .bind(SEARCH_HIGHLIGHT_START)
+        .bind(SEARCH_HIGHLIGHT_END)
+        .bind(&fts_query)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut hits = Vec::new();
+        for row in rows {
+            hits.push(SearchHit {
+                message: self.message_from_row(&row).await?,
+                highlighted_snippet: row.get("highlighted_snippet"),
+            });
+        }
+
+        Ok(hits)
+    }
+
     // Sync state operations
     pub async fn update_sync_state(
         &self,
@@ -349,7 +642,7 @@ impl Database {
             INSERT INTO sync_state (label_id, history_id, last_sync)
             VALUES (?, ?, CURRENT_TIMESTAMP)
             ON CONFLICT(label_id) DO UPDATE SET
-                history_id = excluded.history_id,
+                history_id = COALESCE(excluded.history_id, sync_state.history_id),
                 last_sync = CURRENT_TIMESTAMP
             "#,
         )
@@ -372,13 +665,492 @@ impl Database {
 
         Ok(row.map(|r| r.get("last_sync")))
     }
+
+    // Store the nextPageToken of the last page loaded for a label (None at the end of the list)
+    pub async fn update_page_token(
+        &self,
+        label_id: &str,
+        page_token: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE sync_state SET page_token = ? WHERE label_id = ?")
+            .bind(page_token)
+            .bind(label_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_page_token(&self, label_id: &str) -> Result<Option<String>, sqlx::Error> {
+        let row = sqlx::query("SELECT page_token FROM sync_state WHERE label_id = ?")
+            .bind(label_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.and_then(|r| r.get("page_token")))
+    }
+
+    // The history id is mailbox-wide, whichever label is shown
+    pub async fn get_mailbox_history_id(&self) -> Result<Option<String>, sqlx::Error> {
+        let row = sqlx::query("SELECT history_id FROM mailbox_state WHERE id = 1")
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.map(|r| r.get("history_id")))
+    }
+
+    pub async fn set_mailbox_history_id(&self, history_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO mailbox_state (id, history_id, last_sync)
+            VALUES (1, ?, CURRENT_TIMESTAMP)
+            ON CONFLICT(id) DO UPDATE SET
+                history_id = excluded.history_id,
+                last_sync = CURRENT_TIMESTAMP
+            "#,
+        )
+        .bind(history_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    // Record that every cached label has been brought up to date
+    pub async fn mark_labels_synced(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE sync_state SET last_sync = CURRENT_TIMESTAMP")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // Incremental sync operations
+    pub async fn delete_message(&self, message_id: &str) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM message_labels WHERE message_id = ?")
+            .bind(message_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            "DELETE FROM messages_fts WHERE rowid = (SELECT rowid FROM messages WHERE id = ?)",
+        )
+        .bind(message_id)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query("DELETE FROM messages WHERE id = ?")
+            .bind(message_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await
+    }
+
+    // Keep the is_unread/is_starred columns in step with the UNREAD and STARRED labels
+    async fn update_message_flags(
+        conn: &mut SqliteConnection,
+        message_id: &str,
+        label_ids: &[String],
+        present: bool,
+    ) -> Result<(), sqlx::Error> {
+        if label_ids.iter().any(|id| id == "UNREAD") {
+            sqlx::query("UPDATE messages SET is_unread = ? WHERE id = ?")
+                .bind(present)
+                .bind(message_id)
+                .execute(&mut *conn)
+                .await?;
+        }
+        if label_ids.iter().any(|id| id == "STARRED") {
+            sqlx::query("UPDATE messages SET is_starred = ? WHERE id = ?")
+                .bind(present)
+                .bind(message_id)
+                .execute(&mut *conn)
+                .await?;
+        }
+        Ok(())
+    }
+
+    // Labels that are not cached yet are stored under their id until the next
+    // label fetch brings in their name
+    pub async fn add_message_labels(
+        &self,
+        message_id: &str,
+        label_ids: &[String],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        Self::update_message_flags(&mut tx, message_id, label_ids, true).await?;
+
+        for label_id in label_ids {
+            sqlx::query("INSERT OR IGNORE INTO labels (id, name) VALUES (?, ?)")
+                .bind(label_id)
+                .bind(label_id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query(
+                "INSERT OR IGNORE INTO message_labels (message_id, label_id) VALUES (?, ?)",
+            )
+            .bind(message_id)
+            .bind(label_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await
+    }
+
+    pub async fn remove_message_labels(
+        &self,
+        message_id: &str,
+        label_ids: &[String],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        Self::update_message_flags(&mut tx, message_id, label_ids, false).await?;
+
+        for label_id in label_ids {
+            sqlx::query("DELETE FROM message_labels WHERE message_id = ? AND label_id = ?")
+                .bind(message_id)
+                .bind(label_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await
+    }
+
+    // Compose autosave operations
+
+    // Replace the autosaved message. Attachments are only rewritten when given,
+    // otherwise the ones saved before are kept.
+    pub async fn save_compose(
+        &self,
+        compose: &SavedCompose,
+        attachments: Option<&[OutgoingAttachment]>,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            INSERT OR REPLACE INTO compose_autosave (
+                id, to_addr, to_cursor, cc, cc_cursor, bcc, bcc_cursor,
+                subject, subject_cursor, body, body_cursor, focused_field, show_bcc,
+                thread_id, in_reply_to, reference_ids, draft_id, draft_message_id, saved_at
+            ) VALUES (1, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP)
+            "#,
+        )
+        .bind(&compose.to)
+        .bind(compose.to_cursor_position as i64)
+        .bind(&compose.cc)
+        .bind(compose.cc_cursor_position as i64)
+        .bind(&compose.bcc)
+        .bind(compose.bcc_cursor_position as i64)
+        .bind(&compose.subject)
+        .bind(compose.subject_cursor_position as i64)
+        .bind(&compose.body)
+        .bind(compose.body_cursor_position as i64)
+        .bind(&compose.focused_field)
+        .bind(compose.show_bcc)
+        .bind(&compose.thread_id)
+        .bind(&compose.in_reply_to)
+        .bind(compose.references.join(" "))
+        .bind(&compose.draft_id)
+        .bind(&compose.draft_message_id)
+        .execute(&mut *tx)
+        .await?;
+
+        if let Some(attachments) = attachments {
+            sqlx::query("DELETE FROM compose_autosave_attachments")
+                .execute(&mut *tx)
+                .await?;
+
+            for (position, attachment) in attachments.iter().enumerate() {
+                sqlx::query(
+                    "INSERT INTO compose_autosave_attachments (position, filename, content_type, content) VALUES (?, ?, ?, ?)",
+                )
+                .bind(position as i64)
+                .bind(&attachment.filename)
+                .bind(&attachment.content_type)
+                .bind(&attachment.content)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await
+    }
+
+    pub async fn load_compose(&self) -> Result<Option<RestorableCompose>, sqlx::Error> {
+        let Some(row) = sqlx::query("SELECT * FROM compose_autosave WHERE id = 1")
+            .fetch_optional(&self.pool)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let compose = SavedCompose {
+            to: row.get("to_addr"),
+            to_cursor_position: row.get::<i64, _>("to_cursor") as usize,
+            cc: row.get("cc"),
+            cc_cursor_position: row.get::<i64, _>("cc_cursor") as usize,
+            bcc: row.get("bcc"),
+            bcc_cursor_position: row.get::<i64, _>("bcc_cursor") as usize,
+            subject: row.get("subject"),
+            subject_cursor_position: row.get::<i64, _>("subject_cursor") as usize,
+            body: row.get("body"),
+            body_cursor_position: row.get::<i64, _>("body_cursor") as usize,
+            focused_field: row.get("focused_field"),
+            show_bcc: row.get("show_bcc"),
+            thread_id: row.get("thread_id"),
+            in_reply_to: row.get("in_reply_to"),
+            references: row
+                .get::<String, _>("reference_ids")
+                .split_whitespace()
+                .map(str::to_string)
+                .collect(),
+            draft_id: row.get("draft_id"),
+            draft_message_id: row.get("draft_message_id"),
+        };
+        let saved_at = row
+            .get::<Option<DateTime<Utc>>, _>("saved_at")
+            .unwrap_or_else(Utc::now);
+
+        let attachments = sqlx::query(
+            "SELECT filename, content_type, content FROM compose_autosave_attachments ORDER BY position",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| OutgoingAttachment {
+            filename: row.get("filename"),
+            content_type: row.get("content_type"),
+            content: row.get("content"),
+        })
+        .collect();
+
+        Ok(Some(RestorableCompose {
+            compose,
+            attachments,
+            saved_at,
+        }))
+    }
+
+    pub async fn clear_compose(&self) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM compose_autosave")
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM compose_autosave_attachments")
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await
+    }
+
+    // Outbox operations
+    pub async fn add_outbox_entry(
+        &self,
+        action: &OutboxAction,
+        description: &str,
+        subject: &str,
+    ) -> Result<OutboxEntry, sqlx::Error> {
+        let (name, message_ids, thread_id, raw, draft_id) = match action {
+            OutboxAction::Send {
+                raw,
+                thread_id,
+                draft_id,
+            } => ("send", "", thread_id, Some(raw), draft_id),
+            OutboxAction::Modify {
+                action,
+                message_ids,
+                thread_id,
+            } => (
+                action.name(),
+                &*message_ids.join(" "),
+                thread_id,
+                None,
+                &None,
+            ),
+        };
+
+        let row = sqlx::query(
+            r#"
+            INSERT INTO outbox (action, message_ids, thread_id, raw, draft_id, description, subject)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            RETURNING id, created_at
+            "#,
+        )
+        .bind(name)
+        .bind(message_ids)
+        .bind(thread_id)
+        .bind(raw)
+        .bind(draft_id)
+        .bind(description)
+        .bind(subject)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(OutboxEntry {
+            id: row.get("id"),
+            action: action.clone(),
+            description: description.to_string(),
+            subject: subject.to_string(),
+            error: None,
+            created_at: row
+                .get::<Option<DateTime<Utc>>, _>("created_at")
+                .unwrap_or_else(Utc::now),
+        })
+    }
+
+    // Every outbox entry, oldest first. Entries whose action is not known are skipped.
+    pub async fn get_outbox(&self) -> Result<Vec<OutboxEntry>, sqlx::Error> {
+        let rows = sqlx::query("SELECT * FROM outbox ORDER BY id")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            let name: String = row.get("action");
+            let thread_id: Option<String> = row.get("thread_id");
+            let action = if name == "send" {
+                OutboxAction::Send {
+                    raw: row.get::<Option<String>, _>("raw").unwrap_or_default(),
+                    thread_id,
+                    draft_id: row.get("draft_id"),
+                }
+            } else if let Some(action) = crate::outbox::MessageAction::from_name(&name) {
+                OutboxAction::Modify {
+                    action,
+                    message_ids: row
+                        .get::<String, _>("message_ids")
+                        .split_whitespace()
+                        .map(str::to_string)
+                        .collect(),
+                    thread_id,
+                }
+            } else {
+                continue;
+            };
+
+            entries.push(OutboxEntry {
+                id: row.get("id"),
+                action,
+                description: row.get("description"),
+                subject: row.get("subject"),
+                error: row.get("error"),
+                created_at: row
+                    .get::<Option<DateTime<Utc>>, _>("created_at")
+                    .unwrap_or_else(Utc::now),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    pub async fn set_outbox_error(&self, id: i64, error: Option<&str>) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE outbox SET error = ? WHERE id = ?")
+            .bind(error)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_outbox_entry(&self, id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM outbox WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+// Translate a Gmail-style search into an FTS5 query. Bare words match any
+// field by prefix, "quoted phrases" match exactly, from:/to:/subject: restrict
+// a term to that field and a leading '-' excludes it. Operators that the cache
+// cannot answer, like newer_than: or has:attachment, are ignored. Returns None
+// if nothing searchable is left.
+fn fts_query(query: &str) -> Option<String> {
+    let mut included = Vec::new();
+    let mut excluded = Vec::new();
+
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        // Read one term, keeping quoted phrases together
+        let mut term = String::new();
+        let mut in_quotes = false;
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() && !in_quotes {
+                break;
+            }
+            if c == '"' {
+                in_quotes = !in_quotes;
+            }
+            term.push(c);
+            chars.next();
+        }
+
+        let (negated, term) = match term.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, term.as_str()),
+        };
+
+        let (column, value) = match term.split_once(':') {
+            Some(("from", value)) => (Some("from_addr"), value),
+            Some(("to", value)) => (Some("to_addr"), value),
+            Some(("subject", value)) => (Some("subject"), value),
+            Some((operator, _)) if !operator.contains('"') => continue,
+            _ => (None, term),
+        };
+
+        // Quote every term so punctuation like '@' or '.' is not FTS5 syntax
+        let (phrase, prefix) = match value.strip_prefix('"') {
+            Some(quoted) => (quoted.trim_end_matches('"'), ""),
+            None => (value, "*"),
+        };
+        if phrase.trim().is_empty() || (column.is_none() && matches!(phrase, "OR" | "AND")) {
+            continue;
+        }
+        let phrase = format!("\"{}\"{}", phrase.replace('"', "\"\""), prefix);
+        let term = match column {
+            Some(column) => format!("{} : {}", column, phrase),
+            None => phrase,
+        };
+
+        if negated {
+            excluded.push(term);
+        } else {
+            included.push(term);
+        }
+    }
+
+    if included.is_empty() {
+        return None;
+    }
+
+    let mut fts_query = included.join(" ");
+    for term in excluded {
+        fts_query.push_str(" NOT ");
+        fts_query.push_str(&term);
+    }
+    Some(fts_query)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::outbox::MessageAction;
     use sqlx::sqlite::SqlitePoolOptions;
-    use tokio;
 
     async fn setup_test_db() -> Result<Database, sqlx::Error> {
         let pool = SqlitePoolOptions::new()
@@ -458,4 +1230,386 @@ mod tests {
         assert_eq!(messages_allmail.len(), 1);
         assert_eq!(messages_allmail[0].id, "test_msg_1");
     }
+
+    fn sample_message(id: &str, label_ids: &[&str]) -> CachedMessage {
+        CachedMessage {
+            id: id.to_string(),
+            thread_id: Some(format!("thread_{}", id)),
+            label_ids: label_ids.iter().map(|l| l.to_string()).collect(),
+            snippet: Some("Snippet".to_string()),
+            subject: Some("Subject".to_string()),
+            from_addr: Some("sender@example.com".to_string()),
+            to_addr: None,
+            date_str: None,
+            body_text: None,
+            body_html: None,
+            received_date: Utc::now(),
+            internal_date: Utc::now(),
+            is_unread: false,
+            is_starred: false,
+            cache_timestamp: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mailbox_history_id() {
+        let db = setup_test_db().await.unwrap();
+        assert_eq!(db.get_mailbox_history_id().await.unwrap(), None);
+
+        // Stored without any cached label to hang it on
+        db.set_mailbox_history_id("1000").await.unwrap();
+        assert_eq!(
+            db.get_mailbox_history_id().await.unwrap(),
+            Some("1000".to_string())
+        );
+
+        // Per-label sync state does not touch it
+        db.upsert_label(&Label {
+            id: Some("INBOX".to_string()),
+            name: Some("Inbox".to_string()),
+        })
+        .await
+        .unwrap();
+        db.update_sync_state("INBOX", Some("999")).await.unwrap();
+        assert_eq!(
+            db.get_mailbox_history_id().await.unwrap(),
+            Some("1000".to_string())
+        );
+
+        db.set_mailbox_history_id("1200").await.unwrap();
+        assert_eq!(
+            db.get_mailbox_history_id().await.unwrap(),
+            Some("1200".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_page_token_persistence() {
+        let db = setup_test_db().await.unwrap();
+        db.upsert_label(&Label {
+            id: Some("INBOX".to_string()),
+            name: Some("Inbox".to_string()),
+        })
+        .await
+        .unwrap();
+        db.update_sync_state("INBOX", None).await.unwrap();
+
+        db.update_page_token("INBOX", Some("page_2")).await.unwrap();
+        assert_eq!(
+            db.get_page_token("INBOX").await.unwrap(),
+            Some("page_2".to_string())
+        );
+
+        db.update_page_token("INBOX", None).await.unwrap();
+        assert_eq!(db.get_page_token("INBOX").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_apply_label_changes_and_delete() {
+        let db = setup_test_db().await.unwrap();
+        for (id, name) in [("INBOX", "Inbox"), ("STARRED", "Starred")] {
+            db.upsert_label(&Label {
+                id: Some(id.to_string()),
+                name: Some(name.to_string()),
+            })
+            .await
+            .unwrap();
+        }
+        db.upsert_message(&sample_message("msg_1", &["INBOX"]))
+            .await
+            .unwrap();
+
+        db.add_message_labels("msg_1", &["STARRED".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_messages_for_label("STARRED", 10, 0)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        db.remove_message_labels("msg_1", &["INBOX".to_string()])
+            .await
+            .unwrap();
+        assert!(db
+            .get_messages_for_label("INBOX", 10, 0)
+            .await
+            .unwrap()
+            .is_empty());
+
+        db.delete_message("msg_1").await.unwrap();
+        assert!(db
+            .get_messages_for_label("ALLMAIL", 10, 0)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(db
+            .get_messages_for_label("STARRED", 10, 0)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delete_label_drops_its_assignments() {
+        let db = setup_test_db().await.unwrap();
+        for (id, name) in [("INBOX", "Inbox"), ("Label_1", "Receipts")] {
+            db.upsert_label(&Label {
+                id: Some(id.to_string()),
+                name: Some(name.to_string()),
+            })
+            .await
+            .unwrap();
+        }
+        db.upsert_message(&sample_message("msg_1", &["INBOX", "Label_1"]))
+            .await
+            .unwrap();
+        db.update_sync_state("Label_1", None).await.unwrap();
+
+        db.rename_label("Label_1", "Invoices").await.unwrap();
+        let names: Vec<_> = db
+            .get_labels()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|l| l.name)
+            .collect();
+        assert_eq!(names, vec!["Inbox", "Invoices"]);
+
+        db.delete_label("Label_1").await.unwrap();
+        assert_eq!(db.get_labels().await.unwrap().len(), 1);
+        assert_eq!(db.get_sync_state("Label_1").await.unwrap(), None);
+        assert!(db
+            .get_messages_for_label("Label_1", 10, 0)
+            .await
+            .unwrap()
+            .is_empty());
+        // The message itself stays in its other labels
+        assert_eq!(
+            db.get_messages_for_label("INBOX", 10, 0)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // Labels missing from the cache are added rather than dropping the change
+        db.add_message_labels("msg_1", &["Label_1".to_string(), "STARRED".to_string()])
+            .await
+            .unwrap();
+        let messages = db.get_messages_for_label("INBOX", 10, 0).await.unwrap();
+        assert!(messages[0].is_starred);
+        assert_eq!(
+            db.get_messages_for_label("Label_1", 10, 0)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_fts_query_translates_gmail_syntax() {
+        assert_eq!(fts_query("invoice").as_deref(), Some("\"invoice\"*"));
+        assert_eq!(
+            fts_query("from:alice@example.com \"quarterly report\"").as_deref(),
+            Some("from_addr : \"alice@example.com\"* \"quarterly report\"")
+        );
+        assert_eq!(
+            fts_query("lunch -subject:friday newer_than:7d").as_deref(),
+            Some("\"lunch\"* NOT subject : \"friday\"*")
+        );
+        assert_eq!(fts_query("has:attachment -spam"), None);
+        assert_eq!(fts_query("   "), None);
+    }
+
+    #[tokio::test]
+    async fn test_search_ranks_and_highlights_matches() {
+        let db = setup_test_db().await.unwrap();
+        db.upsert_label(&Label {
+            id: Some("INBOX".to_string()),
+            name: Some("Inbox".to_string()),
+        })
+        .await
+        .unwrap();
+
+        let mut in_body = sample_message("in_body", &["INBOX"]);
+        in_body.body_text = Some("Please find the invoice for March attached.".to_string());
+        let mut in_subject = sample_message("in_subject", &["INBOX"]);
+        in_subject.subject = Some("Invoice #42".to_string());
+        let mut unrelated = sample_message("unrelated", &["INBOX"]);
+        unrelated.body_text = Some("See you at lunch".to_string());
+        for message in [&in_body, &in_subject, &unrelated] {
+            db.upsert_message(message).await.unwrap();
+        }
+
+        let hits = db.search("invoice", 10).await.unwrap();
+        let ids: Vec<_> = hits.iter().map(|hit| hit.message.id.as_str()).collect();
+        assert_eq!(ids, vec!["in_subject", "in_body"]);
+        assert_eq!(hits[0].message.label_ids, vec!["INBOX"]);
+        assert!(hits[1].highlighted_snippet.contains(&format!(
+            "{}invoice{}",
+            SEARCH_HIGHLIGHT_START, SEARCH_HIGHLIGHT_END
+        )));
+
+        // Diacritics and prefixes still match
+        assert_eq!(db.search("invoi", 10).await.unwrap().len(), 2);
+        assert_eq!(db.search("lünch", 10).await.unwrap().len(), 1);
+        assert!(db.search("has:attachment", 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_index_follows_upserts_and_deletes() {
+        let db = setup_test_db().await.unwrap();
+        db.upsert_label(&Label {
+            id: Some("INBOX".to_string()),
+            name: Some("Inbox".to_string()),
+        })
+        .await
+        .unwrap();
+
+        let mut message = sample_message("msg_1", &["INBOX"]);
+        message.body_text = Some("The boat leaves at dawn".to_string());
+        db.upsert_message(&message).await.unwrap();
+
+        // Refreshing the list metadata keeps the body fetched earlier searchable
+        let mut refreshed = sample_message("msg_1", &["INBOX"]);
+        refreshed.subject = Some("Travel plans".to_string());
+        db.upsert_message(&refreshed).await.unwrap();
+        assert_eq!(db.search("boat", 10).await.unwrap().len(), 1);
+        assert_eq!(db.search("subject:travel", 10).await.unwrap().len(), 1);
+        assert!(db.search("subject:subject", 10).await.unwrap().is_empty());
+
+        let mut other = sample_message("msg_2", &["INBOX"]);
+        other.body_text = Some("Another boat, another dawn".to_string());
+        db.upsert_message(&other).await.unwrap();
+        assert_eq!(db.search("boat", 10).await.unwrap().len(), 2);
+
+        db.delete_message("msg_1").await.unwrap();
+        let results = db.search("boat", 10).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].message.id, "msg_2");
+
+        let indexed: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM messages_fts")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(indexed, 1);
+    }
+
+    #[tokio::test]
+    async fn test_unread_and_starred_flags_follow_label_changes() {
+        let db = setup_test_db().await.unwrap();
+        for id in ["INBOX", "UNREAD", "STARRED"] {
+            db.upsert_label(&Label {
+                id: Some(id.to_string()),
+                name: Some(id.to_string()),
+            })
+            .await
+            .unwrap();
+        }
+        let mut message = sample_message("msg_1", &["INBOX"]);
+        message.is_unread = true;
+        db.upsert_message(&message).await.unwrap();
+
+        let cached = &db.get_messages_for_label("INBOX", 10, 0).await.unwrap()[0];
+        assert!(cached.is_unread && !cached.is_starred);
+        assert_eq!(cached.label_ids_with_flags(), vec!["INBOX", "UNREAD"]);
+
+        db.remove_message_labels("msg_1", &["UNREAD".to_string()])
+            .await
+            .unwrap();
+        db.add_message_labels("msg_1", &["STARRED".to_string()])
+            .await
+            .unwrap();
+
+        let cached = &db.get_messages_for_label("INBOX", 10, 0).await.unwrap()[0];
+        assert!(!cached.is_unread && cached.is_starred);
+        let mut label_ids = cached.label_ids_with_flags();
+        label_ids.sort();
+        assert_eq!(label_ids, vec!["INBOX", "STARRED"]);
+    }
+
+    #[tokio::test]
+    async fn test_compose_autosave_round_trip() {
+        let db = setup_test_db().await.unwrap();
+        assert!(db.load_compose().await.unwrap().is_none());
+
+        let mut compose = SavedCompose {
+            to: "bob@example.com".to_string(),
+            to_cursor_position: 15,
+            subject: "Re: Plans".to_string(),
+            body: "Friday works".to_string(),
+            body_cursor_position: 6,
+            focused_field: "body".to_string(),
+            in_reply_to: Some("<m1@example.com>".to_string()),
+            references: vec![
+                "<m0@example.com>".to_string(),
+                "<m1@example.com>".to_string(),
+            ],
+            ..Default::default()
+        };
+        let attachment = OutgoingAttachment {
+            filename: "notes.txt".to_string(),
+            content_type: "text/plain".to_string(),
+            content: b"milk".to_vec(),
+        };
+        db.save_compose(&compose, Some(std::slice::from_ref(&attachment)))
+            .await
+            .unwrap();
+
+        // Saving without attachments keeps the ones saved before
+        compose.body.push_str(" for me");
+        db.save_compose(&compose, None).await.unwrap();
+
+        let restored = db.load_compose().await.unwrap().unwrap();
+        assert_eq!(restored.compose, compose);
+        assert_eq!(restored.attachments, vec![attachment]);
+
+        db.clear_compose().await.unwrap();
+        assert!(db.load_compose().await.unwrap().is_none());
+    }
+    #[tokio::test]
+    async fn test_outbox_round_trip() {
+        let db = setup_test_db().await.unwrap();
+        let send = OutboxAction::Send {
+            raw: "Subject: Hi\r\n\r\nHello".to_string(),
+            thread_id: Some("t1".to_string()),
+            draft_id: None,
+        };
+        let archive = OutboxAction::Modify {
+            action: MessageAction::Archive,
+            message_ids: vec!["m1".to_string(), "m2".to_string()],
+            thread_id: None,
+        };
+        let first = db
+            .add_outbox_entry(&send, "Send to bob@example.com", "Hi")
+            .await
+            .unwrap();
+        let second = db
+            .add_outbox_entry(&archive, "Archive", "News")
+            .await
+            .unwrap();
+        assert!(first.id < second.id);
+
+        db.set_outbox_error(first.id, Some("Failed to send email: 400"))
+            .await
+            .unwrap();
+        let outbox = db.get_outbox().await.unwrap();
+        assert_eq!(outbox.len(), 2);
+        assert_eq!(outbox[0].action, send);
+        assert_eq!(
+            outbox[0].error.as_deref(),
+            Some("Failed to send email: 400")
+        );
+        assert_eq!(outbox[1], second);
+
+        db.set_outbox_error(first.id, None).await.unwrap();
+        db.delete_outbox_entry(second.id).await.unwrap();
+        let outbox = db.get_outbox().await.unwrap();
+        assert_eq!(outbox.len(), 1);
+        assert!(outbox[0].error.is_none());
+    }
 }
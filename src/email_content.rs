@@ -1,7 +1,21 @@
-use crate::types::MessagePart;
+use crate::types::{Attachment, MessagePart};
 use base64::engine::general_purpose::URL_SAFE;
 use base64::engine::Engine;
 
+// Value of a header of a message part; header names are case-insensitive
+pub fn header_value<'a>(payload: &'a MessagePart, name: &str) -> Option<&'a str> {
+    payload
+        .headers
+        .as_ref()?
+        .iter()
+        .find(|h| {
+            h.name
+                .as_deref()
+                .is_some_and(|n| n.eq_ignore_ascii_case(name))
+        })
+        .and_then(|h| h.value.as_deref())
+}
+
 // Extract plain text content specifically
 pub fn extract_plain_text_body(payload: &MessagePart) -> Option<String> {
     // Check if this part is plain text
@@ -60,6 +74,34 @@ pub fn extract_html_body(payload: &MessagePart) -> Option<String> {
     None
 }
 
+// Collect the attachments of a message by walking its whole MIME tree.
+// Gmail only sets a file name on parts that are attachments.
+pub fn extract_attachments(payload: &MessagePart) -> Vec<Attachment> {
+    let mut attachments = Vec::new();
+    collect_attachments(payload, &mut attachments);
+    attachments
+}
+
+fn collect_attachments(part: &MessagePart, attachments: &mut Vec<Attachment>) {
+    if let Some(filename) = part.filename.as_ref().filter(|name| !name.is_empty()) {
+        let body = part.body.clone().unwrap_or_default();
+        attachments.push(Attachment {
+            filename: filename.clone(),
+            mime_type: part
+                .mime_type
+                .clone()
+                .unwrap_or_else(|| "application/octet-stream".to_string()),
+            size: body.size.unwrap_or(0),
+            attachment_id: body.attachment_id,
+            data: body.data,
+        });
+    }
+
+    for child in part.parts.iter().flatten() {
+        collect_attachments(child, attachments);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -72,11 +114,12 @@ mod tests {
     ) -> MessagePart {
         MessagePart {
             mime_type: Some(mime_type.to_string()),
-            headers: None,
             body: data.map(|d| MessagePartBody {
                 data: Some(URL_SAFE.encode(d)),
+                ..Default::default()
             }),
             parts,
+            ..Default::default()
         }
     }
 
@@ -153,4 +196,56 @@ mod tests {
         let payload = create_message_part("text/html", Some(""), None);
         assert_eq!(extract_html_body(&payload), Some("".to_string()));
     }
+
+    fn attachment_part(filename: &str, mime_type: &str, attachment_id: &str) -> MessagePart {
+        MessagePart {
+            mime_type: Some(mime_type.to_string()),
+            filename: Some(filename.to_string()),
+            body: Some(MessagePartBody {
+                attachment_id: Some(attachment_id.to_string()),
+                size: Some(2048),
+                data: None,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_extract_attachments_walks_nested_parts() {
+        let alternative = create_message_part(
+            "multipart/alternative",
+            None,
+            Some(vec![
+                create_message_part("text/plain", Some("See attached."), None),
+                create_message_part("text/html", Some("<p>See attached.</p>"), None),
+            ]),
+        );
+        let related = create_message_part(
+            "multipart/related",
+            None,
+            Some(vec![attachment_part("logo.png", "image/png", "att-2")]),
+        );
+        let mixed = create_message_part(
+            "multipart/mixed",
+            None,
+            Some(vec![
+                alternative,
+                attachment_part("report.pdf", "application/pdf", "att-1"),
+                related,
+            ]),
+        );
+
+        let attachments = extract_attachments(&mixed);
+        let names: Vec<_> = attachments.iter().map(|a| a.filename.as_str()).collect();
+        assert_eq!(names, vec!["report.pdf", "logo.png"]);
+        assert_eq!(attachments[0].mime_type, "application/pdf");
+        assert_eq!(attachments[0].size, 2048);
+        assert_eq!(attachments[0].attachment_id.as_deref(), Some("att-1"));
+    }
+
+    #[test]
+    fn test_extract_attachments_ignores_body_parts() {
+        let payload = create_message_part("text/plain", Some("No files here."), None);
+        assert!(extract_attachments(&payload).is_empty());
+    }
 }
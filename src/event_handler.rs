@@ -1,6 +1,26 @@
-use crate::background_tasks::{spawn_message_fetch, spawn_message_fetch_with_cache};
-use crate::gmail_api::{fetch_full_message, load_more_messages, send_email, try_authenticate};
-use crate::state::{AppState, ComposeField, FocusedPane};
+use crate::app::switch_account;
+use crate::background_tasks::{
+    spawn_attachment_download, spawn_message_fetch, spawn_message_fetch_with_cache,
+};
+use crate::email_content::extract_attachments;
+use crate::gmail_api::attachments::fetch_attachment;
+use crate::gmail_api::{
+    add_label, batch_delete, batch_modify, change_labels, create_filter, create_label,
+    delete_filter, delete_label, delete_message_forever, fetch_filters, fetch_full_message,
+    fetch_message, fetch_profile, fetch_raw_message, fetch_thread, find_draft, list_message_ids,
+    load_more_messages, mark_read, mark_thread_read, mark_unread, remove_label, rename_label,
+    save_draft, star, try_authenticate, unstar, untrash_message,
+};
+use crate::mime::OutgoingAttachment;
+use crate::outbox::{
+    apply_message_action, cancel_outbox_entry, retry_outbox_entry, send_message,
+    undo_message_action, Delivery, MessageAction,
+};
+use crate::state::{
+    is_assignable_label, AppState, ComposeField, FilterForm, FocusedPane, LabelPrompt, PurgePrompt,
+    SEARCH_LABEL_ID,
+};
+use crate::types::Message;
 use crossterm::event::{self, KeyCode, KeyModifiers};
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -40,6 +60,249 @@ pub async fn handle_key_event(
         return Ok(false); // Don't quit
     }
 
+    // Handle the offer to restore a message left unsent by the last run
+    if state_guard.restorable_compose.is_some() {
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => state_guard.restore_compose(),
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                state_guard.discard_restorable_compose().await
+            }
+            _ => {} // Ignore other keys while the prompt is showing
+        }
+        return Ok(false); // Don't quit
+    }
+
+    // Handle account switcher popup
+    if state_guard.show_account_switcher {
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => state_guard.account_switcher_down(),
+            KeyCode::Char('k') | KeyCode::Up => state_guard.account_switcher_up(),
+            KeyCode::Enter => {
+                let account = state_guard.selected_switcher_account().cloned();
+                state_guard.close_account_switcher();
+                if let Some(account) = account.filter(|account| *account != state_guard.account) {
+                    drop(state_guard); // Switching re-authenticates, don't hold the lock
+                    if let Err(e) = switch_account(state_arc.clone(), &account).await {
+                        state_arc.write().await.set_error_message(format!(
+                            "Failed to switch to account '{}': {}",
+                            account, e
+                        ));
+                    }
+                }
+            }
+            KeyCode::Char('A') | KeyCode::Esc => state_guard.close_account_switcher(),
+            _ => {} // Ignore other keys while the switcher is showing
+        }
+        return Ok(false); // Don't quit
+    }
+
+    // Handle attachment picker popup
+    if state_guard.show_attachment_picker {
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => state_guard.attachment_picker_down(),
+            KeyCode::Char('k') | KeyCode::Up => state_guard.attachment_picker_up(),
+            KeyCode::Enter => {
+                let attachment = state_guard.selected_attachment();
+                let message_id = state_guard.displayed_message_id();
+                state_guard.close_attachment_picker();
+                if state_guard.attachment_download.is_some() {
+                    state_guard
+                        .set_error_message("Another attachment is still downloading.".to_string());
+                } else if let (Some(attachment), Some(message_id)) = (attachment, message_id) {
+                    drop(state_guard); // Release the lock before spawning
+                    spawn_attachment_download(state_arc.clone(), message_id, attachment);
+                }
+            }
+            KeyCode::Char('w') | KeyCode::Esc => state_guard.close_attachment_picker(),
+            _ => {} // Ignore other keys while the picker is showing
+        }
+        return Ok(false); // Don't quit
+    }
+
+    // Handle search prompt input
+    if state_guard.search_input.is_some() {
+        match key.code {
+            KeyCode::Enter => {
+                let query = state_guard.search_input.take().unwrap_or_default();
+                if state_guard.start_search(&query) {
+                    // Show matches from the local cache while Gmail is searched
+                    let _ = state_guard.load_messages_from_cache(SEARCH_LABEL_ID).await;
+                    state_guard.switch_to_messages_pane();
+                    state_guard.set_loading_messages(true);
+                    drop(state_guard); // Release the lock before spawning
+                    spawn_message_fetch(state_arc.clone());
+                } else {
+                    // The search was cleared, show the folder that is now selected
+                    state_guard.reset_pagination();
+                    drop(state_guard); // Release the lock before spawning
+                    spawn_message_fetch_with_cache(state_arc.clone());
+                }
+            }
+            KeyCode::Esc => state_guard.close_search_prompt(),
+            KeyCode::Backspace => {
+                if let Some(input) = state_guard.search_input.as_mut() {
+                    input.pop();
+                }
+            }
+            KeyCode::Char(c) => {
+                if let Some(input) = state_guard.search_input.as_mut() {
+                    input.push(c);
+                }
+            }
+            _ => {} // Ignore other keys while the prompt is showing
+        }
+        return Ok(false); // Don't quit
+    }
+
+    // Handle the mark-matching prompt input
+    if state_guard.mark_input.is_some() {
+        match key.code {
+            KeyCode::Enter => {
+                let pattern = state_guard.mark_input.take().unwrap_or_default();
+                if state_guard.mark_matching(&pattern) == 0 {
+                    state_guard.set_error_message(format!("No message matches '{}'.", pattern));
+                }
+            }
+            KeyCode::Esc => state_guard.close_mark_prompt(),
+            KeyCode::Backspace => {
+                if let Some(input) = state_guard.mark_input.as_mut() {
+                    input.pop();
+                }
+            }
+            KeyCode::Char(c) => {
+                if let Some(input) = state_guard.mark_input.as_mut() {
+                    input.push(c);
+                }
+            }
+            _ => {} // Ignore other keys while the prompt is showing
+        }
+        return Ok(false); // Don't quit
+    }
+
+    // Handle label management prompt input
+    if let Some(prompt) = state_guard.label_prompt.clone() {
+        match (key.code, prompt) {
+            (KeyCode::Esc, _) => state_guard.close_label_prompt(),
+            (KeyCode::Enter, LabelPrompt::Create { name }) => {
+                state_guard.close_label_prompt();
+                handle_create_label(&mut state_guard, name.trim()).await;
+            }
+            (KeyCode::Enter, LabelPrompt::Rename { label_id, name }) => {
+                state_guard.close_label_prompt();
+                handle_rename_label(&mut state_guard, &label_id, name.trim()).await;
+            }
+            (
+                KeyCode::Char('y') | KeyCode::Char('Y'),
+                LabelPrompt::ConfirmDelete { label_id, .. },
+            ) => {
+                state_guard.close_label_prompt();
+                if handle_delete_label(&mut state_guard, &label_id).await {
+                    // The deleted label was the open folder, show its replacement
+                    state_guard.reset_pagination();
+                    drop(state_guard); // Release the lock before spawning
+                    spawn_message_fetch_with_cache(state_arc.clone());
+                }
+            }
+            (KeyCode::Char('n') | KeyCode::Char('N'), LabelPrompt::ConfirmDelete { .. }) => {
+                state_guard.close_label_prompt()
+            }
+            (KeyCode::Backspace, _) => {
+                if let Some(input) = state_guard.label_prompt_input() {
+                    input.pop();
+                }
+            }
+            (KeyCode::Char(c), _) => {
+                if let Some(input) = state_guard.label_prompt_input() {
+                    input.push(c);
+                }
+            }
+            _ => {} // Ignore other keys while the prompt is showing
+        }
+        return Ok(false); // Don't quit
+    }
+
+    // Handle the confirmation before messages are deleted for good
+    if let Some(prompt) = state_guard.purge_prompt.clone() {
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => {
+                state_guard.close_purge_prompt();
+                match prompt {
+                    PurgePrompt::DeleteForever { message_ids } => {
+                        handle_delete_forever(&mut state_guard, message_ids).await
+                    }
+                    PurgePrompt::EmptyFolder { label_id } => {
+                        handle_empty_folder(&mut state_guard, &label_id).await
+                    }
+                }
+            }
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                state_guard.close_purge_prompt()
+            }
+            _ => {} // Ignore other keys while the prompt is showing
+        }
+        return Ok(false); // Don't quit
+    }
+
+    // Handle the filters screen
+    if state_guard.filters_screen.is_some() {
+        handle_filters_screen_key(&mut state_guard, key.code).await;
+        return Ok(false); // Don't quit
+    }
+
+    // Handle label picker input
+    if state_guard.label_picker.is_some() {
+        match key.code {
+            KeyCode::Enter => {
+                if state_guard
+                    .label_picker
+                    .as_ref()
+                    .is_some_and(|picker| picker.moving)
+                {
+                    handle_move_to_label(&mut state_guard).await;
+                } else {
+                    handle_toggle_label(&mut state_guard).await;
+                }
+                state_guard.close_label_picker();
+            }
+            KeyCode::Esc => state_guard.close_label_picker(),
+            KeyCode::Down => state_guard.label_picker_down(),
+            KeyCode::Up => state_guard.label_picker_up(),
+            KeyCode::Backspace => state_guard.label_picker_pop(),
+            KeyCode::Char(c) => state_guard.label_picker_push(c),
+            _ => {} // Ignore other keys while the picker is showing
+        }
+        return Ok(false); // Don't quit
+    }
+
+    // Handle the attach-file prompt of the compose window
+    if state_guard.composing && state_guard.compose_state.attachment_input.is_some() {
+        match key.code {
+            KeyCode::Enter => {
+                let path = state_guard
+                    .compose_state
+                    .attachment_input
+                    .take()
+                    .unwrap_or_default();
+                if !path.trim().is_empty() {
+                    state_guard.compose_state.attach_file(&path);
+                }
+            }
+            KeyCode::Esc => state_guard.compose_state.attachment_input = None,
+            KeyCode::Backspace => {
+                if let Some(input) = state_guard.compose_state.attachment_input.as_mut() {
+                    input.pop();
+                }
+            }
+            KeyCode::Char(c) => {
+                if let Some(input) = state_guard.compose_state.attachment_input.as_mut() {
+                    input.push(c);
+                }
+            }
+            _ => {} // Ignore other keys while the prompt is showing
+        }
+        return Ok(false); // Don't quit
+    }
+
     // Clear error message on any key press if an error is displayed
     if state_guard.error_message.is_some() {
         state_guard.clear_error_message();
@@ -54,7 +317,7 @@ pub async fn handle_key_event(
             if state_guard.composing
                 && state_guard.compose_state.focused_field != ComposeField::Body
             {
-                state_guard.stop_composing();
+                close_compose(&mut state_guard).await;
                 Ok(false)
             } else if !state_guard.composing {
                 Ok(true) // Signal to quit
@@ -73,12 +336,162 @@ pub async fn handle_key_event(
             Ok(false)
         }
 
+        // Open the account switcher with 'A' (only when not composing)
+        KeyCode::Char('A') if !state_guard.composing => {
+            state_guard.open_account_switcher();
+            Ok(false)
+        }
+
+        // Open the filters screen with 'z' (only when not composing)
+        KeyCode::Char('z') if !state_guard.composing => {
+            handle_open_filters(&mut state_guard, None).await;
+            Ok(false)
+        }
+
+        // Write a filter for the sender of the shown message with 'Z' (in Messages or Content pane)
+        KeyCode::Char('Z')
+            if !state_guard.composing
+                && matches!(
+                    state_guard.focused_pane,
+                    FocusedPane::Messages | FocusedPane::Content
+                ) =>
+        {
+            handle_filter_from_message(&mut state_guard).await;
+            Ok(false)
+        }
+
+        // Toggle the conversation view with 't' (only when not composing)
+        KeyCode::Char('t') if !state_guard.composing => {
+            state_guard.toggle_thread_view();
+            Ok(false)
+        }
+
+        // Move between the messages of the open conversation with 'n'/'p'
+        KeyCode::Char('n')
+            if !state_guard.composing && state_guard.focused_pane == FocusedPane::Content =>
+        {
+            state_guard.thread_select_next();
+            Ok(false)
+        }
+
+        KeyCode::Char('p')
+            if !state_guard.composing && state_guard.focused_pane == FocusedPane::Content =>
+        {
+            state_guard.thread_select_prev();
+            Ok(false)
+        }
+
+        // Manage user labels from the folders pane: 'n' new, 'R' rename, 'D' delete
+        KeyCode::Char('n')
+            if !state_guard.composing && state_guard.focused_pane == FocusedPane::Labels =>
+        {
+            state_guard.open_create_label_prompt();
+            Ok(false)
+        }
+
+        KeyCode::Char('R')
+            if !state_guard.composing && state_guard.focused_pane == FocusedPane::Labels =>
+        {
+            state_guard.open_rename_label_prompt();
+            Ok(false)
+        }
+
+        KeyCode::Char('D')
+            if !state_guard.composing && state_guard.focused_pane == FocusedPane::Labels =>
+        {
+            state_guard.open_delete_label_prompt();
+            Ok(false)
+        }
+
+        // Pick labels to apply or remove with 'l' (only in Messages and Content panes)
+        KeyCode::Char('l')
+            if !state_guard.composing
+                && matches!(
+                    state_guard.focused_pane,
+                    FocusedPane::Messages | FocusedPane::Content
+                ) =>
+        {
+            if !reject_foreign_message(&mut state_guard) {
+                state_guard.open_label_picker();
+            }
+            Ok(false)
+        }
+
+        // Move messages to another label with 'm' (only in Messages and Content panes)
+        KeyCode::Char('m')
+            if !state_guard.composing
+                && matches!(
+                    state_guard.focused_pane,
+                    FocusedPane::Messages | FocusedPane::Content
+                ) =>
+        {
+            if reject_foreign_marks(&mut state_guard) || reject_foreign_message(&mut state_guard) {
+                return Ok(false);
+            }
+            let movable = state_guard
+                .get_current_label()
+                .and_then(|label| label.id.as_deref())
+                .is_some_and(is_assignable_label);
+            if movable {
+                state_guard.open_move_picker();
+            } else {
+                state_guard.set_error_message(
+                    "Messages cannot be moved out of this folder. Use l to apply labels instead."
+                        .to_string(),
+                );
+            }
+            Ok(false)
+        }
+
+        // Save an attachment of the shown message with 'w' (only in Messages and Content panes)
+        KeyCode::Char('w')
+            if !state_guard.composing
+                && matches!(
+                    state_guard.focused_pane,
+                    FocusedPane::Messages | FocusedPane::Content
+                ) =>
+        {
+            if !reject_foreign_message(&mut state_guard) {
+                state_guard.open_attachment_picker();
+            }
+            Ok(false)
+        }
+
+        // Open the Gmail search prompt with '/' (only when not composing)
+        KeyCode::Char('/') if !state_guard.composing => {
+            state_guard.open_search_prompt();
+            Ok(false)
+        }
+
         // Toggle help with ? key (only when not composing)
         KeyCode::Char('?') if !state_guard.composing => {
             state_guard.toggle_help();
             Ok(false)
         }
 
+        // Forward inline with 'F' (in Messages or Content pane)
+        KeyCode::Char('F')
+            if !state_guard.composing
+                && matches!(
+                    state_guard.focused_pane,
+                    FocusedPane::Messages | FocusedPane::Content
+                ) =>
+        {
+            handle_forward(&mut state_guard, false).await
+        }
+
+        // Forward as an attachment with Ctrl+F (in Messages or Content pane)
+        KeyCode::Char('f')
+            if !state_guard.composing
+                && key.modifiers.contains(KeyModifiers::CONTROL)
+                && matches!(
+                    state_guard.focused_pane,
+                    FocusedPane::Messages | FocusedPane::Content
+                ) =>
+        {
+            handle_forward(&mut state_guard, true).await
+        }
+
         // Force refresh current label with 'f' key (only when not composing)
         KeyCode::Char('f') if !state_guard.composing => {
             // Get label ID for immediate cache loading
@@ -104,10 +517,13 @@ pub async fn handle_key_event(
             // Clear error message first
             state_guard.clear_error_message();
 
-            // Try to re-authenticate
-            match try_authenticate().await {
+            // Go through consent again, which also grants scopes the stored
+            // token lacks
+            let account = state_guard.account.clone();
+            match try_authenticate(&account, true).await {
                 Ok(auth_result) => {
                     state_guard.token = auth_result.token;
+                    state_guard.token_refresher = auth_result.token_refresher;
                     // If client secret was loaded from file, set flag for TUI prompt
                     if auth_result.client_secret_loaded_from_file {
                         state_guard.client_secret_deletion_prompt = true;
@@ -207,6 +623,29 @@ pub async fn handle_key_event(
             handle_enter_key(&mut state_guard, state_arc.clone()).await
         }
 
+        // In the Outbox folder 'r' retries the selected entry and 'd' cancels it
+        KeyCode::Char('r')
+            if !state_guard.composing
+                && matches!(
+                    state_guard.focused_pane,
+                    FocusedPane::Messages | FocusedPane::Content
+                )
+                && state_guard.showing_outbox() =>
+        {
+            handle_retry_outbox(&mut state_guard).await
+        }
+
+        KeyCode::Char('d')
+            if !state_guard.composing
+                && matches!(
+                    state_guard.focused_pane,
+                    FocusedPane::Messages | FocusedPane::Content
+                )
+                && state_guard.showing_outbox() =>
+        {
+            handle_cancel_outbox(&mut state_guard).await
+        }
+
         // Reply to message with 'r' key (in Messages or Content pane)
         KeyCode::Char('r')
             if !state_guard.composing
@@ -215,7 +654,72 @@ pub async fn handle_key_event(
                     FocusedPane::Messages | FocusedPane::Content
                 ) =>
         {
-            handle_reply(&mut state_guard, state_arc.clone()).await
+            handle_reply(&mut state_guard, false).await
+        }
+
+        // Reply to all recipients with 'R' (in Messages or Content pane)
+        KeyCode::Char('R')
+            if !state_guard.composing
+                && matches!(
+                    state_guard.focused_pane,
+                    FocusedPane::Messages | FocusedPane::Content
+                ) =>
+        {
+            handle_reply(&mut state_guard, true).await
+        }
+
+        // In Trash and Spam: restore or unspam with 'o', delete forever with
+        // 'D' and empty the folder with 'E' (only in Messages and Content panes)
+        KeyCode::Char('o' | 'D' | 'E')
+            if !state_guard.composing
+                && state_guard.trash_or_spam_folder().is_some()
+                && matches!(
+                    state_guard.focused_pane,
+                    FocusedPane::Messages | FocusedPane::Content
+                ) =>
+        {
+            if reject_foreign_marks(&mut state_guard) || reject_foreign_message(&mut state_guard) {
+                return Ok(false);
+            }
+            match key.code {
+                KeyCode::Char('o') => handle_restore(&mut state_guard).await,
+                KeyCode::Char('D') => state_guard.open_delete_forever_prompt(),
+                _ => state_guard.open_empty_folder_prompt(),
+            }
+            Ok(false)
+        }
+
+        // Mark the selected message for bulk actions with 'x', a range of
+        // messages with 'v' at both ends, and all matching messages with 'M'
+        KeyCode::Char('x')
+            if !state_guard.composing
+                && state_guard.focused_pane == FocusedPane::Messages
+                && !state_guard.showing_outbox() =>
+        {
+            state_guard.toggle_mark();
+            Ok(false)
+        }
+        KeyCode::Char('v')
+            if !state_guard.composing
+                && state_guard.focused_pane == FocusedPane::Messages
+                && !state_guard.showing_outbox() =>
+        {
+            state_guard.toggle_range_mark();
+            Ok(false)
+        }
+        KeyCode::Char('M')
+            if !state_guard.composing
+                && state_guard.focused_pane == FocusedPane::Messages
+                && !state_guard.showing_outbox() =>
+        {
+            state_guard.open_mark_prompt();
+            Ok(false)
+        }
+
+        // Escape drops the marks first, if there are any
+        KeyCode::Esc if !state_guard.composing && state_guard.has_marks() => {
+            state_guard.clear_marks();
+            Ok(false)
         }
 
         // Escape to go back to labels pane (only when not composing)
@@ -226,21 +730,48 @@ pub async fn handle_key_event(
 
         // Archive message with 'a' key (only in Messages and Content panes)
         KeyCode::Char('a') if !state_guard.composing => {
-            handle_archive_message(&mut state_guard).await
+            handle_message_action(&mut state_guard, MessageAction::Archive).await
         }
 
         // Archive message with Backspace key (only in Messages and Content panes)
         KeyCode::Backspace if !state_guard.composing => {
-            handle_archive_message(&mut state_guard).await
+            handle_message_action(&mut state_guard, MessageAction::Archive).await
         }
 
         // Delete message with 'd' key (only in Messages and Content panes)
         KeyCode::Char('d') if !state_guard.composing => {
-            handle_delete_message(&mut state_guard).await
+            handle_message_action(&mut state_guard, MessageAction::Delete).await
+        }
+
+        // Undo the last archive, delete or spam action with 'u'
+        KeyCode::Char('u') if !state_guard.composing => handle_undo(&mut state_guard).await,
+
+        // Toggle read/unread with 'U' (only in Messages and Content panes)
+        KeyCode::Char('U')
+            if !state_guard.composing
+                && matches!(
+                    state_guard.focused_pane,
+                    FocusedPane::Messages | FocusedPane::Content
+                ) =>
+        {
+            handle_toggle_unread(&mut state_guard).await
+        }
+
+        // Toggle the star with '*' (only in Messages and Content panes)
+        KeyCode::Char('*')
+            if !state_guard.composing
+                && matches!(
+                    state_guard.focused_pane,
+                    FocusedPane::Messages | FocusedPane::Content
+                ) =>
+        {
+            handle_toggle_star(&mut state_guard).await
         }
 
         // Mark message as spam with 's' key (only in Messages and Content panes)
-        KeyCode::Char('s') if !state_guard.composing => handle_spam_message(&mut state_guard).await,
+        KeyCode::Char('s') if !state_guard.composing => {
+            handle_message_action(&mut state_guard, MessageAction::Spam).await
+        }
 
         _ => Ok(false),
     }
@@ -267,37 +798,74 @@ async fn handle_compose_mode_input(
             Ok(false)
         }
 
-        // Escape to cancel compose
-        KeyCode::Esc => {
+        // Attach a file with Ctrl+A, drop the last attached file with Ctrl+D
+        KeyCode::Char('a') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            state_guard.compose_state.open_attachment_input();
+            Ok(false)
+        }
+
+        KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            state_guard.compose_state.attachments.pop();
+            state_guard.compose_state.attachment_error = None;
+            Ok(false)
+        }
+
+        // Save as a draft with Ctrl+S, close without saving with Ctrl+X
+        KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            match save_compose_draft(state_guard).await {
+                Ok(()) => state_guard.set_error_message("Draft saved.".to_string()),
+                Err(e) => state_guard.set_error_message(format!("Failed to save draft: {}", e)),
+            }
+            Ok(false)
+        }
+
+        KeyCode::Char('x') if key.modifiers.contains(KeyModifiers::CONTROL) => {
             state_guard.stop_composing();
             Ok(false)
         }
 
+        // Escape saves the message as a draft and closes compose
+        KeyCode::Esc => {
+            close_compose(state_guard).await;
+            Ok(false)
+        }
+
         // Enter to send (only when on Send button)
         KeyCode::Enter => {
             if matches!(state_guard.compose_state.focused_field, ComposeField::Send) {
                 // Send the email
                 state_guard.compose_state.sending = true;
-                let result = send_email(
+                let message = state_guard.compose_state.to_message();
+                let thread_id = compose_thread_id(state_guard);
+                // A resumed draft is sent with drafts.send, which also removes it
+                let draft_id = state_guard.compose_state.draft_id().map(str::to_string);
+                let result = send_message(
                     state_guard,
-                    &state_guard.compose_state.to,
-                    &state_guard.compose_state.cc,
-                    &state_guard.compose_state.bcc,
-                    &state_guard.compose_state.subject,
-                    &state_guard.compose_state.body,
+                    &message,
+                    thread_id.as_deref(),
+                    draft_id.as_deref(),
                 )
                 .await;
 
                 state_guard.compose_state.sending = false;
 
                 match result {
-                    Ok(()) => {
-                        // Email sent successfully, close compose window
+                    Ok(delivery) => {
+                        if let Some(message_id) = compose_draft_message_id(state_guard) {
+                            state_guard.record_message_deleted(&message_id).await;
+                        }
+                        // Email sent or queued, close compose window
                         state_guard.stop_composing();
+                        if delivery == Delivery::Queued {
+                            state_guard.set_error_message(
+                                "Gmail cannot be reached. The email waits in the Outbox and is sent once the connection is back."
+                                    .to_string(),
+                            );
+                        }
                     }
-                    Err(_) => {
-                        // Handle error - for now just keep compose window open
-                        // In a real app, you'd show an error message
+                    Err(e) => {
+                        // Keep the compose window open so the message can be sent again
+                        state_guard.set_error_message(e.to_string());
                     }
                 }
             }
@@ -495,6 +1063,12 @@ async fn handle_enter_key(
             Ok(false)
         }
         FocusedPane::Messages => {
+            // Outbox entries are described without fetching anything
+            if state_guard.showing_outbox() {
+                state_guard.switch_to_content_pane();
+                return Ok(false);
+            }
+
             // Load message content and switch to content pane
             let message_id = state_guard
                 .messages
@@ -504,10 +1078,45 @@ async fn handle_enter_key(
             if let Some(id) = message_id {
                 let id_str = &id;
 
+                // Messages of other accounts are fetched with their own credentials
+                if let Some(account) = state_guard.selected_message_foreign_account() {
+                    if !state_guard.message_bodies.contains_key(id_str) {
+                        if let Err(e) = fetch_foreign_message(state_guard, &account, id_str).await {
+                            state_guard
+                                .set_error_message(format!("Error fetching full message: {}", e));
+                            return Ok(false);
+                        }
+                    }
+                    state_guard.update_current_message_display_headers();
+                    state_guard.switch_to_content_pane();
+                    return Ok(false);
+                }
+
+                // Drafts open in the compose window to be finished
+                if state_guard.selected_message_has_label("DRAFT") {
+                    handle_open_draft(state_guard, id_str).await;
+                    return Ok(false);
+                }
+
+                // In thread view, show the whole conversation
+                if let Some(thread_id) = state_guard.selected_thread_id() {
+                    match fetch_thread(state_guard, &thread_id).await {
+                        Ok(()) => mark_thread_read_on_open(state_guard, &thread_id).await,
+                        Err(e) => state_guard
+                            .set_error_message(format!("Error fetching conversation: {}", e)),
+                    }
+                    state_guard.switch_to_content_pane();
+                    return Ok(false);
+                }
+
                 // Fetch full message content and headers
-                if let Err(e) = fetch_full_message(state_guard, id_str).await {
-                    // Handle error, e.g., log it or display a message
-                    state_guard.set_error_message(format!("Error fetching full message: {}", e));
+                match fetch_full_message(state_guard, id_str).await {
+                    Ok(()) => mark_read_on_open(state_guard, id_str).await,
+                    Err(e) => {
+                        // Handle error, e.g., log it or display a message
+                        state_guard
+                            .set_error_message(format!("Error fetching full message: {}", e));
+                    }
                 }
 
                 state_guard.switch_to_content_pane();
@@ -515,209 +1124,865 @@ async fn handle_enter_key(
             Ok(false)
         }
         FocusedPane::Content => {
-            // In content pane, Enter does nothing or could scroll
+            // Expand or collapse the selected message of an open conversation
+            if state_guard.current_thread().is_some() {
+                state_guard.toggle_thread_message();
+            }
             Ok(false)
         }
     }
 }
 
-async fn handle_reply(
+// Fetch a message of another account in the unified inbox into that
+// account's cache. It is only read, so it stays unread there.
+async fn fetch_foreign_message(
+    state_guard: &mut AppState,
+    account: &str,
+    msg_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut context = state_guard.account_inbox_context(account).await?;
+    fetch_full_message(&mut context, msg_id).await?;
+    if let Some(body) = context.message_bodies.remove(msg_id) {
+        state_guard.message_bodies.insert(msg_id.to_string(), body);
+    }
+    Ok(())
+}
+
+// Opening an unread message marks it as read, like Gmail does
+async fn mark_read_on_open(state_guard: &mut AppState, msg_id: &str) {
+    let is_unread = state_guard
+        .messages
+        .iter()
+        .any(|m| m.id.as_deref() == Some(msg_id) && m.is_unread());
+    if !is_unread {
+        return;
+    }
+    match mark_read(state_guard, msg_id).await {
+        Ok(()) => {
+            state_guard
+                .record_label_change(msg_id, &[], &["UNREAD".to_string()])
+                .await
+        }
+        Err(e) => state_guard.set_error_message(format!("Failed to mark message as read: {}", e)),
+    }
+}
+
+// Opening a conversation marks all of its messages as read
+async fn mark_thread_read_on_open(state_guard: &mut AppState, thread_id: &str) {
+    let has_unread = state_guard
+        .messages
+        .iter()
+        .any(|m| m.thread_id.as_deref() == Some(thread_id) && m.is_unread());
+    if !has_unread {
+        return;
+    }
+    match mark_thread_read(state_guard, thread_id).await {
+        Ok(()) => {
+            for msg_id in state_guard.thread_message_ids(thread_id) {
+                state_guard
+                    .record_label_change(&msg_id, &[], &["UNREAD".to_string()])
+                    .await;
+            }
+        }
+        Err(e) => {
+            state_guard.set_error_message(format!("Failed to mark conversation as read: {}", e))
+        }
+    }
+}
+
+// Toggle the read state of the selected message with 'U'
+async fn handle_toggle_unread(
     state_guard: &mut AppState,
-    state_arc: Arc<RwLock<AppState>>,
 ) -> Result<bool, Box<dyn std::error::Error>> {
-    if let Some(msg) = state_guard.messages.get(state_guard.selected_message) {
-        let message_id = msg.id.clone();
-        let _ = state_guard; // Release the lock before reacquiring
-        let mut state_guard_reacquired = state_arc.write().await;
-
-        // Retrieve the full message body from the cache
-        let original_body_full = message_id.as_ref().and_then(|id| {
-            state_guard_reacquired
-                .message_bodies
-                .get(id)
-                .map(|s| s.clone())
-        });
+    // Marked messages are all marked read if any is unread, else all unread
+    if state_guard.has_marks() {
+        let any_unread = state_guard.marked_messages_any(|m| m.is_unread());
+        let unread = vec!["UNREAD".to_string()];
+        let (add, remove, failure) = if any_unread {
+            (vec![], unread, "mark messages as read")
+        } else {
+            (unread, vec![], "mark messages as unread")
+        };
+        change_marked_labels(state_guard, add, remove, failure).await;
+        return Ok(false);
+    }
+    if reject_foreign_message(state_guard) {
+        return Ok(false);
+    }
+    let Some(msg) = state_guard.messages.get(state_guard.selected_message) else {
+        return Ok(false);
+    };
+    let Some(msg_id) = msg.id.clone() else {
+        return Ok(false);
+    };
+    let unread = vec!["UNREAD".to_string()];
 
-        // Ensure full message headers are loaded if not already
-        if state_guard_reacquired
-            .current_message_display_headers
-            .is_none()
-        {
-            if let Some(id) = message_id {
-                let id_str = &id;
-                let fetch_result = fetch_full_message(&mut state_guard_reacquired, id_str).await;
+    if msg.is_unread() {
+        match mark_read(state_guard, &msg_id).await {
+            Ok(()) => state_guard.record_label_change(&msg_id, &[], &unread).await,
+            Err(e) => {
+                state_guard.set_error_message(format!("Failed to mark message as read: {}", e))
+            }
+        }
+    } else {
+        match mark_unread(state_guard, &msg_id).await {
+            Ok(()) => state_guard.record_label_change(&msg_id, &unread, &[]).await,
+            Err(e) => {
+                state_guard.set_error_message(format!("Failed to mark message as unread: {}", e))
+            }
+        }
+    }
+    Ok(false)
+}
+
+// Toggle the star of the selected message with '*'
+async fn handle_toggle_star(
+    state_guard: &mut AppState,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    // Marked messages are all starred if any is not, else all unstarred
+    if state_guard.has_marks() {
+        let any_unstarred = state_guard.marked_messages_any(|m| !m.is_starred());
+        let starred = vec!["STARRED".to_string()];
+        let (add, remove, failure) = if any_unstarred {
+            (starred, vec![], "star messages")
+        } else {
+            (vec![], starred, "unstar messages")
+        };
+        change_marked_labels(state_guard, add, remove, failure).await;
+        return Ok(false);
+    }
+    if reject_foreign_message(state_guard) {
+        return Ok(false);
+    }
+    let Some(msg) = state_guard.messages.get(state_guard.selected_message) else {
+        return Ok(false);
+    };
+    let Some(msg_id) = msg.id.clone() else {
+        return Ok(false);
+    };
+    let starred = vec!["STARRED".to_string()];
+
+    if msg.is_starred() {
+        match unstar(state_guard, &msg_id).await {
+            Ok(()) => {
+                state_guard
+                    .record_label_change(&msg_id, &[], &starred)
+                    .await
+            }
+            Err(e) => state_guard.set_error_message(format!("Failed to unstar message: {}", e)),
+        }
+    } else {
+        match star(state_guard, &msg_id).await {
+            Ok(()) => {
+                state_guard
+                    .record_label_change(&msg_id, &starred, &[])
+                    .await
+            }
+            Err(e) => state_guard.set_error_message(format!("Failed to star message: {}", e)),
+        }
+    }
+    Ok(false)
+}
+
+async fn handle_create_label(state_guard: &mut AppState, name: &str) {
+    if name.is_empty() {
+        return;
+    }
+    match create_label(state_guard, name).await {
+        Ok(label) => state_guard.record_label_created(label).await,
+        Err(e) => state_guard.set_error_message(format!("Failed to create label: {}", e)),
+    }
+}
+
+async fn handle_rename_label(state_guard: &mut AppState, label_id: &str, name: &str) {
+    if name.is_empty() {
+        return;
+    }
+    match rename_label(state_guard, label_id, name).await {
+        Ok(label) => {
+            let name = label.name.as_deref().unwrap_or(name);
+            state_guard.record_label_renamed(label_id, name).await
+        }
+        Err(e) => state_guard.set_error_message(format!("Failed to rename label: {}", e)),
+    }
+}
 
-                if let Err(e) = fetch_result {
-                    state_guard_reacquired
-                        .set_error_message(format!("Error fetching full message for reply: {}", e));
+// Returns true if the deleted label was the selected folder
+async fn handle_delete_label(state_guard: &mut AppState, label_id: &str) -> bool {
+    match delete_label(state_guard, label_id).await {
+        Ok(()) => state_guard.record_label_deleted(label_id).await,
+        Err(e) => {
+            state_guard.set_error_message(format!("Failed to delete label: {}", e));
+            false
+        }
+    }
+}
+
+// Apply the label under the picker cursor to the selected message, or remove
+// it if the message already has it
+async fn handle_toggle_label(state_guard: &mut AppState) {
+    let Some(label_id) = state_guard
+        .label_picker_selection()
+        .and_then(|label| label.id)
+    else {
+        return;
+    };
+    // Marked messages all get the label, or all lose it if they all have it
+    if state_guard.has_marks() {
+        let label = vec![label_id.clone()];
+        if state_guard.marked_messages_any(|m| !m.has_label(&label_id)) {
+            change_marked_labels(state_guard, label, vec![], "apply label").await;
+        } else if change_marked_labels(state_guard, vec![], label, "remove label").await {
+            // Without the label the messages no longer belong in this folder
+            let current = state_guard.get_current_label().and_then(|l| l.id.clone());
+            if current.as_deref() == Some(label_id.as_str()) {
+                for message_id in state_guard.marked_message_ids() {
+                    state_guard.remove_message(&message_id);
                 }
-            } else {
-                state_guard_reacquired
-                    .set_error_message("Cannot reply: No message ID found.".to_string());
+                state_guard.clear_marks();
             }
         }
+        return;
+    }
+    let Some(msg_id) = state_guard
+        .messages
+        .get(state_guard.selected_message)
+        .and_then(|msg| msg.id.clone())
+    else {
+        return;
+    };
+    let label = vec![label_id.clone()];
 
-        let mut reply_body = String::new();
-        if let Some(original_body) = original_body_full {
-            reply_body.push_str("\n\n"); // Add two blank lines
-            for line in original_body.lines() {
-                reply_body.push_str(&format!("> {}\n", line));
+    if state_guard.selected_message_has_label(&label_id) {
+        match remove_label(state_guard, &msg_id, &label_id).await {
+            Ok(()) => {
+                state_guard.record_label_change(&msg_id, &[], &label).await;
+                // Without the label the message no longer belongs in this folder
+                let current = state_guard.get_current_label().and_then(|l| l.id.clone());
+                if current.as_deref() == Some(label_id.as_str()) {
+                    state_guard.remove_message(&msg_id);
+                }
             }
+            Err(e) => state_guard.set_error_message(format!("Failed to remove label: {}", e)),
         }
+    } else {
+        match add_label(state_guard, &msg_id, &label_id).await {
+            Ok(()) => state_guard.record_label_change(&msg_id, &label, &[]).await,
+            Err(e) => state_guard.set_error_message(format!("Failed to apply label: {}", e)),
+        }
+    }
+}
 
-        let mut to_addr = None;
-        let mut subject_text = None;
-        let mut cc_addr = None;
+// Take the selected or marked messages out of Trash with messages.untrash, or
+// out of Spam back to the inbox
+async fn handle_restore(state_guard: &mut AppState) {
+    let Some(folder) = state_guard.trash_or_spam_folder() else {
+        return;
+    };
+    let message_ids = state_guard.action_message_ids();
+    let result = if folder == "SPAM" {
+        not_spam(state_guard, &message_ids).await
+    } else {
+        untrash(state_guard, &message_ids).await
+    };
+    match result {
+        Ok(()) => {
+            for message_id in &message_ids {
+                state_guard.remove_message(message_id);
+            }
+            state_guard.clear_marks();
+        }
+        Err(e) => state_guard.set_error_message(format!("Failed to restore: {}", e)),
+    }
+}
 
-        if let Some(headers) = state_guard_reacquired
-            .current_message_display_headers
-            .take()
-        {
-            to_addr = Some(headers.from);
-            subject_text = Some(format!("{}", headers.subject));
-            // Keep original CC if it exists, otherwise None
-            cc_addr = if !headers.to.is_empty() {
-                Some(headers.to)
-            } else {
-                None
-            };
+async fn untrash(
+    state_guard: &mut AppState,
+    message_ids: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    for message_id in message_ids {
+        let message = untrash_message(state_guard, message_id).await?;
+        let label_ids = message.label_ids.unwrap_or_default();
+        state_guard.record_labels(message_id, &label_ids).await;
+    }
+    Ok(())
+}
+
+async fn not_spam(
+    state_guard: &mut AppState,
+    message_ids: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let add = vec!["INBOX".to_string()];
+    let remove = vec!["SPAM".to_string()];
+    match message_ids {
+        [message_id] => change_labels(state_guard, message_id, &["INBOX"], &["SPAM"]).await?,
+        _ => batch_modify(state_guard, message_ids, &add, &remove).await?,
+    }
+    for message_id in message_ids {
+        state_guard
+            .record_label_change(message_id, &add, &remove)
+            .await;
+    }
+    Ok(())
+}
+
+// Delete messages for good once the user confirmed it
+async fn handle_delete_forever(state_guard: &mut AppState, message_ids: Vec<String>) {
+    let result = match message_ids.as_slice() {
+        [message_id] => delete_message_forever(state_guard, message_id).await,
+        _ => batch_delete(state_guard, &message_ids).await,
+    };
+    match result {
+        Ok(()) => {
+            for message_id in &message_ids {
+                state_guard.record_message_deleted(message_id).await;
+            }
+            state_guard.clear_marks();
         }
+        Err(e) => state_guard.set_error_message(format!("Failed to delete forever: {}", e)),
+    }
+}
 
-        state_guard_reacquired.start_composing(
-            to_addr,
-            cc_addr,
-            subject_text,
-            Some(reply_body),
-            Some(ComposeField::Body),
-        );
+// Delete everything in Trash or Spam for good once the user confirmed it,
+// including messages that were never loaded into the list
+async fn handle_empty_folder(state_guard: &mut AppState, label_id: &str) {
+    let folder = if label_id == "SPAM" { "Spam" } else { "Trash" };
+    let message_ids = match list_message_ids(state_guard, label_id).await {
+        Ok(message_ids) => message_ids,
+        Err(e) => {
+            state_guard.set_error_message(format!("Failed to empty {}: {}", folder, e));
+            return;
+        }
+    };
+    if let Err(e) = batch_delete(state_guard, &message_ids).await {
+        state_guard.set_error_message(format!("Failed to empty {}: {}", folder, e));
+        return;
+    }
+    for message_id in &message_ids {
+        state_guard.record_message_deleted(message_id).await;
+    }
+    state_guard.clear_marks();
+    state_guard.set_error_message(format!(
+        "{} emptied, {} messages deleted forever.",
+        folder,
+        message_ids.len()
+    ));
+}
+
+// Move the selected or marked messages to the label under the picker cursor:
+// the label is added and the current folder removed in one call
+async fn handle_move_to_label(state_guard: &mut AppState) {
+    let Some(target) = state_guard
+        .label_picker_selection()
+        .and_then(|label| label.id)
+    else {
+        return;
+    };
+    let Some(current) = state_guard.get_current_label().and_then(|l| l.id.clone()) else {
+        return;
+    };
+    let message_ids = state_guard.action_message_ids();
+    let add = vec![target.clone()];
+    let remove = vec![current.clone()];
+
+    let result = match message_ids.as_slice() {
+        [] => return,
+        [message_id] => change_labels(state_guard, message_id, &[&target], &[&current]).await,
+        _ => batch_modify(state_guard, &message_ids, &add, &remove).await,
+    };
+    match result {
+        Ok(()) => {
+            for message_id in &message_ids {
+                state_guard
+                    .record_label_change(message_id, &add, &remove)
+                    .await;
+                state_guard.remove_message(message_id);
+            }
+            state_guard.clear_marks();
+        }
+        Err(e) => state_guard.set_error_message(format!("Failed to move: {}", e)),
+    }
+}
+
+// Fetch the account's filters and show them, with `form` open when a filter
+// is to be written right away
+async fn handle_open_filters(state_guard: &mut AppState, form: Option<FilterForm>) {
+    match fetch_filters(state_guard).await {
+        Ok(filters) => state_guard.open_filters_screen(filters, form),
+        Err(e) => state_guard.set_error_message(format!("Error loading filters: {}", e)),
+    }
+}
+
+async fn handle_filter_from_message(state_guard: &mut AppState) {
+    if reject_foreign_message(state_guard) {
+        return;
+    }
+    let Some(message_id) = state_guard.displayed_message_id() else {
+        return;
+    };
+
+    // The full message has the List-Id header
+    match fetch_message(state_guard, &message_id).await {
+        Ok(message) => {
+            let form = FilterForm::from_message(&message);
+            handle_open_filters(state_guard, Some(form)).await;
+        }
+        Err(e) => {
+            state_guard.set_error_message(format!("Error fetching message for the filter: {}", e))
+        }
+    }
+}
+
+// Keys of the filters screen: the form while a filter is being written,
+// else the list of filters
+async fn handle_filters_screen_key(state_guard: &mut AppState, code: KeyCode) {
+    let Some(screen) = state_guard.filters_screen.as_mut() else {
+        return;
+    };
+
+    if let Some(form) = screen.form.as_mut() {
+        match code {
+            KeyCode::Enter => handle_create_filter(state_guard).await,
+            KeyCode::Esc => {
+                screen.form = None;
+                screen.error = None;
+            }
+            KeyCode::Tab | KeyCode::Down => form.next_field(),
+            KeyCode::BackTab | KeyCode::Up => form.previous_field(),
+            KeyCode::Backspace => form.pop(),
+            KeyCode::Char(c) => form.push(c),
+            _ => {} // Ignore other keys while the form is showing
+        }
+        return;
+    }
+
+    if screen.confirm_delete {
+        match code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => {
+                screen.confirm_delete = false;
+                handle_delete_filter(state_guard).await;
+            }
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => screen.confirm_delete = false,
+            _ => {} // Ignore other keys while the confirmation is showing
+        }
+        return;
+    }
+
+    match code {
+        KeyCode::Char('j') | KeyCode::Down => screen.down(),
+        KeyCode::Char('k') | KeyCode::Up => screen.up(),
+        KeyCode::Char('n') => {
+            screen.form = Some(FilterForm::default());
+            screen.error = None;
+        }
+        KeyCode::Char('d') if screen.selected_filter().is_some() => {
+            screen.confirm_delete = true;
+            screen.error = None;
+        }
+        KeyCode::Char('z') | KeyCode::Esc => state_guard.close_filters_screen(),
+        _ => {} // Ignore other keys while the screen is showing
+    }
+}
+
+async fn handle_create_filter(state_guard: &mut AppState) {
+    let Some(form) = state_guard
+        .filters_screen
+        .as_ref()
+        .and_then(|screen| screen.form.clone())
+    else {
+        return;
+    };
+
+    let result = match form.to_filter(&state_guard.labels) {
+        Ok(filter) => create_filter(state_guard, &filter)
+            .await
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e),
+    };
+    if let Some(screen) = state_guard.filters_screen.as_mut() {
+        match result {
+            Ok(filter) => {
+                screen.add(filter);
+                screen.form = None;
+                screen.error = None;
+            }
+            Err(e) => screen.error = Some(e),
+        }
+    }
+}
+
+async fn handle_delete_filter(state_guard: &mut AppState) {
+    let Some(filter_id) = state_guard
+        .filters_screen
+        .as_ref()
+        .and_then(|screen| screen.selected_filter())
+        .and_then(|filter| filter.id.clone())
+    else {
+        return;
+    };
+
+    let result = delete_filter(state_guard, &filter_id).await;
+    if let Some(screen) = state_guard.filters_screen.as_mut() {
+        match result {
+            Ok(()) => screen.remove(&filter_id),
+            Err(e) => screen.error = Some(e.to_string()),
+        }
+    }
+}
+
+// Add and remove labels of all marked messages with one batchModify call,
+// then update the cache and the list. Returns true if Gmail made the change.
+async fn change_marked_labels(
+    state_guard: &mut AppState,
+    add: Vec<String>,
+    remove: Vec<String>,
+    failure: &str,
+) -> bool {
+    if reject_foreign_marks(state_guard) {
+        return false;
+    }
+    let message_ids = state_guard.marked_message_ids();
+    match batch_modify(state_guard, &message_ids, &add, &remove).await {
+        Ok(()) => {
+            for message_id in &message_ids {
+                state_guard
+                    .record_label_change(message_id, &add, &remove)
+                    .await;
+            }
+            true
+        }
+        Err(e) => {
+            state_guard.set_error_message(format!("Failed to {}: {}", failure, e));
+            false
+        }
+    }
+}
+
+async fn handle_reply(
+    state_guard: &mut AppState,
+    reply_all: bool,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    if reject_foreign_message(state_guard) {
+        return Ok(false);
+    }
+    let Some(message_id) = state_guard.displayed_message_id() else {
+        return Ok(false);
+    };
+
+    // Our own address is left out of the recipients
+    if state_guard.own_email.is_none() {
+        if let Ok(profile) = fetch_profile(state_guard).await {
+            state_guard.own_email = profile.email_address;
+        }
+    }
+
+    // The full message has the Message-ID and References to thread the reply
+    match fetch_message(state_guard, &message_id).await {
+        Ok(original) => state_guard.start_reply(&original, reply_all),
+        Err(e) => {
+            state_guard.set_error_message(format!("Error fetching full message for reply: {}", e))
+        }
     }
     Ok(false)
 }
 
-async fn handle_archive_message(
+async fn handle_forward(
     state_guard: &mut AppState,
+    as_attachment: bool,
 ) -> Result<bool, Box<dyn std::error::Error>> {
-    if matches!(
-        state_guard.focused_pane,
-        FocusedPane::Messages | FocusedPane::Content
-    ) {
-        let selected_message = state_guard.selected_message;
-        if let Some(msg) = state_guard.messages.get(selected_message) {
-            if let Some(msg_id) = &msg.id {
-                // Actually call the Gmail API to archive the message
-                match crate::gmail_api::archive_message(state_guard, msg_id).await {
-                    Ok(()) => {
-                        // Success - remove from UI
-                        state_guard.messages.remove(selected_message);
-                        if state_guard.selected_message >= state_guard.messages.len()
-                            && state_guard.selected_message > 0
-                        {
-                            state_guard.selected_message = state_guard.messages.len() - 1;
-                        }
-                        state_guard.update_message_state();
-                    }
-                    Err(e) => {
-                        let error_msg = e.to_string();
-                        if error_msg.contains("401") || error_msg.contains("invalid authentication")
-                        {
-                            state_guard.set_error_message(
-                                "Authentication expired. Press Ctrl+R to re-authenticate."
-                                    .to_string(),
-                            );
-                        } else {
-                            state_guard
-                                .set_error_message(format!("Failed to archive message: {}", e));
-                        }
-                    }
-                }
+    if reject_foreign_message(state_guard) {
+        return Ok(false);
+    }
+    let Some(message_id) = state_guard.displayed_message_id() else {
+        return Ok(false);
+    };
+
+    let original = match fetch_message(state_guard, &message_id).await {
+        Ok(original) => original,
+        Err(e) => {
+            state_guard.set_error_message(format!("Error fetching message to forward: {}", e));
+            return Ok(false);
+        }
+    };
+
+    if as_attachment {
+        match fetch_raw_message(state_guard, &message_id).await {
+            Ok(raw) => state_guard.start_forward_as_attachment(&original, raw),
+            Err(e) => {
+                state_guard.set_error_message(format!("Error fetching message to forward: {}", e))
             }
         }
+        return Ok(false);
     }
+
+    // An inline forward carries the original attachments along
+    let attachments = match fetch_outgoing_attachments(state_guard, &message_id, &original).await {
+        Ok(attachments) => attachments,
+        Err(e) => {
+            state_guard.set_error_message(format!("Error fetching attachment to forward: {}", e));
+            return Ok(false);
+        }
+    };
+    state_guard.start_forward(&original, attachments);
     Ok(false)
 }
 
-async fn handle_delete_message(
+fn compose_thread_id(state_guard: &AppState) -> Option<String> {
+    state_guard
+        .compose_state
+        .threading
+        .as_ref()
+        .and_then(|threading| threading.thread_id.clone())
+}
+
+// Current message of the draft the compose window is saved to
+fn compose_draft_message_id(state_guard: &AppState) -> Option<String> {
+    state_guard
+        .compose_state
+        .draft
+        .as_ref()
+        .and_then(|draft| draft.message.as_ref())
+        .and_then(|message| message.id.clone())
+}
+
+// Close the compose window, saving what was written as a draft first
+async fn close_compose(state_guard: &mut AppState) {
+    if !state_guard.compose_state.has_content() {
+        state_guard.stop_composing();
+        return;
+    }
+    match save_compose_draft(state_guard).await {
+        Ok(()) => state_guard.stop_composing(),
+        // Keep the window open rather than lose the message
+        Err(e) => state_guard.set_error_message(format!(
+            "Failed to save draft: {}. Press Ctrl+X to close without saving.",
+            e
+        )),
+    }
+}
+
+// Save the compose window as a Gmail draft; later saves update the same draft.
+// Every update gives the draft a new message, so the old one leaves the list
+// until the next sync brings in the new one.
+async fn save_compose_draft(state_guard: &mut AppState) -> Result<(), Box<dyn std::error::Error>> {
+    let message = state_guard.compose_state.to_message();
+    let thread_id = compose_thread_id(state_guard);
+    let old_message_id = compose_draft_message_id(state_guard);
+
+    let draft = save_draft(
+        state_guard,
+        state_guard.compose_state.draft_id(),
+        &message,
+        thread_id.as_deref(),
+    )
+    .await?;
+
+    if let Some(message_id) = old_message_id {
+        state_guard.record_message_deleted(&message_id).await;
+    }
+    state_guard.compose_state.draft = Some(draft);
+    Ok(())
+}
+
+// Open the draft whose message is `message_id` in the compose window
+async fn handle_open_draft(state_guard: &mut AppState, message_id: &str) {
+    let draft = match find_draft(state_guard, message_id).await {
+        Ok(Some(draft)) => draft,
+        Ok(None) => {
+            state_guard
+                .set_error_message("This draft no longer exists. Press f to refresh.".to_string());
+            return;
+        }
+        Err(e) => {
+            state_guard.set_error_message(format!("Error opening draft: {}", e));
+            return;
+        }
+    };
+    let message = match fetch_message(state_guard, message_id).await {
+        Ok(message) => message,
+        Err(e) => {
+            state_guard.set_error_message(format!("Error opening draft: {}", e));
+            return;
+        }
+    };
+    match fetch_outgoing_attachments(state_guard, message_id, &message).await {
+        Ok(attachments) => state_guard.start_draft(draft, &message, attachments),
+        Err(e) => state_guard.set_error_message(format!("Error opening draft: {}", e)),
+    }
+}
+
+// The attachments of a fetched message, downloaded to be sent again
+async fn fetch_outgoing_attachments(
+    state_guard: &AppState,
+    message_id: &str,
+    message: &Message,
+) -> Result<Vec<OutgoingAttachment>, String> {
+    let mut attachments = Vec::new();
+    let Some(payload) = &message.payload else {
+        return Ok(attachments);
+    };
+    for attachment in extract_attachments(payload) {
+        let content = fetch_attachment(state_guard, message_id, &attachment)
+            .await
+            .map_err(|e| format!("{}: {}", attachment.filename, e))?;
+        attachments.push(OutgoingAttachment {
+            filename: attachment.filename,
+            content_type: attachment.mime_type,
+            content,
+        });
+    }
+    Ok(attachments)
+}
+
+// Archive, delete or report the selected message, or its whole conversation in thread view
+async fn handle_message_action(
     state_guard: &mut AppState,
+    action: MessageAction,
 ) -> Result<bool, Box<dyn std::error::Error>> {
-    if matches!(
+    if !matches!(
         state_guard.focused_pane,
         FocusedPane::Messages | FocusedPane::Content
-    ) {
-        let selected_message = state_guard.selected_message;
-        if let Some(msg) = state_guard.messages.get(selected_message) {
-            if let Some(msg_id) = &msg.id {
-                // Actually call the Gmail API to delete the message
-                match crate::gmail_api::delete_message(state_guard, msg_id).await {
-                    Ok(()) => {
-                        // Success - remove from UI
-                        state_guard.messages.remove(selected_message);
-                        if state_guard.selected_message >= state_guard.messages.len()
-                            && state_guard.selected_message > 0
-                        {
-                            state_guard.selected_message = state_guard.messages.len() - 1;
-                        }
-                        state_guard.update_message_state();
-                    }
-                    Err(e) => {
-                        let error_msg = e.to_string();
-                        if error_msg.contains("401") || error_msg.contains("invalid authentication")
-                        {
-                            state_guard.set_error_message(
-                                "Authentication expired. Press Ctrl+R to re-authenticate."
-                                    .to_string(),
-                            );
-                        } else {
-                            state_guard
-                                .set_error_message(format!("Failed to delete message: {}", e));
-                        }
-                    }
-                }
+    ) || reject_foreign_message(state_guard)
+    {
+        return Ok(false);
+    }
+    // Marked messages are changed together
+    if state_guard.has_marks() {
+        if reject_foreign_marks(state_guard) {
+            return Ok(false);
+        }
+        let message_ids = state_guard.marked_message_ids();
+        state_guard.clear_marks();
+        let result = apply_message_action(state_guard, action, message_ids, None).await;
+        report_message_action(state_guard, action, result);
+        return Ok(false);
+    }
+
+    let Some(msg_id) = state_guard
+        .messages
+        .get(state_guard.selected_message)
+        .and_then(|msg| msg.id.clone())
+    else {
+        return Ok(false);
+    };
+
+    // In thread view the action applies to the whole conversation
+    let thread_id = state_guard.selected_thread_id();
+    let message_ids = match &thread_id {
+        Some(thread_id) => state_guard.thread_message_ids(thread_id),
+        None => vec![msg_id],
+    };
+
+    let result = apply_message_action(state_guard, action, message_ids, thread_id).await;
+    report_message_action(state_guard, action, result);
+    Ok(false)
+}
+
+fn report_message_action(
+    state_guard: &mut AppState,
+    action: MessageAction,
+    result: Result<Delivery, Box<dyn std::error::Error>>,
+) {
+    match result {
+        Ok(Delivery::Done) => {}
+        Ok(Delivery::Queued) => state_guard.set_error_message(
+            "Gmail cannot be reached. The change is kept in the Outbox and made once the connection is back."
+                .to_string(),
+        ),
+        Err(e) => {
+            let error_msg = e.to_string();
+            if error_msg.contains("401") || error_msg.contains("invalid authentication") {
+                state_guard.set_error_message(
+                    "Authentication expired. Press Ctrl+R to re-authenticate.".to_string(),
+                );
+            } else {
+                state_guard.set_error_message(format!("Failed to {}: {}", action.failure(), e));
+            }
+        }
+    }
+}
+
+// Undo the last archive, delete or spam action with 'u'
+async fn handle_undo(state_guard: &mut AppState) -> Result<bool, Box<dyn std::error::Error>> {
+    match undo_message_action(state_guard).await {
+        Ok(Some(_)) => {}
+        Ok(None) => state_guard.set_error_message("Nothing to undo.".to_string()),
+        Err(e) => {
+            let error_msg = e.to_string();
+            if error_msg.contains("401") || error_msg.contains("invalid authentication") {
+                state_guard.set_error_message(
+                    "Authentication expired. Press Ctrl+R to re-authenticate.".to_string(),
+                );
+            } else {
+                state_guard.set_error_message(format!("Failed to undo: {}", e));
             }
         }
     }
     Ok(false)
 }
 
-async fn handle_spam_message(
+// Retry the selected entry of the Outbox folder with 'r'
+async fn handle_retry_outbox(
     state_guard: &mut AppState,
 ) -> Result<bool, Box<dyn std::error::Error>> {
-    if matches!(
-        state_guard.focused_pane,
-        FocusedPane::Messages | FocusedPane::Content
-    ) {
-        let selected_message = state_guard.selected_message;
-        if let Some(msg) = state_guard.messages.get(selected_message) {
-            if let Some(msg_id) = &msg.id {
-                // Actually call the Gmail API to mark the message as spam
-                match crate::gmail_api::spam_message(state_guard, msg_id).await {
-                    Ok(()) => {
-                        // Success - remove from UI
-                        state_guard.messages.remove(selected_message);
-                        if state_guard.selected_message >= state_guard.messages.len()
-                            && state_guard.selected_message > 0
-                        {
-                            state_guard.selected_message = state_guard.messages.len() - 1;
-                        }
-                        state_guard.update_message_state();
-                    }
-                    Err(e) => {
-                        let error_msg = e.to_string();
-                        if error_msg.contains("401") || error_msg.contains("invalid authentication")
-                        {
-                            state_guard.set_error_message(
-                                "Authentication expired. Press Ctrl+R to re-authenticate."
-                                    .to_string(),
-                            );
-                        } else {
-                            state_guard.set_error_message(format!(
-                                "Failed to mark message as spam: {}",
-                                e
-                            ));
-                        }
-                    }
-                }
-            }
+    let Some(id) = state_guard.selected_outbox_entry().map(|entry| entry.id) else {
+        return Ok(false);
+    };
+    retry_outbox_entry(state_guard, id).await;
+    match state_guard.outbox.iter().find(|entry| entry.id == id) {
+        None => state_guard.set_error_message("Done, the outbox entry reached Gmail.".to_string()),
+        Some(entry) => {
+            let message = match &entry.error {
+                Some(error) => format!("Gmail refused it again: {}", error),
+                None => "Gmail still cannot be reached, the entry stays in the Outbox.".to_string(),
+            };
+            state_guard.set_error_message(message);
         }
     }
     Ok(false)
 }
+
+// Cancel the selected entry of the Outbox folder with 'd'
+async fn handle_cancel_outbox(
+    state_guard: &mut AppState,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    if let Some(id) = state_guard.selected_outbox_entry().map(|entry| entry.id) {
+        cancel_outbox_entry(state_guard, id).await;
+    }
+    Ok(false)
+}
+
+// Messages of other accounts in the unified inbox are read-only here,
+// and outbox entries are not messages Gmail knows about yet
+fn reject_foreign_message(state_guard: &mut AppState) -> bool {
+    if state_guard.showing_outbox() {
+        state_guard.set_error_message(
+            "Outbox entries can only be retried with r or cancelled with d.".to_string(),
+        );
+        return true;
+    }
+    match state_guard.selected_message_foreign_account() {
+        Some(account) => {
+            state_guard.set_error_message(format!(
+                "This message belongs to the '{}' account. Press A to switch to it first.",
+                account
+            ));
+            true
+        }
+        None => false,
+    }
+}
+
+// Like reject_foreign_message, for the marked messages
+fn reject_foreign_marks(state_guard: &mut AppState) -> bool {
+    match state_guard.marked_foreign_account() {
+        Some(account) => {
+            state_guard.set_error_message(format!(
+                "Some marked messages belong to the '{}' account. Press A to switch to it first.",
+                account
+            ));
+            true
+        }
+        None => false,
+    }
+}
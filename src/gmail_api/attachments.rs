@@ -0,0 +1,115 @@
+use super::client::{api_url, send_authorized};
+use crate::state::AppState;
+use crate::types::{Attachment, MessagePartBody};
+use base64::alphabet::URL_SAFE;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::{DecodePaddingMode, Engine};
+use reqwest::Response;
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+// Gmail sends base64url attachment data, sometimes without padding
+const ATTACHMENT_DATA: GeneralPurpose = GeneralPurpose::new(
+    &URL_SAFE,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+// Start an attachments.get request; read the body with `read_attachment`
+pub async fn request_attachment(
+    state: &AppState,
+    message_id: &str,
+    attachment_id: &str,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    let attachment_url = api_url(
+        state,
+        &format!("messages/{}/attachments/{}", message_id, attachment_id),
+    );
+
+    let response = send_authorized(state, |client| client.get(&attachment_url)).await?;
+
+    if response.status().is_success() {
+        Ok(response)
+    } else {
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        Err(format!("Failed to download attachment: {}", error_text).into())
+    }
+}
+
+// Stream an attachments.get response, calling `progress` with the number of
+// bytes received so far, and decode the attachment content
+pub async fn read_attachment(
+    mut response: Response,
+    mut progress: impl FnMut(u64),
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut raw = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        raw.extend_from_slice(&chunk);
+        progress(raw.len() as u64);
+    }
+
+    let body: MessagePartBody = serde_json::from_slice(&raw)?;
+    decode_attachment_data(body.data.as_deref().unwrap_or(""))
+}
+
+// Content of an attachment, whether Gmail sent it inline or not
+pub async fn fetch_attachment(
+    state: &AppState,
+    message_id: &str,
+    attachment: &Attachment,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    match (&attachment.data, &attachment.attachment_id) {
+        (Some(data), _) => decode_attachment_data(data),
+        (None, Some(attachment_id)) => {
+            let response = request_attachment(state, message_id, attachment_id).await?;
+            read_attachment(response, |_| {}).await
+        }
+        (None, None) => Err(format!("{} has no content", attachment.filename).into()),
+    }
+}
+
+pub fn decode_attachment_data(data: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    Ok(ATTACHMENT_DATA.decode(data)?)
+}
+
+// Number of bytes an attachments.get response for `attachment` is expected to
+// have: the content is base64 encoded inside a small JSON object
+pub fn expected_response_size(attachment: &Attachment) -> u64 {
+    attachment.size.div_ceil(3) * 4 + 64
+}
+
+// Write an attachment into `dir` without overwriting existing files: a taken
+// name gets a counter, like "report (1).pdf". Returns the path written.
+pub fn save_attachment(dir: &Path, filename: &str, content: &[u8]) -> std::io::Result<PathBuf> {
+    // Never let the sender pick a path outside of `dir`
+    let filename = Path::new(filename)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .filter(|name| !name.starts_with('.'))
+        .unwrap_or("attachment");
+    let (stem, extension) = match filename.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (filename, String::new()),
+    };
+
+    std::fs::create_dir_all(dir)?;
+    let mut path = dir.join(filename);
+    let mut counter = 1;
+    // create_new fails instead of truncating a file that appeared meanwhile
+    let mut file = loop {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => break file,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                path = dir.join(format!("{} ({}){}", stem, counter, extension));
+                counter += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    };
+
+    file.write_all(content)?;
+    Ok(path)
+}
@@ -1,15 +1,39 @@
+use crate::accounts::{keyring_username, DEFAULT_ACCOUNT};
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use keyring::Entry;
 use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use yup_oauth2::storage::{TokenInfo, TokenStorage};
 use yup_oauth2::{ApplicationSecret, InstalledFlowAuthenticator, InstalledFlowReturnMethod};
 
 pub const KEYRING_SERVICE_NAME: &str = "rmail-gmail-credentials";
 pub const KEYRING_USERNAME: &str = "default_user"; // Could be user's email if available
 
+// Refresh access tokens this long before Google considers them expired
+const TOKEN_EXPIRY_MARGIN_SECS: i64 = 60;
+
+// Full mail access; filters are settings and need their own scope
+const SCOPES: [&str; 2] = [
+    "https://mail.google.com/",
+    "https://www.googleapis.com/auth/gmail.settings.basic",
+];
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct SecureCredentials {
     pub client_secret: Option<ApplicationSecret>,
     pub token: Option<String>,
+    // Credentials saved by older versions only contain the access token
+    pub refresh_token: Option<String>,
+    pub token_expiry: Option<DateTime<Utc>>,
+    // Scopes Google granted; unknown for credentials saved by older versions
+    pub scopes: Option<Vec<String>>,
+}
+
+impl Default for SecureCredentials {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SecureCredentials {
@@ -17,6 +41,9 @@ impl SecureCredentials {
         Self {
             client_secret: None,
             token: None,
+            refresh_token: None,
+            token_expiry: None,
+            scopes: None,
         }
     }
 
@@ -25,10 +52,50 @@ impl SecureCredentials {
         self
     }
 
-    pub fn with_token(mut self, token: String) -> Self {
-        self.token = Some(token);
+    // Store a token set; an existing refresh token is kept if Google didn't issue a new one
+    pub fn with_tokens(mut self, tokens: OAuthTokens) -> Self {
+        self.token = Some(tokens.access_token);
+        if tokens.refresh_token.is_some() {
+            self.refresh_token = tokens.refresh_token;
+        }
+        self.token_expiry = tokens.expires_at;
+        if tokens.scopes.is_some() {
+            self.scopes = tokens.scopes;
+        }
         self
     }
+
+    fn is_token_expired(&self) -> bool {
+        is_expired(self.token_expiry)
+    }
+
+    // Whether the stored token covers everything the app asks for. Tokens
+    // granted before a scope was added have to go through consent again.
+    fn has_required_scopes(&self) -> bool {
+        self.scopes.as_ref().is_some_and(|granted| {
+            SCOPES
+                .iter()
+                .all(|scope| granted.iter().any(|g| g == scope))
+        })
+    }
+}
+
+// Tokens issued by the OAuth flow or a refresh
+#[derive(Debug, Clone, PartialEq)]
+pub struct OAuthTokens {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    // Granted scopes, when the response says
+    pub scopes: Option<Vec<String>>,
+}
+
+fn is_expired(expires_at: Option<DateTime<Utc>>) -> bool {
+    match expires_at {
+        Some(expiry) => expiry - chrono::Duration::seconds(TOKEN_EXPIRY_MARGIN_SECS) <= Utc::now(),
+        // Without a known expiry we rely on the 401 retry in the request layer
+        None => false,
+    }
 }
 
 // Define a trait for Keyring operations to allow mocking
@@ -60,7 +127,26 @@ pub trait OAuthFlow: Send + Sync {
         &self,
         secret: ApplicationSecret,
         scopes: Vec<String>,
-    ) -> Result<String, Box<dyn std::error::Error>>;
+    ) -> Result<OAuthTokens, Box<dyn std::error::Error>>;
+}
+
+// Token storage that only remembers the last token, so the refresh token
+// issued by the installed flow can be handed to our own keyring storage
+#[derive(Clone, Default)]
+struct CapturedTokenStorage {
+    token: Arc<Mutex<Option<TokenInfo>>>,
+}
+
+#[async_trait]
+impl TokenStorage for CapturedTokenStorage {
+    async fn set(&self, _scopes: &[&str], token: TokenInfo) -> anyhow::Result<()> {
+        *self.token.lock().unwrap() = Some(token);
+        Ok(())
+    }
+
+    async fn get(&self, _scopes: &[&str]) -> Option<TokenInfo> {
+        self.token.lock().unwrap().clone()
+    }
 }
 
 // Implement the trait for the real InstalledFlowAuthenticator
@@ -72,24 +158,165 @@ impl OAuthFlow for RealOAuthFlow {
         &self,
         secret: ApplicationSecret,
         scopes: Vec<String>,
-    ) -> Result<String, Box<dyn std::error::Error>> {
+    ) -> Result<OAuthTokens, Box<dyn std::error::Error>> {
+        let storage = CapturedTokenStorage::default();
         let auth =
             InstalledFlowAuthenticator::builder(secret, InstalledFlowReturnMethod::HTTPRedirect)
+                .with_storage(Box::new(storage.clone()))
                 .build()
                 .await?;
         let scopes_refs: Vec<&str> = scopes.iter().map(|s| s.as_str()).collect();
-        let token = auth
-            .token(&scopes_refs)
-            .await?
-            .token()
-            .unwrap_or("")
-            .to_string();
-        Ok(token)
+        let access_token = auth.token(&scopes_refs).await?;
+
+        let refresh_token = storage
+            .token
+            .lock()
+            .unwrap()
+            .as_ref()
+            .and_then(|info| info.refresh_token.clone());
+        let expires_at = access_token
+            .expiration_time()
+            .and_then(|time| DateTime::from_timestamp(time.unix_timestamp(), 0));
+
+        Ok(OAuthTokens {
+            access_token: access_token.token().unwrap_or("").to_string(),
+            refresh_token,
+            expires_at,
+            scopes: Some(scopes),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct RefreshResponse {
+    access_token: String,
+    expires_in: Option<i64>,
+    refresh_token: Option<String>,
+    scope: Option<String>,
+}
+
+// Exchange a refresh token for a new access token at the secret's token endpoint
+pub async fn refresh_access_token(
+    client: &reqwest::Client,
+    secret: &ApplicationSecret,
+    refresh_token: &str,
+) -> Result<OAuthTokens, Box<dyn std::error::Error>> {
+    let response = client
+        .post(&secret.token_uri)
+        .form(&[
+            ("client_id", secret.client_id.as_str()),
+            ("client_secret", secret.client_secret.as_str()),
+            ("refresh_token", refresh_token),
+            ("grant_type", "refresh_token"),
+        ])
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        let refreshed: RefreshResponse = response.json().await?;
+        Ok(OAuthTokens {
+            access_token: refreshed.access_token,
+            refresh_token: refreshed.refresh_token,
+            expires_at: refreshed
+                .expires_in
+                .map(|secs| Utc::now() + chrono::Duration::seconds(secs)),
+            scopes: refreshed
+                .scope
+                .map(|scope| scope.split_whitespace().map(String::from).collect()),
+        })
+    } else {
+        Err(format!("Failed to refresh access token: {}", response.status()).into())
+    }
+}
+
+// Keeps the current access token and renews it with the refresh token when it
+// expires, persisting every renewed token to the keyring
+pub struct TokenRefresher {
+    client: reqwest::Client,
+    secret: ApplicationSecret,
+    credentials_keyring: Box<dyn KeyringEntry>,
+    current: tokio::sync::Mutex<OAuthTokens>,
+    // Why the last renewed token could not be saved, until the UI shows it
+    keyring_error: Mutex<Option<String>>,
+}
+
+impl TokenRefresher {
+    pub fn new(
+        client: reqwest::Client,
+        secret: ApplicationSecret,
+        credentials_keyring: Box<dyn KeyringEntry>,
+        tokens: OAuthTokens,
+    ) -> Self {
+        Self {
+            client,
+            secret,
+            credentials_keyring,
+            current: tokio::sync::Mutex::new(tokens),
+            keyring_error: Mutex::new(None),
+        }
+    }
+
+    // Why the last renewed token could not be saved to the keyring, once
+    pub fn take_keyring_error(&self) -> Option<String> {
+        self.keyring_error.lock().unwrap().take()
+    }
+
+    // The current access token, refreshed first if it is about to expire
+    pub async fn access_token(&self) -> String {
+        let stale_token = {
+            let current = self.current.lock().await;
+            if !is_expired(current.expires_at) {
+                return current.access_token.clone();
+            }
+            current.access_token.clone()
+        };
+
+        // Fall back to the old token; the request layer retries on 401 anyway
+        self.refresh(&stale_token).await.unwrap_or(stale_token)
+    }
+
+    // Replace stale_token with a fresh one. If another request already
+    // refreshed it in the meantime, that newer token is returned instead.
+    pub async fn refresh(&self, stale_token: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let mut current = self.current.lock().await;
+        if current.access_token != stale_token {
+            return Ok(current.access_token.clone());
+        }
+
+        let refresh_token = current
+            .refresh_token
+            .clone()
+            .ok_or("No refresh token available. Press Ctrl+R to re-authenticate.")?;
+        let refreshed = refresh_access_token(&self.client, &self.secret, &refresh_token).await?;
+
+        current.access_token = refreshed.access_token.clone();
+        current.expires_at = refreshed.expires_at;
+        if refreshed.refresh_token.is_some() {
+            current.refresh_token = refreshed.refresh_token;
+        }
+        if refreshed.scopes.is_some() {
+            current.scopes = refreshed.scopes;
+        }
+
+        let credentials = load_secure_credentials(self.credentials_keyring.as_ref())
+            .await
+            .unwrap_or_else(|_| SecureCredentials::new())
+            .with_client_secret(self.secret.clone())
+            .with_tokens(current.clone());
+        if let Err(e) =
+            save_secure_credentials(self.credentials_keyring.as_ref(), &credentials).await
+        {
+            // The new token still works for this session
+            *self.keyring_error.lock().unwrap() =
+                Some(format!("Failed to save refreshed token to keyring: {}", e));
+        }
+
+        Ok(current.access_token.clone())
     }
 }
 
 // Helper function to load secure credentials from keyring
-async fn load_secure_credentials<K: KeyringEntry>(
+async fn load_secure_credentials<K: KeyringEntry + ?Sized>(
     credentials_keyring: &K,
 ) -> Result<SecureCredentials, Box<dyn std::error::Error>> {
     let credentials_json = credentials_keyring.get_password()?;
@@ -98,7 +325,7 @@ async fn load_secure_credentials<K: KeyringEntry>(
 }
 
 // Helper function to save secure credentials to keyring
-async fn save_secure_credentials<K: KeyringEntry>(
+async fn save_secure_credentials<K: KeyringEntry + ?Sized>(
     credentials_keyring: &K,
     credentials: &SecureCredentials,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -112,26 +339,31 @@ async fn perform_oauth_flow<K: KeyringEntry, O: OAuthFlow>(
     oauth_flow_impl: &O,
     secret: ApplicationSecret,
     credentials_keyring: &K,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let scopes = vec!["https://mail.google.com/".to_string()];
-    let token_string = oauth_flow_impl.perform_flow(secret.clone(), scopes).await?;
+) -> Result<OAuthTokens, Box<dyn std::error::Error>> {
+    let scopes = SCOPES.iter().map(|scope| scope.to_string()).collect();
+    let tokens = oauth_flow_impl.perform_flow(secret.clone(), scopes).await?;
 
     // Load existing credentials or create new ones
     let mut credentials = load_secure_credentials(credentials_keyring)
         .await
         .unwrap_or_else(|_| SecureCredentials::new());
 
-    // Update with new token and client secret
+    // Update with new tokens and client secret
     credentials = credentials
         .with_client_secret(secret)
-        .with_token(token_string.clone());
+        .with_tokens(tokens.clone());
 
     // Save the updated credentials to keyring
     if let Err(e) = save_secure_credentials(credentials_keyring, &credentials).await {
         eprintln!("Failed to save credentials to keyring: {}", e);
     }
 
-    Ok(token_string)
+    // Hand back the refresh token we may have kept from an earlier grant
+    Ok(OAuthTokens {
+        refresh_token: credentials.refresh_token,
+        scopes: credentials.scopes,
+        ..tokens
+    })
 }
 
 // Helper function to load the client secret
@@ -171,20 +403,112 @@ async fn load_client_secret<K: KeyringEntry>(
 pub struct AuthResult {
     pub token: String,
     pub client_secret_loaded_from_file: bool,
+    // Present when a refresh token is available, so expired tokens can be renewed
+    pub token_refresher: Option<Arc<TokenRefresher>>,
 }
 
-// Main authentication function
-pub async fn try_authenticate() -> Result<AuthResult, Box<dyn std::error::Error>> {
-    let credentials_keyring = Entry::new(KEYRING_SERVICE_NAME, KEYRING_USERNAME)?;
+// Main authentication function for the named account. With force_consent the
+// browser flow runs even if a stored token is still usable.
+pub async fn try_authenticate(
+    account: &str,
+    force_consent: bool,
+) -> Result<AuthResult, Box<dyn std::error::Error>> {
+    let credentials_keyring = Entry::new(KEYRING_SERVICE_NAME, &keyring_username(account))?;
     let oauth_flow_impl = RealOAuthFlow;
 
-    try_authenticate_internal(&credentials_keyring, &oauth_flow_impl).await
+    // New accounts reuse the client secret already stored for the default account
+    if account != DEFAULT_ACCOUNT {
+        let default_keyring = Entry::new(KEYRING_SERVICE_NAME, KEYRING_USERNAME)?;
+        inherit_client_secret(&credentials_keyring, &default_keyring).await;
+    }
+
+    let (secret, tokens, client_secret_loaded_from_file) =
+        try_authenticate_internal(&credentials_keyring, &oauth_flow_impl, force_consent).await?;
+
+    let token_refresher = tokens.refresh_token.is_some().then(|| {
+        Arc::new(TokenRefresher::new(
+            reqwest::Client::new(),
+            secret,
+            Box::new(credentials_keyring),
+            tokens.clone(),
+        ))
+    });
+
+    Ok(AuthResult {
+        token: tokens.access_token,
+        client_secret_loaded_from_file,
+        token_refresher,
+    })
+}
+
+// Credentials of an account other than the active one, for the requests made
+// on its behalf by the unified inbox
+#[derive(Clone)]
+pub struct AccountSession {
+    pub token: String,
+    pub token_refresher: Option<Arc<TokenRefresher>>,
+}
+
+// The stored credentials of an account. Unlike try_authenticate this never
+// starts the browser flow; an expired token is renewed on first use.
+pub async fn stored_session(account: &str) -> Result<AccountSession, Box<dyn std::error::Error>> {
+    let credentials_keyring = Entry::new(KEYRING_SERVICE_NAME, &keyring_username(account))?;
+    let credentials = load_secure_credentials(&credentials_keyring)
+        .await
+        .map_err(|_| "not signed in")?;
+    session_from_credentials(credentials, Box::new(credentials_keyring))
+}
+
+fn session_from_credentials(
+    credentials: SecureCredentials,
+    credentials_keyring: Box<dyn KeyringEntry>,
+) -> Result<AccountSession, Box<dyn std::error::Error>> {
+    let token = credentials.token.clone().ok_or("not signed in")?;
+    let token_refresher = match (&credentials.client_secret, &credentials.refresh_token) {
+        (Some(secret), Some(refresh_token)) => Some(Arc::new(TokenRefresher::new(
+            reqwest::Client::new(),
+            secret.clone(),
+            credentials_keyring,
+            OAuthTokens {
+                access_token: token.clone(),
+                refresh_token: Some(refresh_token.clone()),
+                expires_at: credentials.token_expiry,
+                scopes: credentials.scopes.clone(),
+            },
+        ))),
+        _ if credentials.is_token_expired() => return Err("the sign-in has expired".into()),
+        _ => None,
+    };
+    Ok(AccountSession {
+        token,
+        token_refresher,
+    })
+}
+
+// Copy the client secret from another keyring entry if this one has none yet
+async fn inherit_client_secret<K: KeyringEntry>(credentials_keyring: &K, source_keyring: &K) {
+    let credentials = load_secure_credentials(credentials_keyring)
+        .await
+        .unwrap_or_else(|_| SecureCredentials::new());
+    if credentials.client_secret.is_some() {
+        return;
+    }
+
+    if let Ok(source) = load_secure_credentials(source_keyring).await {
+        if let Some(secret) = source.client_secret {
+            let credentials = credentials.with_client_secret(secret);
+            if let Err(e) = save_secure_credentials(credentials_keyring, &credentials).await {
+                eprintln!("Failed to save client secret to keyring: {}", e);
+            }
+        }
+    }
 }
 
 async fn try_authenticate_internal<K: KeyringEntry, O: OAuthFlow>(
     credentials_keyring: &K,
     oauth_flow_impl: &O,
-) -> Result<AuthResult, Box<dyn std::error::Error>> {
+    force_consent: bool,
+) -> Result<(ApplicationSecret, OAuthTokens, bool), Box<dyn std::error::Error>> {
     let mut retry_count = 0;
     let mut client_secret_from_file = false;
     loop {
@@ -193,25 +517,56 @@ async fn try_authenticate_internal<K: KeyringEntry, O: OAuthFlow>(
             client_secret_from_file = true;
         }
 
-        // Try to retrieve token from consolidated credentials first
-        if retry_count == 0 {
+        // Try to retrieve token from consolidated credentials first; one
+        // missing a scope is not used, so the user is asked to grant it
+        if retry_count == 0 && !force_consent {
             if let Ok(credentials) = load_secure_credentials(credentials_keyring).await {
-                if let Some(token) = credentials.token {
-                    return Ok(AuthResult {
-                        token,
-                        client_secret_loaded_from_file: client_secret_from_file,
-                    }); // Success
+                if let Some(token) = credentials
+                    .token
+                    .clone()
+                    .filter(|_| credentials.has_required_scopes())
+                {
+                    let stored = OAuthTokens {
+                        access_token: token,
+                        refresh_token: credentials.refresh_token.clone(),
+                        expires_at: credentials.token_expiry,
+                        scopes: credentials.scopes.clone(),
+                    };
+
+                    if !credentials.is_token_expired() {
+                        return Ok((secret, stored, client_secret_from_file)); // Success
+                    }
+
+                    // Expired: renew silently instead of sending the user through the browser
+                    if let Some(refresh_token) = &stored.refresh_token {
+                        let client = reqwest::Client::new();
+                        if let Ok(refreshed) =
+                            refresh_access_token(&client, &secret, refresh_token).await
+                        {
+                            let credentials = credentials.with_tokens(refreshed);
+                            if let Err(e) =
+                                save_secure_credentials(credentials_keyring, &credentials).await
+                            {
+                                eprintln!("Failed to save refreshed token to keyring: {}", e);
+                            }
+                            let tokens = OAuthTokens {
+                                access_token: credentials.token.unwrap_or_default(),
+                                refresh_token: credentials.refresh_token,
+                                expires_at: credentials.token_expiry,
+                                scopes: credentials.scopes,
+                            };
+                            return Ok((secret, tokens, client_secret_from_file));
+                            // Success
+                        }
+                    }
                 }
             }
         }
 
-        // If no token in keyring or it's a retry attempt, perform OAuth flow
-        match perform_oauth_flow(oauth_flow_impl, secret, credentials_keyring).await {
-            Ok(token_string) => {
-                return Ok(AuthResult {
-                    token: token_string,
-                    client_secret_loaded_from_file: client_secret_from_file,
-                }); // Success
+        // If no usable token in keyring or it's a retry attempt, perform OAuth flow
+        match perform_oauth_flow(oauth_flow_impl, secret.clone(), credentials_keyring).await {
+            Ok(tokens) => {
+                return Ok((secret, tokens, client_secret_from_file)); // Success
             }
             Err(e) => {
                 eprintln!("Authentication failed: {}", e);
@@ -229,3 +584,194 @@ async fn try_authenticate_internal<K: KeyringEntry, O: OAuthFlow>(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_scopes() -> Option<Vec<String>> {
+        Some(SCOPES.iter().map(|scope| scope.to_string()).collect())
+    }
+
+    fn stored_credentials(expiry: Option<DateTime<Utc>>) -> String {
+        stored_credentials_with_scopes(expiry, all_scopes())
+    }
+
+    fn stored_credentials_with_scopes(
+        expiry: Option<DateTime<Utc>>,
+        scopes: Option<Vec<String>>,
+    ) -> String {
+        let credentials = SecureCredentials::new()
+            .with_client_secret(ApplicationSecret {
+                client_id: "client".to_string(),
+                client_secret: "secret".to_string(),
+                token_uri: "http://127.0.0.1:9/token".to_string(),
+                ..Default::default()
+            })
+            .with_tokens(OAuthTokens {
+                access_token: "stored_token".to_string(),
+                refresh_token: None,
+                expires_at: expiry,
+                scopes,
+            });
+        serde_json::to_string(&credentials).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_valid_stored_token_skips_oauth_flow() {
+        let mut keyring = MockKeyringEntry::new();
+        let json = stored_credentials(Some(Utc::now() + chrono::Duration::hours(1)));
+        keyring
+            .expect_get_password()
+            .returning(move || Ok(json.clone()));
+        let mut flow = MockOAuthFlow::new();
+        flow.expect_perform_flow().never();
+
+        let (_, tokens, from_file) = try_authenticate_internal(&keyring, &flow, false)
+            .await
+            .unwrap();
+        assert_eq!(tokens.access_token, "stored_token");
+        assert!(!from_file);
+    }
+
+    #[tokio::test]
+    async fn test_expired_token_without_refresh_token_runs_oauth_flow() {
+        let mut keyring = MockKeyringEntry::new();
+        let json = stored_credentials(Some(Utc::now() - chrono::Duration::hours(1)));
+        keyring
+            .expect_get_password()
+            .returning(move || Ok(json.clone()));
+        keyring.expect_set_password().returning(|_| Ok(()));
+        let mut flow = MockOAuthFlow::new();
+        flow.expect_perform_flow().times(1).returning(|_, _| {
+            Ok(OAuthTokens {
+                access_token: "new_token".to_string(),
+                refresh_token: Some("refresh".to_string()),
+                expires_at: Some(Utc::now() + chrono::Duration::hours(1)),
+                scopes: all_scopes(),
+            })
+        });
+
+        let (_, tokens, _) = try_authenticate_internal(&keyring, &flow, false)
+            .await
+            .unwrap();
+        assert_eq!(tokens.access_token, "new_token");
+        assert_eq!(tokens.refresh_token, Some("refresh".to_string()));
+    }
+
+    // Tokens saved before the filters scope was requested lack it
+    #[tokio::test]
+    async fn test_stored_token_missing_a_scope_runs_oauth_flow() {
+        let mut keyring = MockKeyringEntry::new();
+        let json = stored_credentials_with_scopes(
+            Some(Utc::now() + chrono::Duration::hours(1)),
+            Some(vec!["https://mail.google.com/".to_string()]),
+        );
+        keyring
+            .expect_get_password()
+            .returning(move || Ok(json.clone()));
+        keyring
+            .expect_set_password()
+            .withf(|json| json.contains("gmail.settings.basic"))
+            .returning(|_| Ok(()));
+        let mut flow = MockOAuthFlow::new();
+        flow.expect_perform_flow()
+            .withf(|_, scopes| scopes.len() == SCOPES.len())
+            .times(1)
+            .returning(|_, scopes| {
+                Ok(OAuthTokens {
+                    access_token: "new_token".to_string(),
+                    refresh_token: None,
+                    expires_at: None,
+                    scopes: Some(scopes),
+                })
+            });
+
+        let (_, tokens, _) = try_authenticate_internal(&keyring, &flow, false)
+            .await
+            .unwrap();
+        assert_eq!(tokens.access_token, "new_token");
+    }
+
+    #[tokio::test]
+    async fn test_forced_consent_ignores_valid_stored_token() {
+        let mut keyring = MockKeyringEntry::new();
+        let json = stored_credentials(Some(Utc::now() + chrono::Duration::hours(1)));
+        keyring
+            .expect_get_password()
+            .returning(move || Ok(json.clone()));
+        keyring.expect_set_password().returning(|_| Ok(()));
+        let mut flow = MockOAuthFlow::new();
+        flow.expect_perform_flow().times(1).returning(|_, scopes| {
+            Ok(OAuthTokens {
+                access_token: "new_token".to_string(),
+                refresh_token: None,
+                expires_at: None,
+                scopes: Some(scopes),
+            })
+        });
+
+        let (_, tokens, _) = try_authenticate_internal(&keyring, &flow, true)
+            .await
+            .unwrap();
+        assert_eq!(tokens.access_token, "new_token");
+    }
+
+    #[tokio::test]
+    async fn test_new_account_inherits_client_secret() {
+        let mut keyring = MockKeyringEntry::new();
+        keyring
+            .expect_get_password()
+            .returning(|| Err(keyring::Error::NoEntry));
+        keyring
+            .expect_set_password()
+            .withf(|json| {
+                json.contains("\"client_id\":\"client\"") && !json.contains("stored_token")
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+        let mut source = MockKeyringEntry::new();
+        let json = stored_credentials(None);
+        source
+            .expect_get_password()
+            .returning(move || Ok(json.clone()));
+
+        inherit_client_secret(&keyring, &source).await;
+    }
+
+    #[test]
+    fn test_stored_session_needs_a_usable_token() {
+        let expired: SecureCredentials = serde_json::from_str(&stored_credentials(Some(
+            Utc::now() - chrono::Duration::hours(1),
+        )))
+        .unwrap();
+        assert!(session_from_credentials(expired, Box::new(MockKeyringEntry::new())).is_err());
+
+        let valid: SecureCredentials = serde_json::from_str(&stored_credentials(Some(
+            Utc::now() + chrono::Duration::hours(1),
+        )))
+        .unwrap();
+        let session = session_from_credentials(valid, Box::new(MockKeyringEntry::new())).unwrap();
+        assert_eq!(session.token, "stored_token");
+        assert!(session.token_refresher.is_none());
+    }
+
+    #[test]
+    fn test_with_tokens_keeps_existing_refresh_token() {
+        let credentials = SecureCredentials::new()
+            .with_tokens(OAuthTokens {
+                access_token: "first".to_string(),
+                refresh_token: Some("refresh".to_string()),
+                expires_at: None,
+                scopes: None,
+            })
+            .with_tokens(OAuthTokens {
+                access_token: "second".to_string(),
+                refresh_token: None,
+                expires_at: None,
+                scopes: None,
+            });
+        assert_eq!(credentials.token, Some("second".to_string()));
+        assert_eq!(credentials.refresh_token, Some("refresh".to_string()));
+    }
+}
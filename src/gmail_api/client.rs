@@ -0,0 +1,373 @@
+use crate::state::AppState;
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_API_BASE_URL: &str = "https://gmail.googleapis.com";
+
+// Gmail allows 250 quota units per user per second
+const QUOTA_UNITS_PER_SECOND: f64 = 250.0;
+
+// Background requests leave this share of the quota budget to user actions
+const BACKGROUND_RESERVE_FRACTION: f64 = 0.5;
+
+// Background requests never wait longer than this for quota or retries;
+// they give up instead and the next poll tries again
+const BACKGROUND_MAX_WAIT: Duration = Duration::from_secs(5);
+
+// User actions wait at most this long for quota or retries, since the UI
+// cannot react while they hold the state lock; after that Gmail's answer is
+// returned to the user
+const INTERACTIVE_MAX_WAIT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum RequestError {
+    Http(reqwest::Error),
+    // A background request gave up waiting for quota
+    QuotaDeferred,
+    // A user action gave up waiting out a rate limit that has this much left
+    RateLimited(Duration),
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestError::Http(e) => write!(f, "{}", e),
+            RequestError::QuotaDeferred => {
+                write!(f, "Gmail quota budget exhausted, deferring background sync")
+            }
+            RequestError::RateLimited(wait) => write!(
+                f,
+                "Gmail is limiting requests, try again in {} seconds",
+                wait.as_secs().max(1)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+impl From<reqwest::Error> for RequestError {
+    fn from(e: reqwest::Error) -> Self {
+        RequestError::Http(e)
+    }
+}
+
+// Whether a request failed because Gmail could not be reached at all, rather
+// than because Gmail answered with an error
+pub fn is_network_error(error: &(dyn std::error::Error + 'static)) -> bool {
+    match error.downcast_ref::<RequestError>() {
+        Some(RequestError::Http(e)) => e.is_connect() || e.is_timeout() || e.is_request(),
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RequestPriority {
+    // Triggered by the user, may use the whole quota budget and wait for it
+    Interactive,
+    // Periodic syncs, which yield quota and waiting time to user actions
+    Background,
+}
+
+impl RequestPriority {
+    // Longest total time a request waits for quota or before retries
+    fn max_wait(self) -> Duration {
+        match self {
+            RequestPriority::Interactive => INTERACTIVE_MAX_WAIT,
+            RequestPriority::Background => BACKGROUND_MAX_WAIT,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(32),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // Exponential backoff delay before retry number `attempt` (starting at 0)
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay)
+    }
+}
+
+// Token bucket tracking the per-second Gmail quota shared by all requests
+pub struct QuotaBudget {
+    capacity: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    available: f64,
+    updated: Instant,
+    // Set after a 429 so that no request is sent before Retry-After has passed
+    paused_until: Option<Instant>,
+}
+
+impl Default for QuotaBudget {
+    fn default() -> Self {
+        Self::new(QUOTA_UNITS_PER_SECOND)
+    }
+}
+
+impl QuotaBudget {
+    pub fn new(units_per_second: f64) -> Self {
+        Self {
+            capacity: units_per_second,
+            bucket: Mutex::new(Bucket {
+                available: units_per_second,
+                updated: Instant::now(),
+                paused_until: None,
+            }),
+        }
+    }
+
+    // Take `cost` units, or report how long to wait until they are available
+    fn try_acquire(&self, cost: f64, priority: RequestPriority) -> Result<(), Duration> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+
+        if let Some(paused_until) = bucket.paused_until {
+            if paused_until > now {
+                return Err(paused_until - now);
+            }
+            bucket.paused_until = None;
+        }
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.available = (bucket.available + elapsed * self.capacity).min(self.capacity);
+        bucket.updated = now;
+
+        let reserve = match priority {
+            RequestPriority::Interactive => 0.0,
+            RequestPriority::Background => self.capacity * BACKGROUND_RESERVE_FRACTION,
+        };
+        let needed = (cost + reserve).min(self.capacity);
+        if bucket.available >= needed {
+            bucket.available -= cost;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (needed - bucket.available) / self.capacity,
+            ))
+        }
+    }
+
+    async fn acquire(&self, cost: f64, priority: RequestPriority) -> Result<(), RequestError> {
+        loop {
+            match self.try_acquire(cost, priority) {
+                Ok(()) => return Ok(()),
+                Err(wait) if wait > priority.max_wait() => {
+                    return Err(match priority {
+                        RequestPriority::Interactive => RequestError::RateLimited(wait),
+                        RequestPriority::Background => RequestError::QuotaDeferred,
+                    });
+                }
+                Err(wait) => tokio::time::sleep(wait).await,
+            }
+        }
+    }
+
+    // Stop all requests until `delay` has passed
+    fn pause_for(&self, delay: Duration) {
+        let mut bucket = self.bucket.lock().unwrap();
+        let until = Instant::now() + delay;
+        if bucket.paused_until.is_none_or(|current| current < until) {
+            bucket.paused_until = Some(until);
+        }
+    }
+}
+
+// Build the URL of a `users/me` endpoint on the configured API server,
+// e.g. api_url(state, "messages/send")
+pub fn api_url(state: &AppState, path: &str) -> String {
+    format!(
+        "{}/gmail/v1/users/me/{}",
+        state.api_base_url.trim_end_matches('/'),
+        path
+    )
+}
+
+// URL of a `users/me` endpoint on the media upload path,
+// e.g. upload_url(state, "messages/send?uploadType=resumable")
+pub fn upload_url(state: &AppState, path: &str) -> String {
+    format!(
+        "{}/upload/gmail/v1/users/me/{}",
+        state.api_base_url.trim_end_matches('/'),
+        path
+    )
+}
+
+// Quota units Gmail charges for a request, judged from its endpoint
+fn quota_cost(method: &Method, path: &str) -> f64 {
+    let segments: Vec<&str> = path.trim_end_matches('/').rsplit('/').collect();
+    match (method.as_str(), segments.as_slice()) {
+        ("POST", ["send", ..]) => 100.0,
+        ("POST", ["batchModify" | "batchDelete", ..]) => 50.0,
+        (_, ["history", ..]) => 2.0,
+        (_, ["profile", ..]) => 1.0,
+        ("GET", ["labels", ..]) => 1.0,
+        (_, [_, "threads", ..]) | (_, [_, _, "threads", ..]) => 10.0,
+        _ => 5.0,
+    }
+}
+
+// Delay requested by the server, either in seconds or as an HTTP date
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delay = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(delay.to_std().unwrap_or(Duration::ZERO))
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+// Send a Gmail API request with the current access token. Expired tokens are
+// refreshed before sending, and a 401 response triggers one refresh and retry.
+// Requests are paced by the quota budget, and 429/5xx responses are retried
+// with exponential backoff, honoring Retry-After. Waiting is capped by the
+// request priority: once retrying would take longer, the last response is
+// returned, so that no request holds the state lock for long. Background
+// requests have the lower cap, leaving the lock and the quota to the user.
+// The request is built by `build` so it can be sent more than once.
+pub async fn send_authorized<F>(state: &AppState, build: F) -> Result<Response, RequestError>
+where
+    F: Fn(&reqwest::Client) -> RequestBuilder,
+{
+    let priority = state.request_priority;
+    let mut token = match &state.token_refresher {
+        Some(refresher) => refresher.access_token().await,
+        None => state.token.clone(),
+    };
+    let mut refreshed = false;
+    let mut attempt = 0;
+    let mut waited = Duration::ZERO;
+
+    loop {
+        let request = build(&state.client).bearer_auth(&token).build()?;
+        let cost = quota_cost(request.method(), request.url().path());
+        state.quota.acquire(cost, priority).await?;
+
+        let response = state.client.execute(request).await?;
+        let status = response.status();
+
+        if status == StatusCode::UNAUTHORIZED && !refreshed {
+            refreshed = true;
+            if let Some(refresher) = &state.token_refresher {
+                let new_token = refresher.refresh(&token).await.ok();
+                if let Some(new_token) = new_token {
+                    token = new_token;
+                    continue;
+                }
+            }
+            return Ok(response);
+        }
+
+        if !is_retryable(status) || attempt >= state.retry_policy.max_retries {
+            return Ok(response);
+        }
+
+        let delay = retry_after(&response).unwrap_or_else(|| state.retry_policy.backoff(attempt));
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            // Rate limits apply to the whole account, so hold back every request
+            state.quota.pause_for(delay);
+        }
+        if waited + delay > priority.max_wait() {
+            return Ok(response);
+        }
+
+        tokio::time::sleep(delay).await;
+        waited += delay;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_cost_by_endpoint() {
+        let base = "/gmail/v1/users/me";
+        let cost = |method: Method, path: &str| quota_cost(&method, &format!("{}/{}", base, path));
+        assert_eq!(cost(Method::POST, "messages/send"), 100.0);
+        assert_eq!(cost(Method::POST, "messages/batchModify"), 50.0);
+        assert_eq!(cost(Method::POST, "messages/batchDelete"), 50.0);
+        assert_eq!(cost(Method::GET, "history"), 2.0);
+        assert_eq!(cost(Method::GET, "labels"), 1.0);
+        assert_eq!(cost(Method::GET, "messages/abc"), 5.0);
+        assert_eq!(cost(Method::POST, "messages/abc/modify"), 5.0);
+        assert_eq!(cost(Method::GET, "threads/abc"), 10.0);
+        assert_eq!(cost(Method::POST, "threads/abc/trash"), 10.0);
+    }
+
+    #[test]
+    fn test_background_requests_leave_reserve_for_interactive_ones() {
+        let budget = QuotaBudget::new(100.0);
+
+        // Background may only spend down to the reserved half
+        assert!(budget
+            .try_acquire(40.0, RequestPriority::Background)
+            .is_ok());
+        assert!(budget
+            .try_acquire(40.0, RequestPriority::Background)
+            .is_err());
+
+        // User actions can still use the reserve
+        assert!(budget
+            .try_acquire(40.0, RequestPriority::Interactive)
+            .is_ok());
+        assert!(budget
+            .try_acquire(40.0, RequestPriority::Interactive)
+            .is_err());
+    }
+
+    #[test]
+    fn test_pause_blocks_all_requests() {
+        let budget = QuotaBudget::new(100.0);
+        budget.pause_for(Duration::from_secs(30));
+
+        let wait = budget
+            .try_acquire(1.0, RequestPriority::Interactive)
+            .unwrap_err();
+        assert!(wait > Duration::from_secs(29));
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_max_delay() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(3),
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(3), Duration::from_secs(3));
+    }
+}
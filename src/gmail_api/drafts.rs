@@ -0,0 +1,111 @@
+use super::client::{api_url, send_authorized};
+use super::operations::{check_attachments_size, message_resource, upload_message};
+use crate::mime::OutgoingMessage;
+use crate::state::AppState;
+use crate::types::{Draft, DraftsResponse};
+use reqwest::Method;
+use serde_json::{json, Value};
+
+// A Draft resource for drafts.create/update/send
+fn draft_resource(draft_id: Option<&str>, raw: Option<&str>, thread_id: Option<&str>) -> Value {
+    let mut resource = json!({ "message": message_resource(raw, thread_id) });
+    if let Some(draft_id) = draft_id {
+        resource["id"] = json!(draft_id);
+    }
+    resource
+}
+
+// Save `message` as a new draft, or as the new content of draft `draft_id`
+pub async fn save_draft(
+    state: &AppState,
+    draft_id: Option<&str>,
+    message: &OutgoingMessage,
+    thread_id: Option<&str>,
+) -> Result<Draft, Box<dyn std::error::Error>> {
+    check_attachments_size(message)?;
+    let (method, path) = match draft_id {
+        Some(draft_id) => (Method::PUT, format!("drafts/{}", draft_id)),
+        None => (Method::POST, "drafts".to_string()),
+    };
+
+    let response = upload_message(
+        state,
+        method,
+        &path,
+        message.build(),
+        |raw| draft_resource(draft_id, raw, thread_id),
+        "save draft",
+    )
+    .await?;
+    Ok(serde_json::from_value(response)?)
+}
+
+// Send draft `draft_id` with `message` as its final content. Unlike
+// messages.send this removes the draft, so Gmail keeps no stale copy.
+pub async fn send_draft(
+    state: &AppState,
+    draft_id: &str,
+    message: &OutgoingMessage,
+    thread_id: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    check_attachments_size(message)?;
+    send_raw_draft(state, draft_id, message.build(), thread_id).await
+}
+
+// Send draft `draft_id` with an already built message as its final content
+pub async fn send_raw_draft(
+    state: &AppState,
+    draft_id: &str,
+    raw: String,
+    thread_id: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    upload_message(
+        state,
+        Method::POST,
+        "drafts/send",
+        raw,
+        |raw| draft_resource(Some(draft_id), raw, thread_id),
+        "send draft",
+    )
+    .await?;
+    Ok(())
+}
+
+// The draft whose current message is `message_id`, found with drafts.list
+pub async fn find_draft(
+    state: &AppState,
+    message_id: &str,
+) -> Result<Option<Draft>, Box<dyn std::error::Error>> {
+    let mut page_token: Option<String> = None;
+    loop {
+        let mut path = "drafts?maxResults=500".to_string();
+        if let Some(token) = &page_token {
+            path.push_str(&format!("&pageToken={}", token));
+        }
+        let drafts_url = api_url(state, &path);
+        let response = send_authorized(state, |client| client.get(&drafts_url)).await?;
+
+        if !response.status().is_success() {
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(format!("Failed to list drafts: {}", error_text).into());
+        }
+
+        let page: DraftsResponse = response.json().await?;
+        let found = page.drafts.unwrap_or_default().into_iter().find(|draft| {
+            draft
+                .message
+                .as_ref()
+                .is_some_and(|m| m.id.as_deref() == Some(message_id))
+        });
+        if found.is_some() {
+            return Ok(found);
+        }
+        match page.next_page_token {
+            Some(token) => page_token = Some(token),
+            None => return Ok(None),
+        }
+    }
+}
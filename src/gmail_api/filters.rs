@@ -0,0 +1,65 @@
+use super::client::{api_url, send_authorized};
+use crate::state::AppState;
+use crate::types::{Filter, FiltersResponse};
+use reqwest::{Response, StatusCode};
+
+// The error for a failed filters call. Tokens granted before the app asked
+// for the settings scope get a 403 here, which Ctrl+R fixes.
+async fn filters_error(action: &str, response: Response) -> Box<dyn std::error::Error> {
+    let status = response.status();
+    let error_text = response
+        .text()
+        .await
+        .unwrap_or_else(|_| "Unknown error".to_string());
+    if status == StatusCode::FORBIDDEN
+        && (error_text.contains("ACCESS_TOKEN_SCOPE_INSUFFICIENT")
+            || error_text.contains("insufficientPermissions"))
+    {
+        return "Gmail has not granted access to filters. Press Ctrl+R to re-authorize.".into();
+    }
+    format!("Failed to {}: {}", action, error_text).into()
+}
+
+// The account's filters, as set up in Gmail
+pub async fn fetch_filters(state: &AppState) -> Result<Vec<Filter>, Box<dyn std::error::Error>> {
+    let filters_url = api_url(state, "settings/filters");
+    let response = send_authorized(state, |client| client.get(&filters_url)).await?;
+
+    if response.status().is_success() {
+        let filters_data: FiltersResponse = response.json().await?;
+        Ok(filters_data.filter.unwrap_or_default())
+    } else {
+        Err(filters_error("fetch filters", response).await)
+    }
+}
+
+// Create a filter; Gmail applies it to messages arriving from now on
+pub async fn create_filter(
+    state: &AppState,
+    filter: &Filter,
+) -> Result<Filter, Box<dyn std::error::Error>> {
+    let filters_url = api_url(state, "settings/filters");
+
+    let response = send_authorized(state, |client| client.post(&filters_url).json(filter)).await?;
+
+    if response.status().is_success() {
+        Ok(response.json().await?)
+    } else {
+        Err(filters_error("create filter", response).await)
+    }
+}
+
+pub async fn delete_filter(
+    state: &AppState,
+    filter_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let filter_url = api_url(state, &format!("settings/filters/{}", filter_id));
+
+    let response = send_authorized(state, |client| client.delete(&filter_url)).await?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(filters_error("delete filter", response).await)
+    }
+}
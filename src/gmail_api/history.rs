@@ -0,0 +1,68 @@
+use super::client::{api_url, send_authorized};
+use crate::state::AppState;
+use crate::types::{HistoryRecord, HistoryResponse, Profile};
+
+// Changes collected from users.history.list since a given history id
+pub struct HistoryChanges {
+    pub records: Vec<HistoryRecord>,
+    pub history_id: Option<String>,
+}
+
+// Fetch the mailbox profile, which carries the current mailbox historyId
+pub async fn fetch_profile(state: &AppState) -> Result<Profile, Box<dyn std::error::Error>> {
+    let profile_url = api_url(state, "profile");
+    let response = send_authorized(state, |client| client.get(&profile_url)).await?;
+
+    if response.status().is_success() {
+        Ok(response.json().await?)
+    } else {
+        Err(format!("Failed to fetch profile: {}", response.status()).into())
+    }
+}
+
+// Fetch every history record newer than start_history_id, following nextPageToken.
+// Returns Ok(None) when Gmail no longer knows the start id (HTTP 404), in which
+// case the caller has to fall back to a full resync.
+pub async fn fetch_history(
+    state: &AppState,
+    start_history_id: &str,
+) -> Result<Option<HistoryChanges>, Box<dyn std::error::Error>> {
+    let mut records = Vec::new();
+    let mut history_id = None;
+    let mut page_token: Option<String> = None;
+
+    loop {
+        let mut history_url = api_url(
+            state,
+            &format!("history?startHistoryId={}", start_history_id),
+        );
+        if let Some(token) = &page_token {
+            history_url.push_str(&format!("&pageToken={}", token));
+        }
+
+        let response = send_authorized(state, |client| client.get(&history_url)).await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(format!("Failed to fetch history: {}", response.status()).into());
+        }
+
+        let page: HistoryResponse = response.json().await?;
+        records.extend(page.history.unwrap_or_default());
+        if page.history_id.is_some() {
+            history_id = page.history_id;
+        }
+
+        match page.next_page_token {
+            Some(token) => page_token = Some(token),
+            None => break,
+        }
+    }
+
+    Ok(Some(HistoryChanges {
+        records,
+        history_id,
+    }))
+}
@@ -1,15 +1,11 @@
+use super::client::{api_url, send_authorized};
 use crate::state::AppState;
 use crate::types::{Label, LabelsResponse};
 
 // Helper function to fetch labels
 pub async fn fetch_labels(state: &AppState) -> Result<Vec<Label>, Box<dyn std::error::Error>> {
-    let labels_url = "https://gmail.googleapis.com/gmail/v1/users/me/labels";
-    let response = state
-        .client
-        .get(labels_url)
-        .bearer_auth(&state.token)
-        .send()
-        .await?;
+    let labels_url = api_url(state, "labels");
+    let response = send_authorized(state, |client| client.get(&labels_url)).await?;
 
     if response.status().is_success() {
         let labels_data: LabelsResponse = response.json().await?;
@@ -18,3 +14,73 @@ pub async fn fetch_labels(state: &AppState) -> Result<Vec<Label>, Box<dyn std::e
         Err(format!("Failed to fetch labels: {}", response.status()).into())
     }
 }
+
+// Create a user label that is shown in the label and message lists
+pub async fn create_label(
+    state: &AppState,
+    name: &str,
+) -> Result<Label, Box<dyn std::error::Error>> {
+    let labels_url = api_url(state, "labels");
+
+    let request_body = serde_json::json!({
+        "name": name,
+        "labelListVisibility": "labelShow",
+        "messageListVisibility": "show"
+    });
+
+    let response =
+        send_authorized(state, |client| client.post(&labels_url).json(&request_body)).await?;
+
+    if response.status().is_success() {
+        Ok(response.json().await?)
+    } else {
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        Err(format!("Failed to create label: {}", error_text).into())
+    }
+}
+
+pub async fn rename_label(
+    state: &AppState,
+    label_id: &str,
+    name: &str,
+) -> Result<Label, Box<dyn std::error::Error>> {
+    let label_url = api_url(state, &format!("labels/{}", label_id));
+
+    let request_body = serde_json::json!({ "name": name });
+
+    let response =
+        send_authorized(state, |client| client.patch(&label_url).json(&request_body)).await?;
+
+    if response.status().is_success() {
+        Ok(response.json().await?)
+    } else {
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        Err(format!("Failed to rename label: {}", error_text).into())
+    }
+}
+
+// Delete a user label; Gmail removes it from every message that carries it
+pub async fn delete_label(
+    state: &AppState,
+    label_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let label_url = api_url(state, &format!("labels/{}", label_id));
+
+    let response = send_authorized(state, |client| client.delete(&label_url)).await?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        Err(format!("Failed to delete label: {}", error_text).into())
+    }
+}
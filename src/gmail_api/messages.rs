@@ -1,22 +1,79 @@
-use crate::state::AppState;
-use crate::types::{Message, MessagesResponse};
+use super::client::{api_url, send_authorized};
+use crate::state::{is_outbox_label, is_search_label, is_unified_inbox, AppState};
+use crate::sync::sync_unified_inbox;
+use crate::types::{Message, MessagesResponse, RawMessage};
 use chrono::DateTime;
 use chrono::Utc;
+use futures::stream::{self, StreamExt};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+// Only the headers shown in the message list are requested
+const LIST_METADATA_QUERY: &str =
+    "format=metadata&metadataHeaders=From&metadataHeaders=Subject&metadataHeaders=Date";
+
+// Maximum number of metadata requests in flight at once
+const METADATA_FETCH_CONCURRENCY: usize = 10;
 
 pub async fn fetch_messages_for_label(state: &mut AppState) {
-    // If not cached, fetch initial batch from API
+    // The unified inbox is assembled from the account caches and the outbox
+    // is local, neither is fetched
+    if let Some(label_id) = state
+        .get_current_label()
+        .and_then(|label| label.id.clone())
+        .filter(|label_id| is_unified_inbox(label_id) || is_outbox_label(label_id))
+    {
+        let _ = state.load_messages_from_cache(&label_id).await;
+        return;
+    }
+
+    let page = fetch_first_page(state).await;
+    apply_first_page(state, page).await;
+}
+
+// Same as fetch_messages_for_label, but the list and metadata requests run
+// without holding the state lock. It is only taken to read the current label
+// and, briefly, to apply the page if that label is still shown.
+pub async fn refresh_current_label(state_arc: &Arc<RwLock<AppState>>) {
+    let context = state_arc.read().await.request_context();
+    let shown = |state: &AppState| {
+        (
+            state.get_current_label().and_then(|label| label.id.clone()),
+            state.search_query.clone(),
+        )
+    };
+    let label = shown(&context);
+    if label.0.as_deref().is_some_and(is_unified_inbox) {
+        sync_unified_inbox(state_arc).await;
+        return;
+    }
+    if label.0.as_deref().is_some_and(is_outbox_label) {
+        fetch_messages_for_label(&mut *state_arc.write().await).await;
+        return;
+    }
 
-    // Load 2 screenfuls initially (one visible + one buffer)
+    let page = fetch_first_page(&context).await;
+
+    let mut state = state_arc.write().await;
+    if shown(&state) == label {
+        apply_first_page(&mut state, page).await;
+    }
+}
+
+// A page of a label's messages and the token of the page after it
+pub(crate) type LabelPage = (Vec<Message>, Option<String>);
+
+// Load 2 screenfuls initially (one visible + one buffer)
+pub(crate) async fn fetch_first_page(state: &AppState) -> Option<LabelPage> {
     let initial_batch_size = state.messages_per_screen * 2;
-    match fetch_messages_for_label_index_paginated(
-        state,
-        state.selected_label,
-        0,
-        initial_batch_size,
-    )
-    .await
-    {
-        Some(messages) => {
+    fetch_messages_for_label_index_paginated(state, state.selected_label, None, initial_batch_size)
+        .await
+}
+
+// Show the first page of the current label and cache it
+pub(crate) async fn apply_first_page(state: &mut AppState, page: Option<LabelPage>) {
+    match page {
+        Some((messages, next_page_token)) => {
             // Capture the ID of the currently selected message before updating the list
             let current_selected_message_id = state
                 .messages
@@ -44,88 +101,15 @@ pub async fn fetch_messages_for_label(state: &mut AppState) {
             state.update_message_state();
 
             // Extract headers and save to both in-memory cache and database
+            let current_label_id = state
+                .labels
+                .get(state.selected_label)
+                .and_then(|label| label.id.clone());
             for message in &messages {
-                if let Some(msg_id) = &message.id {
-                    // Extract subject, from, and date from headers if available
-                    let mut subject = None;
-                    let mut from_addr = None;
-                    let mut date_str = None;
-
-                    if let Some(payload) = &message.payload {
-                        if let Some(headers) = &payload.headers {
-                            subject = headers
-                                .iter()
-                                .find(|h| h.name.as_deref() == Some("Subject"))
-                                .and_then(|h| h.value.clone());
-
-                            from_addr = headers
-                                .iter()
-                                .find(|h| h.name.as_deref() == Some("From"))
-                                .and_then(|h| h.value.clone());
-
-                            date_str = headers
-                                .iter()
-                                .find(|h| h.name.as_deref() == Some("Date"))
-                                .and_then(|h| h.value.clone());
-                        }
-                    }
-
-                    // Cache headers in memory for immediate display
-                    if let (Some(subj), Some(from)) = (&subject, &from_addr) {
-                        state
-                            .message_headers
-                            .insert(msg_id.clone(), (subj.clone(), from.clone()));
-                    }
-
-                    // Cache date separately for formatting
-                    if let Some(date) = &date_str {
-                        state
-                            .message_bodies
-                            .insert(format!("{}_date", msg_id), date.clone());
-                    }
-
-                    // Save to database cache if available
-                    if let (Some(db), Some(label)) =
-                        (&state.database, state.labels.get(state.selected_label))
-                    {
-                        if let Some(current_label_id) = &label.id {
-                            // For specific labels, only associate with the current label being viewed
-                            // For ALLMAIL, use all the message's labels
-                            let label_ids = if current_label_id.to_uppercase() == "ALLMAIL" {
-                                message.label_ids.clone().unwrap_or_default()
-                            } else {
-                                vec![current_label_id.clone()]
-                            };
-
-                            let cached_message = crate::database::CachedMessage {
-                                id: msg_id.clone(),
-                                thread_id: message.thread_id.clone(),
-                                label_ids,
-                                snippet: message.snippet.clone(),
-                                subject,
-                                from_addr,
-                                to_addr: None,
-                                date_str: date_str.clone(),
-                                body_text: None,
-                                body_html: None,
-                                received_date: chrono::Utc::now(), // This can still be the current time of caching
-                                internal_date: date_str
-                                    .clone()
-                                    .as_ref()
-                                    .and_then(|s| {
-                                        DateTime::parse_from_rfc2822(s)
-                                            .ok()
-                                            .map(|dt| dt.with_timezone(&Utc))
-                                    })
-                                    .unwrap_or_else(chrono::Utc::now), // Use parsed date or current UTC
-                                is_unread: false,  // Placeholder
-                                is_starred: false, // Placeholder
-                                cache_timestamp: chrono::Utc::now(),
-                            };
-                            let _ = db.upsert_message(&cached_message).await;
-                        }
-                    }
-                }
+                let label_ids = current_label_id
+                    .as_ref()
+                    .map(|current_label_id| labels_to_cache(current_label_id, message));
+                cache_message_metadata(state, message, label_ids).await;
             }
 
             // Update sync state to mark this label as recently synced
@@ -134,13 +118,36 @@ pub async fn fetch_messages_for_label(state: &mut AppState) {
             {
                 if let Some(label_id) = &label.id {
                     let _ = db.update_sync_state(label_id, None).await;
+                    let _ = db
+                        .update_page_token(label_id, next_page_token.as_deref())
+                        .await;
                 }
             }
 
+            // Remember where the next page starts for load_more_messages
+            if let Some(label_id) = current_label_id {
+                state.label_page_tokens.insert(label_id, next_page_token);
+            }
+
             // Also save to in-memory cache for compatibility
             state.cache_messages_for_label(state.selected_label, messages);
         }
         None => {
+            // Searches can still be answered from the local cache while offline
+            if let Some(label_id) = state
+                .get_current_label()
+                .and_then(|label| label.id.clone())
+                .filter(|label_id| is_search_label(label_id))
+            {
+                if state.load_messages_from_cache(&label_id).await.is_ok() {
+                    state.set_error_message(format!(
+                        "Could not reach Gmail. Showing {} matches from the local cache.",
+                        state.messages.len()
+                    ));
+                    return;
+                }
+            }
+
             // Failed to fetch messages from API - this could be due to authentication issues
             // or network problems. Set an error message for the UI to display.
             state.set_error_message(
@@ -151,218 +158,439 @@ pub async fn fetch_messages_for_label(state: &mut AppState) {
     }
 }
 
-// Helper function to fetch full message content and headers
-pub async fn fetch_full_message(
+// Labels to record for a message fetched while viewing `label_id`. For specific
+// labels, only associate it with the label being viewed; for ALLMAIL and search
+// results, which are not real labels, use all of the message's own labels.
+fn labels_to_cache(label_id: &str, message: &Message) -> Vec<String> {
+    if label_id.to_uppercase() == "ALLMAIL" || is_search_label(label_id) {
+        message.label_ids.clone().unwrap_or_default()
+    } else {
+        vec![label_id.to_string()]
+    }
+}
+
+// Fetch list metadata for many messages with a bounded number of requests in flight.
+// Results keep the order of ids; messages that fail to load are skipped.
+pub(crate) async fn fetch_messages_metadata(state: &AppState, ids: &[String]) -> Vec<Message> {
+    let requests: Vec<_> = ids
+        .iter()
+        .map(|id| try_fetch_message_metadata(state, id))
+        .collect();
+
+    stream::iter(requests)
+        .buffered(METADATA_FETCH_CONCURRENCY)
+        .collect::<Vec<Option<Message>>>()
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+async fn try_fetch_message_metadata(state: &AppState, msg_id: &str) -> Option<Message> {
+    fetch_message_metadata(state, msg_id).await.ok()
+}
+
+// Cache the list headers (subject, from, date) of a metadata-format message in
+// memory, and in the database when label_ids is given
+pub(crate) async fn cache_message_metadata(
     state: &mut AppState,
-    msg_id: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let message_url = format!(
-        "https://gmail.googleapis.com/gmail/v1/users/me/messages/{}?format=full",
-        msg_id
-    );
+    message: &Message,
+    label_ids: Option<Vec<String>>,
+) {
+    let Some(msg_id) = &message.id else {
+        return;
+    };
+
+    // Extract subject, from, and date from headers if available
+    let mut subject = None;
+    let mut from_addr = None;
+    let mut date_str = None;
+
+    if let Some(payload) = &message.payload {
+        if let Some(headers) = &payload.headers {
+            subject = headers
+                .iter()
+                .find(|h| h.name.as_deref() == Some("Subject"))
+                .and_then(|h| h.value.clone());
+
+            from_addr = headers
+                .iter()
+                .find(|h| h.name.as_deref() == Some("From"))
+                .and_then(|h| h.value.clone());
+
+            date_str = headers
+                .iter()
+                .find(|h| h.name.as_deref() == Some("Date"))
+                .and_then(|h| h.value.clone());
+        }
+    }
 
-    let response = state
-        .client
-        .get(&message_url)
-        .bearer_auth(&state.token)
-        .send()
-        .await?;
+    // Cache headers in memory for immediate display
+    if let (Some(subj), Some(from)) = (&subject, &from_addr) {
+        state
+            .message_headers
+            .insert(msg_id.clone(), (subj.clone(), from.clone()));
+    }
 
-    if response.status().is_success() {
-        let message: Message = response.json().await?;
+    // Cache date separately for formatting
+    if let Some(date) = &date_str {
+        state
+            .message_bodies
+            .insert(format!("{}_date", msg_id), date.clone());
+    }
 
-        // Extract body content
-        let body_text = crate::email_content::extract_plain_text_body(
-            &message
-                .payload
-                .as_ref()
-                .unwrap_or(&crate::types::MessagePart::default()),
-        )
-        .unwrap_or_default();
-        let body_html = crate::email_content::extract_html_body(
-            &message
-                .payload
+    // Save to database cache if available
+    if let (Some(db), Some(label_ids)) = (&state.database, label_ids) {
+        let cached_message = crate::database::CachedMessage {
+            id: msg_id.clone(),
+            thread_id: message.thread_id.clone(),
+            label_ids,
+            snippet: message.snippet.clone(),
+            subject,
+            from_addr,
+            to_addr: None,
+            date_str: date_str.clone(),
+            body_text: None,
+            body_html: None,
+            received_date: chrono::Utc::now(), // This can still be the current time of caching
+            internal_date: date_str
                 .as_ref()
-                .unwrap_or(&crate::types::MessagePart::default()),
-        );
+                .and_then(|s| {
+                    DateTime::parse_from_rfc2822(s)
+                        .ok()
+                        .map(|dt| dt.with_timezone(&Utc))
+                })
+                .unwrap_or_else(chrono::Utc::now), // Use parsed date or current UTC
+            is_unread: message.is_unread(),
+            is_starred: message.is_starred(),
+            cache_timestamp: chrono::Utc::now(),
+        };
+        let _ = db.upsert_message(&cached_message).await;
+    }
+}
 
-        // Extract headers for display
-        let mut subject = "(no subject)".to_string();
-        let mut from = "(unknown sender)".to_string();
-        let mut to = "(unknown recipient)".to_string();
-        let mut date = "(unknown date)".to_string();
+// Fetch a single message in metadata format, limited to the headers the message list shows
+pub async fn fetch_message_metadata(
+    state: &AppState,
+    msg_id: &str,
+) -> Result<Message, Box<dyn std::error::Error>> {
+    let message_url = api_url(
+        state,
+        &format!("messages/{}?{}", msg_id, LIST_METADATA_QUERY),
+    );
 
-        if let Some(payload) = message.payload.as_ref() {
-            if let Some(headers) = &payload.headers {
-                subject = headers
-                    .iter()
-                    .find(|h| h.name.as_deref() == Some("Subject"))
-                    .and_then(|h| h.value.clone())
-                    .unwrap_or(subject);
+    let response = send_authorized(state, |client| client.get(&message_url)).await?;
 
-                from = headers
-                    .iter()
-                    .find(|h| h.name.as_deref() == Some("From"))
-                    .and_then(|h| h.value.clone())
-                    .unwrap_or(from);
+    if response.status().is_success() {
+        Ok(response.json().await?)
+    } else {
+        Err(format!("Failed to fetch message metadata: {}", response.status()).into())
+    }
+}
 
-                to = headers
-                    .iter()
-                    .find(|h| h.name.as_deref() == Some("To"))
-                    .and_then(|h| h.value.clone())
-                    .unwrap_or(to);
+// Cache the body, date and headers of a full-format message in memory and in
+// the database, returning the headers to display
+pub(crate) async fn cache_full_message(
+    state: &mut AppState,
+    msg_id: &str,
+    message: &Message,
+) -> crate::types::MessageHeadersDisplay {
+    // Extract body content
+    let body_text = crate::email_content::extract_plain_text_body(
+        message
+            .payload
+            .as_ref()
+            .unwrap_or(&crate::types::MessagePart::default()),
+    )
+    .unwrap_or_default();
+    let body_html = crate::email_content::extract_html_body(
+        message
+            .payload
+            .as_ref()
+            .unwrap_or(&crate::types::MessagePart::default()),
+    );
 
-                date = headers
-                    .iter()
-                    .find(|h| h.name.as_deref() == Some("Date"))
-                    .and_then(|h| h.value.clone())
-                    .unwrap_or(date);
-            }
+    // Extract headers for display
+    let mut subject = "(no subject)".to_string();
+    let mut from = "(unknown sender)".to_string();
+    let mut to = "(unknown recipient)".to_string();
+    let mut date = "(unknown date)".to_string();
+
+    if let Some(payload) = message.payload.as_ref() {
+        if let Some(headers) = &payload.headers {
+            subject = headers
+                .iter()
+                .find(|h| h.name.as_deref() == Some("Subject"))
+                .and_then(|h| h.value.clone())
+                .unwrap_or(subject);
+
+            from = headers
+                .iter()
+                .find(|h| h.name.as_deref() == Some("From"))
+                .and_then(|h| h.value.clone())
+                .unwrap_or(from);
+
+            to = headers
+                .iter()
+                .find(|h| h.name.as_deref() == Some("To"))
+                .and_then(|h| h.value.clone())
+                .unwrap_or(to);
+
+            date = headers
+                .iter()
+                .find(|h| h.name.as_deref() == Some("Date"))
+                .and_then(|h| h.value.clone())
+                .unwrap_or(date);
         }
+    }
 
-        // Update state with full message body and display headers
-        state
-            .message_bodies
-            .insert(msg_id.to_string(), body_text.clone());
+    // Update state with full message body
+    state
+        .message_bodies
+        .insert(msg_id.to_string(), body_text.clone());
 
-        // Store the original date string for formatting in UI
-        state
-            .message_bodies
-            .insert(format!("{}_date", msg_id), date.clone());
+    if let Some(payload) = message.payload.as_ref() {
+        state.message_attachments.insert(
+            msg_id.to_string(),
+            crate::email_content::extract_attachments(payload),
+        );
+    }
 
-        state.current_message_display_headers = Some(crate::types::MessageHeadersDisplay {
-            subject,
-            from,
-            to,
-            date: date.clone(), // Use the original date string here
-        });
+    // Store the original date string for formatting in UI
+    state
+        .message_bodies
+        .insert(format!("{}_date", msg_id), date.clone());
+
+    let headers = crate::types::MessageHeadersDisplay {
+        subject,
+        from,
+        to,
+        date: date.clone(), // Use the original date string here
+    };
+
+    // Update database cache if available
+    if let Some(db) = &state.database {
+        let cached_message = crate::database::CachedMessage {
+            id: msg_id.to_string(),
+            thread_id: message.thread_id.clone(),
+            label_ids: message.label_ids.clone().unwrap_or_default(),
+            snippet: message.snippet.clone(),
+            subject: Some(headers.subject.clone()),
+            from_addr: Some(headers.from.clone()),
+            to_addr: Some(headers.to.clone()),
+            date_str: Some(date.clone()), // Store the original RFC 2822 date string
+            body_text: Some(body_text.clone()),
+            body_html,
+            received_date: chrono::Utc::now(),
+            internal_date: chrono::Utc::now(), // This will be updated from the actual date header if parsed
+            is_unread: message.is_unread(),
+            is_starred: message.is_starred(),
+            cache_timestamp: chrono::Utc::now(),
+        };
+        let _ = db.upsert_message(&cached_message).await;
+    }
 
-        // Update database cache if available
-        if let Some(db) = &state.database {
-            let cached_message = crate::database::CachedMessage {
-                id: msg_id.to_string(),
-                thread_id: message.thread_id.clone(),
-                label_ids: message.label_ids.clone().unwrap_or_default(),
-                snippet: message.snippet.clone(),
-                subject: state
-                    .current_message_display_headers
-                    .as_ref()
-                    .map(|h| h.subject.clone()),
-                from_addr: state
-                    .current_message_display_headers
-                    .as_ref()
-                    .map(|h| h.from.clone()),
-                to_addr: state
-                    .current_message_display_headers
-                    .as_ref()
-                    .map(|h| h.to.clone()),
-                date_str: Some(date.clone()), // Store the original RFC 2822 date string
-                body_text: Some(body_text.clone()),
-                body_html: body_html,
-                received_date: chrono::Utc::now(),
-                internal_date: chrono::Utc::now(), // This will be updated from the actual date header if parsed
-                is_unread: false,
-                is_starred: false,
-                cache_timestamp: chrono::Utc::now(),
-            };
-            let _ = db.upsert_message(&cached_message).await;
-        }
+    headers
+}
+
+// Fetch a message in full format without caching it
+pub async fn fetch_message(
+    state: &AppState,
+    msg_id: &str,
+) -> Result<Message, Box<dyn std::error::Error>> {
+    let message_url = api_url(state, &format!("messages/{}?format=full", msg_id));
 
-        Ok(())
+    let response = send_authorized(state, |client| client.get(&message_url)).await?;
+
+    if response.status().is_success() {
+        Ok(response.json().await?)
     } else {
         Err(format!("Failed to fetch full message: {}", response.status()).into())
     }
 }
 
-// Load more messages when scrolling near the end
+// Fetch a message as it was received, for forwarding it as an attachment
+pub async fn fetch_raw_message(
+    state: &AppState,
+    msg_id: &str,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let message_url = api_url(state, &format!("messages/{}?format=raw", msg_id));
+
+    let response = send_authorized(state, |client| client.get(&message_url)).await?;
+
+    if response.status().is_success() {
+        let message: RawMessage = response.json().await?;
+        let raw = message.raw.ok_or("Gmail returned no raw message")?;
+        super::attachments::decode_attachment_data(&raw)
+    } else {
+        Err(format!("Failed to fetch raw message: {}", response.status()).into())
+    }
+}
+
+// Helper function to fetch full message content and headers
+pub async fn fetch_full_message(
+    state: &mut AppState,
+    msg_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let message = fetch_message(state, msg_id).await?;
+    let headers = cache_full_message(state, msg_id, &message).await;
+    state.current_message_display_headers = Some(headers);
+    Ok(())
+}
+
+// Load more messages when scrolling near the end by following the label's nextPageToken
 pub async fn load_more_messages(state: &mut AppState) {
-    let current_count = state.messages.len();
-    let batch_size = state.messages_per_screen;
+    let Some(label_id) = state
+        .labels
+        .get(state.selected_label)
+        .and_then(|label| label.id.clone())
+    else {
+        return;
+    };
+    if is_unified_inbox(&label_id) || is_outbox_label(&label_id) {
+        return;
+    }
+
+    // Fall back to the token persisted by a previous session
+    if !state.label_page_tokens.contains_key(&label_id) {
+        if let Some(db) = &state.database {
+            if let Ok(token) = db.get_page_token(&label_id).await {
+                state.label_page_tokens.insert(label_id.clone(), token);
+            }
+        }
+    }
+
+    // No token means the end of the list has been reached
+    let Some(page_token) = state.label_page_tokens.get(&label_id).cloned().flatten() else {
+        return;
+    };
 
-    if let Some(more_messages) = fetch_messages_for_label_index_paginated(
+    let batch_size = state.messages_per_screen;
+    if let Some((more_messages, next_page_token)) = fetch_messages_for_label_index_paginated(
         state,
         state.selected_label,
-        current_count,
+        Some(&page_token),
         batch_size,
     )
     .await
     {
-        if !more_messages.is_empty() {
-            state.messages.extend(more_messages.clone());
+        // The visible list may have come from the cache, so skip anything already shown
+        let new_messages: Vec<Message> = more_messages
+            .into_iter()
+            .filter(|message| {
+                !state
+                    .messages
+                    .iter()
+                    .any(|shown| shown.id.is_some() && shown.id == message.id)
+            })
+            .collect();
+
+        for message in &new_messages {
+            let label_ids = labels_to_cache(&label_id, message);
+            cache_message_metadata(state, message, Some(label_ids)).await;
+        }
+
+        if let Some(db) = &state.database {
+            let _ = db
+                .update_page_token(&label_id, next_page_token.as_deref())
+                .await;
+        }
+        state
+            .label_page_tokens
+            .insert(label_id.clone(), next_page_token);
+
+        if !new_messages.is_empty() {
+            state.messages.extend(new_messages);
             // Update cache with new messages
-            if let Some(label) = state.labels.get(state.selected_label) {
-                if let Some(label_id) = &label.id {
-                    state
-                        .label_messages_cache
-                        .insert(label_id.clone(), state.messages.clone());
-                }
+            state
+                .label_messages_cache
+                .insert(label_id, state.messages.clone());
+        }
+    }
+}
+
+// Ids of every message with label `label_id`, going through all pages
+pub async fn list_message_ids(
+    state: &AppState,
+    label_id: &str,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut ids = Vec::new();
+    let mut page_token: Option<String> = None;
+    loop {
+        let mut messages_url = reqwest::Url::parse(&api_url(state, "messages"))?;
+        {
+            let mut query = messages_url.query_pairs_mut();
+            query.append_pair("labelIds", label_id);
+            query.append_pair("maxResults", "500");
+            if let Some(token) = &page_token {
+                query.append_pair("pageToken", token);
             }
         }
+        let response = send_authorized(state, |client| client.get(messages_url.clone())).await?;
+
+        if !response.status().is_success() {
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(format!("Failed to list messages: {}", error_text).into());
+        }
+
+        let page: MessagesResponse = response.json().await?;
+        ids.extend(
+            page.messages
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|msg_ref| msg_ref.id),
+        );
+        match page.next_page_token {
+            Some(token) => page_token = Some(token),
+            None => return Ok(ids),
+        }
     }
 }
 
-// Helper function to fetch messages for a specific label index with pagination
+// Helper function to fetch one page of messages for a specific label index.
+// Returns the messages together with the token of the following page, if any.
 async fn fetch_messages_for_label_index_paginated(
     state: &AppState,
     label_index: usize,
-    offset: usize,
+    page_token: Option<&str>,
     limit: usize,
-) -> Option<Vec<Message>> {
+) -> Option<LabelPage> {
     if let Some(label) = state.labels.get(label_index) {
         let label_id = label.id.as_deref().unwrap_or("");
 
-        // For "All Mail", don't include labelIds parameter to get all messages
-        let messages_url = if label_id.to_uppercase() == "ALLMAIL" {
-            format!(
-                "https://gmail.googleapis.com/gmail/v1/users/me/messages?maxResults={}&orderBy=date_desc",
-                limit + offset
-            )
-        } else {
-            format!(
-                "https://gmail.googleapis.com/gmail/v1/users/me/messages?labelIds={}&maxResults={}&orderBy=date_desc",
-                label_id,
-                limit + offset
-            )
+        let Ok(mut messages_url) = reqwest::Url::parse(&api_url(state, "messages")) else {
+            return None;
         };
-
-        match state
-            .client
-            .get(&messages_url)
-            .bearer_auth(&state.token)
-            .send()
-            .await
         {
+            let mut query = messages_url.query_pairs_mut();
+            if is_search_label(label_id) {
+                // Search results use the Gmail query syntax instead of a label filter
+                query.append_pair("q", state.search_query.as_deref().unwrap_or(""));
+            } else if label_id.to_uppercase() != "ALLMAIL" {
+                // For "All Mail", don't include labelIds parameter to get all messages
+                query.append_pair("labelIds", label_id);
+            }
+            query.append_pair("maxResults", &limit.to_string());
+            if let Some(token) = page_token {
+                query.append_pair("pageToken", token);
+            }
+        }
+
+        match send_authorized(state, |client| client.get(messages_url.clone())).await {
             Ok(response) => {
                 if response.status().is_success() {
                     if let Ok(messages_data) = response.json::<MessagesResponse>().await {
-                        let message_refs = messages_data.messages.unwrap_or_default();
-                        let mut messages = Vec::new();
-
-                        // Skip messages we already have (offset) and take only what we need
-                        for msg_ref in message_refs.iter().skip(offset).take(limit) {
-                            if let Some(id) = &msg_ref.id {
-                                // Use metadata format to get headers (subject, from) immediately
-                                let message_url = format!(
-                                    "https://gmail.googleapis.com/gmail/v1/users/me/messages/{}?format=metadata",
-                                    id
-                                );
-
-                                if let Ok(msg_response) = state
-                                    .client
-                                    .get(&message_url)
-                                    .bearer_auth(&state.token)
-                                    .send()
-                                    .await
-                                {
-                                    if msg_response.status().is_success() {
-                                        if let Ok(message) = msg_response.json::<Message>().await {
-                                            messages.push(message);
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        return Some(messages);
+                        let ids: Vec<String> = messages_data
+                            .messages
+                            .unwrap_or_default()
+                            .iter()
+                            .filter_map(|msg_ref| msg_ref.id.clone())
+                            .collect();
+                        let messages = fetch_messages_metadata(state, &ids).await;
+                        return Some((messages, messages_data.next_page_token));
                     }
                 }
             }
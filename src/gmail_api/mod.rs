@@ -1,21 +1,48 @@
 //! Gmail API module split into logical submodules
 //!
 //! This module provides all Gmail API functionality organized into:
+//! - attachments: Attachment downloads
 //! - auth: Authentication and keyring operations
-//! - labels: Label fetching operations
+//! - client: Authorized request sending with transparent token refresh
+//! - drafts: Saving, finding and sending drafts
+//! - filters: Listing, creating and deleting server-side filters
+//! - history: Mailbox profile and history (incremental sync) operations
+//! - labels: Label fetching, creation, renaming and deletion
 //! - messages: Message fetching and loading
-//! - operations: Message actions (send, archive, delete)
+//! - operations: Message actions (send, archive, delete, untrash, delete forever, read/unread,
+//!   star, labels)
+//! - threads: Conversation fetching and whole-thread actions
 
+pub mod attachments;
 pub mod auth;
+pub mod client;
+pub mod drafts;
+pub mod filters;
+pub mod history;
 pub mod labels;
 pub mod messages;
 pub mod operations;
+pub mod threads;
 
 // Re-export commonly used functions for backwards compatibility
 pub use auth::try_authenticate;
-pub use labels::fetch_labels;
-pub use messages::{fetch_full_message, fetch_messages_for_label, load_more_messages};
-pub use operations::{archive_message, delete_message, send_email, spam_message};
+pub use drafts::{find_draft, save_draft, send_draft, send_raw_draft};
+pub use filters::{create_filter, delete_filter, fetch_filters};
+pub use history::{fetch_history, fetch_profile};
+pub use labels::{create_label, delete_label, fetch_labels, rename_label};
+pub use messages::{
+    fetch_full_message, fetch_message, fetch_messages_for_label, fetch_raw_message,
+    list_message_ids, load_more_messages, refresh_current_label,
+};
+pub use operations::{
+    add_label, archive_message, batch_delete, batch_modify, change_labels, delete_message,
+    delete_message_forever, mark_read, mark_unread, remove_label, send_email, send_raw_email,
+    spam_message, star, unstar, untrash_message,
+};
+pub use threads::{
+    archive_thread, delete_thread, fetch_thread, mark_thread_read, modify_thread, spam_thread,
+    untrash_thread,
+};
 
 // Re-export auth constants
 pub use auth::{KEYRING_SERVICE_NAME, KEYRING_USERNAME};
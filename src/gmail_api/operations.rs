@@ -1,61 +1,174 @@
+use super::client::{api_url, send_authorized, upload_url};
+use crate::mime::OutgoingMessage;
 use crate::state::AppState;
+use crate::types::Message;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::engine::Engine;
+use reqwest::Method;
+use serde_json::{json, Value};
 
-// Send email using Gmail API
-pub async fn send_email(
-    state: &AppState,
-    to: &str,
-    cc: &str,
-    bcc: &str,
-    subject: &str,
-    body: &str,
+// Messages whose base64url encoding is larger than this go through the
+// resumable upload endpoint; the JSON `raw` field of a plain messages.send
+// request is limited to a few MB
+pub const SIMPLE_SEND_LIMIT: usize = 5 * 1024 * 1024;
+
+// Gmail rejects messages whose attachments add up to more than this
+pub const MAX_ATTACHMENTS_SIZE: usize = 25 * 1024 * 1024;
+
+pub(crate) fn check_attachments_size(
+    message: &OutgoingMessage,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
-    use base64::engine::Engine;
+    let attachments_size: usize = message.attachments.iter().map(|a| a.content.len()).sum();
+    if attachments_size > MAX_ATTACHMENTS_SIZE {
+        return Err(format!(
+            "Attachments add up to {} MB, Gmail accepts at most {} MB",
+            attachments_size / (1024 * 1024),
+            MAX_ATTACHMENTS_SIZE / (1024 * 1024)
+        )
+        .into());
+    }
+    Ok(())
+}
 
-    // Create email message in RFC 2822 format
-    let mut email_content = String::new();
+// A Message resource for the raw message `raw`, in conversation `thread_id`;
+// without `raw` it is the metadata of a resumable upload
+pub(crate) fn message_resource(raw: Option<&str>, thread_id: Option<&str>) -> Value {
+    let mut resource = json!({});
+    if let Some(raw) = raw {
+        resource["raw"] = json!(raw);
+    }
+    if let Some(thread_id) = thread_id {
+        resource["threadId"] = json!(thread_id);
+    }
+    resource
+}
 
-    // Add headers
-    email_content.push_str(&format!("To: {}\r\n", to));
-    if !cc.is_empty() {
-        email_content.push_str(&format!("Cc: {}\r\n", cc));
+// Length of `len` bytes once encoded as unpadded base64url
+fn base64_len(len: usize) -> usize {
+    (len * 4).div_ceil(3)
+}
+
+// Upload `message` to `path`, messages/send or a drafts endpoint, and return
+// the response. `resource(raw)` builds the request body around the base64url
+// message; it is called with None for the metadata of a resumable upload,
+// which is used for messages too large for the `raw` field.
+pub(crate) async fn upload_message(
+    state: &AppState,
+    method: Method,
+    path: &str,
+    message: String,
+    resource: impl Fn(Option<&str>) -> Value,
+    action: &str,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    if base64_len(message.len()) > SIMPLE_SEND_LIMIT {
+        return upload_resumable(
+            state,
+            method,
+            path,
+            message.into_bytes(),
+            resource(None),
+            action,
+        )
+        .await;
     }
-    if !bcc.is_empty() {
-        email_content.push_str(&format!("Bcc: {}\r\n", bcc));
+
+    let request_body = resource(Some(&URL_SAFE_NO_PAD.encode(message.as_bytes())));
+    let url = api_url(state, path);
+    let response = send_authorized(state, |client| {
+        client.request(method.clone(), &url).json(&request_body)
+    })
+    .await?;
+
+    if response.status().is_success() {
+        Ok(response.json().await.unwrap_or(Value::Null))
+    } else {
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        Err(format!("Failed to {}: {}", action, error_text).into())
     }
-    email_content.push_str(&format!("Subject: {}\r\n", subject));
-    email_content.push_str("Content-Type: text/plain; charset=utf-8\r\n");
-    email_content.push_str("\r\n");
+}
+
+// Send email using Gmail API, as part of the conversation `thread_id` if given
+pub async fn send_email(
+    state: &AppState,
+    message: &OutgoingMessage,
+    thread_id: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    check_attachments_size(message)?;
+    send_raw_email(state, message.build(), thread_id).await
+}
 
-    // Add body
-    email_content.push_str(body);
+// Send an already built message, e.g. one that waited in the outbox
+pub async fn send_raw_email(
+    state: &AppState,
+    raw: String,
+    thread_id: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    upload_message(
+        state,
+        Method::POST,
+        "messages/send",
+        raw,
+        |raw| message_resource(raw, thread_id),
+        "send email",
+    )
+    .await?;
+    Ok(())
+}
 
-    // Encode the email content in base64
-    let encoded_email = URL_SAFE_NO_PAD.encode(email_content.as_bytes());
+// Upload a large message with a resumable upload: the first request opens an
+// upload session with the metadata, the second one sends the message to the
+// session URL
+async fn upload_resumable(
+    state: &AppState,
+    method: Method,
+    path: &str,
+    message: Vec<u8>,
+    metadata: Value,
+    action: &str,
+) -> Result<Value, Box<dyn std::error::Error>> {
+    let start_url = upload_url(state, &format!("{}?uploadType=resumable", path));
+    let response = send_authorized(state, |client| {
+        client
+            .request(method.clone(), &start_url)
+            .header("X-Upload-Content-Type", "message/rfc822")
+            .header("X-Upload-Content-Length", message.len())
+            .json(&metadata)
+    })
+    .await?;
 
-    // Create the request body
-    let request_body = serde_json::json!({
-        "raw": encoded_email
-    });
+    if !response.status().is_success() {
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        return Err(format!("Failed to start upload: {}", error_text).into());
+    }
+    let session_url = response
+        .headers()
+        .get(reqwest::header::LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .ok_or("Gmail did not return an upload session")?
+        .to_string();
 
-    // Send the email
-    let send_url = "https://gmail.googleapis.com/gmail/v1/users/me/messages/send";
-    let response = state
-        .client
-        .post(send_url)
-        .bearer_auth(&state.token)
-        .json(&request_body)
-        .send()
-        .await?;
+    let response = send_authorized(state, |client| {
+        client
+            .put(&session_url)
+            .header(reqwest::header::CONTENT_TYPE, "message/rfc822")
+            .body(message.clone())
+    })
+    .await?;
 
     if response.status().is_success() {
-        Ok(())
+        Ok(response.json().await.unwrap_or(Value::Null))
     } else {
         let error_text = response
             .text()
             .await
             .unwrap_or_else(|_| "Unknown error".to_string());
-        Err(format!("Failed to send email: {}", error_text).into())
+        Err(format!("Failed to {}: {}", action, error_text).into())
     }
 }
 
@@ -64,22 +177,14 @@ pub async fn archive_message(
     state: &AppState,
     message_id: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let modify_url = format!(
-        "https://gmail.googleapis.com/gmail/v1/users/me/messages/{}/modify",
-        message_id
-    );
+    let modify_url = api_url(state, &format!("messages/{}/modify", message_id));
 
     let request_body = serde_json::json!({
         "removeLabelIds": ["INBOX"]
     });
 
-    let response = state
-        .client
-        .post(&modify_url)
-        .bearer_auth(&state.token)
-        .json(&request_body)
-        .send()
-        .await?;
+    let response =
+        send_authorized(state, |client| client.post(&modify_url).json(&request_body)).await?;
 
     if response.status().is_success() {
         Ok(())
@@ -97,17 +202,9 @@ pub async fn delete_message(
     state: &AppState,
     message_id: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let trash_url = format!(
-        "https://gmail.googleapis.com/gmail/v1/users/me/messages/{}/trash",
-        message_id
-    );
-
-    let response = state
-        .client
-        .post(&trash_url)
-        .bearer_auth(&state.token)
-        .send()
-        .await?;
+    let trash_url = api_url(state, &format!("messages/{}/trash", message_id));
+
+    let response = send_authorized(state, |client| client.post(&trash_url)).await?;
 
     if response.status().is_success() {
         Ok(())
@@ -125,23 +222,15 @@ pub async fn spam_message(
     state: &AppState,
     message_id: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let modify_url = format!(
-        "https://gmail.googleapis.com/gmail/v1/users/me/messages/{}/modify",
-        message_id
-    );
+    let modify_url = api_url(state, &format!("messages/{}/modify", message_id));
 
     let request_body = serde_json::json!({
         "addLabelIds": ["SPAM"],
         "removeLabelIds": ["INBOX"]
     });
 
-    let response = state
-        .client
-        .post(&modify_url)
-        .bearer_auth(&state.token)
-        .json(&request_body)
-        .send()
-        .await?;
+    let response =
+        send_authorized(state, |client| client.post(&modify_url).json(&request_body)).await?;
 
     if response.status().is_success() {
         Ok(())
@@ -153,3 +242,192 @@ pub async fn spam_message(
         Err(format!("Failed to mark message as spam: {}", error_text).into())
     }
 }
+
+// Take a message out of the trash, returning it with the labels it has now
+pub async fn untrash_message(
+    state: &AppState,
+    message_id: &str,
+) -> Result<Message, Box<dyn std::error::Error>> {
+    let untrash_url = api_url(state, &format!("messages/{}/untrash", message_id));
+
+    let response = send_authorized(state, |client| client.post(&untrash_url)).await?;
+
+    if response.status().is_success() {
+        Ok(response.json().await?)
+    } else {
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        Err(format!("Failed to restore message: {}", error_text).into())
+    }
+}
+
+// Delete a message for good, skipping the trash
+pub async fn delete_message_forever(
+    state: &AppState,
+    message_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let message_url = api_url(state, &format!("messages/{}", message_id));
+
+    let response = send_authorized(state, |client| client.delete(&message_url)).await?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        Err(format!("Failed to delete message forever: {}", error_text).into())
+    }
+}
+
+// Delete many messages for good with messages.batchDelete, in one call for
+// up to 1000 messages
+pub async fn batch_delete(
+    state: &AppState,
+    message_ids: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let batch_url = api_url(state, "messages/batchDelete");
+
+    for ids in message_ids.chunks(BATCH_MODIFY_LIMIT) {
+        let request_body = serde_json::json!({ "ids": ids });
+
+        let response =
+            send_authorized(state, |client| client.post(&batch_url).json(&request_body)).await?;
+
+        if !response.status().is_success() {
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(format!("Failed to delete messages forever: {}", error_text).into());
+        }
+    }
+    Ok(())
+}
+
+// Most message ids a single messages.batchModify or batchDelete call accepts
+const BATCH_MODIFY_LIMIT: usize = 1000;
+
+// Add and remove labels of many messages with messages.batchModify, in one
+// call for up to 1000 messages
+pub async fn batch_modify(
+    state: &AppState,
+    message_ids: &[String],
+    add: &[String],
+    remove: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let batch_url = api_url(state, "messages/batchModify");
+
+    for ids in message_ids.chunks(BATCH_MODIFY_LIMIT) {
+        let request_body = serde_json::json!({
+            "ids": ids,
+            "addLabelIds": add,
+            "removeLabelIds": remove
+        });
+
+        let response =
+            send_authorized(state, |client| client.post(&batch_url).json(&request_body)).await?;
+
+        if !response.status().is_success() {
+            let error_text = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(format!("Failed to change labels of messages: {}", error_text).into());
+        }
+    }
+    Ok(())
+}
+
+// Add and remove labels of a message; `action` names the operation in errors
+async fn modify_message_labels(
+    state: &AppState,
+    message_id: &str,
+    add: &[&str],
+    remove: &[&str],
+    action: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let modify_url = api_url(state, &format!("messages/{}/modify", message_id));
+
+    let request_body = serde_json::json!({
+        "addLabelIds": add,
+        "removeLabelIds": remove
+    });
+
+    let response =
+        send_authorized(state, |client| client.post(&modify_url).json(&request_body)).await?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        Err(format!("Failed to {}: {}", action, error_text).into())
+    }
+}
+
+// Mark a message as read by removing the UNREAD label
+pub async fn mark_read(
+    state: &AppState,
+    message_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    modify_message_labels(state, message_id, &[], &["UNREAD"], "mark message as read").await
+}
+
+// Mark a message as unread by adding the UNREAD label
+pub async fn mark_unread(
+    state: &AppState,
+    message_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    modify_message_labels(
+        state,
+        message_id,
+        &["UNREAD"],
+        &[],
+        "mark message as unread",
+    )
+    .await
+}
+
+// Star a message by adding the STARRED label
+pub async fn star(state: &AppState, message_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    modify_message_labels(state, message_id, &["STARRED"], &[], "star message").await
+}
+
+// Unstar a message by removing the STARRED label
+pub async fn unstar(state: &AppState, message_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    modify_message_labels(state, message_id, &[], &["STARRED"], "unstar message").await
+}
+
+// Apply a label to a message
+pub async fn add_label(
+    state: &AppState,
+    message_id: &str,
+    label_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    modify_message_labels(state, message_id, &[label_id], &[], "apply label").await
+}
+
+// Remove a label from a message
+pub async fn remove_label(
+    state: &AppState,
+    message_id: &str,
+    label_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    modify_message_labels(state, message_id, &[], &[label_id], "remove label").await
+}
+
+// Add and remove several labels of a message in one call
+pub async fn change_labels(
+    state: &AppState,
+    message_id: &str,
+    add: &[&str],
+    remove: &[&str],
+) -> Result<(), Box<dyn std::error::Error>> {
+    modify_message_labels(state, message_id, add, remove, "change labels").await
+}
@@ -0,0 +1,183 @@
+use super::client::{api_url, send_authorized};
+use super::messages::cache_full_message;
+use crate::state::{AppState, ThreadMessage};
+use crate::types::Thread;
+
+// Fetch a whole conversation with threads.get and open it in the content pane
+pub async fn fetch_thread(
+    state: &mut AppState,
+    thread_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let thread_url = api_url(state, &format!("threads/{}?format=full", thread_id));
+
+    let response = send_authorized(state, |client| client.get(&thread_url)).await?;
+
+    if response.status().is_success() {
+        let thread: Thread = response.json().await?;
+
+        let mut messages = Vec::new();
+        for message in thread.messages.unwrap_or_default() {
+            let Some(msg_id) = message.id.clone() else {
+                continue;
+            };
+            let headers = cache_full_message(state, &msg_id, &message).await;
+            messages.push(ThreadMessage {
+                id: msg_id,
+                headers,
+                snippet: message.snippet.clone().unwrap_or_default(),
+                expanded: false,
+            });
+        }
+
+        // The header panel shows the latest message, like the message list does
+        state.current_message_display_headers = messages.last().map(|m| m.headers.clone());
+        state.open_thread(thread_id, messages);
+        Ok(())
+    } else {
+        Err(format!("Failed to fetch thread: {}", response.status()).into())
+    }
+}
+
+// Archive a whole conversation by removing the INBOX label from all its messages
+pub async fn archive_thread(
+    state: &AppState,
+    thread_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let modify_url = api_url(state, &format!("threads/{}/modify", thread_id));
+
+    let request_body = serde_json::json!({
+        "removeLabelIds": ["INBOX"]
+    });
+
+    let response =
+        send_authorized(state, |client| client.post(&modify_url).json(&request_body)).await?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        Err(format!("Failed to archive conversation: {}", error_text).into())
+    }
+}
+
+// Delete a whole conversation by moving it to trash
+pub async fn delete_thread(
+    state: &AppState,
+    thread_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let trash_url = api_url(state, &format!("threads/{}/trash", thread_id));
+
+    let response = send_authorized(state, |client| client.post(&trash_url)).await?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        Err(format!("Failed to delete conversation: {}", error_text).into())
+    }
+}
+
+// Mark a whole conversation as spam by adding the SPAM label and removing INBOX
+pub async fn spam_thread(
+    state: &AppState,
+    thread_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let modify_url = api_url(state, &format!("threads/{}/modify", thread_id));
+
+    let request_body = serde_json::json!({
+        "addLabelIds": ["SPAM"],
+        "removeLabelIds": ["INBOX"]
+    });
+
+    let response =
+        send_authorized(state, |client| client.post(&modify_url).json(&request_body)).await?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        Err(format!("Failed to mark conversation as spam: {}", error_text).into())
+    }
+}
+
+// Mark every message of a conversation as read by removing the UNREAD label
+pub async fn mark_thread_read(
+    state: &AppState,
+    thread_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let modify_url = api_url(state, &format!("threads/{}/modify", thread_id));
+
+    let request_body = serde_json::json!({
+        "removeLabelIds": ["UNREAD"]
+    });
+
+    let response =
+        send_authorized(state, |client| client.post(&modify_url).json(&request_body)).await?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        Err(format!("Failed to mark conversation as read: {}", error_text).into())
+    }
+}
+
+// Take a conversation out of Trash
+pub async fn untrash_thread(
+    state: &AppState,
+    thread_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let untrash_url = api_url(state, &format!("threads/{}/untrash", thread_id));
+
+    let response = send_authorized(state, |client| client.post(&untrash_url)).await?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        Err(format!("Failed to restore conversation: {}", error_text).into())
+    }
+}
+
+// Add and remove labels on every message of a conversation
+pub async fn modify_thread(
+    state: &AppState,
+    thread_id: &str,
+    add: &[&str],
+    remove: &[&str],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let modify_url = api_url(state, &format!("threads/{}/modify", thread_id));
+
+    let request_body = serde_json::json!({
+        "addLabelIds": add,
+        "removeLabelIds": remove
+    });
+
+    let response =
+        send_authorized(state, |client| client.post(&modify_url).json(&request_body)).await?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        let error_text = response
+            .text()
+            .await
+            .unwrap_or_else(|_| "Unknown error".to_string());
+        Err(format!("Failed to change conversation labels: {}", error_text).into())
+    }
+}
@@ -1,3 +1,4 @@
+pub mod accounts;
 pub mod app;
 pub mod background_tasks;
 pub mod cli;
@@ -5,7 +6,9 @@ pub mod database;
 pub mod email_content;
 pub mod event_handler;
 pub mod gmail_api;
+pub mod mime;
 pub mod notifications;
+pub mod outbox;
 pub mod state;
 pub mod sync;
 pub mod terminal;
@@ -1,3 +1,4 @@
+mod accounts;
 mod app;
 mod background_tasks;
 mod cli;
@@ -5,13 +6,16 @@ mod database;
 mod email_content;
 mod event_handler;
 mod gmail_api;
+mod mime;
 mod notifications;
+mod outbox;
 mod state;
 mod sync;
 mod terminal;
 mod types;
 mod ui;
 
+use accounts::validate_account_name;
 use app::{draw_loading_screens, initialize_app, run_app_loop};
 use clap::Parser;
 use cli::{handle_keyring_clear, Cli};
@@ -22,8 +26,10 @@ use types::LoadingStage;
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
+    validate_account_name(&cli.account)?;
+
     if cli.clear_keyring {
-        handle_keyring_clear()?;
+        handle_keyring_clear(&cli.account)?;
         return Ok(());
     }
 
@@ -33,7 +39,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     draw_loading_screens(&mut terminal, LoadingStage::Authenticating)?;
 
     // Initialize the application (authentication, database, notifications, labels)
-    let (state_arc, notification_rx) = match initialize_app().await {
+    let (state_arc, notification_rx) = match initialize_app(&cli.account, &cli.api_base_url).await {
         Ok((state, rx)) => (state, rx),
         Err(e) => {
             cleanup_terminal(&mut terminal)?;
@@ -45,6 +51,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
+    if let Some(download_dir) = cli.download_dir {
+        state_arc.write().await.download_dir = download_dir;
+    }
+
     // Show loading screen for labels
     draw_loading_screens(&mut terminal, LoadingStage::FetchingLabels)?;
 
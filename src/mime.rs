@@ -0,0 +1,875 @@
+//! Building outgoing messages
+//!
+//! Every message tuimail sends is built here, so that all of them carry Date,
+//! Message-ID and MIME-Version headers, RFC 2047-encode non-ASCII header text
+//! and use a transfer encoding that keeps the body intact on 7-bit transports:
+//! plain 7bit when the text allows it, quoted-printable otherwise. The subject,
+//! threading headers and quote of replies are derived here as well.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::engine::Engine;
+use chrono::{DateTime, FixedOffset, Local};
+use std::path::Path;
+
+// Encoded body lines and, where possible, header lines stay within this
+const MAX_LINE_LENGTH: usize = 76;
+
+// An encoded-word may be at most 75 characters long; "=?UTF-8?B?" and "?="
+// take 12 of them, which leaves 60 base64 characters for 45 bytes of text
+const ENCODED_WORD_MAX_BYTES: usize = 45;
+
+// Domain of generated Message-IDs when the sender is not known
+const FALLBACK_MESSAGE_ID_DOMAIN: &str = "tuimail.local";
+
+// A file to send along with a message
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutgoingAttachment {
+    pub filename: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+}
+
+impl OutgoingAttachment {
+    pub fn from_path(path: &Path) -> std::io::Result<Self> {
+        let content = std::fs::read(path)?;
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "attachment".to_string());
+        Ok(Self {
+            content_type: detect_content_type(&filename, &content).to_string(),
+            filename,
+            content,
+        })
+    }
+}
+
+// Guess the MIME type of a file from its extension, or else from its first bytes
+pub fn detect_content_type(filename: &str, content: &[u8]) -> &'static str {
+    let extension = filename
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase())
+        .unwrap_or_default();
+    let by_extension = match extension.as_str() {
+        "txt" | "log" => "text/plain",
+        "csv" => "text/csv",
+        "htm" | "html" => "text/html",
+        "md" => "text/markdown",
+        "ics" => "text/calendar",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "doc" => "application/msword",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xls" => "application/vnd.ms-excel",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "ppt" => "application/vnd.ms-powerpoint",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "odt" => "application/vnd.oasis.opendocument.text",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "eml" => "message/rfc822",
+        _ => "",
+    };
+    if !by_extension.is_empty() {
+        return by_extension;
+    }
+
+    const SIGNATURES: [(&[u8], &str); 6] = [
+        (b"%PDF-", "application/pdf"),
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF8", "image/gif"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+    ];
+    if let Some((_, content_type)) = SIGNATURES
+        .iter()
+        .find(|(signature, _)| content.starts_with(signature))
+    {
+        return content_type;
+    }
+    if std::str::from_utf8(content).is_ok() {
+        "text/plain"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+// A message to send. Address fields take comma separated lists like
+// "Jürgen Müller <jm@example.com>, bob@example.com"; empty fields are left out.
+#[derive(Debug, Clone, Default)]
+pub struct OutgoingMessage {
+    pub from: String,
+    pub to: String,
+    pub cc: String,
+    pub bcc: String,
+    pub subject: String,
+    pub body: String,
+    pub attachments: Vec<OutgoingAttachment>,
+    // Generated from the sender's domain when not set
+    pub message_id: Option<String>,
+    // Message-ID of the message this one answers
+    pub in_reply_to: Option<String>,
+    // Message-IDs of the conversation so far, oldest first
+    pub references: Vec<String>,
+    // Time of building when not set
+    pub date: Option<DateTime<FixedOffset>>,
+}
+
+impl OutgoingMessage {
+    // Render the message in RFC 5322 format with CRLF line endings
+    pub fn build(&self) -> String {
+        let mut message = String::new();
+
+        for (name, addresses) in [
+            ("From", &self.from),
+            ("To", &self.to),
+            ("Cc", &self.cc),
+            ("Bcc", &self.bcc),
+        ] {
+            if !addresses.trim().is_empty() {
+                message.push_str(&header(name, &encode_address_list(addresses)));
+            }
+        }
+        message.push_str(&header("Subject", &encode_header_text(&self.subject)));
+        let date = self
+            .date
+            .unwrap_or_else(|| Local::now().fixed_offset())
+            .to_rfc2822();
+        message.push_str(&header("Date", &date));
+        let message_id = self
+            .message_id
+            .clone()
+            .unwrap_or_else(|| generate_message_id(&self.from));
+        message.push_str(&header("Message-ID", &message_id));
+        if let Some(in_reply_to) = &self.in_reply_to {
+            message.push_str(&header("In-Reply-To", in_reply_to));
+        }
+        if !self.references.is_empty() {
+            message.push_str(&header("References", &self.references.join(" ")));
+        }
+        message.push_str("MIME-Version: 1.0\r\n");
+
+        if self.attachments.is_empty() {
+            message.push_str(&text_part(&self.body));
+            return message;
+        }
+
+        let boundary = format!("tuimail-{}", uuid::Uuid::new_v4().simple());
+        message.push_str(&format!(
+            "Content-Type: multipart/mixed; boundary=\"{}\"\r\n",
+            boundary
+        ));
+        message.push_str("\r\n");
+
+        message.push_str(&format!("--{}\r\n", boundary));
+        message.push_str(&text_part(&self.body));
+        message.push_str("\r\n");
+        for attachment in &self.attachments {
+            message.push_str(&format!("--{}\r\n", boundary));
+            message.push_str(&attachment_part(attachment));
+        }
+        message.push_str(&format!("--{}--\r\n", boundary));
+
+        message
+    }
+}
+
+// Subject of a reply: "Re: " is added once, never stacked
+pub fn reply_subject(subject: &str) -> String {
+    let subject = subject.trim();
+    let has_prefix = subject
+        .get(..3)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("re:"));
+    if has_prefix {
+        subject.to_string()
+    } else {
+        format!("Re: {}", subject)
+    }
+}
+
+// The msg-ids ("<...>") in a References or In-Reply-To header
+pub fn parse_message_ids(value: &str) -> Vec<String> {
+    value
+        .split('<')
+        .skip(1)
+        .filter_map(|rest| rest.split_once('>'))
+        .map(|(id, _)| format!("<{}>", id.trim()))
+        .collect()
+}
+
+// References of a reply (RFC 5322 3.6.4): those of the original message, or
+// its In-Reply-To when it has none, followed by its own Message-ID
+pub fn reply_references(
+    references: Option<&str>,
+    in_reply_to: Option<&str>,
+    message_id: &str,
+) -> Vec<String> {
+    let mut ids = parse_message_ids(references.unwrap_or_default());
+    if ids.is_empty() {
+        ids = parse_message_ids(in_reply_to.unwrap_or_default());
+    }
+    ids.extend(parse_message_ids(message_id));
+    ids
+}
+
+// Quote of the original message below a reply, with an
+// "On <date>, <sender> wrote:" attribution
+pub fn quote_reply(date: &str, sender: &str, body: &str) -> String {
+    // Dates may end in a comment like "(UTC)", which chrono does not accept
+    let date = date.split(" (").next().unwrap_or(date).trim();
+    let date = DateTime::parse_from_rfc2822(date)
+        .map(|date| date.format("%a, %b %-d, %Y at %H:%M").to_string())
+        .unwrap_or_else(|_| date.to_string());
+
+    let mut quote = format!("\n\nOn {}, {} wrote:\n", date, sender);
+    for line in body.lines() {
+        if line.is_empty() {
+            quote.push_str(">\n");
+        } else {
+            quote.push_str(&format!("> {}\n", line));
+        }
+    }
+    quote
+}
+
+// Subject of a forward: "Fwd: " is added once, never stacked
+pub fn forward_subject(subject: &str) -> String {
+    let subject = subject.trim();
+    let lower = subject.to_ascii_lowercase();
+    if lower.starts_with("fwd:") || lower.starts_with("fw:") {
+        subject.to_string()
+    } else {
+        format!("Fwd: {}", subject)
+    }
+}
+
+// Text of an inline forward: a block with the original headers, then the
+// original text unchanged
+pub fn forwarded_block(
+    from: &str,
+    date: &str,
+    subject: &str,
+    to: &str,
+    cc: &str,
+    body: &str,
+) -> String {
+    let mut block = String::from("\n\n---------- Forwarded message ---------\n");
+    block.push_str(&format!("From: {}\n", from));
+    block.push_str(&format!("Date: {}\n", date));
+    block.push_str(&format!("Subject: {}\n", subject));
+    block.push_str(&format!("To: {}\n", to));
+    if !cc.is_empty() {
+        block.push_str(&format!("Cc: {}\n", cc));
+    }
+    block.push('\n');
+    block.push_str(body);
+    block
+}
+
+// To and Cc of a reply to a message from `sender` (its Reply-To, or else its
+// From) that went to `to` and `cc`. A reply goes to the sender, or back to
+// the original recipients when we sent the message ourselves; a reply to all
+// copies everyone else. `own` are our addresses, which are never included.
+pub fn reply_recipients(
+    sender: &str,
+    to: &str,
+    cc: &str,
+    own: &[String],
+    reply_all: bool,
+) -> (String, String) {
+    let is_own = |address: &str| {
+        own.iter()
+            .any(|own| own.eq_ignore_ascii_case(address_spec(address)))
+    };
+    let mut seen: Vec<String> = Vec::new();
+    let mut take = |addresses: Vec<&str>| {
+        let mut taken = Vec::new();
+        for address in addresses {
+            let spec = address_spec(address).to_ascii_lowercase();
+            if !is_own(address) && !seen.contains(&spec) {
+                seen.push(spec);
+                taken.push(address.to_string());
+            }
+        }
+        taken
+    };
+
+    let from_us = split_addresses(sender).into_iter().all(is_own);
+    let mut reply_to = if from_us {
+        take(split_addresses(to))
+    } else {
+        take(split_addresses(sender))
+    };
+    let copies = if reply_all {
+        take(
+            split_addresses(to)
+                .into_iter()
+                .chain(split_addresses(cc))
+                .collect(),
+        )
+    } else {
+        Vec::new()
+    };
+    // Replying to our own message that only went to ourselves
+    if reply_to.is_empty() {
+        reply_to = split_addresses(sender)
+            .into_iter()
+            .map(String::from)
+            .collect();
+    }
+
+    (reply_to.join(", "), copies.join(", "))
+}
+
+// A new globally unique Message-ID in the domain of the sender
+pub fn generate_message_id(from: &str) -> String {
+    let domain = address_spec(from)
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.to_string())
+        .filter(|domain| !domain.is_empty())
+        .unwrap_or_else(|| FALLBACK_MESSAGE_ID_DOMAIN.to_string());
+    format!("<{}@{}>", uuid::Uuid::new_v4().simple(), domain)
+}
+
+// The bare address of "Name <addr>" or "addr"
+pub fn address_spec(address: &str) -> &str {
+    match (address.rfind('<'), address.rfind('>')) {
+        (Some(start), Some(end)) if start < end => address[start + 1..end].trim(),
+        _ => address.trim(),
+    }
+}
+
+// Fold a header at spaces so that lines stay within MAX_LINE_LENGTH
+// characters where the value allows it
+fn header(name: &str, value: &str) -> String {
+    let mut folded = format!("{}:", name);
+    let mut line_length = folded.len();
+    for word in value.split(' ').filter(|word| !word.is_empty()) {
+        if line_length + 1 + word.len() > MAX_LINE_LENGTH && line_length > name.len() + 1 {
+            folded.push_str("\r\n");
+            line_length = 0;
+        }
+        folded.push(' ');
+        folded.push_str(word);
+        line_length += 1 + word.len();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+// Header text as is when it is plain ASCII, else as RFC 2047 encoded-words
+pub fn encode_header_text(text: &str) -> String {
+    let printable = text.chars().all(|c| c.is_ascii() && !c.is_ascii_control());
+    if printable && !text.contains("=?") {
+        return text.to_string();
+    }
+
+    let mut words = Vec::new();
+    let mut chunk = String::new();
+    for c in text.chars() {
+        if chunk.len() + c.len_utf8() > ENCODED_WORD_MAX_BYTES {
+            words.push(encoded_word(&chunk));
+            chunk.clear();
+        }
+        chunk.push(c);
+    }
+    if !chunk.is_empty() {
+        words.push(encoded_word(&chunk));
+    }
+    words.join(" ")
+}
+
+fn encoded_word(text: &str) -> String {
+    format!("=?UTF-8?B?{}?=", STANDARD.encode(text.as_bytes()))
+}
+
+// Split an address list at the commas between addresses, not the ones
+// inside quoted display names or angle brackets
+fn split_addresses(list: &str) -> Vec<&str> {
+    let mut addresses = Vec::new();
+    let mut in_quotes = false;
+    let mut in_brackets = false;
+    let mut start = 0;
+    for (i, c) in list.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '<' if !in_quotes => in_brackets = true,
+            '>' if !in_quotes => in_brackets = false,
+            ',' if !in_quotes && !in_brackets => {
+                addresses.push(&list[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    addresses.push(&list[start..]);
+    addresses
+        .into_iter()
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .collect()
+}
+
+// Encode the display names of an address list: non-ASCII names become
+// encoded-words and names with special characters are quoted
+pub fn encode_address_list(list: &str) -> String {
+    split_addresses(list)
+        .into_iter()
+        .map(|address| {
+            let Some(start) = address.rfind('<') else {
+                return address.to_string();
+            };
+            let name = address[..start].trim().trim_matches('"').trim();
+            let addr = address_spec(address);
+            if name.is_empty() {
+                format!("<{}>", addr)
+            } else if !name.is_ascii() {
+                format!("{} <{}>", encode_header_text(name), addr)
+            } else if name.contains(|c: char| "()<>[]:;@\\,.\"".contains(c)) {
+                let escaped = name.replace('\\', "\\\\").replace('"', "\\\"");
+                format!("\"{}\" <{}>", escaped, addr)
+            } else {
+                format!("{} <{}>", name, addr)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// The text/plain part: headers, blank line and body. The body is sent as is
+// when it is short-lined ASCII, otherwise quoted-printable.
+fn text_part(body: &str) -> String {
+    let body = body.replace("\r\n", "\n");
+    let needs_encoding = !body.is_ascii()
+        || body.lines().any(|line| {
+            line.len() > MAX_LINE_LENGTH
+                || line.ends_with([' ', '\t'])
+                || line.chars().any(|c| c.is_ascii_control() && c != '\t')
+        });
+
+    let mut part = String::from("Content-Type: text/plain; charset=utf-8\r\n");
+    if needs_encoding {
+        part.push_str("Content-Transfer-Encoding: quoted-printable\r\n\r\n");
+        part.push_str(&quoted_printable(&body));
+    } else {
+        part.push_str("Content-Transfer-Encoding: 7bit\r\n\r\n");
+        part.push_str(&body.replace('\n', "\r\n"));
+    }
+    part
+}
+
+// Quoted-printable encoding (RFC 2045) with soft line breaks
+pub fn quoted_printable(text: &str) -> String {
+    let text = text.replace("\r\n", "\n");
+    let mut encoded_lines = Vec::new();
+
+    for line in text.split('\n') {
+        let bytes = line.as_bytes();
+        let mut encoded = String::new();
+        let mut line_length = 0;
+        for (i, &byte) in bytes.iter().enumerate() {
+            let is_last = i + 1 == bytes.len();
+            let token = match byte {
+                b' ' | b'\t' if is_last => format!("={:02X}", byte),
+                b' ' | b'\t' => (byte as char).to_string(),
+                33..=60 | 62..=126 => (byte as char).to_string(),
+                _ => format!("={:02X}", byte),
+            };
+            // Leave room for the "=" of a soft line break
+            let limit = if is_last {
+                MAX_LINE_LENGTH
+            } else {
+                MAX_LINE_LENGTH - 1
+            };
+            if line_length + token.len() > limit {
+                encoded.push_str("=\r\n");
+                line_length = 0;
+            }
+            encoded.push_str(&token);
+            line_length += token.len();
+        }
+        encoded_lines.push(encoded);
+    }
+
+    encoded_lines.join("\r\n")
+}
+
+// An attachment part with base64 content, or a forwarded message as is.
+// Non-ASCII file names are given both as an RFC 2231 parameter and, for older
+// clients, as an encoded-word.
+fn attachment_part(attachment: &OutgoingAttachment) -> String {
+    let filename = attachment.filename.replace(['"', '\\', '\r', '\n'], "_");
+    let mut part = String::new();
+
+    if filename.is_ascii() {
+        part.push_str(&format!(
+            "Content-Type: {}; name=\"{}\"\r\n",
+            attachment.content_type, filename
+        ));
+        part.push_str(&format!(
+            "Content-Disposition: attachment; filename=\"{}\"\r\n",
+            filename
+        ));
+    } else {
+        part.push_str(&format!(
+            "Content-Type: {};\r\n name=\"{}\"\r\n",
+            attachment.content_type,
+            encode_header_text(&filename)
+        ));
+        part.push_str(&format!(
+            "Content-Disposition: attachment;\r\n filename*=UTF-8''{}\r\n",
+            percent_encode(&filename)
+        ));
+    }
+
+    // RFC 2046 does not allow base64 for message/rfc822
+    if attachment.content_type == "message/rfc822" {
+        if let Some(text) = embeddable_message(&attachment.content) {
+            let encoding = if text.is_ascii() { "7bit" } else { "8bit" };
+            part.push_str(&format!("Content-Transfer-Encoding: {}\r\n\r\n", encoding));
+            part.push_str(&text);
+            if !text.ends_with("\r\n") {
+                part.push_str("\r\n");
+            }
+            return part;
+        }
+    }
+
+    part.push_str("Content-Transfer-Encoding: base64\r\n\r\n");
+
+    let encoded = STANDARD.encode(&attachment.content);
+    for line in encoded.as_bytes().chunks(MAX_LINE_LENGTH) {
+        part.push_str(std::str::from_utf8(line).unwrap_or_default());
+        part.push_str("\r\n");
+    }
+    part
+}
+
+// A message to embed unencoded: UTF-8 with CRLF line endings and no line
+// longer than SMTP allows. None if it has to be base64 encoded after all.
+fn embeddable_message(content: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(content).ok()?;
+    let text = text.replace("\r\n", "\n").replace('\n', "\r\n");
+    if text
+        .split("\r\n")
+        .any(|line| line.len() > 998 || line.contains('\0'))
+    {
+        return None;
+    }
+    Some(text)
+}
+
+// Percent-encoding of RFC 2231 parameter values
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+                (byte as char).to_string()
+            } else {
+                format!("%{:02X}", byte)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_encoded_words(text: &str) -> String {
+        let bytes: Vec<u8> = text
+            .split_whitespace()
+            .flat_map(|word| {
+                let payload = word
+                    .strip_prefix("=?UTF-8?B?")
+                    .and_then(|word| word.strip_suffix("?="))
+                    .unwrap();
+                STANDARD.decode(payload).unwrap()
+            })
+            .collect();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    fn decode_quoted_printable(text: &str) -> String {
+        let joined = text.replace("=\r\n", "");
+        let mut bytes = Vec::new();
+        let mut iter = joined.bytes();
+        while let Some(byte) = iter.next() {
+            if byte == b'=' {
+                let hex: String = [iter.next().unwrap(), iter.next().unwrap()]
+                    .iter()
+                    .map(|&b| b as char)
+                    .collect();
+                bytes.push(u8::from_str_radix(&hex, 16).unwrap());
+            } else {
+                bytes.push(byte);
+            }
+        }
+        String::from_utf8(bytes).unwrap().replace("\r\n", "\n")
+    }
+
+    fn simple_message() -> OutgoingMessage {
+        OutgoingMessage {
+            from: "me@example.org".to_string(),
+            to: "bob@example.com".to_string(),
+            subject: "Lunch?".to_string(),
+            body: "Noon works.".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_reply_subject_is_idempotent() {
+        assert_eq!(reply_subject("Lunch?"), "Re: Lunch?");
+        assert_eq!(reply_subject("Re: Lunch?"), "Re: Lunch?");
+        assert_eq!(reply_subject("RE:Lunch?"), "RE:Lunch?");
+        assert_eq!(reply_subject(&reply_subject("Grüße")), "Re: Grüße");
+    }
+
+    #[test]
+    fn test_reply_references_extend_the_chain() {
+        assert_eq!(
+            reply_references(Some("<a@x> <b@x>"), Some("<b@x>"), "<c@x>"),
+            vec!["<a@x>", "<b@x>", "<c@x>"]
+        );
+        assert_eq!(
+            reply_references(None, Some("<b@x>"), "<c@x>"),
+            vec!["<b@x>", "<c@x>"]
+        );
+        assert_eq!(reply_references(None, None, "<c@x>"), vec!["<c@x>"]);
+    }
+
+    #[test]
+    fn test_quote_reply_has_attribution() {
+        let quote = quote_reply(
+            "Tue, 10 Jun 2025 14:00:00 +0000 (UTC)",
+            "Ann <ann@example.com>",
+            "Hi,\n\nSee you",
+        );
+        assert_eq!(
+            quote,
+            "\n\nOn Tue, Jun 10, 2025 at 14:00, Ann <ann@example.com> wrote:\n> Hi,\n>\n> See you\n"
+        );
+        assert!(quote_reply("yesterday", "Ann", "x").contains("On yesterday, Ann wrote:"));
+    }
+
+    #[test]
+    fn test_threading_headers() {
+        let raw = OutgoingMessage {
+            in_reply_to: Some("<b@x>".to_string()),
+            references: vec!["<a@x>".to_string(), "<b@x>".to_string()],
+            ..simple_message()
+        }
+        .build();
+        assert!(raw.contains("In-Reply-To: <b@x>\r\n"));
+        assert!(raw.contains("References: <a@x> <b@x>\r\n"));
+        assert!(!simple_message().build().contains("In-Reply-To"));
+    }
+
+    #[test]
+    fn test_reply_recipients() {
+        let own = vec!["me@example.com".to_string()];
+        let to = "Me <ME@example.com>, bob@example.com";
+        let cc = "carol@example.com, Bob <bob@example.com>";
+
+        assert_eq!(
+            reply_recipients("Ann <ann@example.com>", to, cc, &own, false),
+            ("Ann <ann@example.com>".to_string(), String::new())
+        );
+        assert_eq!(
+            reply_recipients("Ann <ann@example.com>", to, cc, &own, true),
+            (
+                "Ann <ann@example.com>".to_string(),
+                "bob@example.com, carol@example.com".to_string()
+            )
+        );
+        // The sender is not copied again
+        assert_eq!(
+            reply_recipients("bob@example.com", to, "", &own, true),
+            ("bob@example.com".to_string(), String::new())
+        );
+        // Our own message goes back to its recipients
+        assert_eq!(
+            reply_recipients("me@example.com", "bob@example.com", cc, &own, true),
+            (
+                "bob@example.com".to_string(),
+                "carol@example.com".to_string()
+            )
+        );
+        assert_eq!(
+            reply_recipients("me@example.com", "me@example.com", "", &own, true),
+            ("me@example.com".to_string(), String::new())
+        );
+    }
+
+    #[test]
+    fn test_forward_subject_and_block() {
+        assert_eq!(forward_subject("Report"), "Fwd: Report");
+        assert_eq!(forward_subject("FW: Report"), "FW: Report");
+        assert_eq!(forward_subject(&forward_subject("Report")), "Fwd: Report");
+
+        let block = forwarded_block("Ann <ann@x>", "Tue", "Report", "me@x", "", "Text");
+        assert_eq!(
+            block,
+            "\n\n---------- Forwarded message ---------\nFrom: Ann <ann@x>\nDate: Tue\nSubject: Report\nTo: me@x\n\nText"
+        );
+    }
+
+    #[test]
+    fn test_forwarded_message_is_embedded_unencoded() {
+        let original = "Subject: Grüße\nFrom: ann@x\n\nHallo\n";
+        let raw = OutgoingMessage {
+            attachments: vec![OutgoingAttachment {
+                filename: "Grüße.eml".to_string(),
+                content_type: "message/rfc822".to_string(),
+                content: original.as_bytes().to_vec(),
+            }],
+            ..simple_message()
+        }
+        .build();
+
+        assert!(raw.contains(
+            "Content-Transfer-Encoding: 8bit\r\n\r\nSubject: Grüße\r\nFrom: ann@x\r\n\r\nHallo\r\n--"
+        ));
+    }
+
+    #[test]
+    fn test_ascii_header_text_is_unchanged() {
+        assert_eq!(encode_header_text("Quarterly report"), "Quarterly report");
+    }
+
+    #[test]
+    fn test_non_ascii_subject_is_encoded_in_short_words() {
+        let subject = "Grüße aus München 🎉 und noch viel mehr Text, damit es umbricht";
+        let encoded = encode_header_text(subject);
+        assert!(encoded.is_ascii());
+        assert!(encoded.split(' ').all(|word| word.len() <= 75));
+        assert_eq!(decode_encoded_words(&encoded), subject);
+    }
+
+    #[test]
+    fn test_display_names_are_encoded_or_quoted() {
+        assert_eq!(
+            encode_address_list("Jürgen <j@example.com>, bob@example.com"),
+            "=?UTF-8?B?SsO8cmdlbg==?= <j@example.com>, bob@example.com"
+        );
+        assert_eq!(
+            encode_address_list("\"Doe, Jane\" <jane@example.com>,Al <al@example.com>"),
+            "\"Doe, Jane\" <jane@example.com>, Al <al@example.com>"
+        );
+        assert_eq!(
+            encode_address_list("J. Smith <js@example.com>"),
+            "\"J. Smith\" <js@example.com>"
+        );
+    }
+
+    #[test]
+    fn test_long_headers_are_folded() {
+        let folded = header("Subject", &"word ".repeat(40));
+        assert!(folded.ends_with("\r\n"));
+        for line in folded.trim_end().split("\r\n") {
+            assert!(line.len() <= MAX_LINE_LENGTH, "{:?}", line);
+        }
+        assert!(folded
+            .split("\r\n")
+            .skip(1)
+            .all(|l| l.is_empty() || l.starts_with(' ')));
+    }
+
+    #[test]
+    fn test_quoted_printable_round_trip_and_line_length() {
+        let text = format!("Schöne Grüße = 100%\n{}\ntrailing space ", "x".repeat(200));
+        let encoded = quoted_printable(&text);
+        assert!(encoded.is_ascii());
+        assert!(encoded
+            .split("\r\n")
+            .all(|line| line.len() <= MAX_LINE_LENGTH));
+        assert!(encoded.contains("Sch=C3=B6ne"));
+        assert!(encoded.contains("=3D"));
+        assert!(encoded.ends_with("space=20"));
+        assert_eq!(decode_quoted_printable(&encoded), text);
+    }
+
+    #[test]
+    fn test_message_has_standard_headers() {
+        let raw = OutgoingMessage {
+            date: DateTime::parse_from_rfc2822("Tue, 10 Jun 2025 14:00:00 +0200").ok(),
+            ..simple_message()
+        }
+        .build();
+
+        assert!(raw.contains("From: me@example.org\r\n"));
+        assert!(raw.contains("Date: Tue, 10 Jun 2025 14:00:00 +0200\r\n"));
+        assert!(raw.contains("MIME-Version: 1.0\r\n"));
+        let message_id = raw
+            .lines()
+            .find_map(|line| line.strip_prefix("Message-ID: "))
+            .unwrap();
+        assert!(message_id.starts_with('<') && message_id.ends_with("@example.org>"));
+        assert!(raw.contains("Content-Transfer-Encoding: 7bit\r\n\r\nNoon works."));
+        assert!(!raw.contains("Cc:") && !raw.contains("Bcc:"));
+    }
+
+    #[test]
+    fn test_message_id_is_kept_and_generated_uniquely() {
+        let raw = OutgoingMessage {
+            message_id: Some("<fixed@example.org>".to_string()),
+            ..simple_message()
+        }
+        .build();
+        assert!(raw.contains("Message-ID: <fixed@example.org>\r\n"));
+
+        assert_ne!(generate_message_id(""), generate_message_id(""));
+        assert!(generate_message_id("").ends_with("@tuimail.local>"));
+        assert!(generate_message_id("Me <me@example.net>").ends_with("@example.net>"));
+    }
+
+    #[test]
+    fn test_non_ascii_body_is_quoted_printable() {
+        let raw = OutgoingMessage {
+            subject: "Überraschung 🎂".to_string(),
+            body: "Alles Gute zum Geburtstag! 🎂\nBis bald".to_string(),
+            ..simple_message()
+        }
+        .build();
+
+        assert!(raw.is_ascii());
+        assert!(raw.contains("Subject: =?UTF-8?B?"));
+        assert!(raw.contains("Content-Transfer-Encoding: quoted-printable\r\n"));
+        let body = raw.split("\r\n\r\n").nth(1).unwrap();
+        assert_eq!(
+            decode_quoted_printable(body),
+            "Alles Gute zum Geburtstag! 🎂\nBis bald"
+        );
+    }
+
+    #[test]
+    fn test_attachment_with_non_ascii_name() {
+        let raw = OutgoingMessage {
+            attachments: vec![OutgoingAttachment {
+                filename: "Übersicht.pdf".to_string(),
+                content_type: "application/pdf".to_string(),
+                content: b"%PDF-1.4".to_vec(),
+            }],
+            ..simple_message()
+        }
+        .build();
+
+        assert!(raw.is_ascii());
+        assert!(raw.contains("Content-Type: multipart/mixed; boundary="));
+        assert!(raw.contains("filename*=UTF-8''%C3%9Cbersicht.pdf"));
+        assert!(raw.contains(" name=\"=?UTF-8?B?"));
+        assert!(raw.contains(&STANDARD.encode(b"%PDF-1.4")));
+    }
+}
@@ -40,6 +40,12 @@ impl NotificationService {
 // Gmail Push Notification setup (for future implementation)
 pub struct GmailPushNotifications;
 
+impl Default for GmailPushNotifications {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl GmailPushNotifications {
     pub fn new() -> Self {
         Self
@@ -0,0 +1,481 @@
+//! Offline outbox
+//!
+//! Sends, archives, deletions and spam reports that fail because Gmail cannot
+//! be reached are kept in the `outbox` table instead of being lost. They show
+//! in the cache and the message list right away, are replayed in order by the
+//! background sync once Gmail answers again, and are listed in the OUTBOX
+//! virtual folder, where entries Gmail refused can be retried or cancelled.
+//!
+//! Archives, deletions and spam reports are also kept on an undo stack, so
+//! that the last ones can be reverted, or simply dropped from the outbox
+//! while they still wait there.
+
+use crate::database::OutboxEntry;
+use crate::gmail_api::client::is_network_error;
+use crate::gmail_api::operations::check_attachments_size;
+use crate::gmail_api::{
+    archive_message, archive_thread, batch_modify, change_labels, delete_message, delete_thread,
+    modify_thread, send_draft, send_email, send_raw_draft, send_raw_email, spam_message,
+    spam_thread, untrash_message, untrash_thread,
+};
+use crate::mime::OutgoingMessage;
+use crate::state::{AppState, UndoEntry, UndoMessage};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+// How replaying an entry went: whether Gmail was unreachable and the error
+// text, as the error itself is not Send
+type Replayed = std::result::Result<(), (bool, String)>;
+
+// Actions on messages that can wait in the outbox
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageAction {
+    Archive,
+    Delete,
+    Spam,
+}
+
+impl MessageAction {
+    // Name under which the action is stored in the outbox table
+    pub fn name(self) -> &'static str {
+        match self {
+            MessageAction::Archive => "archive",
+            MessageAction::Delete => "delete",
+            MessageAction::Spam => "spam",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "archive" => Some(MessageAction::Archive),
+            "delete" => Some(MessageAction::Delete),
+            "spam" => Some(MessageAction::Spam),
+            _ => None,
+        }
+    }
+
+    // What the action failed to do, for error messages
+    pub fn failure(self) -> &'static str {
+        match self {
+            MessageAction::Archive => "archive message",
+            MessageAction::Delete => "delete message",
+            MessageAction::Spam => "mark message as spam",
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            MessageAction::Archive => "Archive",
+            MessageAction::Delete => "Move to Trash",
+            MessageAction::Spam => "Report spam",
+        }
+    }
+
+    // Labels the action adds to and removes from each message
+    pub fn label_changes(self) -> (Vec<String>, Vec<String>) {
+        let inbox = vec!["INBOX".to_string()];
+        match self {
+            MessageAction::Archive => (vec![], inbox),
+            MessageAction::Delete => (vec!["TRASH".to_string()], inbox),
+            MessageAction::Spam => (vec!["SPAM".to_string()], inbox),
+        }
+    }
+
+    async fn run(
+        self,
+        state: &AppState,
+        message_ids: &[String],
+        thread_id: Option<&str>,
+    ) -> Result<()> {
+        if let Some(thread_id) = thread_id {
+            return match self {
+                MessageAction::Archive => archive_thread(state, thread_id).await,
+                MessageAction::Delete => delete_thread(state, thread_id).await,
+                MessageAction::Spam => spam_thread(state, thread_id).await,
+            };
+        }
+        // Several messages are changed together with one batchModify call
+        if message_ids.len() > 1 {
+            let (add, remove) = self.label_changes();
+            return batch_modify(state, message_ids, &add, &remove).await;
+        }
+        for message_id in message_ids {
+            match self {
+                MessageAction::Archive => archive_message(state, message_id).await?,
+                MessageAction::Delete => delete_message(state, message_id).await?,
+                MessageAction::Spam => spam_message(state, message_id).await?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutboxAction {
+    // A built message for conversation `thread_id`, sent with drafts.send
+    // when it was written as draft `draft_id`
+    Send {
+        raw: String,
+        thread_id: Option<String>,
+        draft_id: Option<String>,
+    },
+    // An action on messages, or on the whole conversation `thread_id`
+    Modify {
+        action: MessageAction,
+        message_ids: Vec<String>,
+        thread_id: Option<String>,
+    },
+}
+
+impl OutboxAction {
+    async fn run(&self, state: &AppState) -> Result<()> {
+        match self {
+            OutboxAction::Send {
+                raw,
+                thread_id,
+                draft_id,
+            } => match draft_id {
+                Some(draft_id) => {
+                    send_raw_draft(state, draft_id, raw.clone(), thread_id.as_deref()).await
+                }
+                None => send_raw_email(state, raw.clone(), thread_id.as_deref()).await,
+            },
+            OutboxAction::Modify {
+                action,
+                message_ids,
+                thread_id,
+            } => action.run(state, message_ids, thread_id.as_deref()).await,
+        }
+    }
+}
+
+// Whether an action reached Gmail or waits in the outbox
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Delivery {
+    Done,
+    Queued,
+}
+
+// Send a composed message, with drafts.send if it was saved as draft `draft_id`
+pub async fn send_message(
+    state: &mut AppState,
+    message: &OutgoingMessage,
+    thread_id: Option<&str>,
+    draft_id: Option<&str>,
+) -> Result<Delivery> {
+    check_attachments_size(message)?;
+    if !state.has_pending_outbox() {
+        let result = match draft_id {
+            Some(draft_id) => send_draft(state, draft_id, message, thread_id).await,
+            None => send_email(state, message, thread_id).await,
+        };
+        if !should_queue(state, result)? {
+            return Ok(Delivery::Done);
+        }
+    }
+
+    let recipients: Vec<&str> = [&message.to, &message.cc, &message.bcc]
+        .into_iter()
+        .map(|field| field.trim())
+        .filter(|field| !field.is_empty())
+        .collect();
+    let action = OutboxAction::Send {
+        raw: message.build(),
+        thread_id: thread_id.map(str::to_string),
+        draft_id: draft_id.map(str::to_string),
+    };
+    let description = format!("Send to {}", recipients.join(", "));
+    queue(state, action, description, message.subject.clone()).await
+}
+
+// Archive, delete or report messages, or the whole conversation `thread_id`.
+// The change shows in the cache and the message list right away, also when it
+// has to wait in the outbox.
+pub async fn apply_message_action(
+    state: &mut AppState,
+    action: MessageAction,
+    message_ids: Vec<String>,
+    thread_id: Option<String>,
+) -> Result<Delivery> {
+    let mut delivery = Delivery::Done;
+    let mut outbox_id = None;
+    if state.has_pending_outbox()
+        || should_queue(
+            state,
+            action.run(state, &message_ids, thread_id.as_deref()).await,
+        )?
+    {
+        let subject = message_ids
+            .first()
+            .and_then(|id| state.message_headers.get(id))
+            .map(|(subject, _)| subject.clone())
+            .unwrap_or_default();
+        let queued = OutboxAction::Modify {
+            action,
+            message_ids: message_ids.clone(),
+            thread_id: thread_id.clone(),
+        };
+        delivery = queue(state, queued, action.description().to_string(), subject).await?;
+        outbox_id = state.outbox.last().map(|entry| entry.id);
+    }
+
+    let undo = UndoEntry {
+        action,
+        label_id: state.get_current_label().and_then(|l| l.id.clone()),
+        thread_id: thread_id.clone(),
+        messages: state.undo_messages(&message_ids, action),
+        outbox_id,
+    };
+    state.push_undo(undo);
+
+    let (add, remove) = action.label_changes();
+    for message_id in &message_ids {
+        state.record_label_change(message_id, &add, &remove).await;
+    }
+    match &thread_id {
+        Some(thread_id) => state.remove_thread(thread_id),
+        None => {
+            for message_id in &message_ids {
+                state.remove_message(message_id);
+            }
+        }
+    }
+    Ok(delivery)
+}
+
+// Whether a failed attempt should wait in the outbox: only when Gmail could
+// not be reached and there is a database to keep it in. Other errors are returned.
+fn should_queue(state: &AppState, result: Result<()>) -> Result<bool> {
+    match result {
+        Ok(()) => Ok(false),
+        Err(e) if is_network_error(e.as_ref()) && state.database.is_some() => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+// Keep `action` in the outbox. Once something waits there, later actions
+// queue behind it to keep their order.
+async fn queue(
+    state: &mut AppState,
+    action: OutboxAction,
+    description: String,
+    subject: String,
+) -> Result<Delivery> {
+    let Some(db) = state.database.clone() else {
+        return Err("Cannot queue the action without a local database".into());
+    };
+    let entry = db.add_outbox_entry(&action, &description, &subject).await?;
+    state.outbox.push(entry);
+    state.refresh_outbox_view();
+    Ok(Delivery::Queued)
+}
+
+// Replay the pending outbox entries in order, returning how many reached
+// Gmail. Stops at the first one that still cannot get through; entries Gmail
+// refuses keep the error and wait for the user to retry or cancel them.
+pub async fn replay_outbox(state: &mut AppState) -> usize {
+    if state.database.is_none() {
+        return 0;
+    }
+
+    let mut delivered = 0;
+    for entry in pending_outbox_entries(state) {
+        let result = replay_entry(state, &entry).await;
+        delivered += usize::from(result.is_ok());
+        if !record_replay(state, entry.id, result).await {
+            break;
+        }
+    }
+
+    state.refresh_outbox_view();
+    delivered
+}
+
+// replay_outbox for the shared state. The requests run on a snapshot without
+// the state lock, which is only taken to record how each of them went.
+pub async fn replay_outbox_in_background(state_arc: &Arc<RwLock<AppState>>) -> usize {
+    let (context, pending) = {
+        let state = state_arc.read().await;
+        if state.database.is_none() {
+            return 0;
+        }
+        (state.request_context(), pending_outbox_entries(&state))
+    };
+
+    let mut delivered = 0;
+    for entry in pending {
+        let result = replay_entry(&context, &entry).await;
+        delivered += usize::from(result.is_ok());
+        if !record_replay(&mut *state_arc.write().await, entry.id, result).await {
+            break;
+        }
+    }
+
+    state_arc.write().await.refresh_outbox_view();
+    delivered
+}
+
+// Entries that wait to be replayed, in order
+fn pending_outbox_entries(state: &AppState) -> Vec<OutboxEntry> {
+    state
+        .outbox
+        .iter()
+        .filter(|entry| entry.error.is_none())
+        .cloned()
+        .collect()
+}
+
+async fn replay_entry(state: &AppState, entry: &OutboxEntry) -> Replayed {
+    entry
+        .action
+        .run(state)
+        .await
+        .map_err(|e| (is_network_error(e.as_ref()), e.to_string()))
+}
+
+// A delivered entry leaves the outbox and a refused one keeps Gmail's error.
+// Returns false when Gmail could not be reached and the replay has to stop.
+async fn record_replay(state: &mut AppState, id: i64, result: Replayed) -> bool {
+    let Some(db) = state.database.clone() else {
+        return false;
+    };
+    match result {
+        Ok(()) => {
+            let _ = db.delete_outbox_entry(id).await;
+            state.outbox.retain(|queued| queued.id != id);
+        }
+        Err((true, _)) => return false,
+        Err((false, error)) => {
+            let _ = db.set_outbox_error(id, Some(&error)).await;
+            if let Some(queued) = state.outbox.iter_mut().find(|queued| queued.id == id) {
+                queued.error = Some(error);
+            }
+        }
+    }
+    true
+}
+
+// Try a refused entry again, together with anything else that is pending
+pub async fn retry_outbox_entry(state: &mut AppState, id: i64) {
+    if let Some(db) = state.database.clone() {
+        let _ = db.set_outbox_error(id, None).await;
+    }
+    if let Some(entry) = state.outbox.iter_mut().find(|entry| entry.id == id) {
+        entry.error = None;
+    }
+    replay_outbox(state).await;
+}
+
+// Take an entry out of the outbox, returning it if it was still there
+async fn remove_outbox_entry(state: &mut AppState, id: i64) -> Option<OutboxEntry> {
+    let index = state.outbox.iter().position(|entry| entry.id == id)?;
+    let entry = state.outbox.remove(index);
+    if let Some(db) = state.database.clone() {
+        let _ = db.delete_outbox_entry(id).await;
+    }
+    Some(entry)
+}
+
+// Drop an entry, undoing the label changes it made to the cache
+pub async fn cancel_outbox_entry(state: &mut AppState, id: i64) {
+    let Some(entry) = remove_outbox_entry(state, id).await else {
+        return;
+    };
+
+    if let OutboxAction::Modify {
+        action,
+        message_ids,
+        ..
+    } = &entry.action
+    {
+        let (added, removed) = action.label_changes();
+        for message_id in message_ids {
+            state
+                .record_label_change(message_id, &removed, &added)
+                .await;
+        }
+    }
+    state.refresh_outbox_view();
+}
+
+// Revert the last archive, deletion or spam report, returning it. An action
+// still waiting in the outbox is dropped from there, one that reached Gmail
+// is reverted with untrash and modify calls. The messages go back to their
+// labels in the cache and to their places in the list.
+pub async fn undo_message_action(state: &mut AppState) -> Result<Option<MessageAction>> {
+    let Some(entry) = state.undo_stack.pop() else {
+        return Ok(None);
+    };
+
+    let queued = match entry.outbox_id {
+        Some(id) => remove_outbox_entry(state, id).await.is_some(),
+        None => false,
+    };
+    if !queued {
+        let reverted = match &entry.thread_id {
+            Some(thread_id) => revert_thread(state, thread_id, entry.action).await,
+            None => revert_labels(state, &entry.messages).await,
+        };
+        if let Err(e) = reverted {
+            // Keep it for another try
+            state.undo_stack.push(entry);
+            return Err(e);
+        }
+    }
+
+    for undo in &entry.messages {
+        if let Some(message_id) = &undo.message.id {
+            state
+                .record_label_change(message_id, &undo.removed, &undo.added)
+                .await;
+        }
+    }
+    state.reinsert_messages(entry.label_id.as_deref(), entry.messages);
+    state.refresh_outbox_view();
+    Ok(Some(entry.action))
+}
+
+// The inverse of an action on a whole conversation, made on the whole
+// conversation again
+async fn revert_thread(state: &AppState, thread_id: &str, action: MessageAction) -> Result<()> {
+    let (added, removed) = action.label_changes();
+    if added.iter().any(|label| label == "TRASH") {
+        untrash_thread(state, thread_id).await?;
+    }
+    let add: Vec<&str> = removed.iter().map(String::as_str).collect();
+    let remove: Vec<&str> = added
+        .iter()
+        .map(String::as_str)
+        .filter(|label| *label != "TRASH")
+        .collect();
+    if !add.is_empty() || !remove.is_empty() {
+        modify_thread(state, thread_id, &add, &remove).await?;
+    }
+    Ok(())
+}
+
+// The inverse calls of an action: untrash what it trashed, then give back
+// the labels it removed and take away the others it added
+async fn revert_labels(state: &AppState, messages: &[UndoMessage]) -> Result<()> {
+    for undo in messages {
+        let Some(message_id) = undo.message.id.as_deref() else {
+            continue;
+        };
+        if undo.added.iter().any(|label| label == "TRASH") {
+            untrash_message(state, message_id).await?;
+        }
+        let add: Vec<&str> = undo.removed.iter().map(String::as_str).collect();
+        let remove: Vec<&str> = undo
+            .added
+            .iter()
+            .map(String::as_str)
+            .filter(|label| *label != "TRASH")
+            .collect();
+        if !add.is_empty() || !remove.is_empty() {
+            change_labels(state, message_id, &add, &remove).await?;
+        }
+    }
+    Ok(())
+}
@@ -1,8 +1,58 @@
-use crate::database::Database;
-use crate::types::{Label, Message};
+use crate::accounts::{database_url, DEFAULT_ACCOUNT};
+use crate::database::{CachedMessage, Database, OutboxEntry, RestorableCompose, SavedCompose};
+use crate::email_content::{extract_plain_text_body, header_value};
+use crate::gmail_api::auth::{stored_session, AccountSession, TokenRefresher};
+use crate::gmail_api::client::{QuotaBudget, RequestPriority, RetryPolicy, DEFAULT_API_BASE_URL};
+use crate::gmail_api::operations::MAX_ATTACHMENTS_SIZE;
+use crate::mime::{
+    address_spec, forward_subject, forwarded_block, parse_message_ids, quote_reply,
+    reply_recipients, reply_references, reply_subject, OutgoingAttachment, OutgoingMessage,
+};
+use crate::outbox::MessageAction;
+use crate::types::{
+    Attachment, Draft, Filter, FilterAction, FilterCriteria, Label, Message, MessageHeadersDisplay,
+    MessageRef,
+};
 use ratatui::widgets::ListState;
 use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Virtual label that merges the cached inboxes of every account
+pub const UNIFIED_INBOX_LABEL_ID: &str = "UNIFIED";
+
+pub fn is_unified_inbox(label_id: &str) -> bool {
+    label_id.eq_ignore_ascii_case(UNIFIED_INBOX_LABEL_ID)
+}
+
+// Virtual label holding the results of the current Gmail search
+pub const SEARCH_LABEL_ID: &str = "SEARCH";
+
+pub fn is_search_label(label_id: &str) -> bool {
+    label_id.eq_ignore_ascii_case(SEARCH_LABEL_ID)
+}
+
+// Virtual label listing the actions waiting to reach Gmail
+pub const OUTBOX_LABEL_ID: &str = "OUTBOX";
+
+pub fn is_outbox_label(label_id: &str) -> bool {
+    label_id.eq_ignore_ascii_case(OUTBOX_LABEL_ID)
+}
+
+// Outbox entries are listed under message ids like "outbox-3"
+const OUTBOX_MESSAGE_PREFIX: &str = "outbox-";
+
+fn outbox_label() -> Label {
+    Label {
+        id: Some(OUTBOX_LABEL_ID.to_string()),
+        name: Some("OUTBOX".to_string()),
+    }
+}
+
+// Maximum number of matches shown when a search is answered from the cache
+const LOCAL_SEARCH_LIMIT: i64 = 200;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum FocusedPane {
@@ -11,6 +61,388 @@ pub enum FocusedPane {
     Content,
 }
 
+// A message of the conversation shown in the content pane
+#[derive(Debug, Clone)]
+pub struct ThreadMessage {
+    pub id: String,
+    pub headers: MessageHeadersDisplay,
+    pub snippet: String,
+    pub expanded: bool,
+}
+
+// A conversation fetched with threads.get, oldest message first
+#[derive(Debug, Clone)]
+pub struct OpenThread {
+    pub id: String,
+    pub messages: Vec<ThreadMessage>,
+    // Message the expand/collapse keys act on
+    pub selected: usize,
+}
+
+// Prompt over the folders pane for managing user labels
+#[derive(Debug, Clone, PartialEq)]
+pub enum LabelPrompt {
+    Create { name: String },
+    Rename { label_id: String, name: String },
+    ConfirmDelete { label_id: String, name: String },
+}
+
+// Confirmation before messages are deleted for good
+#[derive(Debug, Clone, PartialEq)]
+pub enum PurgePrompt {
+    // The selected or marked messages
+    DeleteForever { message_ids: Vec<String> },
+    // Everything in the TRASH or SPAM folder
+    EmptyFolder { label_id: String },
+}
+
+// Fuzzy finder for applying and removing labels on the selected message, or
+// for picking the label to move it to
+#[derive(Debug, Clone, Default)]
+pub struct LabelPicker {
+    pub query: String,
+    // Index into the current matches
+    pub selected: usize,
+    // Move the message out of the current folder to the picked label
+    pub moving: bool,
+}
+
+// Labels Gmail does not let messages.modify add or remove, and virtual labels
+const UNASSIGNABLE_LABEL_IDS: [&str; 6] = [
+    "SENT",
+    "DRAFT",
+    "ALLMAIL",
+    UNIFIED_INBOX_LABEL_ID,
+    SEARCH_LABEL_ID,
+    OUTBOX_LABEL_ID,
+];
+
+// Whether messages.modify can add or remove `label_id`
+pub fn is_assignable_label(label_id: &str) -> bool {
+    !UNASSIGNABLE_LABEL_IDS
+        .iter()
+        .any(|unassignable| label_id.eq_ignore_ascii_case(unassignable))
+}
+
+// Gmail gives user labels IDs like "Label_12"; only those can be renamed or deleted
+pub fn is_user_label(label_id: &str) -> bool {
+    label_id.starts_with("Label_")
+}
+
+// Score `candidate` against a fuzzy `query`: every query character has to
+// appear in order (ignoring case). Runs of consecutive characters and matches
+// at the start of a word score higher, and shorter candidates win ties.
+// Returns None if the candidate does not match.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+
+    for c in query.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let index = position + candidate[position..].iter().position(|&x| x == c)?;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == index) {
+            score += 5;
+        }
+        if index == 0 || !candidate[index - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous = Some(index);
+        position = index + 1;
+    }
+
+    Some(score * 100 - candidate.len() as i64)
+}
+
+// A field of the filter form: the criteria, the label to apply, then the
+// actions that are switched on and off
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FilterField {
+    #[default]
+    From,
+    To,
+    Subject,
+    Query,
+    NegatedQuery,
+    Label,
+    SkipInbox,
+    MarkRead,
+    Star,
+    NeverSpam,
+    Trash,
+}
+
+// Form order, which Tab and Shift+Tab follow
+pub const FILTER_FIELDS: [FilterField; 11] = [
+    FilterField::From,
+    FilterField::To,
+    FilterField::Subject,
+    FilterField::Query,
+    FilterField::NegatedQuery,
+    FilterField::Label,
+    FilterField::SkipInbox,
+    FilterField::MarkRead,
+    FilterField::Star,
+    FilterField::NeverSpam,
+    FilterField::Trash,
+];
+
+// A filter being written on the filters screen
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FilterForm {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub query: String,
+    pub negated_query: String,
+    // Name of the label to apply
+    pub label: String,
+    pub skip_inbox: bool,
+    pub mark_read: bool,
+    pub star: bool,
+    pub never_spam: bool,
+    pub trash: bool,
+    pub focused: FilterField,
+}
+
+impl FilterForm {
+    // A form matching the sender of `message`, and its mailing list when it
+    // came through one
+    pub fn from_message(message: &Message) -> Self {
+        let payload = message.payload.clone().unwrap_or_default();
+        let from = header_value(&payload, "From").map(address_spec);
+        let list = header_value(&payload, "List-Id").map(address_spec);
+        Self {
+            from: from.unwrap_or_default().to_string(),
+            query: list
+                .filter(|list| !list.is_empty())
+                .map(|list| format!("list:{}", list))
+                .unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    // Text of `field`, None for the actions that are switched on and off
+    pub fn text(&self, field: FilterField) -> Option<&str> {
+        match field {
+            FilterField::From => Some(&self.from),
+            FilterField::To => Some(&self.to),
+            FilterField::Subject => Some(&self.subject),
+            FilterField::Query => Some(&self.query),
+            FilterField::NegatedQuery => Some(&self.negated_query),
+            FilterField::Label => Some(&self.label),
+            _ => None,
+        }
+    }
+
+    fn text_mut(&mut self, field: FilterField) -> Option<&mut String> {
+        match field {
+            FilterField::From => Some(&mut self.from),
+            FilterField::To => Some(&mut self.to),
+            FilterField::Subject => Some(&mut self.subject),
+            FilterField::Query => Some(&mut self.query),
+            FilterField::NegatedQuery => Some(&mut self.negated_query),
+            FilterField::Label => Some(&mut self.label),
+            _ => None,
+        }
+    }
+
+    // Whether the action `field` is switched on, None for the text fields
+    pub fn checked(&self, field: FilterField) -> Option<bool> {
+        match field {
+            FilterField::SkipInbox => Some(self.skip_inbox),
+            FilterField::MarkRead => Some(self.mark_read),
+            FilterField::Star => Some(self.star),
+            FilterField::NeverSpam => Some(self.never_spam),
+            FilterField::Trash => Some(self.trash),
+            _ => None,
+        }
+    }
+
+    fn checked_mut(&mut self, field: FilterField) -> Option<&mut bool> {
+        match field {
+            FilterField::SkipInbox => Some(&mut self.skip_inbox),
+            FilterField::MarkRead => Some(&mut self.mark_read),
+            FilterField::Star => Some(&mut self.star),
+            FilterField::NeverSpam => Some(&mut self.never_spam),
+            FilterField::Trash => Some(&mut self.trash),
+            _ => None,
+        }
+    }
+
+    pub fn next_field(&mut self) {
+        let index = FILTER_FIELDS.iter().position(|f| *f == self.focused);
+        let next = index.map_or(0, |index| (index + 1) % FILTER_FIELDS.len());
+        self.focused = FILTER_FIELDS[next];
+    }
+
+    pub fn previous_field(&mut self) {
+        let index = FILTER_FIELDS.iter().position(|f| *f == self.focused);
+        let previous = index.map_or(0, |index| {
+            (index + FILTER_FIELDS.len() - 1) % FILTER_FIELDS.len()
+        });
+        self.focused = FILTER_FIELDS[previous];
+    }
+
+    // Type `c` into the focused text field, or switch the focused action on
+    // or off with a space
+    pub fn push(&mut self, c: char) {
+        let focused = self.focused;
+        if let Some(text) = self.text_mut(focused) {
+            text.push(c);
+        } else if c == ' ' {
+            if let Some(checked) = self.checked_mut(focused) {
+                *checked = !*checked;
+            }
+        }
+    }
+
+    pub fn pop(&mut self) {
+        let focused = self.focused;
+        if let Some(text) = self.text_mut(focused) {
+            text.pop();
+        }
+    }
+
+    // The filter to create, with the label looked up by name in `labels`
+    pub fn to_filter(&self, labels: &[Label]) -> Result<Filter, String> {
+        let text = |value: &str| Some(value.trim().to_string()).filter(|v| !v.is_empty());
+        let criteria = FilterCriteria {
+            from: text(&self.from),
+            to: text(&self.to),
+            subject: text(&self.subject),
+            query: text(&self.query),
+            negated_query: text(&self.negated_query),
+        };
+        if criteria == FilterCriteria::default() {
+            return Err("Fill in at least one of the search criteria.".to_string());
+        }
+
+        let mut action = FilterAction::default();
+        if let Some(name) = text(&self.label) {
+            let label_id = labels
+                .iter()
+                .find(|label| {
+                    label
+                        .name
+                        .as_deref()
+                        .is_some_and(|n| n.eq_ignore_ascii_case(&name))
+                })
+                .and_then(|label| label.id.clone())
+                .filter(|id| is_assignable_label(id))
+                .ok_or_else(|| format!("There is no label named '{}'.", name))?;
+            action.add_label_ids.push(label_id);
+        }
+        if self.star {
+            action.add_label_ids.push("STARRED".to_string());
+        }
+        if self.trash {
+            action.add_label_ids.push("TRASH".to_string());
+        }
+        if self.skip_inbox {
+            action.remove_label_ids.push("INBOX".to_string());
+        }
+        if self.mark_read {
+            action.remove_label_ids.push("UNREAD".to_string());
+        }
+        if self.never_spam {
+            action.remove_label_ids.push("SPAM".to_string());
+        }
+        if action == FilterAction::default() {
+            return Err("Pick at least one action.".to_string());
+        }
+
+        Ok(Filter {
+            id: None,
+            criteria,
+            action,
+        })
+    }
+}
+
+// The filters screen: the account's Gmail filters and, while a new one is
+// being written, its form
+#[derive(Debug, Clone, Default)]
+pub struct FiltersScreen {
+    pub filters: Vec<Filter>,
+    pub list_state: ListState,
+    pub form: Option<FilterForm>,
+    // Waiting for y/n before the selected filter is deleted
+    pub confirm_delete: bool,
+    // Why the last change was refused, shown until the next one
+    pub error: Option<String>,
+}
+
+impl FiltersScreen {
+    pub fn selected_filter(&self) -> Option<&Filter> {
+        self.filters.get(self.list_state.selected().unwrap_or(0))
+    }
+
+    pub fn up(&mut self) {
+        let selected = self.list_state.selected().unwrap_or(0);
+        self.list_state.select(Some(selected.saturating_sub(1)));
+    }
+
+    pub fn down(&mut self) {
+        let selected = self.list_state.selected().unwrap_or(0);
+        if selected + 1 < self.filters.len() {
+            self.list_state.select(Some(selected + 1));
+        }
+    }
+
+    // Add a filter Gmail created and select it
+    pub fn add(&mut self, filter: Filter) {
+        self.filters.push(filter);
+        self.list_state.select(Some(self.filters.len() - 1));
+    }
+
+    pub fn remove(&mut self, filter_id: &str) {
+        self.filters
+            .retain(|filter| filter.id.as_deref() != Some(filter_id));
+        let selected = self.list_state.selected().unwrap_or(0);
+        self.list_state
+            .select(Some(selected.min(self.filters.len().saturating_sub(1))));
+    }
+}
+
+// Attachment download running in the background
+#[derive(Debug, Clone)]
+pub struct AttachmentDownload {
+    pub filename: String,
+    // Expected and received size of the attachments.get response in bytes;
+    // `received` is updated by the download task without taking the state lock
+    pub total: u64,
+    pub received: Arc<AtomicU64>,
+}
+
+impl AttachmentDownload {
+    pub fn percent(&self) -> u64 {
+        let received = self.received.load(Ordering::Relaxed);
+        (received * 100)
+            .checked_div(self.total)
+            .unwrap_or(0)
+            .min(99)
+    }
+}
+
+// Where attachments are saved unless --download-dir says otherwise:
+// ~/Downloads if it exists, else the working directory
+pub fn default_download_dir() -> PathBuf {
+    std::env::var_os("HOME")
+        .map(|home| PathBuf::from(home).join("Downloads"))
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+// Where a reply belongs: the conversation and the message it answers
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplyThreading {
+    pub thread_id: Option<String>,
+    pub in_reply_to: Option<String>,
+    pub references: Vec<String>,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum ComposeField {
     To,
@@ -21,6 +453,74 @@ pub enum ComposeField {
     Send,
 }
 
+impl ComposeField {
+    // Name under which the focused field is autosaved
+    pub fn name(&self) -> &'static str {
+        match self {
+            ComposeField::To => "to",
+            ComposeField::Cc => "cc",
+            ComposeField::Bcc => "bcc",
+            ComposeField::Subject => "subject",
+            ComposeField::Body => "body",
+            ComposeField::Send => "send",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "to" => Some(ComposeField::To),
+            "cc" => Some(ComposeField::Cc),
+            "bcc" => Some(ComposeField::Bcc),
+            "subject" => Some(ComposeField::Subject),
+            "body" => Some(ComposeField::Body),
+            "send" => Some(ComposeField::Send),
+            _ => None,
+        }
+    }
+}
+
+// How often at most the message being composed is written to the database
+pub const COMPOSE_AUTOSAVE_INTERVAL: Duration = Duration::from_secs(2);
+
+// What the compose autosave last wrote
+#[derive(Debug, Clone)]
+pub struct ComposeAutosave {
+    pub compose: SavedCompose,
+    // Name and size of each saved attachment, to tell when they need rewriting
+    pub attachments: Vec<(String, usize)>,
+    pub saved_at: Instant,
+}
+
+// How many archive, delete and spam actions 'u' can revert
+const UNDO_LIMIT: usize = 20;
+
+// A message taken out of the list by an action that can be undone
+#[derive(Debug, Clone)]
+pub struct UndoMessage {
+    // The message as it was before the action
+    pub message: Message,
+    // Index in the visible list, and in each per-label cache that held it
+    pub position: usize,
+    pub cache_positions: Vec<(String, usize)>,
+    // Labels the action actually added and removed
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+// An applied archive, delete or spam action
+#[derive(Debug, Clone)]
+pub struct UndoEntry {
+    pub action: MessageAction,
+    // Label whose list the messages were taken out of
+    pub label_id: Option<String>,
+    // Conversation the action changed as a whole, in thread view; it is
+    // reverted as a whole too, including messages that were not loaded
+    pub thread_id: Option<String>,
+    pub messages: Vec<UndoMessage>,
+    // The outbox entry of the action while it waits there
+    pub outbox_id: Option<i64>,
+}
+
 pub struct ComposeState {
     pub to: String,
     pub to_cursor_position: usize,
@@ -35,6 +535,21 @@ pub struct ComposeState {
     pub focused_field: ComposeField,
     pub show_bcc: bool,
     pub sending: bool,
+    pub attachments: Vec<OutgoingAttachment>,
+    // Path typed into the attach-file prompt while it is open
+    pub attachment_input: Option<String>,
+    // Why the last file could not be attached
+    pub attachment_error: Option<String>,
+    // Set when the message is a reply
+    pub threading: Option<ReplyThreading>,
+    // The Gmail draft this message is saved to, once it has been saved
+    pub draft: Option<Draft>,
+}
+
+impl Default for ComposeState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ComposeState {
@@ -53,6 +568,11 @@ impl ComposeState {
             focused_field: ComposeField::To,
             show_bcc: false,
             sending: false,
+            attachments: Vec::new(),
+            attachment_input: None,
+            attachment_error: None,
+            threading: None,
+            draft: None,
         }
     }
 
@@ -70,6 +590,163 @@ impl ComposeState {
         self.focused_field = ComposeField::To;
         self.show_bcc = false;
         self.sending = false;
+        self.attachments.clear();
+        self.attachment_input = None;
+        self.attachment_error = None;
+        self.threading = None;
+        self.draft = None;
+    }
+
+    // Whether anything has been typed or attached, i.e. worth a draft
+    pub fn has_content(&self) -> bool {
+        [&self.to, &self.cc, &self.bcc, &self.subject, &self.body]
+            .iter()
+            .any(|field| !field.trim().is_empty())
+            || !self.attachments.is_empty()
+    }
+
+    pub fn draft_id(&self) -> Option<&str> {
+        self.draft.as_ref().and_then(|draft| draft.id.as_deref())
+    }
+
+    // The message to send from what has been typed so far
+    pub fn to_message(&self) -> OutgoingMessage {
+        OutgoingMessage {
+            to: self.to.clone(),
+            cc: self.cc.clone(),
+            bcc: self.bcc.clone(),
+            subject: self.subject.clone(),
+            body: self.body.clone(),
+            attachments: self.attachments.clone(),
+            in_reply_to: self
+                .threading
+                .as_ref()
+                .and_then(|threading| threading.in_reply_to.clone()),
+            references: self
+                .threading
+                .as_ref()
+                .map(|threading| threading.references.clone())
+                .unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    // Snapshot of the fields, cursors and focus for the autosave
+    pub fn to_saved(&self) -> SavedCompose {
+        let threading = self.threading.clone().unwrap_or_default();
+        SavedCompose {
+            to: self.to.clone(),
+            to_cursor_position: self.to_cursor_position,
+            cc: self.cc.clone(),
+            cc_cursor_position: self.cc_cursor_position,
+            bcc: self.bcc.clone(),
+            bcc_cursor_position: self.bcc_cursor_position,
+            subject: self.subject.clone(),
+            subject_cursor_position: self.subject_cursor_position,
+            body: self.body.clone(),
+            body_cursor_position: self.body_cursor_position,
+            focused_field: self.focused_field.name().to_string(),
+            show_bcc: self.show_bcc,
+            thread_id: threading.thread_id,
+            in_reply_to: threading.in_reply_to,
+            references: threading.references,
+            draft_id: self.draft_id().map(str::to_string),
+            draft_message_id: self
+                .draft
+                .as_ref()
+                .and_then(|draft| draft.message.as_ref())
+                .and_then(|message| message.id.clone()),
+        }
+    }
+
+    // Names and sizes of the attachments, cheap to compare between autosaves
+    pub fn attachment_summary(&self) -> Vec<(String, usize)> {
+        self.attachments
+            .iter()
+            .map(|a| (a.filename.clone(), a.content.len()))
+            .collect()
+    }
+
+    // Put back an autosaved message
+    pub fn restore(&mut self, saved: SavedCompose, attachments: Vec<OutgoingAttachment>) {
+        self.clear();
+        // Cursors are byte offsets, keep them on the text they belong to
+        let cursor = |text: &str, position: usize| {
+            if text.is_char_boundary(position) {
+                position
+            } else {
+                text.len()
+            }
+        };
+        self.to_cursor_position = cursor(&saved.to, saved.to_cursor_position);
+        self.to = saved.to;
+        self.cc_cursor_position = cursor(&saved.cc, saved.cc_cursor_position);
+        self.cc = saved.cc;
+        self.bcc_cursor_position = cursor(&saved.bcc, saved.bcc_cursor_position);
+        self.bcc = saved.bcc;
+        self.subject_cursor_position = cursor(&saved.subject, saved.subject_cursor_position);
+        self.subject = saved.subject;
+        self.body_cursor_position = cursor(&saved.body, saved.body_cursor_position);
+        self.body = saved.body;
+        self.focused_field =
+            ComposeField::from_name(&saved.focused_field).unwrap_or(ComposeField::To);
+        self.show_bcc = saved.show_bcc;
+        self.attachments = attachments;
+        if saved.thread_id.is_some() || saved.in_reply_to.is_some() || !saved.references.is_empty()
+        {
+            self.threading = Some(ReplyThreading {
+                thread_id: saved.thread_id,
+                in_reply_to: saved.in_reply_to,
+                references: saved.references,
+            });
+        }
+        if saved.draft_id.is_some() {
+            self.draft = Some(Draft {
+                id: saved.draft_id,
+                message: saved.draft_message_id.map(|id| MessageRef { id: Some(id) }),
+            });
+        }
+    }
+
+    pub fn open_attachment_input(&mut self) {
+        self.attachment_input = Some(String::new());
+        self.attachment_error = None;
+    }
+
+    // Attach the file at `path` (a leading ~ means the home directory),
+    // keeping the total within what Gmail accepts
+    pub fn attach_file(&mut self, path: &str) {
+        let path = path.trim();
+        let path = match path.strip_prefix("~/") {
+            Some(rest) => std::env::var_os("HOME")
+                .map(|home| Path::new(&home).join(rest))
+                .unwrap_or_else(|| PathBuf::from(path)),
+            None => PathBuf::from(path),
+        };
+
+        match OutgoingAttachment::from_path(&path) {
+            Ok(attachment) => {
+                let total: usize = self
+                    .attachments
+                    .iter()
+                    .map(|a| a.content.len())
+                    .sum::<usize>()
+                    + attachment.content.len();
+                if total > MAX_ATTACHMENTS_SIZE {
+                    self.attachment_error = Some(format!(
+                        "{} is too large: attachments may add up to {} MB",
+                        attachment.filename,
+                        MAX_ATTACHMENTS_SIZE / (1024 * 1024)
+                    ));
+                } else {
+                    self.attachments.push(attachment);
+                    self.attachment_error = None;
+                }
+            }
+            Err(e) => {
+                self.attachment_error = Some(format!("Cannot attach {}: {}", path.display(), e));
+            }
+        }
     }
 }
 
@@ -89,11 +766,22 @@ pub struct AppState {
     pub message_headers: HashMap<String, (String, String)>, // msg_id -> (subject, from)
     pub current_message_display_headers: Option<crate::types::MessageHeadersDisplay>,
     pub client: reqwest::Client,
+    // Gmail API server, overridable to point the app at a local fake server
+    pub api_base_url: String,
     pub token: String,
+    // Renews expired access tokens; when set it supersedes `token`
+    pub token_refresher: Option<Arc<TokenRefresher>>,
+    // Request pacing: quota shared by all requests, retry behaviour for 429/5xx
+    // and whether requests currently come from a background sync
+    pub quota: Arc<QuotaBudget>,
+    pub retry_policy: RetryPolicy,
+    pub request_priority: RequestPriority,
     // Cache for preloaded messages by label ID
     pub label_messages_cache: HashMap<String, Vec<Message>>,
     // Track which labels have been loaded
     pub loaded_labels: HashSet<String>,
+    // Next Gmail pageToken per label ID (None once the last page is loaded)
+    pub label_page_tokens: HashMap<String, Option<String>>,
     // Pagination tracking
     pub messages_per_screen: usize,
     pub current_page: usize,
@@ -109,6 +797,57 @@ pub struct AppState {
     pub error_message: Option<String>,
     // Confirmation dialog for client_secret.json deletion
     pub client_secret_deletion_prompt: bool,
+    // Active account and every account with a local database
+    pub account: String,
+    pub accounts: Vec<String>,
+    // Account switcher popup
+    pub show_account_switcher: bool,
+    pub account_switcher_state: ListState,
+    // Databases of the other accounts, opened on demand for the unified inbox
+    pub account_databases: HashMap<String, Arc<Database>>,
+    // Stored credentials of the other accounts, loaded on demand to keep
+    // their part of the unified inbox up to date
+    pub account_sessions: HashMap<String, AccountSession>,
+    // Owning account of each message shown in the unified inbox (msg_id -> account)
+    pub message_accounts: HashMap<String, String>,
+    // Text typed into the search prompt while it is open
+    pub search_input: Option<String>,
+    // Gmail query (q=) behind the SEARCH virtual label
+    pub search_query: Option<String>,
+    // Highlighted excerpts of the messages matched in the local cache (msg_id -> excerpt)
+    pub search_snippets: HashMap<String, String>,
+    // Group the message list by conversation and show whole threads
+    pub thread_view: bool,
+    // Number of messages in each conversation fetched so far (thread_id -> count)
+    pub thread_sizes: HashMap<String, usize>,
+    pub open_thread: Option<OpenThread>,
+    pub label_prompt: Option<LabelPrompt>,
+    pub label_picker: Option<LabelPicker>,
+    pub purge_prompt: Option<PurgePrompt>,
+    pub filters_screen: Option<FiltersScreen>,
+    // Attachments of every fully fetched message (msg_id -> attachments)
+    pub message_attachments: HashMap<String, Vec<Attachment>>,
+    pub download_dir: PathBuf,
+    pub attachment_download: Option<AttachmentDownload>,
+    // Attachment picker popup
+    pub show_attachment_picker: bool,
+    pub attachment_picker_state: ListState,
+    // Address of the signed-in account, from users.getProfile
+    pub own_email: Option<String>,
+    // What the compose autosave last wrote, None when nothing is saved
+    pub compose_autosave: Option<ComposeAutosave>,
+    // Autosaved message left over from the last run, offered for restoring
+    pub restorable_compose: Option<RestorableCompose>,
+    // Actions waiting to reach Gmail, oldest first
+    pub outbox: Vec<OutboxEntry>,
+    // Archive, delete and spam actions that can be undone, newest last
+    pub undo_stack: Vec<UndoEntry>,
+    // Messages marked for bulk actions
+    pub marked_messages: HashSet<String>,
+    // Message where a range selection started, while one is being made
+    pub mark_anchor: Option<usize>,
+    // Text typed into the mark-matching prompt while it is open
+    pub mark_input: Option<String>,
 }
 
 impl AppState {
@@ -133,9 +872,15 @@ impl AppState {
             message_headers: HashMap::new(),
             current_message_display_headers: None,
             client,
+            api_base_url: DEFAULT_API_BASE_URL.to_string(),
             token,
+            token_refresher: None,
+            quota: Arc::new(QuotaBudget::default()),
+            retry_policy: RetryPolicy::default(),
+            request_priority: RequestPriority::Interactive,
             label_messages_cache: HashMap::new(),
             loaded_labels: HashSet::new(),
+            label_page_tokens: HashMap::new(),
             messages_per_screen: 10, // Default, will be updated based on screen size
             current_page: 0,
             screen_height: 24, // Default, will be updated
@@ -144,6 +889,36 @@ impl AppState {
             use_local_cache: false,
             error_message: None, // Initialize error message as None
             client_secret_deletion_prompt: false,
+            account: DEFAULT_ACCOUNT.to_string(),
+            accounts: vec![DEFAULT_ACCOUNT.to_string()],
+            show_account_switcher: false,
+            account_switcher_state: ListState::default(),
+            account_databases: HashMap::new(),
+            account_sessions: HashMap::new(),
+            message_accounts: HashMap::new(),
+            search_input: None,
+            search_query: None,
+            search_snippets: HashMap::new(),
+            thread_view: false,
+            thread_sizes: HashMap::new(),
+            open_thread: None,
+            label_prompt: None,
+            label_picker: None,
+            purge_prompt: None,
+            filters_screen: None,
+            message_attachments: HashMap::new(),
+            download_dir: default_download_dir(),
+            attachment_download: None,
+            show_attachment_picker: false,
+            attachment_picker_state: ListState::default(),
+            own_email: None,
+            compose_autosave: None,
+            restorable_compose: None,
+            outbox: Vec::new(),
+            undo_stack: Vec::new(),
+            marked_messages: HashSet::new(),
+            mark_anchor: None,
+            mark_input: None,
         }
     }
 
@@ -162,7 +937,313 @@ impl AppState {
     }
 
     pub fn update_message_state(&mut self) {
-        self.message_state.select(Some(self.selected_message));
+        if !self.thread_view || self.selected_message >= self.messages.len() {
+            self.message_state.select(Some(self.selected_message));
+            return;
+        }
+
+        // Only the newest message of each conversation has a row; select its row
+        let rows = self.thread_rows();
+        let row = match rows.binary_search(&self.selected_message) {
+            Ok(row) => row,
+            Err(_) => {
+                let thread_id = self.messages[self.selected_message].thread_id.clone();
+                let row = rows
+                    .iter()
+                    .position(|&i| self.messages[i].thread_id == thread_id)
+                    .unwrap_or(0);
+                self.selected_message = rows.get(row).copied().unwrap_or(0);
+                row
+            }
+        };
+        self.message_state.select(Some(row));
+    }
+
+    // Indexes into `messages` of the rows shown in the message list: every
+    // message, or in thread view the first (newest) message of each thread
+    pub fn thread_rows(&self) -> Vec<usize> {
+        if !self.thread_view {
+            return (0..self.messages.len()).collect();
+        }
+        let mut seen = HashSet::new();
+        (0..self.messages.len())
+            .filter(|&i| match &self.messages[i].thread_id {
+                Some(thread_id) => seen.insert(thread_id.as_str()),
+                None => true,
+            })
+            .collect()
+    }
+
+    // Number of messages in each loaded conversation, as reported by
+    // threads.get once it has been opened, or else as loaded in the list.
+    // Counted in one pass so the list can be drawn without rescanning it
+    pub fn thread_counts(&self) -> HashMap<&str, usize> {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for thread_id in self.messages.iter().filter_map(|m| m.thread_id.as_deref()) {
+            *counts.entry(thread_id).or_default() += 1;
+        }
+        for (thread_id, count) in counts.iter_mut() {
+            if let Some(&size) = self.thread_sizes.get(*thread_id) {
+                *count = size;
+            }
+        }
+        counts
+    }
+
+    // Thread of the selected row while in thread view
+    pub fn selected_thread_id(&self) -> Option<String> {
+        if !self.thread_view {
+            return None;
+        }
+        self.messages
+            .get(self.selected_message)
+            .and_then(|m| m.thread_id.clone())
+    }
+
+    // Ids of the messages on the row of `messages[index]`: the whole
+    // conversation in thread view
+    fn row_message_ids(&self, index: usize) -> Vec<String> {
+        let Some(message) = self.messages.get(index) else {
+            return Vec::new();
+        };
+        match message.thread_id.as_ref().filter(|_| self.thread_view) {
+            Some(thread_id) => self
+                .messages
+                .iter()
+                .filter(|m| m.thread_id.as_ref() == Some(thread_id))
+                .filter_map(|m| m.id.clone())
+                .collect(),
+            None => message.id.clone().into_iter().collect(),
+        }
+    }
+
+    // Mark the selected row for bulk actions, or unmark it if it is marked
+    pub fn toggle_mark(&mut self) {
+        let ids = self.row_message_ids(self.selected_message);
+        if ids.iter().all(|id| self.marked_messages.contains(id)) {
+            for id in &ids {
+                self.marked_messages.remove(id);
+            }
+        } else {
+            self.marked_messages.extend(ids);
+        }
+    }
+
+    // Start a range selection at the selected row, or mark the range that
+    // ends there if one was started
+    pub fn toggle_range_mark(&mut self) {
+        if self.mark_anchor.is_some() {
+            let ids = self.range_message_ids();
+            self.marked_messages.extend(ids);
+            self.mark_anchor = None;
+        } else if !self.messages.is_empty() {
+            self.mark_anchor = Some(self.selected_message);
+        }
+    }
+
+    // Messages between the start of the range selection and the selected row
+    fn range_message_ids(&self) -> Vec<String> {
+        let Some(anchor) = self.mark_anchor else {
+            return Vec::new();
+        };
+        let (first, last) = if anchor <= self.selected_message {
+            (anchor, self.selected_message)
+        } else {
+            (self.selected_message, anchor)
+        };
+        (first..=last.min(self.messages.len().saturating_sub(1)))
+            .flat_map(|index| self.row_message_ids(index))
+            .collect()
+    }
+
+    // Mark every listed message whose sender, subject or snippet contains
+    // `pattern`, ignoring case. Returns how many were marked.
+    pub fn mark_matching(&mut self, pattern: &str) -> usize {
+        let pattern = pattern.trim().to_lowercase();
+        let matching: Vec<String> = self
+            .messages
+            .iter()
+            .filter_map(|m| {
+                let id = m.id.as_ref()?;
+                let (subject, from) = self.message_headers.get(id).cloned().unwrap_or_default();
+                let snippet = m.snippet.as_deref().unwrap_or("");
+                [subject.as_str(), from.as_str(), snippet]
+                    .iter()
+                    .any(|text| text.to_lowercase().contains(&pattern))
+                    .then(|| id.clone())
+            })
+            .collect();
+        let count = matching.len();
+        self.marked_messages.extend(matching);
+        count
+    }
+
+    pub fn clear_marks(&mut self) {
+        self.marked_messages.clear();
+        self.mark_anchor = None;
+    }
+
+    // Whether there is a selection for bulk actions
+    pub fn has_marks(&self) -> bool {
+        !self.marked_message_ids().is_empty()
+    }
+
+    // Whether the row of `messages[index]` is marked or in the range being selected
+    pub fn is_marked(&self, index: usize) -> bool {
+        let in_range = self.mark_anchor.is_some_and(|anchor| {
+            let (first, last) = if anchor <= self.selected_message {
+                (anchor, self.selected_message)
+            } else {
+                (self.selected_message, anchor)
+            };
+            (first..=last).contains(&index)
+        });
+        in_range
+            || self
+                .messages
+                .get(index)
+                .and_then(|m| m.id.as_ref())
+                .is_some_and(|id| self.marked_messages.contains(id))
+    }
+
+    // Ids of the marked messages and of the range being selected, in list order
+    pub fn marked_message_ids(&self) -> Vec<String> {
+        let range: HashSet<String> = self.range_message_ids().into_iter().collect();
+        self.messages
+            .iter()
+            .filter_map(|m| m.id.clone())
+            .filter(|id| self.marked_messages.contains(id) || range.contains(id))
+            .collect()
+    }
+
+    // Messages an action applies to: the marked ones, or else the selected
+    // one, with its whole conversation in thread view
+    pub fn action_message_ids(&self) -> Vec<String> {
+        let marked = self.marked_message_ids();
+        if marked.is_empty() {
+            self.row_message_ids(self.selected_message)
+        } else {
+            marked
+        }
+    }
+
+    // Whether any marked message satisfies `predicate`
+    pub fn marked_messages_any(&self, predicate: impl Fn(&Message) -> bool) -> bool {
+        let marked = self.marked_message_ids();
+        self.messages
+            .iter()
+            .filter(|m| m.id.as_ref().is_some_and(|id| marked.contains(id)))
+            .any(predicate)
+    }
+
+    // Account of a marked message that belongs to another account, if any
+    pub fn marked_foreign_account(&self) -> Option<String> {
+        self.marked_message_ids().iter().find_map(|id| {
+            self.message_accounts
+                .get(id)
+                .filter(|account| **account != self.account)
+                .cloned()
+        })
+    }
+
+    pub fn open_mark_prompt(&mut self) {
+        self.mark_input = Some(String::new());
+    }
+
+    pub fn close_mark_prompt(&mut self) {
+        self.mark_input = None;
+    }
+
+    pub fn toggle_thread_view(&mut self) {
+        self.thread_view = !self.thread_view;
+        self.open_thread = None;
+        self.content_scroll_offset = 0;
+        self.update_message_state();
+        self.update_current_message_display_headers();
+    }
+
+    // Show a fetched conversation with only its latest message expanded
+    pub fn open_thread(&mut self, thread_id: &str, mut messages: Vec<ThreadMessage>) {
+        if let Some(latest) = messages.last_mut() {
+            latest.expanded = true;
+        }
+        self.thread_sizes
+            .insert(thread_id.to_string(), messages.len());
+        self.open_thread = Some(OpenThread {
+            id: thread_id.to_string(),
+            selected: messages.len().saturating_sub(1),
+            messages,
+        });
+        self.content_scroll_offset = 0;
+    }
+
+    // The open conversation, if it belongs to the selected row
+    pub fn current_thread(&self) -> Option<&OpenThread> {
+        let thread_id = self.selected_thread_id()?;
+        self.open_thread
+            .as_ref()
+            .filter(|thread| thread.id == thread_id)
+    }
+
+    pub fn thread_select_next(&mut self) {
+        if let Some(thread) = self.open_thread.as_mut() {
+            if thread.selected + 1 < thread.messages.len() {
+                thread.selected += 1;
+            }
+        }
+    }
+
+    pub fn thread_select_prev(&mut self) {
+        if let Some(thread) = self.open_thread.as_mut() {
+            thread.selected = thread.selected.saturating_sub(1);
+        }
+    }
+
+    // Expand or collapse the selected message of the open conversation
+    pub fn toggle_thread_message(&mut self) {
+        if let Some(thread) = self.open_thread.as_mut() {
+            if let Some(message) = thread.messages.get_mut(thread.selected) {
+                message.expanded = !message.expanded;
+            }
+        }
+    }
+
+    // Ids of the messages of a conversation, from the list and the open thread
+    pub fn thread_message_ids(&self, thread_id: &str) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .messages
+            .iter()
+            .filter(|m| m.thread_id.as_deref() == Some(thread_id))
+            .filter_map(|m| m.id.clone())
+            .collect();
+        if let Some(thread) = self.open_thread.as_ref().filter(|t| t.id == thread_id) {
+            for message in &thread.messages {
+                if !ids.contains(&message.id) {
+                    ids.push(message.id.clone());
+                }
+            }
+        }
+        ids
+    }
+
+    // Drop every message of a conversation from the list, after it was archived,
+    // deleted or marked as spam as a whole
+    pub fn remove_thread(&mut self, thread_id: &str) {
+        self.messages
+            .retain(|m| m.thread_id.as_deref() != Some(thread_id));
+        self.thread_sizes.remove(thread_id);
+        if self
+            .open_thread
+            .as_ref()
+            .is_some_and(|thread| thread.id == thread_id)
+        {
+            self.open_thread = None;
+        }
+        self.selected_message = self
+            .selected_message
+            .min(self.messages.len().saturating_sub(1));
+        self.content_scroll_offset = 0;
+        self.update_message_state();
     }
 
     // Get messages for a label from cache or current messages
@@ -202,6 +1283,201 @@ impl AppState {
         }
     }
 
+    // Remove a message from the visible list and the per-label caches,
+    // keeping the selection within bounds. Returns true if it was visible.
+    pub fn remove_message(&mut self, message_id: &str) -> bool {
+        for cached_messages in self.label_messages_cache.values_mut() {
+            cached_messages.retain(|m| m.id.as_deref() != Some(message_id));
+        }
+
+        let Some(index) = self
+            .messages
+            .iter()
+            .position(|m| m.id.as_deref() == Some(message_id))
+        else {
+            return false;
+        };
+
+        self.messages.remove(index);
+        if index < self.selected_message
+            || (self.selected_message >= self.messages.len() && self.selected_message > 0)
+        {
+            self.selected_message -= 1;
+        }
+        self.update_message_state();
+        true
+    }
+
+    // Forget a message deleted for good: drop it from the list, the per-label
+    // caches and the database
+    pub async fn record_message_deleted(&mut self, message_id: &str) {
+        if let Some(db) = &self.database {
+            let _ = db.delete_message(message_id).await;
+        }
+        self.remove_message(message_id);
+    }
+
+    // Record the labels Gmail reports for a message, as the change from the
+    // ones it had before
+    pub async fn record_labels(&mut self, message_id: &str, label_ids: &[String]) {
+        let known = self
+            .messages
+            .iter()
+            .chain(self.label_messages_cache.values().flatten())
+            .find(|m| m.id.as_deref() == Some(message_id))
+            .and_then(|m| m.label_ids.clone());
+        let add: Vec<String> = label_ids
+            .iter()
+            .filter(|id| !known.as_ref().is_some_and(|known| known.contains(id)))
+            .cloned()
+            .collect();
+        let remove: Vec<String> = known
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|id| !label_ids.contains(id))
+            .collect();
+        self.record_label_change(message_id, &add, &remove).await;
+    }
+
+    // Apply a label change made on the server to the visible list, the
+    // in-memory label caches and the database
+    pub async fn record_label_change(
+        &mut self,
+        message_id: &str,
+        add: &[String],
+        remove: &[String],
+    ) {
+        let visible = self.messages.iter_mut();
+        let cached = self.label_messages_cache.values_mut().flatten();
+        for message in visible
+            .chain(cached)
+            .filter(|m| m.id.as_deref() == Some(message_id))
+        {
+            let label_ids = message.label_ids.get_or_insert_with(Vec::new);
+            label_ids.retain(|id| !remove.contains(id));
+            for id in add {
+                if !label_ids.contains(id) {
+                    label_ids.push(id.clone());
+                }
+            }
+        }
+
+        if let Some(db) = &self.database {
+            if !add.is_empty() {
+                let _ = db.add_message_labels(message_id, add).await;
+            }
+            if !remove.is_empty() {
+                let _ = db.remove_message_labels(message_id, remove).await;
+            }
+        }
+    }
+
+    // Snapshot the listed messages `message_ids` before `action` changes their
+    // labels and takes them out of the list, so that it can be undone
+    pub fn undo_messages(&self, message_ids: &[String], action: MessageAction) -> Vec<UndoMessage> {
+        let (add, remove) = action.label_changes();
+        message_ids
+            .iter()
+            .filter_map(|message_id| {
+                let position = self
+                    .messages
+                    .iter()
+                    .position(|m| m.id.as_deref() == Some(message_id.as_str()))?;
+                let message = self.messages[position].clone();
+                let cache_positions = self
+                    .label_messages_cache
+                    .iter()
+                    .filter_map(|(label_id, cached)| {
+                        cached
+                            .iter()
+                            .position(|m| m.id.as_deref() == Some(message_id.as_str()))
+                            .map(|index| (label_id.clone(), index))
+                    })
+                    .collect();
+                // Without known labels assume the action changed all it touches
+                let (added, removed) = match &message.label_ids {
+                    Some(labels) => (
+                        add.iter()
+                            .filter(|l| !labels.contains(l))
+                            .cloned()
+                            .collect(),
+                        remove
+                            .iter()
+                            .filter(|l| labels.contains(l))
+                            .cloned()
+                            .collect(),
+                    ),
+                    None => (add.clone(), remove.clone()),
+                };
+                Some(UndoMessage {
+                    message,
+                    position,
+                    cache_positions,
+                    added,
+                    removed,
+                })
+            })
+            .collect()
+    }
+
+    pub fn push_undo(&mut self, entry: UndoEntry) {
+        self.undo_stack.push(entry);
+        if self.undo_stack.len() > UNDO_LIMIT {
+            self.undo_stack.remove(0);
+        }
+    }
+
+    // Put messages taken out by an undone action back where they were, in the
+    // list if it still shows label `label_id` and in the per-label caches.
+    // The first of them is selected.
+    pub fn reinsert_messages(&mut self, label_id: Option<&str>, mut messages: Vec<UndoMessage>) {
+        messages.sort_by_key(|undo| undo.position);
+        for undo in &messages {
+            let message_id = undo.message.id.as_deref();
+            for (cache_label, index) in &undo.cache_positions {
+                if let Some(cached) = self.label_messages_cache.get_mut(cache_label) {
+                    if !cached.iter().any(|m| m.id.as_deref() == message_id) {
+                        cached.insert((*index).min(cached.len()), undo.message.clone());
+                    }
+                }
+            }
+        }
+
+        let current_label = self.get_current_label().and_then(|l| l.id.as_deref());
+        if label_id.is_none() || current_label != label_id {
+            return;
+        }
+        let mut selected = None;
+        for undo in messages {
+            if self
+                .messages
+                .iter()
+                .any(|m| m.id.is_some() && m.id == undo.message.id)
+            {
+                continue;
+            }
+            let position = undo.position.min(self.messages.len());
+            self.messages.insert(position, undo.message);
+            selected.get_or_insert(position);
+        }
+        if let Some(position) = selected {
+            self.selected_message = position;
+            self.content_scroll_offset = 0;
+            self.update_message_state();
+            self.update_current_message_display_headers();
+        }
+    }
+
+    // Insert a newly arrived message at the top of the visible list,
+    // keeping the same message selected
+    pub fn insert_message_at_top(&mut self, message: Message) {
+        if !self.messages.is_empty() {
+            self.selected_message += 1;
+        }
+        self.messages.insert(0, message);
+        self.update_message_state();
+    }
+
     // Update screen dimensions and calculate messages per screen
     pub fn update_screen_size(&mut self, height: u16) {
         self.screen_height = height;
@@ -228,6 +1504,7 @@ impl AppState {
     pub fn reset_pagination(&mut self) {
         self.current_page = 0;
         self.selected_message = 0;
+        self.clear_marks();
         self.update_message_state();
         // Reset content scroll when changing messages
         self.content_scroll_offset = 0;
@@ -249,6 +1526,12 @@ impl AppState {
         let mut priority_labels = Vec::new();
         let mut other_labels = Vec::new();
 
+        // The unified inbox and the outbox are re-added below, only when they are needed
+        self.labels.retain(|l| {
+            let id = l.id.as_deref().unwrap_or("");
+            !is_unified_inbox(id) && !is_outbox_label(id)
+        });
+
         // First, collect priority labels in order
         for priority_id in &priority_order {
             if let Some(pos) = self
@@ -260,6 +1543,21 @@ impl AppState {
             }
         }
 
+        // Add the unified inbox right after this account's inbox
+        if self.accounts.len() > 1 {
+            let position = priority_labels
+                .iter()
+                .position(|l| l.id.as_deref().unwrap_or("").to_uppercase() == "INBOX")
+                .map_or(0, |inbox| inbox + 1);
+            priority_labels.insert(
+                position,
+                Label {
+                    id: Some(UNIFIED_INBOX_LABEL_ID.to_string()),
+                    name: Some("ALL INBOXES".to_string()),
+                },
+            );
+        }
+
         // Add "All Mail" if it doesn't exist but we have other labels
         if !priority_labels
             .iter()
@@ -279,6 +1577,23 @@ impl AppState {
         // Combine priority labels first, then others
         self.labels = priority_labels;
         self.labels.append(&mut other_labels);
+
+        if !self.outbox.is_empty() {
+            let position = self.outbox_label_position();
+            self.labels.insert(position, outbox_label());
+        }
+    }
+
+    // The outbox folder follows Drafts, or Sent
+    fn outbox_label_position(&self) -> usize {
+        ["DRAFT", "SENT"]
+            .iter()
+            .find_map(|id| {
+                self.labels
+                    .iter()
+                    .position(|l| l.id.as_deref().unwrap_or("").eq_ignore_ascii_case(id))
+            })
+            .map_or(0, |position| position + 1)
     }
 
     // Navigation methods for pane-based movement
@@ -291,8 +1606,10 @@ impl AppState {
                 }
             }
             FocusedPane::Messages => {
-                if self.selected_message > 0 {
-                    self.selected_message -= 1;
+                // In thread view, skip the older messages of the previous thread
+                let rows = self.thread_rows();
+                if let Some(&previous) = rows.iter().rev().find(|&&i| i < self.selected_message) {
+                    self.selected_message = previous;
                     self.update_message_state();
                     self.update_current_message_display_headers(); // Update headers on selection change
                 }
@@ -315,8 +1632,10 @@ impl AppState {
                 }
             }
             FocusedPane::Messages => {
-                if self.selected_message + 1 < self.messages.len() {
-                    self.selected_message += 1;
+                // In thread view, skip the older messages of the current thread
+                let rows = self.thread_rows();
+                if let Some(&next) = rows.iter().find(|&&i| i > self.selected_message) {
+                    self.selected_message = next;
                     self.update_message_state();
                     self.update_current_message_display_headers(); // Update headers on selection change
                 }
@@ -417,11 +1736,199 @@ impl AppState {
         self.compose_state.focused_field = initial_focus.unwrap_or(ComposeField::To);
     }
 
+    // Open the compose window with a reply (to all) to `original`, threaded
+    // into its conversation and quoting its text
+    pub fn start_reply(&mut self, original: &Message, reply_all: bool) {
+        let payload = original.payload.clone().unwrap_or_default();
+        let header = |name| header_value(&payload, name).unwrap_or_default();
+
+        let body = extract_plain_text_body(&payload).unwrap_or_default();
+        let sender = Some(header("Reply-To"))
+            .filter(|reply_to| !reply_to.trim().is_empty())
+            .unwrap_or(header("From"));
+        let own: Vec<String> = self.own_email.iter().cloned().collect();
+        let (to, cc) = reply_recipients(sender, header("To"), header("Cc"), &own, reply_all);
+        self.start_composing(
+            Some(to),
+            Some(cc),
+            Some(reply_subject(header("Subject"))),
+            Some(quote_reply(header("Date"), header("From"), &body)),
+            Some(ComposeField::Body),
+        );
+
+        let message_id = header("Message-ID");
+        self.compose_state.threading = Some(ReplyThreading {
+            thread_id: original.thread_id.clone(),
+            in_reply_to: Some(message_id.to_string()).filter(|id| !id.is_empty()),
+            references: reply_references(
+                header_value(&payload, "References"),
+                header_value(&payload, "In-Reply-To"),
+                message_id,
+            ),
+        });
+    }
+
+    // Open the compose window with `original` forwarded inline: its headers
+    // and text in the body, its attachments (already fetched) attached
+    pub fn start_forward(&mut self, original: &Message, attachments: Vec<OutgoingAttachment>) {
+        let payload = original.payload.clone().unwrap_or_default();
+        let header = |name| header_value(&payload, name).unwrap_or_default();
+
+        let body = extract_plain_text_body(&payload).unwrap_or_default();
+        self.start_composing(
+            None,
+            None,
+            Some(forward_subject(header("Subject"))),
+            Some(forwarded_block(
+                header("From"),
+                header("Date"),
+                header("Subject"),
+                header("To"),
+                header("Cc"),
+                &body,
+            )),
+            None,
+        );
+        self.compose_state.attachments = attachments;
+    }
+
+    // Open the compose window with `raw`, the original message as received,
+    // attached as a message/rfc822 file
+    pub fn start_forward_as_attachment(&mut self, original: &Message, raw: Vec<u8>) {
+        let payload = original.payload.clone().unwrap_or_default();
+        let subject = header_value(&payload, "Subject").unwrap_or_default();
+
+        let name = subject.replace(['/', '\\', ':', '*', '?', '"', '<', '>', '|'], "_");
+        let name = match name.trim() {
+            "" => "Forwarded message",
+            name => name,
+        };
+        self.start_composing(None, None, Some(forward_subject(subject)), None, None);
+        self.compose_state.attachments = vec![OutgoingAttachment {
+            filename: format!("{}.eml", name),
+            content_type: "message/rfc822".to_string(),
+            content: raw,
+        }];
+    }
+
+    // Open draft `draft` (whose message is `message`) in the compose window,
+    // with its attachments (already fetched)
+    pub fn start_draft(
+        &mut self,
+        draft: Draft,
+        message: &Message,
+        attachments: Vec<OutgoingAttachment>,
+    ) {
+        let payload = message.payload.clone().unwrap_or_default();
+        let header = |name| Some(header_value(&payload, name).unwrap_or_default().to_string());
+
+        self.start_composing(
+            header("To"),
+            header("Cc"),
+            header("Subject"),
+            extract_plain_text_body(&payload),
+            None,
+        );
+        if let Some(bcc) = header("Bcc").filter(|bcc| !bcc.is_empty()) {
+            self.compose_state.bcc_cursor_position = bcc.len();
+            self.compose_state.bcc = bcc;
+            self.compose_state.show_bcc = true;
+        }
+        self.compose_state.attachments = attachments;
+        // A draft reply stays in its conversation
+        if let Some(in_reply_to) = header_value(&payload, "In-Reply-To") {
+            self.compose_state.threading = Some(ReplyThreading {
+                thread_id: message.thread_id.clone(),
+                in_reply_to: Some(in_reply_to.to_string()),
+                references: parse_message_ids(
+                    header_value(&payload, "References").unwrap_or_default(),
+                ),
+            });
+        }
+        self.compose_state.draft = Some(draft);
+    }
+
     pub fn stop_composing(&mut self) {
         self.composing = false;
         self.compose_state.clear();
     }
 
+    // Persist the message being composed so it survives a crash. Called from the
+    // main loop: writes at most every COMPOSE_AUTOSAVE_INTERVAL and only when
+    // something changed, and drops the saved copy once composing has ended.
+    pub async fn autosave_compose(&mut self) {
+        let Some(db) = self.database.clone() else {
+            return;
+        };
+
+        if !self.composing || !self.compose_state.has_content() {
+            if self.compose_autosave.take().is_some() {
+                let _ = db.clear_compose().await;
+            }
+            return;
+        }
+
+        if self
+            .compose_autosave
+            .as_ref()
+            .is_some_and(|saved| saved.saved_at.elapsed() < COMPOSE_AUTOSAVE_INTERVAL)
+        {
+            return;
+        }
+
+        let compose = self.compose_state.to_saved();
+        let attachments = self.compose_state.attachment_summary();
+        let (compose_changed, attachments_changed) = match &self.compose_autosave {
+            Some(saved) => (saved.compose != compose, saved.attachments != attachments),
+            None => (true, true),
+        };
+        if !compose_changed && !attachments_changed {
+            return;
+        }
+
+        // Attachments are only rewritten when they changed
+        let attachment_content =
+            attachments_changed.then_some(self.compose_state.attachments.as_slice());
+        if db.save_compose(&compose, attachment_content).await.is_ok() {
+            self.compose_autosave = Some(ComposeAutosave {
+                compose,
+                attachments,
+                saved_at: Instant::now(),
+            });
+        }
+    }
+
+    // Look for a message that was still being composed when the app last stopped
+    pub async fn load_restorable_compose(&mut self) {
+        if let Some(db) = &self.database {
+            self.restorable_compose = db.load_compose().await.unwrap_or(None);
+        }
+    }
+
+    // Reopen the compose window with the autosaved message
+    pub fn restore_compose(&mut self) {
+        let Some(restorable) = self.restorable_compose.take() else {
+            return;
+        };
+        self.compose_state
+            .restore(restorable.compose.clone(), restorable.attachments);
+        self.composing = true;
+        // It is in the database already
+        self.compose_autosave = Some(ComposeAutosave {
+            compose: restorable.compose,
+            attachments: self.compose_state.attachment_summary(),
+            saved_at: Instant::now(),
+        });
+    }
+
+    pub async fn discard_restorable_compose(&mut self) {
+        if self.restorable_compose.take().is_some() {
+            if let Some(db) = &self.database {
+                let _ = db.clear_compose().await;
+            }
+        }
+    }
+
     pub fn compose_next_field(&mut self) {
         use ComposeField::*;
         self.compose_state.focused_field = match self.compose_state.focused_field {
@@ -465,6 +1972,178 @@ impl AppState {
         }
     }
 
+    pub async fn load_outbox(&mut self) {
+        if let Some(db) = &self.database {
+            self.outbox = db.get_outbox().await.unwrap_or_default();
+        }
+    }
+
+    // Whether something waits to be replayed; refused entries wait for the user instead
+    pub fn has_pending_outbox(&self) -> bool {
+        self.outbox.iter().any(|entry| entry.error.is_none())
+    }
+
+    // TRASH or SPAM when one of them is the shown folder
+    pub fn trash_or_spam_folder(&self) -> Option<String> {
+        self.get_current_label()
+            .and_then(|label| label.id.clone())
+            .filter(|id| id == "TRASH" || id == "SPAM")
+    }
+
+    pub fn open_delete_forever_prompt(&mut self) {
+        let message_ids = self.action_message_ids();
+        if !message_ids.is_empty() {
+            self.purge_prompt = Some(PurgePrompt::DeleteForever { message_ids });
+        }
+    }
+
+    pub fn open_empty_folder_prompt(&mut self) {
+        if let Some(label_id) = self.trash_or_spam_folder() {
+            self.purge_prompt = Some(PurgePrompt::EmptyFolder { label_id });
+        }
+    }
+
+    pub fn close_purge_prompt(&mut self) {
+        self.purge_prompt = None;
+    }
+
+    // Show the filters screen with `filters`, and with `form` open when a
+    // filter is to be written right away
+    pub fn open_filters_screen(&mut self, filters: Vec<Filter>, form: Option<FilterForm>) {
+        let mut list_state = ListState::default();
+        list_state.select(Some(0));
+        self.filters_screen = Some(FiltersScreen {
+            filters,
+            list_state,
+            form,
+            ..Default::default()
+        });
+    }
+
+    pub fn close_filters_screen(&mut self) {
+        self.filters_screen = None;
+    }
+
+    pub fn showing_outbox(&self) -> bool {
+        self.get_current_label()
+            .and_then(|label| label.id.as_deref())
+            .is_some_and(is_outbox_label)
+    }
+
+    // The outbox entry selected in the Outbox folder
+    pub fn selected_outbox_entry(&self) -> Option<&OutboxEntry> {
+        if !self.showing_outbox() {
+            return None;
+        }
+        let id: i64 = self
+            .messages
+            .get(self.selected_message)?
+            .id
+            .as_deref()?
+            .strip_prefix(OUTBOX_MESSAGE_PREFIX)?
+            .parse()
+            .ok()?;
+        self.outbox.iter().find(|entry| entry.id == id)
+    }
+
+    // Show the outbox folder while it has entries or is being looked at,
+    // and keep its list current
+    pub fn refresh_outbox_view(&mut self) {
+        let showing = self.showing_outbox();
+        let position = self
+            .labels
+            .iter()
+            .position(|l| is_outbox_label(l.id.as_deref().unwrap_or("")));
+        match position {
+            Some(position) if self.outbox.is_empty() && !showing => {
+                self.labels.remove(position);
+                if self.selected_label > position {
+                    self.selected_label -= 1;
+                }
+                self.update_label_state();
+            }
+            None if !self.outbox.is_empty() => {
+                let position = self.outbox_label_position();
+                self.labels.insert(position, outbox_label());
+                if self.selected_label >= position && self.labels.len() > 1 {
+                    self.selected_label += 1;
+                }
+                self.update_label_state();
+            }
+            _ => {}
+        }
+
+        if showing {
+            self.show_outbox();
+        }
+    }
+
+    // List the outbox entries as messages of the Outbox folder
+    fn show_outbox(&mut self) {
+        self.messages = self
+            .outbox
+            .iter()
+            .rev()
+            .map(|entry| Message {
+                id: Some(format!("{}{}", OUTBOX_MESSAGE_PREFIX, entry.id)),
+                snippet: Some(match &entry.error {
+                    Some(error) => format!("Failed: {}", error),
+                    None => "Waiting for a connection".to_string(),
+                }),
+                payload: None,
+                thread_id: None,
+                label_ids: Some(vec![OUTBOX_LABEL_ID.to_string()]),
+            })
+            .collect();
+
+        for entry in &self.outbox {
+            let id = format!("{}{}", OUTBOX_MESSAGE_PREFIX, entry.id);
+            let subject = if entry.subject.is_empty() {
+                "(no subject)".to_string()
+            } else {
+                entry.subject.clone()
+            };
+            let status = match &entry.error {
+                Some(error) => format!(
+                    "Gmail refused it: {}\n\nPress r to try again or d to cancel it.",
+                    error
+                ),
+                None => "Waiting for a connection to Gmail.\n\nPress d to cancel it.".to_string(),
+            };
+            self.message_bodies.insert(
+                id.clone(),
+                format!("{}\nSubject: {}\n\n{}", entry.description, subject, status),
+            );
+            self.message_bodies
+                .insert(format!("{}_date", id), entry.created_at.to_rfc2822());
+            self.message_headers
+                .insert(id, (subject, entry.description.clone()));
+        }
+
+        self.selected_message = self
+            .selected_message
+            .min(self.messages.len().saturating_sub(1));
+        self.update_message_state();
+        self.update_current_message_display_headers();
+    }
+
+    // A copy of what the Gmail API helpers read, so requests can be made
+    // without holding the state lock. The database is shared.
+    pub fn request_context(&self) -> AppState {
+        let mut context = AppState::new(self.client.clone(), self.token.clone());
+        context.database = self.database.clone();
+        context.api_base_url = self.api_base_url.clone();
+        context.token_refresher = self.token_refresher.clone();
+        context.quota = self.quota.clone();
+        context.retry_policy = self.retry_policy.clone();
+        context.request_priority = self.request_priority;
+        context.labels = self.labels.clone();
+        context.selected_label = self.selected_label;
+        context.search_query = self.search_query.clone();
+        context.messages_per_screen = self.messages_per_screen;
+        context
+    }
+
     // Database and sync integration methods
     pub fn set_database(&mut self, database: Arc<Database>) {
         self.database = Some(database);
@@ -476,10 +2155,35 @@ impl AppState {
         &mut self,
         label_id: &str,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        if let Some(db) = &self.database {
-            let cached_messages = db
-                .get_messages_for_label(label_id, self.messages_per_screen as i64, 0)
-                .await?;
+        if is_outbox_label(label_id) {
+            self.show_outbox();
+            return Ok(());
+        }
+
+        // Gmail search results are only kept in memory for the current query
+        if is_search_label(label_id) {
+            if let Some(cached_messages) = self.label_messages_cache.get(label_id) {
+                self.messages = cached_messages.clone();
+                self.selected_message = self
+                    .selected_message
+                    .min(self.messages.len().saturating_sub(1));
+                self.update_message_state();
+                return Ok(());
+            }
+        }
+
+        if let Some(db) = self.database.clone() {
+            let cached_messages = if is_unified_inbox(label_id) {
+                self.load_unified_inbox(self.messages_per_screen).await?
+            } else if is_search_label(label_id) {
+                // Without Gmail's results, answer the search from the full-text index
+                self.message_accounts.clear();
+                self.search_cache(&db).await?
+            } else {
+                self.message_accounts.clear();
+                db.get_messages_for_label(label_id, self.messages_per_screen as i64, 0)
+                    .await?
+            };
 
             // Capture the ID of the currently selected message before updating the list
             let current_selected_message_id = self
@@ -495,7 +2199,7 @@ impl AppState {
                     snippet: cached.snippet.clone(),
                     payload: None,
                     thread_id: cached.thread_id.clone(),
-                    label_ids: Some(cached.label_ids.clone()),
+                    label_ids: Some(cached.label_ids_with_flags()),
                 })
                 .collect();
 
@@ -566,6 +2270,429 @@ impl AppState {
         Ok(())
     }
 
+    // Merge the newest cached INBOX messages of every account, remembering
+    // which account each message belongs to
+    async fn load_unified_inbox(
+        &mut self,
+        limit: usize,
+    ) -> Result<Vec<CachedMessage>, Box<dyn std::error::Error>> {
+        let mut merged = Vec::new();
+        self.message_accounts.clear();
+
+        for account in self.accounts.clone() {
+            let db = if account == self.account {
+                match &self.database {
+                    Some(db) => db.clone(),
+                    None => continue,
+                }
+            } else {
+                self.account_database(&account).await?
+            };
+
+            for message in db.get_messages_for_label("INBOX", limit as i64, 0).await? {
+                self.message_accounts
+                    .insert(message.id.clone(), account.clone());
+                merged.push(message);
+            }
+        }
+
+        merged.sort_by_key(|message| std::cmp::Reverse(message.internal_date));
+        merged.truncate(limit);
+        Ok(merged)
+    }
+
+    // Open (once) the database of another account
+    async fn account_database(
+        &mut self,
+        account: &str,
+    ) -> Result<Arc<Database>, Box<dyn std::error::Error>> {
+        if let Some(db) = self.account_databases.get(account) {
+            return Ok(db.clone());
+        }
+        let db = Arc::new(Database::new(&database_url(account)).await?);
+        self.account_databases
+            .insert(account.to_string(), db.clone());
+        Ok(db)
+    }
+
+    // A request context that acts on behalf of `account`, with its own
+    // credentials and database, showing its INBOX
+    pub async fn account_inbox_context(
+        &mut self,
+        account: &str,
+    ) -> Result<AppState, Box<dyn std::error::Error>> {
+        let mut context = self.request_context();
+        if account != self.account {
+            let session = match self.account_sessions.get(account) {
+                Some(session) => session.clone(),
+                None => {
+                    let session = stored_session(account).await?;
+                    self.account_sessions
+                        .insert(account.to_string(), session.clone());
+                    session
+                }
+            };
+            context.token = session.token;
+            context.token_refresher = session.token_refresher;
+            context.set_database(self.account_database(account).await?);
+        }
+        let inbox = Label {
+            id: Some("INBOX".to_string()),
+            name: Some("INBOX".to_string()),
+        };
+        // Cached messages refer to their labels
+        if let Some(db) = &context.database {
+            db.upsert_label(&inbox).await?;
+        }
+        context.account = account.to_string();
+        context.labels = vec![inbox];
+        context.selected_label = 0;
+        context.search_query = None;
+        Ok(context)
+    }
+
+    // The account owning the selected message, if it is not the active one.
+    // Such messages come from the unified inbox and can only be read from cache.
+    pub fn selected_message_foreign_account(&self) -> Option<String> {
+        let msg_id = self.messages.get(self.selected_message)?.id.as_ref()?;
+        self.message_accounts
+            .get(msg_id)
+            .filter(|account| **account != self.account)
+            .cloned()
+    }
+
+    // Message shown in the content pane: the selected message of an open
+    // conversation, or else the selected message of the list
+    pub fn displayed_message_id(&self) -> Option<String> {
+        if let Some(thread) = self.current_thread() {
+            return thread.messages.get(thread.selected).map(|m| m.id.clone());
+        }
+        self.messages
+            .get(self.selected_message)
+            .and_then(|m| m.id.clone())
+    }
+
+    pub fn current_attachments(&self) -> &[Attachment] {
+        self.displayed_message_id()
+            .and_then(|id| self.message_attachments.get(&id))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    pub fn open_attachment_picker(&mut self) {
+        if self.current_attachments().is_empty() {
+            self.set_error_message(
+                "This message has no attachments, or it has not been opened yet.".to_string(),
+            );
+            return;
+        }
+        self.attachment_picker_state.select(Some(0));
+        self.show_attachment_picker = true;
+    }
+
+    pub fn close_attachment_picker(&mut self) {
+        self.show_attachment_picker = false;
+    }
+
+    pub fn attachment_picker_up(&mut self) {
+        let selected = self.attachment_picker_state.selected().unwrap_or(0);
+        self.attachment_picker_state
+            .select(Some(selected.saturating_sub(1)));
+    }
+
+    pub fn attachment_picker_down(&mut self) {
+        let selected = self.attachment_picker_state.selected().unwrap_or(0);
+        if selected + 1 < self.current_attachments().len() {
+            self.attachment_picker_state.select(Some(selected + 1));
+        }
+    }
+
+    pub fn selected_attachment(&self) -> Option<Attachment> {
+        let selected = self.attachment_picker_state.selected().unwrap_or(0);
+        self.current_attachments().get(selected).cloned()
+    }
+
+    pub fn open_account_switcher(&mut self) {
+        let current = self
+            .accounts
+            .iter()
+            .position(|account| *account == self.account)
+            .unwrap_or(0);
+        self.account_switcher_state.select(Some(current));
+        self.show_account_switcher = true;
+    }
+
+    pub fn close_account_switcher(&mut self) {
+        self.show_account_switcher = false;
+    }
+
+    pub fn account_switcher_up(&mut self) {
+        let selected = self.account_switcher_state.selected().unwrap_or(0);
+        self.account_switcher_state
+            .select(Some(selected.saturating_sub(1)));
+    }
+
+    pub fn account_switcher_down(&mut self) {
+        let selected = self.account_switcher_state.selected().unwrap_or(0);
+        if selected + 1 < self.accounts.len() {
+            self.account_switcher_state.select(Some(selected + 1));
+        }
+    }
+
+    pub fn selected_switcher_account(&self) -> Option<&String> {
+        self.accounts
+            .get(self.account_switcher_state.selected().unwrap_or(0))
+    }
+
+    // Run the current search against the local cache, remembering the
+    // highlighted excerpt of every match
+    async fn search_cache(&mut self, db: &Database) -> Result<Vec<CachedMessage>, sqlx::Error> {
+        let query = self.search_query.clone().unwrap_or_default();
+        let hits = db.search(&query, LOCAL_SEARCH_LIMIT).await?;
+        self.search_snippets = hits
+            .iter()
+            .map(|hit| (hit.message.id.clone(), hit.highlighted_snippet.clone()))
+            .collect();
+        Ok(hits.into_iter().map(|hit| hit.message).collect())
+    }
+
+    pub fn open_search_prompt(&mut self) {
+        self.search_input = Some(self.search_query.clone().unwrap_or_default());
+    }
+
+    pub fn close_search_prompt(&mut self) {
+        self.search_input = None;
+    }
+
+    pub fn open_create_label_prompt(&mut self) {
+        self.label_prompt = Some(LabelPrompt::Create {
+            name: String::new(),
+        });
+    }
+
+    // Rename and delete only apply to user labels; system labels are refused
+    // with an explanation
+    fn selected_user_label(&mut self) -> Option<(String, String)> {
+        let label = self.get_current_label()?;
+        let label_id = label.id.clone().unwrap_or_default();
+        let name = label.name.clone().unwrap_or_default();
+        if is_user_label(&label_id) {
+            Some((label_id, name))
+        } else {
+            self.set_error_message(format!(
+                "'{}' is a system label and cannot be changed.",
+                name
+            ));
+            None
+        }
+    }
+
+    pub fn open_rename_label_prompt(&mut self) {
+        if let Some((label_id, name)) = self.selected_user_label() {
+            self.label_prompt = Some(LabelPrompt::Rename { label_id, name });
+        }
+    }
+
+    pub fn open_delete_label_prompt(&mut self) {
+        if let Some((label_id, name)) = self.selected_user_label() {
+            self.label_prompt = Some(LabelPrompt::ConfirmDelete { label_id, name });
+        }
+    }
+
+    pub fn close_label_prompt(&mut self) {
+        self.label_prompt = None;
+    }
+
+    // Text field of the label prompt, if it has one
+    pub fn label_prompt_input(&mut self) -> Option<&mut String> {
+        match self.label_prompt.as_mut()? {
+            LabelPrompt::Create { name } | LabelPrompt::Rename { name, .. } => Some(name),
+            LabelPrompt::ConfirmDelete { .. } => None,
+        }
+    }
+
+    // User labels follow the system ones in fetch order, so a new label goes last
+    pub async fn record_label_created(&mut self, label: Label) {
+        if let Some(db) = &self.database {
+            let _ = db.upsert_label(&label).await;
+        }
+        self.labels.push(label);
+    }
+
+    pub async fn record_label_renamed(&mut self, label_id: &str, name: &str) {
+        if let Some(db) = &self.database {
+            let _ = db.rename_label(label_id, name).await;
+        }
+        for label in &mut self.labels {
+            if label.id.as_deref() == Some(label_id) {
+                label.name = Some(name.to_string());
+            }
+        }
+    }
+
+    // Forget a label deleted on the server. Returns true if it was the
+    // selected folder, which then moves to a neighbouring label.
+    pub async fn record_label_deleted(&mut self, label_id: &str) -> bool {
+        if let Some(db) = &self.database {
+            let _ = db.delete_label(label_id).await;
+        }
+        let was_selected = self.get_current_label().and_then(|l| l.id.as_deref()) == Some(label_id);
+        if let Some(position) = self
+            .labels
+            .iter()
+            .position(|l| l.id.as_deref() == Some(label_id))
+        {
+            self.labels.remove(position);
+            if self.selected_label > position
+                || (self.selected_label >= self.labels.len() && self.selected_label > 0)
+            {
+                self.selected_label -= 1;
+            }
+        }
+        self.label_messages_cache.remove(label_id);
+        self.loaded_labels.remove(label_id);
+        self.label_page_tokens.remove(label_id);
+        let remaining = self.messages.iter_mut();
+        let cached = self.label_messages_cache.values_mut().flatten();
+        for message in remaining.chain(cached) {
+            if let Some(label_ids) = message.label_ids.as_mut() {
+                label_ids.retain(|id| id != label_id);
+            }
+        }
+        self.update_label_state();
+        was_selected
+    }
+
+    pub fn open_label_picker(&mut self) {
+        if self.messages.get(self.selected_message).is_some() {
+            self.label_picker = Some(LabelPicker::default());
+        }
+    }
+
+    // Pick the label to move the selected message to, out of the current folder
+    pub fn open_move_picker(&mut self) {
+        if self.messages.get(self.selected_message).is_some() {
+            self.label_picker = Some(LabelPicker {
+                moving: true,
+                ..LabelPicker::default()
+            });
+        }
+    }
+
+    pub fn close_label_picker(&mut self) {
+        self.label_picker = None;
+    }
+
+    // Labels matching the picker query, best match first
+    pub fn label_picker_matches(&self) -> Vec<&Label> {
+        let query = self
+            .label_picker
+            .as_ref()
+            .map(|picker| picker.query.as_str())
+            .unwrap_or("");
+        // A message is not moved to the folder it is already in
+        let excluded = self
+            .label_picker
+            .as_ref()
+            .filter(|picker| picker.moving)
+            .and_then(|_| self.get_current_label())
+            .and_then(|label| label.id.as_deref());
+        let mut matches: Vec<(i64, &Label)> = self
+            .labels
+            .iter()
+            .filter(|l| {
+                let id = l.id.as_deref().unwrap_or("");
+                is_assignable_label(id) && Some(id) != excluded
+            })
+            .filter_map(|l| Some((fuzzy_score(query, l.name.as_deref()?)?, l)))
+            .collect();
+        matches.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        matches.into_iter().map(|(_, label)| label).collect()
+    }
+
+    pub fn label_picker_push(&mut self, c: char) {
+        if let Some(picker) = self.label_picker.as_mut() {
+            picker.query.push(c);
+            picker.selected = 0;
+        }
+    }
+
+    pub fn label_picker_pop(&mut self) {
+        if let Some(picker) = self.label_picker.as_mut() {
+            picker.query.pop();
+            picker.selected = 0;
+        }
+    }
+
+    pub fn label_picker_down(&mut self) {
+        let count = self.label_picker_matches().len();
+        if let Some(picker) = self.label_picker.as_mut() {
+            if picker.selected + 1 < count {
+                picker.selected += 1;
+            }
+        }
+    }
+
+    pub fn label_picker_up(&mut self) {
+        if let Some(picker) = self.label_picker.as_mut() {
+            picker.selected = picker.selected.saturating_sub(1);
+        }
+    }
+
+    // Label under the picker cursor
+    pub fn label_picker_selection(&self) -> Option<Label> {
+        let selected = self.label_picker.as_ref()?.selected;
+        self.label_picker_matches().get(selected).cloned().cloned()
+    }
+
+    // Whether the selected message carries `label_id`
+    pub fn selected_message_has_label(&self, label_id: &str) -> bool {
+        self.messages
+            .get(self.selected_message)
+            .is_some_and(|m| m.has_label(label_id))
+    }
+
+    // Show a Gmail search as the SEARCH virtual label at the top of the folder
+    // list, replacing any previous search. An empty query removes the label.
+    // Returns true if there is a search to run.
+    pub fn start_search(&mut self, query: &str) -> bool {
+        let query = query.trim();
+        if let Some(position) = self
+            .labels
+            .iter()
+            .position(|l| is_search_label(l.id.as_deref().unwrap_or("")))
+        {
+            self.labels.remove(position);
+            if self.selected_label > position
+                || (self.selected_label == position && self.selected_label > 0)
+            {
+                self.selected_label -= 1;
+            }
+        }
+        self.label_messages_cache.remove(SEARCH_LABEL_ID);
+        self.loaded_labels.remove(SEARCH_LABEL_ID);
+        self.label_page_tokens.remove(SEARCH_LABEL_ID);
+        self.search_snippets.clear();
+
+        if query.is_empty() {
+            self.search_query = None;
+            self.update_label_state();
+            return false;
+        }
+
+        self.labels.insert(
+            0,
+            Label {
+                id: Some(SEARCH_LABEL_ID.to_string()),
+                name: Some(format!("SEARCH: {}", query)),
+            },
+        );
+        self.search_query = Some(query.to_string());
+        self.selected_label = 0;
+        self.update_label_state();
+        self.reset_pagination();
+        true
+    }
+
     // Check if cache is stale for a given label (older than 5 minutes)
     pub async fn is_cache_stale(&self, label_id: &str) -> bool {
         if let Some(db) = &self.database {
@@ -1,2 +1,320 @@
-// Sync functionality - placeholder for future implementation
-// All previous sync code was unused and has been removed to resolve compiler warnings
+//! Incremental mailbox synchronisation
+//!
+//! The mailbox historyId is recorded in the `mailbox_state` table. Each sync
+//! applies the `users.history.list` deltas since that id to the SQLite cache
+//! and the in-memory `AppState`, and only falls back to a full resync of the
+//! current label when no history id is stored yet or Gmail reports it as
+//! expired. A sync first fetches everything it needs (`fetch_changes`) and
+//! then applies it (`apply_changes`), so that the background sync only holds
+//! the state lock for the second step.
+
+use crate::gmail_api::client::RequestPriority;
+use crate::gmail_api::history::HistoryChanges;
+use crate::gmail_api::messages::{
+    apply_first_page, cache_message_metadata, fetch_first_page, fetch_messages_metadata, LabelPage,
+};
+use crate::gmail_api::{fetch_history, fetch_messages_for_label, fetch_profile};
+use crate::state::{is_outbox_label, is_unified_inbox, AppState, UNIFIED_INBOX_LABEL_ID};
+use crate::types::{HistoryRecord, Message, Profile};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+#[derive(Debug, PartialEq)]
+pub enum SyncOutcome {
+    // History deltas were applied; holds the number of applied changes
+    Incremental(usize),
+    // No usable history id, the current label was re-downloaded
+    FullResync,
+}
+
+// What Gmail reports since the last sync, fetched without changing the state
+pub enum MailboxChanges {
+    // History records since the stored id, with the list metadata of the
+    // messages they add to the current view
+    Incremental {
+        changes: HistoryChanges,
+        metadata: HashMap<String, Message>,
+    },
+    // No usable history id: the profile and the first page of the current
+    // label, which is not fetched for the labels kept locally
+    FullResync {
+        profile: Profile,
+        page: Option<LabelPage>,
+    },
+}
+
+// Bring the cache and the visible message list up to date with the server
+pub async fn sync_mailbox(state: &mut AppState) -> Result<SyncOutcome, Box<dyn std::error::Error>> {
+    let changes = fetch_changes(state).await?;
+    apply_changes(state, changes).await
+}
+
+// sync_mailbox for the shared state. The requests run on a snapshot without
+// the state lock, which is only taken briefly to apply what they returned.
+pub async fn sync_mailbox_in_background(
+    state_arc: &Arc<RwLock<AppState>>,
+) -> Result<SyncOutcome, String> {
+    let mut context = state_arc.read().await.request_context();
+    context.request_priority = RequestPriority::Background;
+    let changes = fetch_changes(&context).await.map_err(|e| e.to_string())?;
+
+    let mut state = state_arc.write().await;
+    // A downloaded page is only shown if its label still is; the next sync
+    // starts over otherwise
+    if matches!(changes, MailboxChanges::FullResync { .. }) && shown(&state) != shown(&context) {
+        return Ok(SyncOutcome::FullResync);
+    }
+    apply_changes(&mut state, changes)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// The label and search the message list shows
+fn shown(state: &AppState) -> (Option<String>, Option<String>) {
+    (
+        state.get_current_label().and_then(|label| label.id.clone()),
+        state.search_query.clone(),
+    )
+}
+
+// The unified inbox and the outbox are assembled from the cache
+fn shows_local_label(state: &AppState) -> bool {
+    state
+        .get_current_label()
+        .and_then(|label| label.id.as_deref())
+        .is_some_and(|label_id| is_unified_inbox(label_id) || is_outbox_label(label_id))
+}
+
+// Fetch the history since the stored id, or what a full resync needs when
+// there is none. The profile is read before the label so that nothing
+// arriving during the download is missed.
+pub async fn fetch_changes(state: &AppState) -> Result<MailboxChanges, Box<dyn std::error::Error>> {
+    let stored_history_id = match &state.database {
+        Some(db) => db.get_mailbox_history_id().await.unwrap_or(None),
+        None => None,
+    };
+
+    if let Some(start_history_id) = stored_history_id {
+        let history = fetch_history(state, &start_history_id).await?;
+        if let Some(changes) = history {
+            let metadata = fetch_added_metadata(state, &changes.records).await;
+            return Ok(MailboxChanges::Incremental { changes, metadata });
+        }
+    }
+
+    let profile = fetch_profile(state).await?;
+    let page = if shows_local_label(state) {
+        None
+    } else {
+        fetch_first_page(state).await
+    };
+    Ok(MailboxChanges::FullResync { profile, page })
+}
+
+// List metadata of the messages that add_message will cache: every new
+// message, and the ones labelled into the current view. Messages that are
+// already gone again (e.g. a draft that was sent) are left out.
+async fn fetch_added_metadata(
+    state: &AppState,
+    records: &[HistoryRecord],
+) -> HashMap<String, Message> {
+    let mut ids: Vec<String> = Vec::new();
+    for record in records {
+        let added = record
+            .messages_added
+            .iter()
+            .flatten()
+            .filter_map(|added| added.message.id.clone());
+        let labelled = record
+            .labels_added
+            .iter()
+            .flatten()
+            .filter(|change| {
+                is_current_label_affected(state, change.label_ids.as_deref().unwrap_or_default())
+            })
+            .filter_map(|change| change.message.id.clone());
+        for id in added.chain(labelled) {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+    }
+
+    fetch_messages_metadata(state, &ids)
+        .await
+        .into_iter()
+        .filter_map(|message| Some((message.id.clone()?, message)))
+        .collect()
+}
+
+// Apply fetched changes to the cache and the message list
+pub async fn apply_changes(
+    state: &mut AppState,
+    changes: MailboxChanges,
+) -> Result<SyncOutcome, Box<dyn std::error::Error>> {
+    match changes {
+        MailboxChanges::Incremental { changes, metadata } => {
+            let applied = apply_history(state, &changes.records, &metadata).await;
+            if let (Some(db), Some(history_id)) = (&state.database, changes.history_id) {
+                db.set_mailbox_history_id(&history_id).await?;
+                db.mark_labels_synced().await?;
+            }
+            Ok(SyncOutcome::Incremental(applied))
+        }
+        MailboxChanges::FullResync { profile, page } => {
+            // Re-show the current label and start tracking history from now on
+            state.own_email = profile.email_address.clone();
+            if shows_local_label(state) {
+                // Only reads the cache for these
+                fetch_messages_for_label(state).await;
+            } else {
+                apply_first_page(state, page).await;
+            }
+            if let (Some(db), Some(history_id)) = (&state.database, profile.history_id) {
+                db.set_mailbox_history_id(&history_id).await?;
+            }
+            Ok(SyncOutcome::FullResync)
+        }
+    }
+}
+
+// Sync the INBOX of every account into that account's cache, each with its
+// own credentials, then show the merged unified inbox. The requests run
+// without the state lock; it is only taken to set up the accounts and to
+// show the result.
+pub async fn sync_unified_inbox(state_arc: &Arc<RwLock<AppState>>) {
+    let mut contexts = Vec::new();
+    let mut failures = Vec::new();
+    {
+        let mut state = state_arc.write().await;
+        for account in state.accounts.clone() {
+            match state.account_inbox_context(&account).await {
+                Ok(context) => contexts.push(context),
+                Err(e) => failures.push(format!("'{}': {}", account, e)),
+            }
+        }
+    }
+
+    for context in &mut contexts {
+        if let Err(e) = sync_mailbox(context).await {
+            failures.push(format!("'{}': {}", context.account, e));
+        }
+    }
+
+    let mut state = state_arc.write().await;
+    let showing_unified_inbox = state
+        .get_current_label()
+        .and_then(|label| label.id.as_deref())
+        .is_some_and(is_unified_inbox);
+    if showing_unified_inbox {
+        let _ = state.load_messages_from_cache(UNIFIED_INBOX_LABEL_ID).await;
+    }
+    if !failures.is_empty() {
+        state.set_error_message(format!(
+            "Failed to sync the unified inbox for {}",
+            failures.join(", ")
+        ));
+    }
+}
+
+// Apply history records in order, returning the number of changes applied
+pub async fn apply_history(
+    state: &mut AppState,
+    records: &[HistoryRecord],
+    metadata: &HashMap<String, Message>,
+) -> usize {
+    let mut applied = 0;
+
+    for record in records {
+        for added in record.messages_added.iter().flatten() {
+            if let Some(id) = &added.message.id {
+                let label_ids = added.message.label_ids.clone().unwrap_or_default();
+                add_message(state, id, &label_ids, metadata).await;
+                applied += 1;
+            }
+        }
+
+        for deleted in record.messages_deleted.iter().flatten() {
+            if let Some(id) = &deleted.message.id {
+                state.record_message_deleted(id).await;
+                applied += 1;
+            }
+        }
+
+        for change in record.labels_added.iter().flatten() {
+            if let Some(id) = &change.message.id {
+                let label_ids = change.label_ids.clone().unwrap_or_default();
+                state.record_label_change(id, &label_ids, &[]).await;
+                if is_current_label_affected(state, &label_ids) {
+                    add_message(state, id, &label_ids, metadata).await;
+                }
+                applied += 1;
+            }
+        }
+
+        for change in record.labels_removed.iter().flatten() {
+            if let Some(id) = &change.message.id {
+                let label_ids = change.label_ids.clone().unwrap_or_default();
+                state.record_label_change(id, &[], &label_ids).await;
+                if is_current_label_affected(state, &label_ids) && !is_all_mail(state) {
+                    state.remove_message(id);
+                }
+                applied += 1;
+            }
+        }
+    }
+
+    applied
+}
+
+fn is_all_mail(state: &AppState) -> bool {
+    state
+        .get_current_label()
+        .and_then(|label| label.id.as_deref())
+        .map(|id| id.eq_ignore_ascii_case("ALLMAIL"))
+        .unwrap_or(false)
+}
+
+fn is_current_label_affected(state: &AppState, label_ids: &[String]) -> bool {
+    match state
+        .get_current_label()
+        .and_then(|label| label.id.as_deref())
+    {
+        // The unified inbox shows this account's INBOX
+        Some(current) if is_unified_inbox(current) => label_ids.iter().any(|id| id == "INBOX"),
+        Some(current) => label_ids.iter().any(|id| id == current),
+        None => false,
+    }
+}
+
+// Cache a new or newly-labelled message and show it if it belongs in the current view
+async fn add_message(
+    state: &mut AppState,
+    msg_id: &str,
+    label_ids: &[String],
+    metadata: &HashMap<String, Message>,
+) {
+    let belongs_in_view = is_all_mail(state) || is_current_label_affected(state, label_ids);
+    let already_shown = state
+        .messages
+        .iter()
+        .any(|m| m.id.as_deref() == Some(msg_id));
+
+    // Without metadata the message was gone again, or it only entered a
+    // view that was left in the meantime
+    let Some(mut message) = metadata.get(msg_id).cloned() else {
+        return;
+    };
+
+    let all_label_ids = message
+        .label_ids
+        .clone()
+        .unwrap_or_else(|| label_ids.to_vec());
+    message.label_ids = Some(all_label_ids.clone());
+    cache_message_metadata(state, &message, Some(all_label_ids)).await;
+
+    if belongs_in_view && !already_shown {
+        state.insert_message_at_top(message);
+    }
+}
@@ -1,4 +1,4 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize)]
 pub struct LabelsResponse {
@@ -11,49 +11,130 @@ pub struct Label {
     pub name: Option<String>,
 }
 
+// A Gmail filter: Gmail applies its action to incoming messages that match
+// its criteria
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Filter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub criteria: FilterCriteria,
+    #[serde(default)]
+    pub action: FilterAction,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct FilterCriteria {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    // Search terms the message has to match, like "list:news.example.com"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    // Search terms the message must not match
+    #[serde(rename = "negatedQuery", skip_serializing_if = "Option::is_none")]
+    pub negated_query: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct FilterAction {
+    #[serde(rename = "addLabelIds", default, skip_serializing_if = "Vec::is_empty")]
+    pub add_label_ids: Vec<String>,
+    #[serde(
+        rename = "removeLabelIds",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub remove_label_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub forward: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FiltersResponse {
+    pub filter: Option<Vec<Filter>>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct MessagesResponse {
     pub messages: Option<Vec<MessageRef>>,
+    #[serde(rename = "nextPageToken")]
+    pub next_page_token: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct MessageRef {
     pub id: Option<String>,
 }
 
+// A Gmail draft; its message is replaced by every update
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct Draft {
+    pub id: Option<String>,
+    pub message: Option<MessageRef>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DraftsResponse {
+    pub drafts: Option<Vec<Draft>>,
+    #[serde(rename = "nextPageToken")]
+    pub next_page_token: Option<String>,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Message {
     pub id: Option<String>,
     pub snippet: Option<String>,
     pub payload: Option<MessagePart>,
     #[serde(rename = "threadId")]
-    #[allow(dead_code)]
     pub thread_id: Option<String>,
     #[serde(rename = "labelIds")]
-    #[allow(dead_code)]
     pub label_ids: Option<Vec<String>>,
 }
 
+impl Message {
+    pub fn has_label(&self, label_id: &str) -> bool {
+        self.label_ids
+            .as_ref()
+            .is_some_and(|ids| ids.iter().any(|id| id == label_id))
+    }
+
+    pub fn is_unread(&self) -> bool {
+        self.has_label("UNREAD")
+    }
+
+    pub fn is_starred(&self) -> bool {
+        self.has_label("STARRED")
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
+pub struct Thread {
+    // Oldest first
+    pub messages: Option<Vec<Message>>,
+}
+
+// A message fetched with format=raw
+#[derive(Debug, Deserialize)]
+pub struct RawMessage {
+    // The whole RFC 2822 message, base64url encoded
+    pub raw: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
 pub struct MessagePart {
     #[serde(rename = "mimeType")]
     pub mime_type: Option<String>,
+    // Only set on parts that are attachments
+    pub filename: Option<String>,
     pub headers: Option<Vec<Header>>,
     pub body: Option<MessagePartBody>,
     pub parts: Option<Vec<MessagePart>>,
 }
 
-impl Default for MessagePart {
-    fn default() -> Self {
-        MessagePart {
-            mime_type: None,
-            headers: None,
-            body: None,
-            parts: None,
-        }
-    }
-}
-
 #[derive(Debug, Deserialize, Clone)]
 pub struct Header {
     pub name: Option<String>,
@@ -68,11 +149,67 @@ pub struct MessageHeadersDisplay {
     pub date: String,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, Default)]
 pub struct MessagePartBody {
+    // Large parts are fetched separately with attachments.get
+    #[serde(rename = "attachmentId")]
+    pub attachment_id: Option<String>,
+    pub size: Option<u64>,
     pub data: Option<String>,
 }
 
+// A file attached to a message
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub filename: String,
+    pub mime_type: String,
+    pub size: u64,
+    pub attachment_id: Option<String>,
+    // Content of small attachments that Gmail sends inline (base64url)
+    pub data: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Profile {
+    #[serde(rename = "emailAddress")]
+    pub email_address: Option<String>,
+    #[serde(rename = "historyId")]
+    pub history_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryResponse {
+    pub history: Option<Vec<HistoryRecord>>,
+    #[serde(rename = "nextPageToken")]
+    pub next_page_token: Option<String>,
+    #[serde(rename = "historyId")]
+    pub history_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct HistoryRecord {
+    #[serde(rename = "messagesAdded")]
+    pub messages_added: Option<Vec<HistoryMessageChange>>,
+    #[serde(rename = "messagesDeleted")]
+    pub messages_deleted: Option<Vec<HistoryMessageChange>>,
+    #[serde(rename = "labelsAdded")]
+    pub labels_added: Option<Vec<HistoryLabelChange>>,
+    #[serde(rename = "labelsRemoved")]
+    pub labels_removed: Option<Vec<HistoryLabelChange>>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct HistoryMessageChange {
+    pub message: Message,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct HistoryLabelChange {
+    pub message: Message,
+    #[serde(rename = "labelIds")]
+    pub label_ids: Option<Vec<String>>,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum LoadingStage {
     Authenticating,
@@ -1,5 +1,9 @@
-use crate::state::{AppState, ComposeField, FocusedPane};
-use crate::types::LoadingStage;
+use crate::database::{SEARCH_HIGHLIGHT_END, SEARCH_HIGHLIGHT_START};
+use crate::state::{
+    is_search_label, AppState, ComposeField, FilterField, FocusedPane, LabelPrompt, OpenThread,
+    PurgePrompt, FILTER_FIELDS,
+};
+use crate::types::{Filter, Label, LoadingStage};
 use chrono::{DateTime, Local};
 use ratatui::{prelude::*, widgets::*};
 
@@ -20,6 +24,31 @@ fn format_email_date(date_str: &str) -> String {
     }
 }
 
+// Helper function to render a search excerpt with its matched terms highlighted
+fn highlighted_snippet_line(snippet: &str) -> Line<'static> {
+    let mut spans = Vec::new();
+    for (i, part) in snippet.split(SEARCH_HIGHLIGHT_START).enumerate() {
+        // Every part after the first starts with a match
+        let (matched, rest) = match part.split_once(SEARCH_HIGHLIGHT_END) {
+            Some((matched, rest)) if i > 0 => (matched, rest),
+            _ => ("", part),
+        };
+        if !matched.is_empty() {
+            spans.push(Span::styled(
+                matched.replace('\n', " "),
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+            ));
+        }
+        spans.push(Span::styled(
+            rest.replace('\n', " "),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    Line::from(spans)
+}
+
 // Draw loading screen
 pub fn draw_loading_screen(f: &mut ratatui::Frame, stage: &LoadingStage) {
     let area = f.size();
@@ -72,6 +101,68 @@ pub fn draw_main_ui(f: &mut ratatui::Frame, state: &mut AppState) {
         return; // Don't draw other overlays if confirmation popup is active
     }
 
+    // Offer to restore a message left unsent by the last run
+    if state.restorable_compose.is_some() {
+        draw_main_ui_base(f, state);
+        draw_restore_compose_popup(f, state);
+        return;
+    }
+
+    // Search prompt over the main UI
+    if state.search_input.is_some() {
+        draw_main_ui_base(f, state);
+        draw_search_prompt(f, state);
+        return;
+    }
+
+    // Confirmation before messages are deleted for good
+    if state.purge_prompt.is_some() {
+        draw_main_ui_base(f, state);
+        draw_purge_prompt(f, state);
+        return;
+    }
+
+    // Filters screen over the main UI
+    if state.filters_screen.is_some() {
+        draw_main_ui_base(f, state);
+        draw_filters_screen(f, state);
+        return;
+    }
+
+    // Mark-matching prompt over the main UI
+    if state.mark_input.is_some() {
+        draw_main_ui_base(f, state);
+        draw_mark_prompt(f, state);
+        return;
+    }
+
+    // Label management prompt and label picker over the main UI
+    if state.label_prompt.is_some() {
+        draw_main_ui_base(f, state);
+        draw_label_prompt(f, state);
+        return;
+    }
+
+    if state.label_picker.is_some() {
+        draw_main_ui_base(f, state);
+        draw_label_picker(f, state);
+        return;
+    }
+
+    // Attachment picker popup over the main UI
+    if state.show_attachment_picker {
+        draw_main_ui_base(f, state);
+        draw_attachment_picker_popup(f, state);
+        return;
+    }
+
+    // Account switcher popup over the main UI
+    if state.show_account_switcher {
+        draw_main_ui_base(f, state);
+        draw_account_switcher_popup(f, state);
+        return;
+    }
+
     // If there's an error message, draw it as a popup over everything else
     if state.error_message.is_some() {
         draw_error_popup(f, state);
@@ -113,7 +204,11 @@ fn draw_main_ui_base(f: &mut ratatui::Frame, state: &mut AppState) {
         .map(|l| ListItem::new(l.name.as_deref().unwrap_or("(unnamed)")))
         .collect();
 
-    let folders_title = "Folders";
+    let folders_title = if state.accounts.len() > 1 {
+        format!("Folders ({})", state.account)
+    } else {
+        "Folders".to_string()
+    };
 
     let folders_border_style = if state.focused_pane == FocusedPane::Labels {
         Style::default().fg(Color::Green)
@@ -138,6 +233,11 @@ fn draw_main_ui_base(f: &mut ratatui::Frame, state: &mut AppState) {
     f.render_stateful_widget(folders, chunks[0], &mut state.label_state);
 
     // Middle: Message list
+    let showing_search = state
+        .get_current_label()
+        .and_then(|label| label.id.as_deref())
+        .is_some_and(is_search_label);
+    let showing_outbox = state.showing_outbox();
     let msg_items: Vec<_> = if state.loading_messages && state.messages.is_empty() {
         // Only show loading if we have no cached messages to display
         vec![
@@ -147,16 +247,18 @@ fn draw_main_ui_base(f: &mut ratatui::Frame, state: &mut AppState) {
             ListItem::new(""),
         ]
     } else {
+        // In thread view only the newest message of each conversation is listed
+        let thread_counts = state.thread_counts();
         state
-            .messages
-            .iter()
-            .enumerate()
+            .thread_rows()
+            .into_iter()
+            .map(|i| (i, &state.messages[i]))
             .map(|(i, m)| {
                 let snippet = m.snippet.as_deref().unwrap_or("(no snippet)");
                 let msg_id = m.id.as_deref().unwrap_or("");
 
                 // Check if we have cached headers for this message
-                if let Some((subject, from)) = state.message_headers.get(msg_id) {
+                let item = if let Some((subject, from)) = state.message_headers.get(msg_id) {
                     // Check if we have a cached date for this message
                     let date_key = format!("{}_date", msg_id);
                     let formatted_date = state
@@ -169,11 +271,24 @@ fn draw_main_ui_base(f: &mut ratatui::Frame, state: &mut AppState) {
                     // chunks[1].width includes the full column width
                     // Subtract 2 for left/right borders + 2 for left/right padding + 2 extra buffer = 6 total
                     let available_width = (chunks[1].width as usize).saturating_sub(6); // 2 for borders, 2 for padding, 2 for highlight symbol
-                    let from_prefix = "From: ";
-                    let from_text = format!("{}{}", from_prefix, from);
+
+                    // Outbox entries show what waits to be done instead of a sender
+                    let from_prefix = if showing_outbox {
+                        ""
+                    } else if m.is_starred() {
+                        "★ From: "
+                    } else {
+                        "From: "
+                    };
+                    // The unified inbox tags each message with its account
+                    let from_text = match state.message_accounts.get(msg_id) {
+                        Some(account) => format!("[{}] {}{}", account, from_prefix, from),
+                        None => format!("{}{}", from_prefix, from),
+                    };
 
                     // Calculate spacing needed to right-align the date
-                    let total_content_len = from_text.len() + formatted_date.len();
+                    let total_content_len =
+                        from_text.chars().count() + formatted_date.chars().count();
                     let spacing = if total_content_len < available_width {
                         available_width.saturating_sub(total_content_len)
                     } else {
@@ -182,14 +297,52 @@ fn draw_main_ui_base(f: &mut ratatui::Frame, state: &mut AppState) {
 
                     let from_line =
                         format!("{}{}{}", from_text, " ".repeat(spacing), formatted_date);
-                    ListItem::new(format!("{}\nSubject: {}", from_line, subject))
+                    let thread_size = m
+                        .thread_id
+                        .as_deref()
+                        .and_then(|thread_id| thread_counts.get(thread_id).copied())
+                        .unwrap_or(1);
+                    let subject_line = if state.thread_view && thread_size > 1 {
+                        format!("Subject: {} ({})", subject, thread_size)
+                    } else {
+                        format!("Subject: {}", subject)
+                    };
+                    let mut lines = vec![Line::from(from_line), Line::from(subject_line)];
+                    // Matches from the local search show where the terms were found
+                    if let Some(snippet) =
+                        state.search_snippets.get(msg_id).filter(|_| showing_search)
+                    {
+                        lines.push(highlighted_snippet_line(snippet));
+                    }
+                    if showing_outbox {
+                        lines.push(Line::styled(
+                            snippet.to_string(),
+                            Style::default().fg(Color::DarkGray),
+                        ));
+                    }
+                    ListItem::new(lines)
                 } else {
                     ListItem::new(format!("#{}: {}", i + 1, snippet))
+                };
+
+                // Unread messages stand out in bold, marked ones on a gray background
+                let mut style = Style::default();
+                if m.is_unread() {
+                    style = style.add_modifier(Modifier::BOLD);
                 }
+                if state.is_marked(i) {
+                    style = style.bg(Color::DarkGray);
+                }
+                item.style(style)
             })
             .collect()
     };
-    let messages_title = "Messages";
+    let marked_count = state.marked_message_ids().len();
+    let messages_title = if marked_count > 0 {
+        format!("Messages ({} marked)", marked_count)
+    } else {
+        "Messages".to_string()
+    };
 
     let messages_border_style = if state.focused_pane == FocusedPane::Messages {
         Style::default().fg(Color::Green)
@@ -213,10 +366,20 @@ fn draw_main_ui_base(f: &mut ratatui::Frame, state: &mut AppState) {
         .highlight_symbol("▶ ");
     f.render_stateful_widget(messages, chunks[1], &mut state.message_state);
 
-    // Right: Message detail with scrolling
+    // Right: Message detail with scrolling, with the attachments under the headers
+    let attachment_lines = attachment_lines(state);
+    let attachments_height = if attachment_lines.is_empty() {
+        0
+    } else {
+        attachment_lines.len().min(5) as u16 + 2
+    };
     let content_chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Length(6), Constraint::Min(0)]) // 6 lines for headers, rest for body
+        .constraints([
+            Constraint::Length(6), // 6 lines for headers
+            Constraint::Length(attachments_height),
+            Constraint::Min(0),
+        ])
         .split(chunks[2]);
 
     let content_title = "Email Content";
@@ -233,7 +396,20 @@ fn draw_main_ui_base(f: &mut ratatui::Frame, state: &mut AppState) {
         .title("Headers")
         .border_style(content_border_style);
 
-    let header_text = if let Some(headers) = &state.current_message_display_headers {
+    let header_text = if let Some(thread) = state.current_thread() {
+        let latest = thread.messages.last().map(|m| &m.headers);
+        format!(
+            "Conversation: {}\nMessages: {}\nLatest: {}\nFrom: {}",
+            latest.map(|h| h.subject.as_str()).unwrap_or("(no subject)"),
+            thread.messages.len(),
+            latest
+                .map(|h| format_email_date(&h.date))
+                .unwrap_or_default(),
+            latest
+                .map(|h| h.from.as_str())
+                .unwrap_or("(unknown sender)")
+        )
+    } else if let Some(headers) = &state.current_message_display_headers {
         format!(
             "From: {}\nTo: {}\nDate: {}\nSubject: {}",
             headers.from,
@@ -250,7 +426,139 @@ fn draw_main_ui_base(f: &mut ratatui::Frame, state: &mut AppState) {
         .wrap(Wrap { trim: true });
     f.render_widget(header_paragraph, content_chunks[0]);
 
-    // Draw message body
+    if !attachment_lines.is_empty() {
+        let attachments = Paragraph::new(attachment_lines).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Attachments (w: save)")
+                .border_style(content_border_style),
+        );
+        f.render_widget(attachments, content_chunks[1]);
+    }
+
+    // Draw message body, or the whole conversation in thread view
+    if let Some(thread) = state.current_thread() {
+        let lines: Vec<Line> = conversation_lines(state, thread)
+            .into_iter()
+            .skip(state.content_scroll_offset)
+            .collect();
+        let conversation = Paragraph::new(lines)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Conversation (n/p: select, Enter: expand/collapse)")
+                    .border_style(content_border_style)
+                    .padding(Padding::uniform(1)),
+            )
+            .wrap(Wrap { trim: true });
+        f.render_widget(conversation, content_chunks[2]);
+    } else {
+        draw_message_body(
+            f,
+            state,
+            content_chunks[2],
+            content_title,
+            content_border_style,
+        );
+    }
+
+    draw_help_bar(f, state, main_chunks[1]);
+}
+
+// Human readable file size, e.g. "1.5 MB"
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+// One line per attachment of the shown message, and the progress of a running download
+fn attachment_lines(state: &AppState) -> Vec<Line<'static>> {
+    let mut lines: Vec<Line> = state
+        .current_attachments()
+        .iter()
+        .map(|attachment| {
+            Line::from(format!(
+                "📎 {} ({}, {})",
+                attachment.filename,
+                attachment.mime_type,
+                format_size(attachment.size)
+            ))
+        })
+        .collect();
+    if let Some(download) = &state.attachment_download {
+        lines.push(Line::styled(
+            format!("Downloading {}… {}%", download.filename, download.percent()),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    lines
+}
+
+// Render a conversation: collapsed messages take one line, expanded ones
+// show their headers and body
+fn conversation_lines(state: &AppState, thread: &OpenThread) -> Vec<Line<'static>> {
+    let mut lines = Vec::new();
+    for (i, message) in thread.messages.iter().enumerate() {
+        let marker = if i == thread.selected { "▶ " } else { "  " };
+        let fold = if message.expanded { "▾" } else { "▸" };
+        let summary = format!(
+            "{}{} {} · {}",
+            marker,
+            fold,
+            message.headers.from,
+            format_email_date(&message.headers.date)
+        );
+        let summary_style = if i == thread.selected {
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().add_modifier(Modifier::BOLD)
+        };
+        lines.push(Line::styled(summary, summary_style));
+
+        if message.expanded {
+            lines.push(Line::styled(
+                format!("    To: {}", message.headers.to),
+                Style::default().fg(Color::DarkGray),
+            ));
+            lines.push(Line::from(""));
+            let body = state
+                .message_bodies
+                .get(&message.id)
+                .map(String::as_str)
+                .unwrap_or("");
+            for line in body.lines() {
+                lines.push(Line::from(format!("    {}", line)));
+            }
+        } else if !message.snippet.is_empty() {
+            lines.push(Line::styled(
+                format!("    {}", message.snippet),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        lines.push(Line::from(""));
+    }
+    lines
+}
+
+fn draw_message_body(
+    f: &mut ratatui::Frame,
+    state: &AppState,
+    area: Rect,
+    content_title: &str,
+    content_border_style: Style,
+) {
     let msg_body = if let Some(msg) = state.messages.get(state.selected_message) {
         let id = msg.id.as_deref().unwrap_or("");
         state
@@ -279,31 +587,49 @@ fn draw_main_ui_base(f: &mut ratatui::Frame, state: &mut AppState) {
                 .padding(Padding::uniform(1)),
         )
         .wrap(Wrap { trim: true });
-    f.render_widget(email, content_chunks[1]);
+    f.render_widget(email, area);
+}
 
-    // Status bar with key bindings (only show when help is enabled)
+// Status bar with key bindings (only show when help is enabled)
+fn draw_help_bar(f: &mut ratatui::Frame, state: &AppState, area: Rect) {
     if state.show_help {
         let help_text = match state.focused_pane {
-            FocusedPane::Labels => vec![
+            FocusedPane::Labels => [
                 "j/k or ↑/↓: Navigate up/down through folders",
                 "Enter: Select folder and switch to messages",
-                "Tab/Shift+Tab: Switch panes | c: Compose email | f: Refresh messages",
+                "Tab/Shift+Tab: Switch panes | c: Compose email | f: Refresh messages | /: Search",
+                "n: New label | R: Rename label | D: Delete label | z: Filters | A: Switch account",
+                "Ctrl+R: Re-authenticate | ?: Toggle this help | q: Quit",
+            ]
+            .join("\n"),
+            FocusedPane::Messages if state.showing_outbox() => [
+                "j/k or ↑/↓: Navigate up/down through the outbox",
+                "Enter: View entry | r: Retry entry | d: Cancel entry | c: Compose",
+                "Entries reach Gmail in order once it can be reached again",
+                "Tab/Shift+Tab: Switch panes | /: Search | Esc: Back to folders",
                 "Ctrl+R: Re-authenticate | ?: Toggle this help | q: Quit application",
             ]
             .join("\n"),
-            FocusedPane::Messages => vec![
+            FocusedPane::Messages if state.trash_or_spam_folder().is_some() => [
+                "j/k or ↑/↓: Navigate up/down | Enter: View message | Tab/Shift+Tab: Switch panes",
+                "o: Restore / Not spam | D: Delete forever | E: Empty folder | u: Undo",
+                "x: Mark | v: Mark range | M: Mark matching | Esc: Unmark, back to folders | /: Search",
+                "f: Refresh | Ctrl+R: Re-authenticate | ?: Toggle this help | q: Quit application",
+            ]
+            .join("\n"),
+            FocusedPane::Messages => [
                 "j/k or ↑/↓: Navigate up/down through messages",
-                "Enter: View message content | c: Compose email | r: Reply to message",
-                "a: Archive message | d: Delete message | s: Mark as spam | f: Refresh messages",
-                "Tab/Shift+Tab: Switch panes | Esc: Back to folders",
-                "Ctrl+R: Re-authenticate | ?: Toggle this help | q: Quit application",
+                "Enter: View message | c: Compose | r/R: Reply/Reply all | F/Ctrl+F: Forward/as attachment",
+                "a: Archive | d: Delete | s: Spam | u: Undo | U: Read/unread | *: Star | l: Labels | m: Move | w: Save attachment",
+                "x: Mark | v: Mark range | M: Mark matching | Esc: Unmark, back to folders | /: Search",
+                "z/Z: Filters/Filter from message | Tab/Shift+Tab: Switch panes | t: Conversation view | f: Refresh | ?: Help | q: Quit",
             ]
             .join("\n"),
-            FocusedPane::Content => vec![
-                "j/k or ↑/↓: Scroll up/down through content",
-                "Tab/Shift+Tab: Switch panes | c: Compose email | r: Reply to message",
-                "a: Archive message | d: Delete message | s: Mark as spam | f: Refresh messages",
-                "Esc: Back to folders pane",
+            FocusedPane::Content => [
+                "j/k or ↑/↓: Scroll up/down through content | n/p, Enter: Pick, expand/collapse message",
+                "c: Compose | r/R: Reply/Reply all | F/Ctrl+F: Forward/as attachment | Tab: Switch panes",
+                "a: Archive | d: Delete | s: Spam | u: Undo | U: Read/unread | *: Star | l: Labels | m: Move | w: Save attachment",
+                "f: Refresh messages | z/Z: Filters/Filter from message | Esc: Back to folders pane",
                 "Ctrl+R: Re-authenticate | ?: Toggle this help | q: Quit application",
             ]
             .join("\n"),
@@ -318,7 +644,7 @@ fn draw_main_ui_base(f: &mut ratatui::Frame, state: &mut AppState) {
             )
             .style(Style::default().fg(Color::Gray))
             .wrap(Wrap { trim: true });
-        f.render_widget(status_bar, main_chunks[1]);
+        f.render_widget(status_bar, area);
     }
 }
 
@@ -345,6 +671,442 @@ pub fn draw_error_popup(f: &mut ratatui::Frame, state: &mut AppState) {
     }
 }
 
+// Draw the search prompt
+pub fn draw_search_prompt(f: &mut ratatui::Frame, state: &mut AppState) {
+    let area = f.size();
+    let popup_area = centered_rect(60, 25, area); // 60% width, 25% height
+
+    f.render_widget(Clear, popup_area); // Clear the area first
+
+    let block = Block::default()
+        .title("Search Gmail (Enter: search, Esc: cancel)")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Yellow))
+        .padding(Padding::uniform(1));
+
+    let input = state.search_input.as_deref().unwrap_or("");
+    let text = format!(
+        "{}█\n\nGmail search syntax, e.g. from:bob has:attachment newer_than:7d\nMatches from the local cache show right away, and also work offline.\nSubmit an empty query to clear the search.",
+        input
+    );
+
+    let paragraph = Paragraph::new(text)
+        .block(block)
+        .style(Style::default().fg(Color::White))
+        .wrap(Wrap { trim: false });
+
+    f.render_widget(paragraph, popup_area);
+}
+
+pub fn draw_mark_prompt(f: &mut ratatui::Frame, state: &mut AppState) {
+    let area = f.size();
+    let popup_area = centered_rect(60, 20, area); // 60% width, 20% height
+
+    f.render_widget(Clear, popup_area); // Clear the area first
+
+    let block = Block::default()
+        .title("Mark matching messages (Enter: mark, Esc: cancel)")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Yellow))
+        .padding(Padding::uniform(1));
+
+    let input = state.mark_input.as_deref().unwrap_or("");
+    let text = format!(
+        "{}█\n\nMarks the listed messages whose sender, subject or snippet contains the text.\nSubmit it empty to mark them all.",
+        input
+    );
+
+    let paragraph = Paragraph::new(text)
+        .block(block)
+        .style(Style::default().fg(Color::White))
+        .wrap(Wrap { trim: false });
+
+    f.render_widget(paragraph, popup_area);
+}
+
+// Draw the confirmation before messages are deleted for good
+pub fn draw_purge_prompt(f: &mut ratatui::Frame, state: &mut AppState) {
+    let Some(prompt) = &state.purge_prompt else {
+        return;
+    };
+    let area = f.size();
+    let popup_area = centered_rect(60, 20, area); // 60% width, 20% height
+
+    f.render_widget(Clear, popup_area); // Clear the area first
+
+    let (title, text) = match prompt {
+        PurgePrompt::DeleteForever { message_ids } => (
+            "Delete forever".to_string(),
+            format!(
+                "Delete {} forever?\nThis skips the trash and cannot be undone.\n\nPress 'y' for Yes, 'n' for No",
+                if message_ids.len() == 1 {
+                    "this message".to_string()
+                } else {
+                    format!("these {} messages", message_ids.len())
+                }
+            ),
+        ),
+        PurgePrompt::EmptyFolder { label_id } => {
+            let folder = if label_id == "SPAM" { "Spam" } else { "Trash" };
+            (
+                format!("Empty {}", folder),
+                format!(
+                    "Delete every message in {} forever?\nThis cannot be undone.\n\nPress 'y' for Yes, 'n' for No",
+                    folder
+                ),
+            )
+        }
+    };
+
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Red))
+        .padding(Padding::uniform(1));
+
+    let paragraph = Paragraph::new(text)
+        .block(block)
+        .style(Style::default().fg(Color::White))
+        .wrap(Wrap { trim: false });
+
+    f.render_widget(paragraph, popup_area);
+}
+
+// Name of a label for the filters screen, its ID when it is not known
+fn label_name<'a>(labels: &'a [Label], label_id: &'a str) -> &'a str {
+    labels
+        .iter()
+        .find(|label| label.id.as_deref() == Some(label_id))
+        .and_then(|label| label.name.as_deref())
+        .unwrap_or(label_id)
+}
+
+// One line describing a filter: its criteria in Gmail search syntax, then
+// what it does
+fn filter_summary(filter: &Filter, labels: &[Label]) -> String {
+    let criteria = &filter.criteria;
+    let mut terms = Vec::new();
+    if let Some(from) = &criteria.from {
+        terms.push(format!("from:({})", from));
+    }
+    if let Some(to) = &criteria.to {
+        terms.push(format!("to:({})", to));
+    }
+    if let Some(subject) = &criteria.subject {
+        terms.push(format!("subject:({})", subject));
+    }
+    if let Some(query) = &criteria.query {
+        terms.push(query.clone());
+    }
+    if let Some(negated_query) = &criteria.negated_query {
+        terms.push(format!("-({})", negated_query));
+    }
+
+    let action = &filter.action;
+    let mut actions = Vec::new();
+    for label_id in &action.add_label_ids {
+        actions.push(match label_id.as_str() {
+            "STARRED" => "Star it".to_string(),
+            "TRASH" => "Delete it".to_string(),
+            "IMPORTANT" => "Mark as important".to_string(),
+            _ => format!("Apply '{}'", label_name(labels, label_id)),
+        });
+    }
+    for label_id in &action.remove_label_ids {
+        actions.push(match label_id.as_str() {
+            "INBOX" => "Skip the inbox".to_string(),
+            "UNREAD" => "Mark as read".to_string(),
+            "SPAM" => "Never send to spam".to_string(),
+            "IMPORTANT" => "Never mark as important".to_string(),
+            _ => format!("Remove '{}'", label_name(labels, label_id)),
+        });
+    }
+    if let Some(forward) = &action.forward {
+        actions.push(format!("Forward to {}", forward));
+    }
+
+    format!("{}  →  {}", terms.join(" "), actions.join(", "))
+}
+
+// Draw the filters screen: the account's filters, or the form for a new one
+pub fn draw_filters_screen(f: &mut ratatui::Frame, state: &mut AppState) {
+    let Some(screen) = &state.filters_screen else {
+        return;
+    };
+    let area = f.size();
+    let popup_area = centered_rect(80, 70, area); // 80% width, 70% height
+
+    f.render_widget(Clear, popup_area); // Clear the area first
+
+    let title = if screen.form.is_some() {
+        "New filter (Tab: next field, Space: switch action, Enter: create, Esc: back)"
+    } else {
+        "Filters (n: new, d: delete, Esc: close)"
+    };
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Yellow))
+        .padding(Padding::uniform(1));
+    let inner = block.inner(popup_area);
+    f.render_widget(block, popup_area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(2)])
+        .split(inner);
+
+    let status = if let Some(error) = &screen.error {
+        Paragraph::new(error.as_str()).style(Style::default().fg(Color::Red))
+    } else if screen.confirm_delete {
+        Paragraph::new("Delete this filter? Press 'y' for Yes, 'n' for No")
+            .style(Style::default().fg(Color::Red))
+    } else if screen.form.is_none() && screen.filters.is_empty() {
+        Paragraph::new("No filters yet. Press n to write one.")
+            .style(Style::default().fg(Color::Gray))
+    } else {
+        Paragraph::new("")
+    };
+    f.render_widget(status.wrap(Wrap { trim: false }), chunks[1]);
+
+    if let Some(form) = &screen.form {
+        let lines: Vec<Line> = FILTER_FIELDS
+            .iter()
+            .map(|&field| {
+                let name = match field {
+                    FilterField::From => "From",
+                    FilterField::To => "To",
+                    FilterField::Subject => "Subject",
+                    FilterField::Query => "Has the words",
+                    FilterField::NegatedQuery => "Doesn't have",
+                    FilterField::Label => "Apply the label",
+                    FilterField::SkipInbox => "Skip the inbox",
+                    FilterField::MarkRead => "Mark as read",
+                    FilterField::Star => "Star it",
+                    FilterField::NeverSpam => "Never send to spam",
+                    FilterField::Trash => "Delete it",
+                };
+                let focused = form.focused == field;
+                let text = match (form.text(field), form.checked(field)) {
+                    (Some(text), _) => {
+                        format!("{:<16}{}{}", name, text, if focused { "█" } else { "" })
+                    }
+                    (None, checked) => {
+                        let mark = if checked == Some(true) { "[x]" } else { "[ ]" };
+                        format!("{} {}", mark, name)
+                    }
+                };
+                let style = if focused {
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(Color::White)
+                };
+                Line::styled(text, style)
+            })
+            .collect();
+        f.render_widget(Paragraph::new(lines), chunks[0]);
+        return;
+    }
+
+    let items: Vec<_> = screen
+        .filters
+        .iter()
+        .map(|filter| ListItem::new(filter_summary(filter, &state.labels)))
+        .collect();
+
+    let mut list_state = screen.list_state.clone();
+    let filters = List::new(items)
+        .highlight_style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("▶ ");
+    f.render_stateful_widget(filters, chunks[0], &mut list_state);
+}
+
+// Draw the prompt for creating, renaming or deleting a user label
+pub fn draw_label_prompt(f: &mut ratatui::Frame, state: &mut AppState) {
+    let Some(prompt) = &state.label_prompt else {
+        return;
+    };
+    let area = f.size();
+    let popup_area = centered_rect(60, 20, area); // 60% width, 20% height
+
+    f.render_widget(Clear, popup_area); // Clear the area first
+
+    let (title, text) = match prompt {
+        LabelPrompt::Create { name } => (
+            "New label (Enter: create, Esc: cancel)",
+            format!("{}█\n\nUse / to nest labels, e.g. Projects/Tuimail", name),
+        ),
+        LabelPrompt::Rename { name, .. } => (
+            "Rename label (Enter: rename, Esc: cancel)",
+            format!("{}█", name),
+        ),
+        LabelPrompt::ConfirmDelete { name, .. } => (
+            "Delete label",
+            format!(
+                "Delete the label '{}'?\nMessages keep their other labels.\n\nPress 'y' for Yes, 'n' for No",
+                name
+            ),
+        ),
+    };
+
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Yellow))
+        .padding(Padding::uniform(1));
+
+    let paragraph = Paragraph::new(text)
+        .block(block)
+        .style(Style::default().fg(Color::White))
+        .wrap(Wrap { trim: false });
+
+    f.render_widget(paragraph, popup_area);
+}
+
+// Draw the fuzzy label picker for the selected message
+pub fn draw_label_picker(f: &mut ratatui::Frame, state: &mut AppState) {
+    let Some(picker) = &state.label_picker else {
+        return;
+    };
+    let area = f.size();
+    let popup_area = centered_rect(50, 60, area); // 50% width, 60% height
+
+    f.render_widget(Clear, popup_area); // Clear the area first
+
+    let title = if picker.moving {
+        "Move to (type to filter, Enter: move, Esc: close)"
+    } else {
+        "Labels (type to filter, Enter: apply/remove, Esc: close)"
+    };
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Yellow))
+        .padding(Padding::uniform(1));
+    let inner = block.inner(popup_area);
+    f.render_widget(block, popup_area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(2), Constraint::Min(0)])
+        .split(inner);
+
+    f.render_widget(
+        Paragraph::new(format!("{}█", picker.query)).style(Style::default().fg(Color::White)),
+        chunks[0],
+    );
+
+    let items: Vec<_> = state
+        .label_picker_matches()
+        .iter()
+        .map(|label| {
+            let name = label.name.as_deref().unwrap_or("");
+            if picker.moving {
+                return ListItem::new(name.to_string());
+            }
+            let applied = label
+                .id
+                .as_deref()
+                .is_some_and(|id| state.selected_message_has_label(id));
+            let mark = if applied { "[x]" } else { "[ ]" };
+            ListItem::new(format!("{} {}", mark, name))
+        })
+        .collect();
+
+    let mut list_state = ListState::default();
+    list_state.select(Some(picker.selected));
+    let labels = List::new(items)
+        .highlight_style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("▶ ");
+    f.render_stateful_widget(labels, chunks[1], &mut list_state);
+}
+
+// Draw the popup for picking the attachment to save
+pub fn draw_attachment_picker_popup(f: &mut ratatui::Frame, state: &mut AppState) {
+    let area = f.size();
+    let popup_area = centered_rect(60, 40, area); // 60% width, 40% height
+
+    f.render_widget(Clear, popup_area); // Clear the area first
+
+    let items: Vec<_> = state
+        .current_attachments()
+        .iter()
+        .map(|attachment| {
+            ListItem::new(format!(
+                "{} ({})",
+                attachment.filename,
+                format_size(attachment.size)
+            ))
+        })
+        .collect();
+
+    let title = format!(
+        "Save attachment to {} (Enter: save, Esc: close)",
+        state.download_dir.display()
+    );
+    let attachments = List::new(items)
+        .block(
+            Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow))
+                .padding(Padding::uniform(1)),
+        )
+        .highlight_style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("▶ ");
+    f.render_stateful_widget(attachments, popup_area, &mut state.attachment_picker_state);
+}
+
+// Draw the account switcher popup
+pub fn draw_account_switcher_popup(f: &mut ratatui::Frame, state: &mut AppState) {
+    let area = f.size();
+    let popup_area = centered_rect(40, 40, area); // 40% width, 40% height
+
+    f.render_widget(Clear, popup_area); // Clear the area first
+
+    let items: Vec<_> = state
+        .accounts
+        .iter()
+        .map(|account| {
+            if *account == state.account {
+                ListItem::new(format!("{} (active)", account))
+            } else {
+                ListItem::new(account.as_str())
+            }
+        })
+        .collect();
+
+    let accounts = List::new(items)
+        .block(
+            Block::default()
+                .title("Accounts (Enter: switch, Esc: close)")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow))
+                .padding(Padding::uniform(1)),
+        )
+        .highlight_style(
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("▶ ");
+    f.render_stateful_widget(accounts, popup_area, &mut state.account_switcher_state);
+}
+
 // Draw client secret deletion confirmation popup
 pub fn draw_client_secret_confirmation_popup(f: &mut ratatui::Frame, _state: &mut AppState) {
     let area = f.size();
@@ -368,6 +1130,48 @@ pub fn draw_client_secret_confirmation_popup(f: &mut ratatui::Frame, _state: &mu
     f.render_widget(paragraph, popup_area);
 }
 
+// Draw the offer to restore an autosaved message
+pub fn draw_restore_compose_popup(f: &mut ratatui::Frame, state: &mut AppState) {
+    let Some(restorable) = &state.restorable_compose else {
+        return;
+    };
+    let area = f.size();
+    let popup_area = centered_rect(60, 30, area); // 60% width, 30% height
+
+    f.render_widget(Clear, popup_area); // Clear the area first
+
+    let block = Block::default()
+        .title("Unsent message")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Yellow))
+        .padding(Padding::uniform(1));
+
+    let compose = &restorable.compose;
+    let or_none = |text: &str, none: &str| {
+        if text.trim().is_empty() {
+            none.to_string()
+        } else {
+            text.to_string()
+        }
+    };
+    let text = format!(
+        "A message you were writing was not sent.\n\nTo: {}\nSubject: {}\nLast saved: {}\n\nRestore it? Press 'y' for Yes, 'n' to discard it",
+        or_none(&compose.to, "(no recipients)"),
+        or_none(&compose.subject, "(no subject)"),
+        restorable
+            .saved_at
+            .with_timezone(&Local)
+            .format("%a, %b %-d at %H:%M")
+    );
+
+    let paragraph = Paragraph::new(text)
+        .block(block)
+        .style(Style::default().fg(Color::White))
+        .wrap(Wrap { trim: false });
+
+    f.render_widget(paragraph, popup_area);
+}
+
 pub fn draw_compose_ui(f: &mut ratatui::Frame, state: &mut AppState) {
     let area = f.size();
 
@@ -378,9 +1182,14 @@ pub fn draw_compose_ui(f: &mut ratatui::Frame, state: &mut AppState) {
     f.render_widget(Clear, popup_area);
 
     // Main compose window
+    let title = if state.compose_state.draft.is_some() {
+        "Compose Email (draft)"
+    } else {
+        "Compose Email"
+    };
     let compose_block = Block::default()
         .borders(Borders::ALL)
-        .title("Compose Email")
+        .title(title)
         .border_style(Style::default().fg(Color::Blue));
     f.render_widget(compose_block, popup_area);
 
@@ -403,6 +1212,7 @@ pub fn draw_compose_ui(f: &mut ratatui::Frame, state: &mut AppState) {
     constraints.extend_from_slice(&[
         Constraint::Length(3), // Subject - single line height
         Constraint::Min(8),    // Body
+        Constraint::Length(3), // Attachments
         Constraint::Length(3), // Send button
     ]);
 
@@ -560,6 +1370,49 @@ pub fn draw_compose_ui(f: &mut ratatui::Frame, state: &mut AppState) {
     }
     chunk_idx += 1;
 
+    // Attachments, or the attach-file prompt while it is open
+    let compose = &state.compose_state;
+    let (attachments_text, attachments_style) = if let Some(input) = &compose.attachment_input {
+        (
+            format!("Attach file: {}", input),
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )
+    } else if let Some(error) = &compose.attachment_error {
+        (error.clone(), Style::default().fg(Color::Red))
+    } else if compose.attachments.is_empty() {
+        (
+            "No attachments (Ctrl+A: attach a file)".to_string(),
+            Style::default().fg(Color::DarkGray),
+        )
+    } else {
+        let names: Vec<_> = compose
+            .attachments
+            .iter()
+            .map(|a| {
+                format!(
+                    "📎 {} ({})",
+                    a.filename,
+                    format_size(a.content.len() as u64)
+                )
+            })
+            .collect();
+        (names.join("  "), Style::default())
+    };
+    let attachments_field = Paragraph::new(attachments_text)
+        .style(attachments_style)
+        .block(Block::default().borders(Borders::ALL).title("Attachments:"))
+        .wrap(Wrap { trim: true });
+    f.render_widget(attachments_field, chunks[chunk_idx]);
+    if let Some(input) = &compose.attachment_input {
+        f.set_cursor(
+            chunks[chunk_idx].x + 1 + "Attach file: ".len() as u16 + input.chars().count() as u16,
+            chunks[chunk_idx].y + 1,
+        );
+    }
+    chunk_idx += 1;
+
     // Send button
     let send_style = if state.compose_state.focused_field == ComposeField::Send {
         Style::default()
@@ -584,7 +1437,7 @@ pub fn draw_compose_ui(f: &mut ratatui::Frame, state: &mut AppState) {
 
     // Help text at bottom
     let help_text =
-        "Tab/Shift+Tab: Navigate | Ctrl+B: Toggle Bcc | Enter: Send (on Send button) | Esc: Cancel";
+        "Tab: Navigate | Ctrl+B: Bcc | Ctrl+A/Ctrl+D: Attach/drop file | Enter: Send (on Send) | Ctrl+S: Save draft | Esc: Save and close | Ctrl+X: Discard";
     let help_area = Rect {
         x: popup_area.x,
         y: popup_area.y + popup_area.height,
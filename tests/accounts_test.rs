@@ -0,0 +1,238 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::fake_gmail::FakeMessage;
+use common::{press, start_with_inbox};
+use crossterm::event::KeyCode;
+use std::fs;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tuimail::accounts::{
+    database_file, database_url, discover_accounts, keyring_username, validate_account_name,
+    DEFAULT_ACCOUNT,
+};
+use tuimail::database::{CachedMessage, Database};
+use tuimail::gmail_api::auth::AccountSession;
+use tuimail::gmail_api::{refresh_current_label, KEYRING_USERNAME};
+use tuimail::state::{AppState, FocusedPane, UNIFIED_INBOX_LABEL_ID};
+use tuimail::types::Label;
+
+fn inbox_message(id: &str, minutes_ago: i64) -> CachedMessage {
+    let date = Utc::now() - Duration::minutes(minutes_ago);
+    CachedMessage {
+        id: id.to_string(),
+        thread_id: None,
+        label_ids: vec!["INBOX".to_string()],
+        snippet: None,
+        subject: Some(format!("Subject {}", id)),
+        from_addr: Some("sender@example.com".to_string()),
+        to_addr: None,
+        date_str: None,
+        body_text: Some(format!("Body {}", id)),
+        body_html: None,
+        received_date: date,
+        internal_date: date,
+        is_unread: false,
+        is_starred: false,
+        cache_timestamp: Utc::now(),
+    }
+}
+
+async fn account_db_with_inbox(url: &str, messages: &[CachedMessage]) -> Arc<Database> {
+    let db = Arc::new(Database::new(url).await.unwrap());
+    db.upsert_label(&Label {
+        id: Some("INBOX".to_string()),
+        name: Some("Inbox".to_string()),
+    })
+    .await
+    .unwrap();
+    for message in messages {
+        db.upsert_message(message).await.unwrap();
+    }
+    db
+}
+
+#[test]
+fn test_default_account_keeps_original_names() {
+    assert_eq!(keyring_username(DEFAULT_ACCOUNT), KEYRING_USERNAME);
+    assert_eq!(database_url(DEFAULT_ACCOUNT), "sqlite:rmail.db");
+    assert_eq!(database_file("work"), "rmail-work.db");
+    assert_ne!(keyring_username("work"), keyring_username(DEFAULT_ACCOUNT));
+}
+
+#[test]
+fn test_validate_account_name() {
+    assert!(validate_account_name("work").is_ok());
+    assert!(validate_account_name("side_project-2").is_ok());
+    assert!(validate_account_name("").is_err());
+    assert!(validate_account_name("../etc").is_err());
+    assert!(validate_account_name("my account").is_err());
+}
+
+#[test]
+fn test_discover_accounts_from_database_files() {
+    let dir = std::env::temp_dir().join(format!("tuimail_accounts_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for file in [
+        "rmail-work.db",
+        "rmail.db",
+        "rmail-personal.db",
+        "notes.db",
+        "rmail-.db",
+    ] {
+        fs::write(dir.join(file), "").unwrap();
+    }
+
+    let accounts = discover_accounts(&dir);
+    assert_eq!(accounts, vec!["default", "personal", "work"]);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_unified_inbox_merges_account_caches() {
+    let other_account = "unified-test-work";
+    let _ = fs::remove_file("test_unified_inbox.db");
+    let _ = fs::remove_file(database_file(other_account));
+
+    let own_db = account_db_with_inbox(
+        "sqlite:test_unified_inbox.db",
+        &[inbox_message("own_old", 30), inbox_message("own_new", 1)],
+    )
+    .await;
+    account_db_with_inbox(
+        &database_url(other_account),
+        &[inbox_message("work_mid", 10)],
+    )
+    .await;
+
+    let client = reqwest::Client::new();
+    let mut state = AppState::new(client, "fake_token".to_string());
+    state.set_database(own_db);
+    state.accounts = vec![DEFAULT_ACCOUNT.to_string(), other_account.to_string()];
+    state.labels = vec![Label {
+        id: Some("INBOX".to_string()),
+        name: Some("Inbox".to_string()),
+    }];
+    state.order_labels();
+    assert_eq!(
+        state.labels[1].id.as_deref(),
+        Some(UNIFIED_INBOX_LABEL_ID),
+        "Unified inbox should follow the account's inbox"
+    );
+
+    state
+        .load_messages_from_cache(UNIFIED_INBOX_LABEL_ID)
+        .await
+        .unwrap();
+
+    let ids: Vec<_> = state
+        .messages
+        .iter()
+        .map(|m| m.id.clone().unwrap())
+        .collect();
+    assert_eq!(ids, vec!["own_new", "work_mid", "own_old"]);
+
+    // Only messages of the other account are treated as foreign
+    assert_eq!(state.selected_message_foreign_account(), None);
+    state.selected_message = 1;
+    assert_eq!(
+        state.selected_message_foreign_account(),
+        Some(other_account.to_string())
+    );
+    assert_eq!(
+        state.message_bodies.get("work_mid").map(String::as_str),
+        Some("Body work_mid")
+    );
+
+    let _ = fs::remove_file("test_unified_inbox.db");
+    let _ = fs::remove_file(database_file(other_account));
+}
+
+// The other account's inbox is fetched with that account's own token, both
+// for the list and for opening a message
+#[tokio::test]
+async fn test_unified_inbox_syncs_other_accounts_with_their_credentials() {
+    let other_account = "unified-sync-work";
+    let _ = fs::remove_file(database_file(other_account));
+    let fake = start_with_inbox(vec![
+        FakeMessage::new("work-1", "Standup").body("Moved to 10:00."),
+        FakeMessage::new("work-2", "Quarterly report"),
+    ])
+    .await;
+
+    // Both accounts talk to the same fake; the active one, synced first, is
+    // answered with an empty inbox
+    fake.mailbox().respond_next(
+        200,
+        &[],
+        r#"{"emailAddress": "me@example.com", "historyId": "1"}"#,
+    );
+    fake.mailbox()
+        .respond_next(200, &[], r#"{"resultSizeEstimate": 0}"#);
+
+    let _ = fs::remove_file("test_unified_sync.db");
+    let mut state = AppState::new(reqwest::Client::new(), "active-token".to_string());
+    state.api_base_url = fake.base_url();
+    state.set_database(Arc::new(
+        Database::new("sqlite:test_unified_sync.db").await.unwrap(),
+    ));
+    state.accounts = vec![DEFAULT_ACCOUNT.to_string(), other_account.to_string()];
+    state.account_sessions.insert(
+        other_account.to_string(),
+        AccountSession {
+            token: "work-token".to_string(),
+            token_refresher: None,
+        },
+    );
+    state.labels = vec![Label {
+        id: Some("INBOX".to_string()),
+        name: Some("Inbox".to_string()),
+    }];
+    state.order_labels();
+    state.selected_label = 1;
+    state.focused_pane = FocusedPane::Messages;
+    let state_arc = Arc::new(RwLock::new(state));
+
+    refresh_current_label(&state_arc).await;
+    {
+        let state = state_arc.read().await;
+        let ids: Vec<_> = state.messages.iter().filter_map(|m| m.id.clone()).collect();
+        assert_eq!(ids, vec!["work-1", "work-2"]);
+        assert_eq!(
+            state.selected_message_foreign_account(),
+            Some(other_account.to_string())
+        );
+        assert!(!state.message_bodies.contains_key("work-1"));
+    }
+
+    press(&state_arc, KeyCode::Enter).await;
+    {
+        let state = state_arc.read().await;
+        assert_eq!(state.focused_pane, FocusedPane::Content);
+        assert_eq!(
+            state.message_bodies.get("work-1").map(String::as_str),
+            Some("Moved to 10:00.")
+        );
+    }
+
+    let requests = fake.requests();
+    let work_requests: Vec<_> = requests
+        .iter()
+        .filter(|r| r.authorization.as_deref() == Some("Bearer work-token"))
+        .collect();
+    assert!(work_requests
+        .iter()
+        .any(|r| r.path.starts_with("messages?")));
+    assert!(work_requests
+        .iter()
+        .any(|r| r.path.starts_with("messages/work-1")));
+    assert!(
+        !requests.iter().any(|r| r.method == "POST"),
+        "Reading another account's message must not change it"
+    );
+
+    let _ = fs::remove_file("test_unified_sync.db");
+    let _ = fs::remove_file(database_file(other_account));
+}
@@ -0,0 +1,141 @@
+mod common;
+
+use common::fake_gmail::{FakeGmail, FakeMessage, Mailbox};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tuimail::event_handler::handle_key_event;
+use tuimail::gmail_api::attachments::{read_attachment, request_attachment, save_attachment};
+use tuimail::gmail_api::{fetch_full_message, fetch_messages_for_label};
+use tuimail::state::{AppState, FocusedPane};
+use tuimail::types::Label;
+
+fn report() -> Vec<u8> {
+    (0..50_000u32).map(|i| (i % 251) as u8).collect()
+}
+
+async fn state_with_attachments() -> (FakeGmail, AppState) {
+    let fake = FakeGmail::start_with(Mailbox {
+        messages: vec![FakeMessage::new("m1", "Quarterly report")
+            .attachment("report.pdf", "application/pdf", &report())
+            .attachment("notes.txt", "text/plain", b"Remember the milk")],
+        ..Default::default()
+    })
+    .await;
+    let mut state = AppState::new(reqwest::Client::new(), "test-token".to_string());
+    state.api_base_url = fake.base_url();
+    state.labels = vec![Label {
+        id: Some("INBOX".to_string()),
+        name: Some("INBOX".to_string()),
+    }];
+    fetch_messages_for_label(&mut state).await;
+    fetch_full_message(&mut state, "m1").await.unwrap();
+    (fake, state)
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tuimail_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+#[tokio::test]
+async fn test_attachments_are_listed_and_downloaded() {
+    let (_fake, state) = state_with_attachments().await;
+
+    // The body still comes from the text part
+    assert_eq!(state.message_bodies["m1"], "Body of Quarterly report");
+    let attachments = state.current_attachments().to_vec();
+    let names: Vec<_> = attachments.iter().map(|a| a.filename.as_str()).collect();
+    assert_eq!(names, vec!["report.pdf", "notes.txt"]);
+    assert_eq!(attachments[0].size, 50_000);
+
+    let attachment_id = attachments[0].attachment_id.as_deref().unwrap();
+    let response = request_attachment(&state, "m1", attachment_id)
+        .await
+        .unwrap();
+    let mut progress = Vec::new();
+    let content = read_attachment(response, |bytes| progress.push(bytes))
+        .await
+        .unwrap();
+    assert_eq!(content, report());
+    assert!(!progress.is_empty());
+    assert!(progress.windows(2).all(|w| w[0] < w[1]));
+
+    assert!(request_attachment(&state, "m1", "att-9").await.is_err());
+}
+
+#[test]
+fn test_save_attachment_keeps_existing_files() {
+    let dir = temp_dir("save_attachment");
+
+    let first = save_attachment(&dir, "notes.txt", b"one").unwrap();
+    let second = save_attachment(&dir, "notes.txt", b"two").unwrap();
+    // File names from the sender cannot escape the download directory
+    let escaped = save_attachment(&dir, "../../etc/passwd", b"three").unwrap();
+
+    assert_eq!(first, dir.join("notes.txt"));
+    assert_eq!(second, dir.join("notes (1).txt"));
+    assert_eq!(escaped, dir.join("passwd"));
+    assert_eq!(fs::read(&first).unwrap(), b"one");
+    assert_eq!(fs::read(&second).unwrap(), b"two");
+
+    // Downloads saving the same name at once never overwrite each other
+    let saves: Vec<_> = (0..8)
+        .map(|i| {
+            let dir = dir.clone();
+            std::thread::spawn(move || {
+                let content = format!("copy {}", i);
+                let path = save_attachment(&dir, "photo.jpg", content.as_bytes()).unwrap();
+                (path, content)
+            })
+        })
+        .collect();
+    for save in saves {
+        let (path, content) = save.join().unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), content);
+    }
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn test_save_key_downloads_chosen_attachment_in_background() {
+    let (_fake, mut state) = state_with_attachments().await;
+    let dir = temp_dir("download_key");
+    state.download_dir = dir.clone();
+    state.focused_pane = FocusedPane::Content;
+    let state_arc = Arc::new(RwLock::new(state));
+
+    for code in [KeyCode::Char('w'), KeyCode::Down, KeyCode::Enter] {
+        let key = KeyEvent::new(code, KeyModifiers::NONE);
+        handle_key_event(key, state_arc.clone()).await.unwrap();
+    }
+
+    let mut saved = false;
+    for _ in 0..100 {
+        {
+            let state = state_arc.read().await;
+            if state.attachment_download.is_none() && state.error_message.is_some() {
+                assert!(state
+                    .error_message
+                    .as_deref()
+                    .unwrap()
+                    .contains("notes.txt"));
+                saved = true;
+                break;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(saved, "download did not finish");
+    assert_eq!(
+        fs::read(dir.join("notes.txt")).unwrap(),
+        b"Remember the milk"
+    );
+
+    let _ = fs::remove_dir_all(&dir);
+}
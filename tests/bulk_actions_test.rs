@@ -0,0 +1,188 @@
+mod common;
+
+use common::fake_gmail::{FakeGmail, FakeMessage};
+use common::{inbox_state, press, server_labels, start_with_inbox, type_text, visible_ids};
+use crossterm::event::KeyCode;
+use std::fs;
+use tuimail::outbox::replay_outbox;
+use tuimail::state::FocusedPane;
+
+fn inbox_messages() -> Vec<FakeMessage> {
+    vec![
+        FakeMessage::new("newsletter", "Weekly news"),
+        FakeMessage::new("invoice-1", "Invoice March"),
+        FakeMessage::new("lunch", "Lunch?").labels(&["INBOX", "UNREAD"]),
+        FakeMessage::new("invoice-2", "Invoice April"),
+    ]
+}
+
+// Bodies of the batchModify calls made so far
+fn batch_modify_bodies(fake: &FakeGmail) -> Vec<serde_json::Value> {
+    fake.requests()
+        .into_iter()
+        .filter(|r| r.path.ends_with("messages/batchModify"))
+        .map(|r| serde_json::from_str(&r.body).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_marked_messages_are_archived_together_and_can_be_undone() {
+    let fake = start_with_inbox(inbox_messages()).await;
+    let db_path = "test_bulk_archive.db";
+    let (state_arc, db) = inbox_state(&fake, db_path).await;
+
+    // Mark the first and the third message
+    press(&state_arc, KeyCode::Char('x')).await;
+    press(&state_arc, KeyCode::Char('j')).await;
+    press(&state_arc, KeyCode::Char('j')).await;
+    press(&state_arc, KeyCode::Char('x')).await;
+    {
+        let state = state_arc.read().await;
+        assert_eq!(state.marked_message_ids(), ["newsletter", "lunch"]);
+        assert!(state.is_marked(0));
+        assert!(!state.is_marked(1));
+    }
+
+    press(&state_arc, KeyCode::Char('a')).await;
+    {
+        let state = state_arc.read().await;
+        assert!(state.error_message.is_none());
+        assert_eq!(visible_ids(&state), ["invoice-1", "invoice-2"]);
+        assert!(!state.has_marks());
+    }
+    let batches = batch_modify_bodies(&fake);
+    assert_eq!(batches.len(), 1);
+    assert_eq!(
+        batches[0]["ids"],
+        serde_json::json!(["newsletter", "lunch"])
+    );
+    assert_eq!(batches[0]["removeLabelIds"], serde_json::json!(["INBOX"]));
+    assert!(server_labels(&fake, "newsletter").is_empty());
+    assert_eq!(server_labels(&fake, "lunch"), ["UNREAD"]);
+    assert_eq!(
+        db.get_messages_for_label("INBOX", 50, 0)
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+
+    // Undo brings both back to their places
+    press(&state_arc, KeyCode::Char('u')).await;
+    assert_eq!(
+        visible_ids(&*state_arc.read().await),
+        ["newsletter", "invoice-1", "lunch", "invoice-2"]
+    );
+    assert_eq!(server_labels(&fake, "lunch"), ["UNREAD", "INBOX"]);
+
+    let _ = fs::remove_file(db_path);
+}
+
+#[tokio::test]
+async fn test_range_selection_stars_and_marks_read_in_one_call_each() {
+    let fake = start_with_inbox(inbox_messages()).await;
+    let db_path = "test_bulk_flags.db";
+    let (state_arc, db) = inbox_state(&fake, db_path).await;
+
+    // Select the first three messages as a range
+    press(&state_arc, KeyCode::Char('v')).await;
+    press(&state_arc, KeyCode::Char('j')).await;
+    press(&state_arc, KeyCode::Char('j')).await;
+    assert_eq!(state_arc.read().await.marked_message_ids().len(), 3);
+    press(&state_arc, KeyCode::Char('v')).await;
+    {
+        let state = state_arc.read().await;
+        assert!(state.mark_anchor.is_none());
+        assert_eq!(
+            state.marked_message_ids(),
+            ["newsletter", "invoice-1", "lunch"]
+        );
+    }
+
+    press(&state_arc, KeyCode::Char('*')).await;
+    // One of them is unread, so all are marked read
+    press(&state_arc, KeyCode::Char('U')).await;
+    {
+        let state = state_arc.read().await;
+        assert!(state.messages[..3].iter().all(|m| m.is_starred()));
+        assert!(state.messages.iter().all(|m| !m.is_unread()));
+        assert!(!state.messages[3].is_starred());
+        // The marks stay for further actions
+        assert_eq!(state.marked_message_ids().len(), 3);
+    }
+    let batches = batch_modify_bodies(&fake);
+    assert_eq!(batches.len(), 2);
+    assert_eq!(batches[0]["addLabelIds"], serde_json::json!(["STARRED"]));
+    assert_eq!(batches[1]["removeLabelIds"], serde_json::json!(["UNREAD"]));
+    assert_eq!(server_labels(&fake, "lunch"), ["INBOX", "STARRED"]);
+    let cached = db.get_messages_for_label("INBOX", 50, 0).await.unwrap();
+    assert_eq!(cached.iter().filter(|m| m.is_starred).count(), 3);
+    assert!(cached.iter().all(|m| !m.is_unread));
+
+    // Escape drops the marks before leaving the pane
+    press(&state_arc, KeyCode::Esc).await;
+    {
+        let state = state_arc.read().await;
+        assert!(!state.has_marks());
+        assert_eq!(state.focused_pane, FocusedPane::Messages);
+    }
+
+    let _ = fs::remove_file(db_path);
+}
+
+#[tokio::test]
+async fn test_mark_matching_then_label_and_delete_offline() {
+    let fake = start_with_inbox(inbox_messages()).await;
+    let db_path = "test_bulk_matching.db";
+    let (state_arc, db) = inbox_state(&fake, db_path).await;
+
+    press(&state_arc, KeyCode::Char('M')).await;
+    type_text(&state_arc, "nothing like this").await;
+    press(&state_arc, KeyCode::Enter).await;
+    {
+        let mut state = state_arc.write().await;
+        assert!(state.error_message.is_some());
+        state.clear_error_message();
+        assert!(!state.has_marks());
+    }
+
+    press(&state_arc, KeyCode::Char('M')).await;
+    type_text(&state_arc, "INVOICE").await;
+    press(&state_arc, KeyCode::Enter).await;
+    assert_eq!(
+        state_arc.read().await.marked_message_ids(),
+        ["invoice-1", "invoice-2"]
+    );
+
+    // Apply the Receipts label to both
+    press(&state_arc, KeyCode::Char('l')).await;
+    type_text(&state_arc, "Receipts").await;
+    press(&state_arc, KeyCode::Enter).await;
+    assert_eq!(server_labels(&fake, "invoice-1"), ["INBOX", "Label_1"]);
+    assert_eq!(server_labels(&fake, "invoice-2"), ["INBOX", "Label_1"]);
+    assert_eq!(batch_modify_bodies(&fake).len(), 1);
+
+    // Deleting them offline waits in the outbox as a single entry
+    state_arc.write().await.api_base_url = "http://127.0.0.1:9".to_string();
+    press(&state_arc, KeyCode::Char('d')).await;
+    {
+        let mut state = state_arc.write().await;
+        state.clear_error_message();
+        assert_eq!(visible_ids(&state), ["newsletter", "lunch"]);
+        assert_eq!(state.outbox.len(), 1);
+
+        state.api_base_url = fake.base_url();
+        assert_eq!(replay_outbox(&mut state).await, 1);
+    }
+    let batches = batch_modify_bodies(&fake);
+    assert_eq!(batches.len(), 2);
+    assert_eq!(
+        batches[1]["ids"],
+        serde_json::json!(["invoice-1", "invoice-2"])
+    );
+    assert_eq!(batches[1]["addLabelIds"], serde_json::json!(["TRASH"]));
+    assert_eq!(server_labels(&fake, "invoice-2"), ["Label_1", "TRASH"]);
+    assert!(db.get_outbox().await.unwrap().is_empty());
+
+    let _ = fs::remove_file(db_path);
+}
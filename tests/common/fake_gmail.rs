@@ -0,0 +1,1031 @@
+//! A small in-process fake of the Gmail REST API.
+//!
+//! `FakeGmail::start()` binds a local port and serves the `users/me` endpoints
+//! the app uses (labels list/create/patch/delete, profile, messages
+//! list/get/modify/batchModify/trash/untrash/delete/batchDelete/send,
+//! resumable message uploads, attachments get, drafts
+//! list/create/update/send, threads get/modify/trash/untrash, history and
+//! settings filters list/create/delete) from an in-memory `Mailbox`. Point
+//! `AppState::api_base_url` at `base_url()` to run the real `gmail_api` code
+//! against it, then inspect the mailbox and the recorded requests.
+
+use base64::engine::general_purpose::{STANDARD, URL_SAFE, URL_SAFE_NO_PAD};
+use base64::engine::Engine;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+const API_PREFIX: &str = "/gmail/v1/users/me/";
+
+#[derive(Debug, Clone)]
+pub struct FakeMessage {
+    pub id: String,
+    pub thread_id: String,
+    pub label_ids: Vec<String>,
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub date: String,
+    pub body: String,
+    // (filename, MIME type, content), served through attachments.get
+    pub attachments: Vec<(String, String, Vec<u8>)>,
+    // Further headers, like Cc or References
+    pub extra_headers: Vec<(String, String)>,
+}
+
+impl FakeMessage {
+    pub fn new(id: &str, subject: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            thread_id: format!("thread-{}", id),
+            label_ids: vec!["INBOX".to_string()],
+            from: "sender@example.com".to_string(),
+            to: "me@example.com".to_string(),
+            subject: subject.to_string(),
+            date: "Tue, 10 Jun 2025 14:00:00 +0000".to_string(),
+            body: format!("Body of {}", subject),
+            attachments: Vec::new(),
+            extra_headers: Vec::new(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.extra_headers
+            .push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn attachment(mut self, filename: &str, mime_type: &str, content: &[u8]) -> Self {
+        self.attachments.push((
+            filename.to_string(),
+            mime_type.to_string(),
+            content.to_vec(),
+        ));
+        self
+    }
+
+    // Content of the attachment with the ID used in the message payload
+    pub fn attachment_content(&self, attachment_id: &str) -> Option<&[u8]> {
+        let index: usize = attachment_id.strip_prefix("att-")?.parse().ok()?;
+        self.attachments
+            .get(index)
+            .map(|(_, _, content)| content.as_slice())
+    }
+
+    pub fn labels(mut self, label_ids: &[&str]) -> Self {
+        self.label_ids = label_ids.iter().map(|id| id.to_string()).collect();
+        self
+    }
+
+    pub fn from(mut self, from: &str) -> Self {
+        self.from = from.to_string();
+        self
+    }
+
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = body.to_string();
+        self
+    }
+
+    pub fn thread(mut self, thread_id: &str) -> Self {
+        self.thread_id = thread_id.to_string();
+        self
+    }
+
+    fn reference(&self) -> Value {
+        json!({
+            "id": self.id,
+            "threadId": self.thread_id,
+            "labelIds": self.label_ids,
+        })
+    }
+
+    // The message as received, for format=raw (attachments are left out)
+    fn to_raw(&self) -> String {
+        let mut raw = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMessage-ID: <{}@mail.example.com>\r\n",
+            self.from, self.to, self.subject, self.date, self.id
+        );
+        for (name, value) in &self.extra_headers {
+            raw.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        raw.push_str("\r\n");
+        raw.push_str(&self.body.replace('\n', "\r\n"));
+        raw
+    }
+
+    fn to_json(&self, format: &str) -> Value {
+        if format == "raw" {
+            return json!({
+                "id": self.id,
+                "threadId": self.thread_id,
+                "raw": URL_SAFE.encode(self.to_raw()),
+            });
+        }
+
+        let mut headers = vec![
+            json!({ "name": "From", "value": self.from }),
+            json!({ "name": "To", "value": self.to }),
+            json!({ "name": "Subject", "value": self.subject }),
+            json!({ "name": "Date", "value": self.date }),
+            json!({ "name": "Message-ID", "value": format!("<{}@mail.example.com>", self.id) }),
+        ];
+        for (name, value) in &self.extra_headers {
+            headers.push(json!({ "name": name, "value": value }));
+        }
+        let mut payload = json!({
+            "mimeType": "text/plain",
+            "headers": headers,
+        });
+        if format != "metadata" {
+            payload["body"] = json!({ "data": URL_SAFE.encode(self.body.as_bytes()) });
+        }
+        if format != "metadata" && !self.attachments.is_empty() {
+            let mut parts = vec![json!({
+                "mimeType": "text/plain",
+                "filename": "",
+                "body": payload["body"].take(),
+            })];
+            for (index, (filename, mime_type, content)) in self.attachments.iter().enumerate() {
+                parts.push(json!({
+                    "mimeType": mime_type,
+                    "filename": filename,
+                    "body": { "attachmentId": format!("att-{}", index), "size": content.len() },
+                }));
+            }
+            payload["mimeType"] = json!("multipart/mixed");
+            payload["body"] = json!({ "size": 0 });
+            payload["parts"] = json!(parts);
+        }
+
+        json!({
+            "id": self.id,
+            "threadId": self.thread_id,
+            "labelIds": self.label_ids,
+            "snippet": self.body.chars().take(100).collect::<String>(),
+            "payload": payload,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    // Path and query relative to /gmail/v1/users/me/
+    pub path: String,
+    pub authorization: Option<String>,
+    pub body: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct CannedResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+pub struct Mailbox {
+    pub email_address: String,
+    pub labels: Vec<(String, String)>,
+    // Newest first, like Gmail's list order
+    pub messages: Vec<FakeMessage>,
+    pub history_id: u64,
+    // Start ids below this are reported as expired (HTTP 404)
+    pub oldest_history_id: u64,
+    pub history: Vec<(u64, Value)>,
+    // Decoded `raw` of every sent message
+    pub sent: Vec<String>,
+    pub requests: Vec<RecordedRequest>,
+    // Returned, in order, instead of routing the next requests
+    pub canned_responses: VecDeque<CannedResponse>,
+    pub upload_sessions: usize,
+    // (method, path, metadata) of the request each upload session completes
+    pub upload_requests: HashMap<usize, (String, String, Value)>,
+    // (draft id, id of its current message)
+    pub drafts: Vec<(String, String)>,
+    pub next_draft: usize,
+    // Filter resources as Gmail returns them, with their ids
+    pub filters: Vec<Value>,
+    pub next_filter: usize,
+    // Access tokens handed out by the OAuth token endpoint at /token
+    pub token_refreshes: usize,
+    // How long every response is held back, to simulate a slow connection
+    pub response_delay: Duration,
+}
+
+impl Default for Mailbox {
+    fn default() -> Self {
+        let labels = [
+            ("INBOX", "INBOX"),
+            ("SENT", "SENT"),
+            ("SPAM", "SPAM"),
+            ("TRASH", "TRASH"),
+            ("Label_1", "Receipts"),
+        ];
+        Self {
+            email_address: "me@example.com".to_string(),
+            labels: labels
+                .iter()
+                .map(|(id, name)| (id.to_string(), name.to_string()))
+                .collect(),
+            messages: Vec::new(),
+            history_id: 100,
+            oldest_history_id: 0,
+            history: Vec::new(),
+            sent: Vec::new(),
+            requests: Vec::new(),
+            canned_responses: VecDeque::new(),
+            upload_sessions: 0,
+            upload_requests: HashMap::new(),
+            drafts: Vec::new(),
+            next_draft: 1,
+            filters: Vec::new(),
+            next_filter: 1,
+            token_refreshes: 0,
+            response_delay: Duration::ZERO,
+        }
+    }
+}
+
+impl Mailbox {
+    pub fn message(&self, id: &str) -> Option<&FakeMessage> {
+        self.messages.iter().find(|m| m.id == id)
+    }
+
+    // Messages of a conversation, oldest first like threads.get returns them
+    pub fn thread(&self, thread_id: &str) -> Vec<&FakeMessage> {
+        self.messages
+            .iter()
+            .rev()
+            .filter(|m| m.thread_id == thread_id)
+            .collect()
+    }
+
+    // Apply a label change to every message of a conversation
+    pub fn modify_thread(&mut self, thread_id: &str, add: &[String], remove: &[String]) -> bool {
+        let ids: Vec<String> = self
+            .thread(thread_id)
+            .iter()
+            .map(|m| m.id.clone())
+            .collect();
+        for id in &ids {
+            self.modify(id, add, remove);
+        }
+        !ids.is_empty()
+    }
+
+    // Deliver a new message, recording it in the history
+    pub fn deliver(&mut self, message: FakeMessage) {
+        let record = json!({ "messagesAdded": [{ "message": message.reference() }] });
+        self.messages.insert(0, message);
+        self.record_history(record);
+    }
+
+    // Permanently delete a message, recording it in the history
+    pub fn delete(&mut self, id: &str) {
+        if let Some(position) = self.messages.iter().position(|m| m.id == id) {
+            let message = self.messages.remove(position);
+            self.record_history(json!({ "messagesDeleted": [{ "message": message.reference() }] }));
+        }
+    }
+
+    pub fn modify(&mut self, id: &str, add: &[String], remove: &[String]) -> Option<Value> {
+        let message = self.messages.iter_mut().find(|m| m.id == id)?;
+        let added: Vec<String> = add
+            .iter()
+            .filter(|label| !message.label_ids.contains(label))
+            .cloned()
+            .collect();
+        let removed: Vec<String> = remove
+            .iter()
+            .filter(|label| message.label_ids.contains(label))
+            .cloned()
+            .collect();
+        message.label_ids.retain(|label| !removed.contains(label));
+        message.label_ids.extend(added.iter().cloned());
+        let reference = message.reference();
+
+        let mut record = json!({});
+        if !added.is_empty() {
+            record["labelsAdded"] = json!([{ "message": reference, "labelIds": added }]);
+        }
+        if !removed.is_empty() {
+            record["labelsRemoved"] = json!([{ "message": reference, "labelIds": removed }]);
+        }
+        if !added.is_empty() || !removed.is_empty() {
+            self.record_history(record);
+        }
+        Some(reference)
+    }
+
+    // Forget all history so far, as Gmail does after about a week
+    pub fn expire_history(&mut self) {
+        self.oldest_history_id = self.history_id;
+        self.history.clear();
+    }
+
+    // Queue a response for the next request, before normal routing
+    pub fn respond_next(&mut self, status: u16, headers: &[(&str, &str)], body: &str) {
+        self.canned_responses.push_back(CannedResponse {
+            status,
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            body: body.to_string(),
+        });
+    }
+
+    fn record_history(&mut self, mut record: Value) {
+        self.history_id += 1;
+        record["id"] = json!(self.history_id.to_string());
+        self.history.push((self.history_id, record));
+    }
+}
+
+pub struct FakeGmail {
+    base_url: String,
+    mailbox: Arc<Mutex<Mailbox>>,
+}
+
+impl FakeGmail {
+    pub async fn start() -> Self {
+        Self::start_with(Mailbox::default()).await
+    }
+
+    pub async fn start_with(mailbox: Mailbox) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let mailbox = Arc::new(Mutex::new(mailbox));
+
+        let server_mailbox = mailbox.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle_connection(stream, server_mailbox.clone()));
+            }
+        });
+
+        Self { base_url, mailbox }
+    }
+
+    pub fn base_url(&self) -> String {
+        self.base_url.clone()
+    }
+
+    pub fn mailbox(&self) -> MutexGuard<'_, Mailbox> {
+        self.mailbox.lock().unwrap()
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.mailbox().requests.clone()
+    }
+}
+
+async fn handle_connection(stream: TcpStream, mailbox: Arc<Mutex<Mailbox>>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).await.unwrap_or(0) == 0 {
+        return;
+    }
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    let mut authorization = None;
+    let mut host = String::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await.unwrap_or(0) == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            match name.to_ascii_lowercase().as_str() {
+                "content-length" => content_length = value.trim().parse().unwrap_or(0),
+                "authorization" => authorization = Some(value.trim().to_string()),
+                "host" => host = value.trim().to_string(),
+                _ => {}
+            }
+        }
+    }
+
+    let mut body = vec![0; content_length];
+    if reader.read_exact(&mut body).await.is_err() {
+        return;
+    }
+    let body = String::from_utf8_lossy(&body).to_string();
+
+    let (response, delay) = {
+        let mut mailbox = mailbox.lock().unwrap();
+        // Media uploads are recorded and routed as "upload/<path>"
+        let path = match target.strip_prefix("/upload") {
+            Some(upload) => format!(
+                "upload/{}",
+                upload
+                    .strip_prefix(API_PREFIX)
+                    .or_else(|| upload.strip_prefix('/'))
+                    .unwrap_or(upload)
+            ),
+            None => target
+                .strip_prefix(API_PREFIX)
+                .unwrap_or(&target)
+                .to_string(),
+        };
+        mailbox.requests.push(RecordedRequest {
+            method: method.clone(),
+            path: path.clone(),
+            authorization,
+            body: body.clone(),
+        });
+        let response = match mailbox.canned_responses.pop_front() {
+            Some(canned) => canned,
+            None => {
+                let (status, value) = route(&mut mailbox, &method, &path, &body);
+                // Starting a resumable upload answers with the session URL
+                let headers = match value["uploadSession"].as_str() {
+                    Some(session) => vec![(
+                        "Location".to_string(),
+                        format!("http://{}/upload/session/{}", host, session),
+                    )],
+                    None => Vec::new(),
+                };
+                CannedResponse {
+                    status,
+                    headers,
+                    // Like Gmail, a 204 has no body at all
+                    body: if status == 204 {
+                        String::new()
+                    } else {
+                        value.to_string()
+                    },
+                }
+            }
+        };
+        (response, mailbox.response_delay)
+    };
+    tokio::time::sleep(delay).await;
+
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason_phrase(response.status),
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    let _ = writer.write_all(head.as_bytes()).await;
+    let _ = writer.write_all(response.body.as_bytes()).await;
+    let _ = writer.shutdown().await;
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        409 => "Conflict",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+fn not_found() -> (u16, Value) {
+    (
+        404,
+        json!({ "error": { "code": 404, "message": "Requested entity was not found." } }),
+    )
+}
+
+fn bad_request(message: &str) -> (u16, Value) {
+    (400, json!({ "error": { "code": 400, "message": message } }))
+}
+
+fn route(mailbox: &mut Mailbox, method: &str, path: &str, raw_body: &str) -> (u16, Value) {
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+    let query = parse_query(query);
+    let segments: Vec<&str> = path.split('/').collect();
+    let body: Value = serde_json::from_str(raw_body).unwrap_or(Value::Null);
+
+    match (method, segments.as_slice()) {
+        // The OAuth token endpoint, outside the Gmail API paths
+        ("POST", ["", "token"]) => {
+            mailbox.token_refreshes += 1;
+            (
+                200,
+                json!({
+                    "access_token": format!("refreshed-token-{}", mailbox.token_refreshes),
+                    "expires_in": 3600,
+                }),
+            )
+        }
+        ("GET", ["profile"]) => (
+            200,
+            json!({
+                "emailAddress": mailbox.email_address,
+                "historyId": mailbox.history_id.to_string(),
+            }),
+        ),
+        ("GET", ["labels"]) => {
+            let labels: Vec<Value> = mailbox
+                .labels
+                .iter()
+                .map(|(id, name)| json!({ "id": id, "name": name }))
+                .collect();
+            (200, json!({ "labels": labels }))
+        }
+        ("POST", ["labels"]) => {
+            let name = body["name"].as_str().unwrap_or_default();
+            if name.is_empty() {
+                return bad_request("Invalid label name");
+            }
+            if mailbox.labels.iter().any(|(_, existing)| existing == name) {
+                return (
+                    409,
+                    json!({ "error": { "code": 409, "message": "Label name exists or conflicts" } }),
+                );
+            }
+            let id = format!("Label_{}", mailbox.labels.len() + 1);
+            mailbox.labels.push((id.clone(), name.to_string()));
+            (200, json!({ "id": id, "name": name, "type": "user" }))
+        }
+        ("PATCH", ["labels", id]) => {
+            let name = body["name"].as_str().unwrap_or_default().to_string();
+            match mailbox
+                .labels
+                .iter_mut()
+                .find(|(existing, _)| existing == id)
+            {
+                Some(label) if id.starts_with("Label_") && !name.is_empty() => {
+                    label.1 = name.clone();
+                    (200, json!({ "id": id, "name": name, "type": "user" }))
+                }
+                Some(_) => bad_request("Invalid label update"),
+                None => not_found(),
+            }
+        }
+        ("DELETE", ["labels", id]) => {
+            if !id.starts_with("Label_") || !mailbox.labels.iter().any(|(l, _)| l == id) {
+                return not_found();
+            }
+            mailbox.labels.retain(|(l, _)| l != id);
+            for message in &mut mailbox.messages {
+                message.label_ids.retain(|l| l != id);
+            }
+            (204, Value::Null)
+        }
+        ("GET", ["settings", "filters"]) => {
+            // Like Gmail, leave the list out when there are no filters
+            if mailbox.filters.is_empty() {
+                (200, json!({}))
+            } else {
+                (200, json!({ "filter": mailbox.filters }))
+            }
+        }
+        ("POST", ["settings", "filters"]) => {
+            let empty = |value: &Value| value.as_object().is_none_or(|o| o.is_empty());
+            if empty(&body["criteria"]) || empty(&body["action"]) {
+                return bad_request("Filter doesn't have any criteria or actions");
+            }
+            let mut filter = body.clone();
+            filter["id"] = json!(format!("filter-{}", mailbox.next_filter));
+            mailbox.next_filter += 1;
+            mailbox.filters.push(filter.clone());
+            (200, filter)
+        }
+        ("DELETE", ["settings", "filters", id]) => {
+            let before = mailbox.filters.len();
+            mailbox.filters.retain(|filter| filter["id"] != *id);
+            if mailbox.filters.len() == before {
+                return not_found();
+            }
+            (204, Value::Null)
+        }
+        ("GET", ["messages"]) => list_messages(mailbox, &query),
+        ("GET", ["messages", id]) => {
+            let format = first(&query, "format").unwrap_or("full");
+            match mailbox.message(id) {
+                Some(message) => (200, message.to_json(format)),
+                None => not_found(),
+            }
+        }
+        ("GET", ["messages", id, "attachments", attachment_id]) => {
+            match mailbox
+                .message(id)
+                .and_then(|m| m.attachment_content(attachment_id))
+            {
+                Some(content) => (
+                    200,
+                    json!({ "size": content.len(), "data": URL_SAFE.encode(content) }),
+                ),
+                None => not_found(),
+            }
+        }
+        ("POST", ["messages", "send"]) => send_message(mailbox, &body),
+        ("PUT", ["upload", "session", session]) => {
+            let Some((method, path, mut metadata)) = session
+                .parse()
+                .ok()
+                .and_then(|session| mailbox.upload_requests.remove(&session))
+            else {
+                return not_found();
+            };
+            // Complete the request as if the message had come as its `raw`
+            let raw = json!(URL_SAFE_NO_PAD.encode(raw_body.as_bytes()));
+            if path.starts_with("drafts") {
+                metadata["message"]["raw"] = raw;
+            } else {
+                metadata["raw"] = raw;
+            }
+            route(mailbox, &method, &path, &metadata.to_string())
+        }
+        (_, ["upload", rest @ ..]) if first(&query, "uploadType") == Some("resumable") => {
+            mailbox.upload_sessions += 1;
+            let session = mailbox.upload_sessions;
+            mailbox
+                .upload_requests
+                .insert(session, (method.to_string(), rest.join("/"), body));
+            (200, json!({ "uploadSession": session.to_string() }))
+        }
+        ("GET", ["drafts"]) => {
+            let drafts: Vec<Value> = mailbox
+                .drafts
+                .iter()
+                .filter_map(|(id, message_id)| {
+                    let message = mailbox.message(message_id)?;
+                    Some(json!({ "id": id, "message": message.reference() }))
+                })
+                .collect();
+            (200, json!({ "drafts": drafts }))
+        }
+        ("POST", ["drafts"]) => {
+            let id = format!("draft-{}", mailbox.next_draft);
+            save_draft(mailbox, &id, &body)
+        }
+        ("PUT", ["drafts", id]) => {
+            let Some(position) = mailbox.drafts.iter().position(|(d, _)| d == id) else {
+                return not_found();
+            };
+            let (_, message_id) = mailbox.drafts.remove(position);
+            mailbox.delete(&message_id);
+            save_draft(mailbox, id, &body)
+        }
+        ("POST", ["drafts", "send"]) => {
+            let id = body["id"].as_str().unwrap_or_default();
+            let Some(position) = mailbox.drafts.iter().position(|(d, _)| d == id) else {
+                return not_found();
+            };
+            let Some(decoded) = decode_raw(&body["message"]) else {
+                return bad_request("'raw' is required");
+            };
+            let (_, message_id) = mailbox.drafts.remove(position);
+            mailbox.delete(&message_id);
+            deliver_sent(mailbox, decoded, body["message"]["threadId"].as_str())
+        }
+        ("DELETE", ["messages", id]) => {
+            if mailbox.message(id).is_none() {
+                return not_found();
+            }
+            mailbox.delete(id);
+            (204, Value::Null)
+        }
+        ("POST", ["messages", "batchDelete"]) => {
+            for id in string_list(&body["ids"]) {
+                mailbox.delete(&id);
+            }
+            (204, Value::Null)
+        }
+        ("POST", ["messages", "batchModify"]) => {
+            let add = string_list(&body["addLabelIds"]);
+            let remove = string_list(&body["removeLabelIds"]);
+            for id in string_list(&body["ids"]) {
+                mailbox.modify(&id, &add, &remove);
+            }
+            (204, Value::Null)
+        }
+        ("POST", ["messages", id, "modify"]) => {
+            let add = string_list(&body["addLabelIds"]);
+            let remove = string_list(&body["removeLabelIds"]);
+            match mailbox.modify(id, &add, &remove) {
+                Some(reference) => (200, reference),
+                None => not_found(),
+            }
+        }
+        ("POST", ["messages", id, "trash"]) => {
+            match mailbox.modify(id, &["TRASH".to_string()], &["INBOX".to_string()]) {
+                Some(reference) => (200, reference),
+                None => not_found(),
+            }
+        }
+        ("POST", ["messages", id, "untrash"]) => {
+            match mailbox.modify(id, &[], &["TRASH".to_string()]) {
+                Some(reference) => (200, reference),
+                None => not_found(),
+            }
+        }
+        ("GET", ["threads", id]) => {
+            let format = first(&query, "format").unwrap_or("full");
+            let messages: Vec<Value> = mailbox
+                .thread(id)
+                .iter()
+                .map(|m| m.to_json(format))
+                .collect();
+            if messages.is_empty() {
+                return not_found();
+            }
+            (200, json!({ "id": id, "messages": messages }))
+        }
+        ("POST", ["threads", id, "modify"]) => {
+            let add = string_list(&body["addLabelIds"]);
+            let remove = string_list(&body["removeLabelIds"]);
+            if mailbox.modify_thread(id, &add, &remove) {
+                (200, json!({ "id": id }))
+            } else {
+                not_found()
+            }
+        }
+        ("POST", ["threads", id, "trash"]) => {
+            if mailbox.modify_thread(id, &["TRASH".to_string()], &["INBOX".to_string()]) {
+                (200, json!({ "id": id }))
+            } else {
+                not_found()
+            }
+        }
+        ("POST", ["threads", id, "untrash"]) => {
+            if mailbox.modify_thread(id, &[], &["TRASH".to_string()]) {
+                (200, json!({ "id": id }))
+            } else {
+                not_found()
+            }
+        }
+        ("GET", ["history"]) => list_history(mailbox, &query),
+        _ => not_found(),
+    }
+}
+
+fn list_messages(mailbox: &Mailbox, query: &HashMap<String, Vec<String>>) -> (u16, Value) {
+    let label_ids = query.get("labelIds").cloned().unwrap_or_default();
+    let max_results: usize = first(query, "maxResults")
+        .and_then(|max| max.parse().ok())
+        .unwrap_or(100);
+    let offset: usize = first(query, "pageToken")
+        .and_then(|token| token.parse().ok())
+        .unwrap_or(0);
+
+    let search = first(query, "q").unwrap_or("");
+
+    let matching: Vec<&FakeMessage> = mailbox
+        .messages
+        .iter()
+        .filter(|m| label_ids.iter().all(|label| m.label_ids.contains(label)))
+        .filter(|m| matches_search(m, search))
+        .collect();
+
+    let page: Vec<Value> = matching
+        .iter()
+        .skip(offset)
+        .take(max_results)
+        .map(|m| json!({ "id": m.id, "threadId": m.thread_id }))
+        .collect();
+
+    let mut response = json!({ "resultSizeEstimate": matching.len() });
+    if !page.is_empty() {
+        response["messages"] = json!(page);
+    }
+    if offset + max_results < matching.len() {
+        response["nextPageToken"] = json!((offset + max_results).to_string());
+    }
+    (200, response)
+}
+
+// A small subset of the Gmail search syntax: from:, to:, subject:, label:
+// and bare words, all as case-insensitive substring matches
+fn matches_search(message: &FakeMessage, search: &str) -> bool {
+    search.split_whitespace().all(|term| {
+        let term = term.to_lowercase();
+        let contains = |field: &str, value: &str| field.to_lowercase().contains(value);
+        match term.split_once(':') {
+            Some(("from", value)) => contains(&message.from, value),
+            Some(("to", value)) => contains(&message.to, value),
+            Some(("subject", value)) => contains(&message.subject, value),
+            Some(("label", value)) => message
+                .label_ids
+                .iter()
+                .any(|label| label.eq_ignore_ascii_case(value)),
+            _ => contains(&message.subject, &term) || contains(&message.body, &term),
+        }
+    })
+}
+
+fn send_message(mailbox: &mut Mailbox, body: &Value) -> (u16, Value) {
+    let Some(decoded) = decode_raw(body) else {
+        return bad_request("'raw' is required and must be base64url");
+    };
+
+    deliver_sent(mailbox, decoded, body["threadId"].as_str())
+}
+
+// The decoded `raw` field of a Message resource
+fn decode_raw(message: &Value) -> Option<String> {
+    let raw = message["raw"].as_str()?;
+    URL_SAFE_NO_PAD
+        .decode(raw.trim_end_matches('='))
+        .ok()
+        .map(|bytes| String::from_utf8_lossy(&bytes).to_string())
+}
+
+// Store the message of a drafts.create/update request as the current
+// message of draft `id`
+fn save_draft(mailbox: &mut Mailbox, id: &str, body: &Value) -> (u16, Value) {
+    let Some(decoded) = decode_raw(&body["message"]) else {
+        return bad_request("'message.raw' is required and must be base64url");
+    };
+    let message_id = format!("draft-msg-{}", mailbox.next_draft);
+    mailbox.next_draft += 1;
+
+    let mut message = parse_raw(&message_id, &decoded).labels(&["DRAFT"]);
+    if message.from.is_empty() {
+        message.from = mailbox.email_address.clone();
+    }
+    if let Some(thread_id) = body["message"]["threadId"].as_str() {
+        message = message.thread(thread_id);
+    }
+    let reference = message.reference();
+    mailbox.deliver(message);
+    mailbox.drafts.push((id.to_string(), message_id));
+    (200, json!({ "id": id, "message": reference }))
+}
+
+// A message from a raw RFC 2822 message as the app builds it: headers, the
+// first text/plain part as the body and base64 parts with a file name as
+// attachments. Encoded words and quoted-printable are left as they are.
+fn parse_raw(id: &str, raw: &str) -> FakeMessage {
+    let (head, body) = raw.split_once("\r\n\r\n").unwrap_or((raw, ""));
+    let head = head.replace("\r\n ", " ").replace("\r\n\t", " ");
+    let header = |name: &str| {
+        head.split("\r\n")
+            .find_map(|line| {
+                let (key, value) = line.split_once(": ")?;
+                key.eq_ignore_ascii_case(name).then(|| value.to_string())
+            })
+            .unwrap_or_default()
+    };
+
+    let mut message = FakeMessage::new(id, &header("Subject"));
+    message.from = header("From");
+    message.to = header("To");
+    for name in ["Cc", "Bcc", "In-Reply-To", "References"] {
+        let value = header(name);
+        if !value.is_empty() {
+            message = message.header(name, &value);
+        }
+    }
+
+    let content_type = header("Content-Type");
+    let Some(boundary) = content_type
+        .split("boundary=\"")
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+    else {
+        message.body = body.replace("\r\n", "\n");
+        return message;
+    };
+
+    message.body = String::new();
+    let delimiter = format!("--{}", boundary);
+    for part in body.split(&delimiter).skip(1) {
+        let Some((part_head, part_body)) = part.split_once("\r\n\r\n") else {
+            continue;
+        };
+        let part_head = part_head.replace("\r\n ", " ");
+        let filename = part_head
+            .split("filename=\"")
+            .nth(1)
+            .and_then(|rest| rest.split('"').next());
+        match filename {
+            Some(filename) => {
+                let mime_type = part_head
+                    .split("Content-Type: ")
+                    .nth(1)
+                    .and_then(|rest| rest.split(';').next())
+                    .unwrap_or("application/octet-stream");
+                let encoded: String = part_body.split_whitespace().collect();
+                let content = STANDARD.decode(encoded).unwrap_or_default();
+                message = message.attachment(filename, mime_type, &content);
+            }
+            None if message.body.is_empty() && part_head.contains("text/plain") => {
+                message.body = part_body.trim_end_matches("\r\n").replace("\r\n", "\n");
+            }
+            None => {}
+        }
+    }
+    message
+}
+
+// Record a sent message and file it under SENT, in the given conversation
+fn deliver_sent(mailbox: &mut Mailbox, decoded: String, thread_id: Option<&str>) -> (u16, Value) {
+    let subject = decoded
+        .lines()
+        .find_map(|line| line.strip_prefix("Subject: "))
+        .unwrap_or_default()
+        .to_string();
+    let id = format!("sent-{}", mailbox.sent.len() + 1);
+    mailbox.sent.push(decoded);
+    let mut message = FakeMessage::new(&id, &subject).labels(&["SENT"]);
+    if let Some(thread_id) = thread_id {
+        message = message.thread(thread_id);
+    }
+    mailbox.deliver(message);
+
+    let reference = mailbox.message(&id).map(FakeMessage::reference);
+    (200, reference.unwrap_or(Value::Null))
+}
+
+fn list_history(mailbox: &Mailbox, query: &HashMap<String, Vec<String>>) -> (u16, Value) {
+    let Some(start) = first(query, "startHistoryId").and_then(|id| id.parse::<u64>().ok()) else {
+        return bad_request("startHistoryId is required");
+    };
+    if start < mailbox.oldest_history_id {
+        return not_found();
+    }
+
+    let records: Vec<Value> = mailbox
+        .history
+        .iter()
+        .filter(|(id, _)| *id > start)
+        .map(|(_, record)| record.clone())
+        .collect();
+
+    let mut response = json!({ "historyId": mailbox.history_id.to_string() });
+    if !records.is_empty() {
+        response["history"] = json!(records);
+    }
+    (200, response)
+}
+
+fn first<'a>(query: &'a HashMap<String, Vec<String>>, name: &str) -> Option<&'a str> {
+    query
+        .get(name)
+        .and_then(|values| values.first())
+        .map(String::as_str)
+}
+
+fn string_list(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(|v| v.as_str().map(String::from))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn parse_query(query: &str) -> HashMap<String, Vec<String>> {
+    let mut params: HashMap<String, Vec<String>> = HashMap::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        params
+            .entry(percent_decode(name))
+            .or_default()
+            .push(percent_decode(value));
+    }
+    params
+}
+
+pub fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => match u8::from_str_radix(&value[i + 1..i + 3], 16) {
+                Ok(byte) => {
+                    decoded.push(byte);
+                    i += 2;
+                }
+                Err(_) => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
@@ -0,0 +1,102 @@
+// Shared helpers for the integration tests. Each test crate uses a different
+// subset of them.
+#![allow(dead_code)]
+
+pub mod fake_gmail;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use fake_gmail::{FakeGmail, FakeMessage, Mailbox};
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tuimail::database::Database;
+use tuimail::event_handler::handle_key_event;
+use tuimail::gmail_api::{fetch_labels, fetch_messages_for_label};
+use tuimail::state::{AppState, FocusedPane};
+
+// A fake account whose inbox holds `messages`, newest first
+pub async fn start_with_inbox(messages: Vec<FakeMessage>) -> FakeGmail {
+    FakeGmail::start_with(Mailbox {
+        messages,
+        ..Default::default()
+    })
+    .await
+}
+
+// The inbox of the fake account, cached in a fresh database at db_path
+pub async fn inbox_state(
+    fake: &FakeGmail,
+    db_path: &str,
+) -> (Arc<RwLock<AppState>>, Arc<Database>) {
+    folder_state(fake, db_path, "INBOX").await
+}
+
+// Folder `label_id` of the fake account, cached in a fresh database at db_path
+pub async fn folder_state(
+    fake: &FakeGmail,
+    db_path: &str,
+    label_id: &str,
+) -> (Arc<RwLock<AppState>>, Arc<Database>) {
+    let _ = fs::remove_file(db_path);
+    let db = Arc::new(Database::new(&format!("sqlite:{}", db_path)).await.unwrap());
+
+    let mut state = AppState::new(reqwest::Client::new(), "test-token".to_string());
+    state.api_base_url = fake.base_url();
+    state.set_database(db.clone());
+    state.labels = fetch_labels(&state).await.unwrap();
+    for label in &state.labels {
+        db.upsert_label(label).await.unwrap();
+    }
+    state.order_labels();
+    state.selected_label = state
+        .labels
+        .iter()
+        .position(|l| l.id.as_deref() == Some(label_id))
+        .unwrap();
+    fetch_messages_for_label(&mut state).await;
+    state.focused_pane = FocusedPane::Messages;
+    (Arc::new(RwLock::new(state)), db)
+}
+
+pub async fn press(state_arc: &Arc<RwLock<AppState>>, code: KeyCode) {
+    press_with(state_arc, code, KeyModifiers::NONE).await;
+}
+
+pub async fn press_with(state_arc: &Arc<RwLock<AppState>>, code: KeyCode, modifiers: KeyModifiers) {
+    // A key handler that locks the state twice would hang here
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        handle_key_event(KeyEvent::new(code, modifiers), state_arc.clone()),
+    )
+    .await
+    .expect("key handler did not finish")
+    .unwrap();
+}
+
+pub async fn type_text(state_arc: &Arc<RwLock<AppState>>, text: &str) {
+    for c in text.chars() {
+        press(state_arc, KeyCode::Char(c)).await;
+    }
+}
+
+pub fn visible_ids(state: &AppState) -> Vec<String> {
+    state.messages.iter().filter_map(|m| m.id.clone()).collect()
+}
+
+pub fn server_labels(fake: &FakeGmail, id: &str) -> Vec<String> {
+    fake.mailbox().message(id).unwrap().label_ids.clone()
+}
+
+// Ids of the messages cached under label_id, sorted
+pub async fn cached_ids(db: &Database, label_id: &str) -> Vec<String> {
+    let mut ids: Vec<String> = db
+        .get_messages_for_label(label_id, 50, 0)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|m| m.id)
+        .collect();
+    ids.sort();
+    ids
+}
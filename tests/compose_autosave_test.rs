@@ -0,0 +1,123 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::fs;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::RwLock;
+use tuimail::database::Database;
+use tuimail::event_handler::handle_key_event;
+use tuimail::mime::OutgoingAttachment;
+use tuimail::state::{AppState, ComposeField, COMPOSE_AUTOSAVE_INTERVAL};
+
+// A fresh start of the app on the database at db_path, without network access
+async fn start_app(db_path: &str) -> (Arc<RwLock<AppState>>, Arc<Database>) {
+    let db = Arc::new(Database::new(&format!("sqlite:{}", db_path)).await.unwrap());
+    let mut state = AppState::new(reqwest::Client::new(), "test-token".to_string());
+    state.api_base_url = "http://127.0.0.1:9".to_string();
+    state.set_database(db.clone());
+    state.load_restorable_compose().await;
+    (Arc::new(RwLock::new(state)), db)
+}
+
+async fn press(state_arc: &Arc<RwLock<AppState>>, code: KeyCode, modifiers: KeyModifiers) {
+    handle_key_event(KeyEvent::new(code, modifiers), state_arc.clone())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_unsent_message_is_restored_after_restart() {
+    let db_path = std::env::temp_dir().join(format!("tuimail_autosave_{}.db", std::process::id()));
+    let db_path = db_path.to_str().unwrap();
+    let _ = fs::remove_file(db_path);
+
+    {
+        let (state_arc, _db) = start_app(db_path).await;
+        let mut state = state_arc.write().await;
+        assert!(state.restorable_compose.is_none());
+        state.start_composing(
+            Some("bob@example.com".to_string()),
+            None,
+            Some("Plans".to_string()),
+            Some("Maybe Friday?".to_string()),
+            Some(ComposeField::Body),
+        );
+        state.compose_state.body_cursor_position = 5;
+        state.compose_state.attachments.push(OutgoingAttachment {
+            filename: "map.txt".to_string(),
+            content_type: "text/plain".to_string(),
+            content: b"Turn left".to_vec(),
+        });
+        state.autosave_compose().await;
+        // The app dies here without leaving compose
+    }
+
+    let (state_arc, db) = start_app(db_path).await;
+    assert!(state_arc.read().await.restorable_compose.is_some());
+    // Other keys leave the prompt up
+    press(&state_arc, KeyCode::Char('c'), KeyModifiers::NONE).await;
+    assert!(!state_arc.read().await.composing);
+
+    press(&state_arc, KeyCode::Char('y'), KeyModifiers::NONE).await;
+    {
+        let state = state_arc.read().await;
+        assert!(state.composing);
+        assert!(state.restorable_compose.is_none());
+        let compose = &state.compose_state;
+        assert_eq!(compose.to, "bob@example.com");
+        assert_eq!(compose.subject, "Plans");
+        assert_eq!(compose.body, "Maybe Friday?");
+        assert_eq!(compose.focused_field, ComposeField::Body);
+        assert_eq!(compose.body_cursor_position, 5);
+        assert_eq!(compose.attachments.len(), 1);
+        assert_eq!(compose.attachments[0].content, b"Turn left");
+    }
+
+    // Closing the compose window drops the saved copy
+    press(&state_arc, KeyCode::Char('x'), KeyModifiers::CONTROL).await;
+    state_arc.write().await.autosave_compose().await;
+    assert!(db.load_compose().await.unwrap().is_none());
+
+    let _ = fs::remove_file(db_path);
+}
+
+#[tokio::test]
+async fn test_autosave_is_throttled_and_can_be_discarded() {
+    let db_path = std::env::temp_dir().join(format!(
+        "tuimail_autosave_discard_{}.db",
+        std::process::id()
+    ));
+    let db_path = db_path.to_str().unwrap();
+    let _ = fs::remove_file(db_path);
+
+    {
+        let (state_arc, db) = start_app(db_path).await;
+        let mut state = state_arc.write().await;
+        // An empty compose window is not worth saving
+        state.start_composing(None, None, None, None, None);
+        state.autosave_compose().await;
+        assert!(db.load_compose().await.unwrap().is_none());
+
+        state.compose_state.to = "bob@example.com".to_string();
+        state.autosave_compose().await;
+        state.compose_state.subject = "Plans".to_string();
+        state.autosave_compose().await;
+        let saved = db.load_compose().await.unwrap().unwrap();
+        assert_eq!(saved.compose.subject, "");
+
+        if let Some(autosave) = state.compose_autosave.as_mut() {
+            autosave.saved_at = Instant::now() - COMPOSE_AUTOSAVE_INTERVAL;
+        }
+        state.autosave_compose().await;
+        let saved = db.load_compose().await.unwrap().unwrap();
+        assert_eq!(saved.compose.subject, "Plans");
+    }
+
+    let (state_arc, db) = start_app(db_path).await;
+    press(&state_arc, KeyCode::Char('n'), KeyModifiers::NONE).await;
+    let state = state_arc.read().await;
+    assert!(!state.composing);
+    assert!(state.restorable_compose.is_none());
+    assert!(db.load_compose().await.unwrap().is_none());
+
+    let _ = fs::remove_file(db_path);
+}
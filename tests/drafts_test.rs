@@ -0,0 +1,187 @@
+mod common;
+
+use common::fake_gmail::{FakeGmail, FakeMessage, Mailbox};
+use common::press_with;
+use crossterm::event::{KeyCode, KeyModifiers};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tuimail::gmail_api::fetch_messages_for_label;
+use tuimail::state::{AppState, ComposeField, FocusedPane};
+use tuimail::types::Label;
+
+async fn state_for(fake: &FakeGmail, label_id: &str) -> Arc<RwLock<AppState>> {
+    let mut state = AppState::new(reqwest::Client::new(), "test-token".to_string());
+    state.api_base_url = fake.base_url();
+    state.labels = vec![Label {
+        id: Some(label_id.to_string()),
+        name: Some(label_id.to_string()),
+    }];
+    fetch_messages_for_label(&mut state).await;
+    state.focused_pane = FocusedPane::Messages;
+    Arc::new(RwLock::new(state))
+}
+
+async fn compose(state_arc: &Arc<RwLock<AppState>>, to: &str, subject: &str, body: &str) {
+    let mut state = state_arc.write().await;
+    state.start_composing(
+        Some(to.to_string()),
+        None,
+        Some(subject.to_string()),
+        Some(body.to_string()),
+        Some(ComposeField::Body),
+    );
+}
+
+#[tokio::test]
+async fn test_escape_saves_a_draft() {
+    let fake = FakeGmail::start().await;
+    let state_arc = state_for(&fake, "INBOX").await;
+
+    // Nothing typed, nothing saved
+    press_with(&state_arc, KeyCode::Char('c'), KeyModifiers::NONE).await;
+    press_with(&state_arc, KeyCode::Esc, KeyModifiers::NONE).await;
+    assert!(!state_arc.read().await.composing);
+    assert!(fake.mailbox().drafts.is_empty());
+
+    compose(&state_arc, "bob@example.com", "Plans", "Maybe Friday?").await;
+    press_with(&state_arc, KeyCode::Esc, KeyModifiers::NONE).await;
+    assert!(!state_arc.read().await.composing);
+
+    let mailbox = fake.mailbox();
+    assert_eq!(mailbox.drafts.len(), 1);
+    let message = mailbox.message(&mailbox.drafts[0].1).unwrap();
+    assert_eq!(message.label_ids, vec!["DRAFT"]);
+    assert_eq!(message.subject, "Plans");
+    assert_eq!(message.to, "bob@example.com");
+    assert_eq!(message.body, "Maybe Friday?");
+    assert!(mailbox.sent.is_empty());
+}
+
+#[tokio::test]
+async fn test_q_outside_the_body_saves_a_draft() {
+    let fake = FakeGmail::start().await;
+    let state_arc = state_for(&fake, "INBOX").await;
+
+    compose(&state_arc, "bob@example.com", "Plans", "Maybe Friday?").await;
+    state_arc.write().await.compose_state.focused_field = ComposeField::Subject;
+    press_with(&state_arc, KeyCode::Char('q'), KeyModifiers::NONE).await;
+    assert!(!state_arc.read().await.composing);
+
+    let mailbox = fake.mailbox();
+    assert_eq!(mailbox.drafts.len(), 1);
+    let message = mailbox.message(&mailbox.drafts[0].1).unwrap();
+    assert_eq!(message.subject, "Plans");
+}
+
+#[tokio::test]
+async fn test_saving_again_updates_the_same_draft() {
+    let fake = FakeGmail::start().await;
+    let state_arc = state_for(&fake, "INBOX").await;
+
+    compose(&state_arc, "bob@example.com", "Plans", "Maybe").await;
+    press_with(&state_arc, KeyCode::Char('s'), KeyModifiers::CONTROL).await;
+    {
+        let mut state = state_arc.write().await;
+        assert!(state.composing);
+        assert_eq!(state.error_message.as_deref(), Some("Draft saved."));
+        assert_eq!(state.compose_state.draft_id(), Some("draft-1"));
+        state.compose_state.body.push_str(" Friday?");
+    }
+    press_with(&state_arc, KeyCode::Esc, KeyModifiers::NONE).await;
+
+    let requests: Vec<_> = fake
+        .requests()
+        .into_iter()
+        .filter(|r| r.path.starts_with("drafts"))
+        .map(|r| (r.method, r.path))
+        .collect();
+    assert_eq!(
+        requests,
+        vec![
+            ("POST".to_string(), "drafts".to_string()),
+            ("PUT".to_string(), "drafts/draft-1".to_string())
+        ]
+    );
+    let mailbox = fake.mailbox();
+    assert_eq!(mailbox.drafts.len(), 1);
+    assert_eq!(
+        mailbox.message(&mailbox.drafts[0].1).unwrap().body,
+        "Maybe Friday?"
+    );
+    assert_eq!(
+        mailbox
+            .messages
+            .iter()
+            .filter(|m| m.label_ids.contains(&"DRAFT".to_string()))
+            .count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_failed_save_keeps_the_compose_window_open() {
+    let fake = FakeGmail::start().await;
+    let state_arc = state_for(&fake, "INBOX").await;
+
+    compose(&state_arc, "bob@example.com", "Plans", "Maybe Friday?").await;
+    fake.mailbox()
+        .respond_next(400, &[], r#"{"error": {"message": "Invalid draft"}}"#);
+    press_with(&state_arc, KeyCode::Esc, KeyModifiers::NONE).await;
+    {
+        let state = state_arc.read().await;
+        assert!(state.composing);
+        assert_eq!(state.compose_state.body, "Maybe Friday?");
+        assert!(state.error_message.as_deref().unwrap().contains("Ctrl+X"));
+    }
+
+    // Ctrl+X closes without saving
+    press_with(&state_arc, KeyCode::Char('x'), KeyModifiers::CONTROL).await;
+    assert!(!state_arc.read().await.composing);
+    assert!(fake.mailbox().drafts.is_empty());
+}
+
+#[tokio::test]
+async fn test_resumed_draft_is_sent_with_drafts_send() {
+    let fake = FakeGmail::start_with(Mailbox {
+        messages: vec![FakeMessage::new("d1", "Quote")
+            .labels(&["DRAFT"])
+            .from("me@example.com")
+            .body("Price is")
+            .header("Cc", "carol@example.com")
+            .attachment("quote.txt", "text/plain", b"42 EUR")],
+        drafts: vec![("draft-7".to_string(), "d1".to_string())],
+        ..Default::default()
+    })
+    .await;
+    fake.mailbox().messages[0].to = "bob@example.com".to_string();
+    let state_arc = state_for(&fake, "DRAFT").await;
+
+    press_with(&state_arc, KeyCode::Enter, KeyModifiers::NONE).await;
+    {
+        let mut state = state_arc.write().await;
+        assert!(state.composing);
+        let compose = &state.compose_state;
+        assert_eq!(compose.draft_id(), Some("draft-7"));
+        assert_eq!(compose.to, "bob@example.com");
+        assert_eq!(compose.cc, "carol@example.com");
+        assert_eq!(compose.subject, "Quote");
+        assert_eq!(compose.body, "Price is");
+        assert_eq!(compose.attachments.len(), 1);
+        assert_eq!(compose.attachments[0].content, b"42 EUR");
+
+        state.compose_state.body.push_str(" 42 EUR.");
+        state.compose_state.focused_field = ComposeField::Send;
+    }
+    press_with(&state_arc, KeyCode::Enter, KeyModifiers::NONE).await;
+
+    let state = state_arc.read().await;
+    assert!(!state.composing);
+    assert!(state.messages.is_empty());
+    let mailbox = fake.mailbox();
+    assert!(mailbox.drafts.is_empty());
+    assert!(mailbox.message("d1").is_none());
+    assert_eq!(mailbox.sent.len(), 1);
+    assert!(mailbox.sent[0].contains("Price is 42 EUR."));
+    assert!(mailbox.sent[0].contains("filename=\"quote.txt\""));
+    assert!(mailbox.requests.iter().all(|r| r.path != "messages/send"));
+}
@@ -203,7 +203,7 @@ async fn test_both_backspace_and_a_key_have_same_behavior() {
 
     // Both should have the same result
     assert_eq!(result1.is_ok(), result2.is_ok());
-    if result1.is_ok() && result2.is_ok() {
-        assert_eq!(result1.unwrap(), result2.unwrap());
+    if let (Ok(quit1), Ok(quit2)) = (result1, result2) {
+        assert_eq!(quit1, quit2);
     }
 }
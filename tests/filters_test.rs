@@ -0,0 +1,207 @@
+mod common;
+
+use common::fake_gmail::{FakeGmail, FakeMessage, Mailbox};
+use common::{inbox_state, press, type_text};
+use crossterm::event::KeyCode;
+use serde_json::json;
+use std::fs;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tuimail::state::{AppState, FilterField};
+
+async fn start_with_digest() -> FakeGmail {
+    let mut digest = FakeMessage::new("digest", "Weekly digest")
+        .header("List-Id", "Rust News <news.rust.example.com>");
+    digest.from = "Rust News <news@rust.example.com>".to_string();
+    FakeGmail::start_with(Mailbox {
+        messages: vec![digest],
+        filters: vec![json!({
+            "id": "filter-existing",
+            "criteria": { "from": "billing@shop.example.com" },
+            "action": { "addLabelIds": ["Label_1"], "removeLabelIds": ["INBOX"] }
+        })],
+        ..Default::default()
+    })
+    .await
+}
+
+// Move the form's focus to `field`
+async fn focus(state_arc: &Arc<RwLock<AppState>>, field: FilterField) {
+    while state_arc
+        .read()
+        .await
+        .filters_screen
+        .as_ref()
+        .and_then(|screen| screen.form.as_ref())
+        .is_some_and(|form| form.focused != field)
+    {
+        press(state_arc, KeyCode::Tab).await;
+    }
+}
+
+#[tokio::test]
+async fn test_filters_are_listed_created_and_deleted() {
+    let fake = start_with_digest().await;
+    let db_path = "test_filters_manage.db";
+    let (state_arc, _db) = inbox_state(&fake, db_path).await;
+
+    press(&state_arc, KeyCode::Char('z')).await;
+    {
+        let state = state_arc.read().await;
+        let screen = state.filters_screen.as_ref().unwrap();
+        assert_eq!(screen.filters.len(), 1);
+        assert_eq!(
+            screen.filters[0].criteria.from.as_deref(),
+            Some("billing@shop.example.com")
+        );
+        assert_eq!(screen.filters[0].action.add_label_ids, ["Label_1"]);
+    }
+
+    // A filter that files invoices under Receipts and marks them read
+    press(&state_arc, KeyCode::Char('n')).await;
+    focus(&state_arc, FilterField::Subject).await;
+    type_text(&state_arc, "Invoice").await;
+    focus(&state_arc, FilterField::Label).await;
+    type_text(&state_arc, "receipts").await;
+    focus(&state_arc, FilterField::MarkRead).await;
+    press(&state_arc, KeyCode::Char(' ')).await;
+    press(&state_arc, KeyCode::Enter).await;
+    {
+        let state = state_arc.read().await;
+        let screen = state.filters_screen.as_ref().unwrap();
+        assert!(screen.form.is_none());
+        assert!(screen.error.is_none());
+        assert_eq!(screen.filters.len(), 2);
+        assert_eq!(screen.list_state.selected(), Some(1));
+    }
+    let created = fake
+        .requests()
+        .into_iter()
+        .find(|r| r.method == "POST")
+        .unwrap();
+    assert!(created.path.ends_with("settings/filters"));
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&created.body).unwrap(),
+        json!({
+            "criteria": { "subject": "Invoice" },
+            "action": { "addLabelIds": ["Label_1"], "removeLabelIds": ["UNREAD"] }
+        })
+    );
+
+    // Delete the existing filter, after confirming
+    press(&state_arc, KeyCode::Char('k')).await;
+    press(&state_arc, KeyCode::Char('d')).await;
+    press(&state_arc, KeyCode::Char('n')).await;
+    assert_eq!(fake.mailbox().filters.len(), 2);
+    press(&state_arc, KeyCode::Char('d')).await;
+    press(&state_arc, KeyCode::Char('y')).await;
+    {
+        let state = state_arc.read().await;
+        let screen = state.filters_screen.as_ref().unwrap();
+        assert_eq!(screen.filters.len(), 1);
+        assert_eq!(
+            screen.filters[0].criteria.subject.as_deref(),
+            Some("Invoice")
+        );
+    }
+    {
+        let mailbox = fake.mailbox();
+        assert_eq!(mailbox.filters.len(), 1);
+        assert_eq!(mailbox.filters[0]["id"], "filter-1");
+    }
+
+    press(&state_arc, KeyCode::Esc).await;
+    assert!(state_arc.read().await.filters_screen.is_none());
+
+    let _ = fs::remove_file(db_path);
+}
+
+#[tokio::test]
+async fn test_filter_from_message_prefills_sender_and_list() {
+    let fake = start_with_digest().await;
+    let db_path = "test_filters_from_message.db";
+    let (state_arc, _db) = inbox_state(&fake, db_path).await;
+
+    press(&state_arc, KeyCode::Char('Z')).await;
+    {
+        let state = state_arc.read().await;
+        let form = state
+            .filters_screen
+            .as_ref()
+            .and_then(|screen| screen.form.as_ref())
+            .unwrap();
+        assert_eq!(form.from, "news@rust.example.com");
+        assert_eq!(form.query, "list:news.rust.example.com");
+    }
+
+    // Gmail refuses filters without actions, so they are not sent
+    press(&state_arc, KeyCode::Enter).await;
+    {
+        let state = state_arc.read().await;
+        let screen = state.filters_screen.as_ref().unwrap();
+        assert!(screen.form.is_some());
+        assert!(screen.error.is_some());
+    }
+    assert!(!fake.requests().iter().any(|r| r.method == "POST"));
+
+    // An unknown label is refused too
+    focus(&state_arc, FilterField::Label).await;
+    type_text(&state_arc, "Newsletters").await;
+    press(&state_arc, KeyCode::Enter).await;
+    assert_eq!(
+        state_arc
+            .read()
+            .await
+            .filters_screen
+            .as_ref()
+            .unwrap()
+            .error
+            .as_deref(),
+        Some("There is no label named 'Newsletters'.")
+    );
+
+    for _ in "Newsletters".chars() {
+        press(&state_arc, KeyCode::Backspace).await;
+    }
+    focus(&state_arc, FilterField::SkipInbox).await;
+    press(&state_arc, KeyCode::Char(' ')).await;
+    press(&state_arc, KeyCode::Enter).await;
+
+    let mailbox = fake.mailbox();
+    assert_eq!(mailbox.filters.len(), 2);
+    assert_eq!(
+        mailbox.filters[1]["criteria"],
+        json!({ "from": "news@rust.example.com", "query": "list:news.rust.example.com" })
+    );
+    assert_eq!(
+        mailbox.filters[1]["action"],
+        json!({ "removeLabelIds": ["INBOX"] })
+    );
+    drop(mailbox);
+
+    let _ = fs::remove_file(db_path);
+}
+
+#[tokio::test]
+async fn test_missing_settings_scope_asks_to_reauthorize() {
+    let fake = start_with_digest().await;
+    let db_path = "test_filters_scope.db";
+    let (state_arc, _db) = inbox_state(&fake, db_path).await;
+
+    fake.mailbox().respond_next(
+        403,
+        &[],
+        r#"{"error": {"code": 403, "message": "Request had insufficient authentication scopes.",
+            "errors": [{"reason": "insufficientPermissions"}],
+            "details": [{"reason": "ACCESS_TOKEN_SCOPE_INSUFFICIENT"}]}}"#,
+    );
+    press(&state_arc, KeyCode::Char('z')).await;
+    {
+        let state = state_arc.read().await;
+        assert!(state.filters_screen.is_none());
+        let error = state.error_message.as_deref().unwrap();
+        assert!(error.contains("Press Ctrl+R to re-authorize"), "{}", error);
+    }
+
+    let _ = fs::remove_file(db_path);
+}
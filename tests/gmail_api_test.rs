@@ -0,0 +1,566 @@
+mod common;
+
+use common::fake_gmail::{FakeGmail, FakeMessage, Mailbox};
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tuimail::database::Database;
+use tuimail::gmail_api::auth::{KeyringEntry, OAuthTokens, TokenRefresher};
+use tuimail::gmail_api::client::{RequestPriority, RetryPolicy};
+use tuimail::gmail_api::{
+    archive_message, delete_message, fetch_full_message, fetch_labels, fetch_messages_for_label,
+    load_more_messages, refresh_current_label, send_email, spam_message,
+};
+use tuimail::mime::OutgoingMessage;
+use tuimail::state::{AppState, SEARCH_LABEL_ID};
+use tuimail::sync::{sync_mailbox, sync_mailbox_in_background, SyncOutcome};
+use tuimail::types::Label;
+
+async fn start_with_messages(messages: Vec<FakeMessage>) -> FakeGmail {
+    FakeGmail::start_with(Mailbox {
+        messages,
+        ..Default::default()
+    })
+    .await
+}
+
+fn state_for(fake: &FakeGmail) -> AppState {
+    let mut state = AppState::new(reqwest::Client::new(), "test-token".to_string());
+    state.api_base_url = fake.base_url();
+    state.labels = vec![Label {
+        id: Some("INBOX".to_string()),
+        name: Some("INBOX".to_string()),
+    }];
+    state.selected_label = 0;
+    state
+}
+
+// Keyring that keeps the saved credentials in memory
+struct MemoryKeyring(std::sync::Mutex<Option<String>>);
+
+impl KeyringEntry for MemoryKeyring {
+    fn get_password(&self) -> Result<String, keyring::Error> {
+        self.0
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or(keyring::Error::NoEntry)
+    }
+    fn set_password(&self, password: &str) -> Result<(), keyring::Error> {
+        *self.0.lock().unwrap() = Some(password.to_string());
+        Ok(())
+    }
+    fn delete_password(&self) -> Result<(), keyring::Error> {
+        *self.0.lock().unwrap() = None;
+        Ok(())
+    }
+}
+
+// Renews "test-token" at the fake server's token endpoint
+fn token_refresher_for(fake: &FakeGmail) -> Arc<TokenRefresher> {
+    let secret = yup_oauth2::ApplicationSecret {
+        client_id: "client".to_string(),
+        client_secret: "secret".to_string(),
+        token_uri: format!("{}/token", fake.base_url()),
+        ..Default::default()
+    };
+    let tokens = OAuthTokens {
+        access_token: "test-token".to_string(),
+        refresh_token: Some("refresh-token".to_string()),
+        expires_at: None,
+        scopes: None,
+    };
+    Arc::new(TokenRefresher::new(
+        reqwest::Client::new(),
+        secret,
+        Box::new(MemoryKeyring(std::sync::Mutex::new(None))),
+        tokens,
+    ))
+}
+
+fn message_ids(state: &AppState) -> Vec<String> {
+    state.messages.iter().filter_map(|m| m.id.clone()).collect()
+}
+
+#[tokio::test]
+async fn test_fetch_labels_uses_configured_base_url() {
+    let fake = FakeGmail::start().await;
+    let state = state_for(&fake);
+
+    let labels = fetch_labels(&state).await.unwrap();
+    let names: Vec<_> = labels.iter().filter_map(|l| l.name.clone()).collect();
+    assert!(names.contains(&"Receipts".to_string()));
+
+    let requests = fake.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].path, "labels");
+    assert_eq!(
+        requests[0].authorization.as_deref(),
+        Some("Bearer test-token")
+    );
+}
+
+#[tokio::test]
+async fn test_fetch_messages_and_load_more_follow_page_tokens() {
+    let fake = start_with_messages(vec![
+        FakeMessage::new("m1", "First"),
+        FakeMessage::new("m2", "Second"),
+        FakeMessage::new("m3", "Third"),
+        FakeMessage::new("spam", "Spam").labels(&["SPAM"]),
+    ])
+    .await;
+    let mut state = state_for(&fake);
+    state.messages_per_screen = 1;
+
+    fetch_messages_for_label(&mut state).await;
+    assert_eq!(message_ids(&state), vec!["m1", "m2"]);
+    assert_eq!(
+        state.message_headers.get("m2"),
+        Some(&("Second".to_string(), "sender@example.com".to_string()))
+    );
+
+    load_more_messages(&mut state).await;
+    assert_eq!(message_ids(&state), vec!["m1", "m2", "m3"]);
+
+    // The last page has no token, so nothing more is requested
+    let request_count = fake.requests().len();
+    load_more_messages(&mut state).await;
+    assert_eq!(fake.requests().len(), request_count);
+}
+
+#[tokio::test]
+async fn test_label_refresh_fetches_metadata_without_holding_the_state_lock() {
+    let ids: Vec<String> = (1..=25).map(|i| format!("m{}", i)).collect();
+    let fake = start_with_messages(
+        ids.iter()
+            .map(|id| FakeMessage::new(id, &format!("Subject {}", id)))
+            .collect(),
+    )
+    .await;
+    fake.mailbox().response_delay = Duration::from_millis(100);
+    let mut state = state_for(&fake);
+    state.messages_per_screen = 15;
+    let state_arc = Arc::new(RwLock::new(state));
+
+    let refresh = tokio::spawn({
+        let state_arc = state_arc.clone();
+        async move { refresh_current_label(&state_arc).await }
+    });
+
+    // Wait until the metadata requests are in flight
+    while fake.requests().len() < 2 {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let started = Instant::now();
+    drop(state_arc.write().await);
+    assert!(started.elapsed() < Duration::from_millis(50));
+
+    refresh.await.unwrap();
+
+    // All 25 rows arrive, in the order Gmail listed them
+    let state = state_arc.read().await;
+    assert_eq!(message_ids(&state), ids);
+    assert_eq!(
+        state.message_headers.get("m25"),
+        Some(&("Subject m25".to_string(), "sender@example.com".to_string()))
+    );
+    let metadata_requests = fake
+        .requests()
+        .iter()
+        .filter(|r| r.path.starts_with("messages/"))
+        .count();
+    assert_eq!(metadata_requests, 25);
+}
+
+#[tokio::test]
+async fn test_fetch_full_message_reads_body_and_headers() {
+    let fake = start_with_messages(vec![FakeMessage::new("m1", "Hello")
+        .from("alice@example.com")
+        .body("Line one\nLine two")])
+    .await;
+    let mut state = state_for(&fake);
+
+    fetch_full_message(&mut state, "m1").await.unwrap();
+
+    assert_eq!(
+        state.message_bodies.get("m1").map(String::as_str),
+        Some("Line one\nLine two")
+    );
+    let headers = state.current_message_display_headers.clone().unwrap();
+    assert_eq!(headers.subject, "Hello");
+    assert_eq!(headers.from, "alice@example.com");
+
+    assert!(fetch_full_message(&mut state, "missing").await.is_err());
+}
+
+#[tokio::test]
+async fn test_message_operations_change_labels_on_server() {
+    let fake = start_with_messages(vec![
+        FakeMessage::new("archive", "Archive me"),
+        FakeMessage::new("trash", "Trash me"),
+        FakeMessage::new("spam", "Spam me"),
+    ])
+    .await;
+    let state = state_for(&fake);
+
+    archive_message(&state, "archive").await.unwrap();
+    delete_message(&state, "trash").await.unwrap();
+    spam_message(&state, "spam").await.unwrap();
+    assert!(archive_message(&state, "missing").await.is_err());
+
+    let mailbox = fake.mailbox();
+    assert!(mailbox.message("archive").unwrap().label_ids.is_empty());
+    assert_eq!(mailbox.message("trash").unwrap().label_ids, vec!["TRASH"]);
+    assert_eq!(mailbox.message("spam").unwrap().label_ids, vec!["SPAM"]);
+}
+
+#[tokio::test]
+async fn test_send_email_posts_raw_message() {
+    let fake = FakeGmail::start().await;
+    let state = state_for(&fake);
+
+    let message = OutgoingMessage {
+        to: "bob@example.com".to_string(),
+        cc: "carol@example.com".to_string(),
+        subject: "Lunch?".to_string(),
+        body: "Noon works.".to_string(),
+        ..Default::default()
+    };
+    send_email(&state, &message, None).await.unwrap();
+
+    let mailbox = fake.mailbox();
+    assert_eq!(mailbox.sent.len(), 1);
+    let raw = &mailbox.sent[0];
+    assert!(raw.contains("To: bob@example.com\r\n"));
+    assert!(raw.contains("Cc: carol@example.com\r\n"));
+    assert!(!raw.contains("Bcc:"));
+    assert!(raw.contains("MIME-Version: 1.0\r\n"));
+    assert!(raw.contains("\r\nDate: ") && raw.contains("\r\nMessage-ID: <"));
+    assert!(raw.ends_with("\r\n\r\nNoon works."));
+}
+
+#[tokio::test]
+async fn test_sync_mailbox_applies_history_from_server() {
+    let db_path = "test_fake_gmail_sync.db";
+    let _ = fs::remove_file(db_path);
+    let db = Arc::new(Database::new(&format!("sqlite:{}", db_path)).await.unwrap());
+    db.upsert_label(&Label {
+        id: Some("INBOX".to_string()),
+        name: Some("INBOX".to_string()),
+    })
+    .await
+    .unwrap();
+
+    let fake = start_with_messages(vec![FakeMessage::new("old", "Old")]).await;
+    let mut state = state_for(&fake);
+    state.set_database(db);
+
+    // Nothing stored yet: the label is downloaded and history tracking starts
+    assert_eq!(
+        sync_mailbox(&mut state).await.unwrap(),
+        SyncOutcome::FullResync
+    );
+    assert_eq!(message_ids(&state), vec!["old"]);
+
+    fake.mailbox().deliver(FakeMessage::new("new", "New"));
+    fake.mailbox().delete("old");
+    assert_eq!(
+        sync_mailbox(&mut state).await.unwrap(),
+        SyncOutcome::Incremental(2)
+    );
+    assert_eq!(message_ids(&state), vec!["new"]);
+
+    // Once Gmail forgets the stored history id, sync falls back to a full download
+    fake.mailbox().deliver(FakeMessage::new("newer", "Newer"));
+    fake.mailbox().expire_history();
+    assert_eq!(
+        sync_mailbox(&mut state).await.unwrap(),
+        SyncOutcome::FullResync
+    );
+    assert_eq!(message_ids(&state), vec!["newer", "new"]);
+
+    let _ = fs::remove_file(db_path);
+}
+
+#[tokio::test]
+async fn test_background_sync_does_not_hold_the_state_lock() {
+    let db_path = "test_fake_gmail_sync_unlocked.db";
+    let _ = fs::remove_file(db_path);
+    let db = Arc::new(Database::new(&format!("sqlite:{}", db_path)).await.unwrap());
+    db.upsert_label(&Label {
+        id: Some("INBOX".to_string()),
+        name: Some("INBOX".to_string()),
+    })
+    .await
+    .unwrap();
+
+    let fake = start_with_messages(vec![FakeMessage::new("old", "Old")]).await;
+    let mut state = state_for(&fake);
+    state.set_database(db);
+    sync_mailbox(&mut state).await.unwrap();
+    let state_arc = Arc::new(RwLock::new(state));
+
+    fake.mailbox().deliver(FakeMessage::new("new", "New"));
+    fake.mailbox().response_delay = Duration::from_millis(100);
+    let request_count = fake.requests().len();
+    let sync = tokio::spawn({
+        let state_arc = state_arc.clone();
+        async move { sync_mailbox_in_background(&state_arc).await }
+    });
+
+    // Wait until the history request is in flight
+    while fake.requests().len() == request_count {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let started = Instant::now();
+    drop(state_arc.write().await);
+    assert!(started.elapsed() < Duration::from_millis(50));
+
+    assert_eq!(sync.await.unwrap(), Ok(SyncOutcome::Incremental(1)));
+    assert_eq!(message_ids(&*state_arc.read().await), vec!["new", "old"]);
+
+    let _ = fs::remove_file(db_path);
+}
+
+#[tokio::test]
+async fn test_sync_tracks_history_while_a_local_folder_is_shown() {
+    let db_path = "test_fake_gmail_sync_outbox.db";
+    let _ = fs::remove_file(db_path);
+    let db = Arc::new(Database::new(&format!("sqlite:{}", db_path)).await.unwrap());
+
+    let fake = start_with_messages(vec![FakeMessage::new("old", "Old")]).await;
+    let mut state = state_for(&fake);
+    state.labels = vec![Label {
+        id: Some("OUTBOX".to_string()),
+        name: Some("Outbox".to_string()),
+    }];
+    state.set_database(db.clone());
+
+    // The outbox has no row in the labels table, the history id is stored anyway
+    assert_eq!(
+        sync_mailbox(&mut state).await.unwrap(),
+        SyncOutcome::FullResync
+    );
+    assert!(db.get_mailbox_history_id().await.unwrap().is_some());
+
+    fake.mailbox().deliver(FakeMessage::new("new", "New"));
+    assert_eq!(
+        sync_mailbox(&mut state).await.unwrap(),
+        SyncOutcome::Incremental(1)
+    );
+
+    let _ = fs::remove_file(db_path);
+}
+
+#[tokio::test]
+async fn test_rate_limited_request_is_retried_after_retry_after() {
+    let fake = FakeGmail::start().await;
+    fake.mailbox().respond_next(
+        429,
+        &[("Retry-After", "0")],
+        r#"{"error":{"code":429,"message":"rateLimitExceeded"}}"#,
+    );
+    let state = state_for(&fake);
+
+    let labels = fetch_labels(&state).await.unwrap();
+    assert!(!labels.is_empty());
+    assert_eq!(fake.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn test_server_errors_back_off_until_retries_run_out() {
+    let fake = FakeGmail::start().await;
+    let mut state = state_for(&fake);
+    state.retry_policy = RetryPolicy {
+        max_retries: 2,
+        base_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(5),
+    };
+
+    fake.mailbox().respond_next(503, &[], "{}");
+    fake.mailbox().respond_next(500, &[], "{}");
+    assert!(fetch_labels(&state).await.is_ok());
+    assert_eq!(fake.requests().len(), 3);
+
+    for _ in 0..3 {
+        fake.mailbox().respond_next(503, &[], "{}");
+    }
+    assert!(fetch_labels(&state).await.is_err());
+    assert_eq!(fake.requests().len(), 6);
+}
+
+#[tokio::test]
+async fn test_background_request_does_not_wait_out_long_rate_limits() {
+    let fake = FakeGmail::start().await;
+    fake.mailbox()
+        .respond_next(429, &[("Retry-After", "60")], "{}");
+    let mut state = state_for(&fake);
+    state.request_priority = RequestPriority::Background;
+
+    let started = Instant::now();
+    assert!(fetch_labels(&state).await.is_err());
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(fake.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_unauthorized_request_refreshes_token_once_and_retries() {
+    let fake = FakeGmail::start().await;
+    let mut state = state_for(&fake);
+    state.token_refresher = Some(token_refresher_for(&fake));
+
+    fake.mailbox()
+        .respond_next(401, &[], r#"{"error":{"code":401}}"#);
+    assert!(fetch_labels(&state).await.is_ok());
+    let requests = fake.requests();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(
+        requests[0].authorization.as_deref(),
+        Some("Bearer test-token")
+    );
+    assert_eq!(requests[1].path, "/token");
+    assert_eq!(
+        requests[2].authorization.as_deref(),
+        Some("Bearer refreshed-token-1")
+    );
+
+    // A token that is refused right after renewing it is not renewed again.
+    // Canned responses answer every request in order, the token one included.
+    {
+        let mut mailbox = fake.mailbox();
+        mailbox.respond_next(401, &[], r#"{"error":{"code":401}}"#);
+        mailbox.respond_next(200, &[], r#"{"access_token":"refreshed-token-2"}"#);
+        mailbox.respond_next(401, &[], r#"{"error":{"code":401}}"#);
+    }
+    assert!(fetch_labels(&state).await.is_err());
+    let paths: Vec<String> = fake.requests()[3..]
+        .iter()
+        .map(|r| r.path.clone())
+        .collect();
+    assert_eq!(paths, ["labels", "/token", "labels"]);
+    assert_eq!(
+        fake.requests()[5].authorization.as_deref(),
+        Some("Bearer refreshed-token-2")
+    );
+    assert_eq!(fake.mailbox().token_refreshes, 1);
+    let refresher = state.token_refresher.as_ref().unwrap();
+    assert!(refresher.take_keyring_error().is_none());
+}
+
+#[tokio::test]
+async fn test_user_action_does_not_wait_out_long_rate_limits() {
+    let fake = FakeGmail::start().await;
+    fake.mailbox().respond_next(
+        429,
+        &[("Retry-After", "120")],
+        r#"{"error":{"code":429,"message":"rateLimitExceeded"}}"#,
+    );
+    let state = state_for(&fake);
+    assert_eq!(state.request_priority, RequestPriority::Interactive);
+
+    // Gmail's answer comes back instead of a two minute wait
+    let started = Instant::now();
+    let error = fetch_labels(&state).await.unwrap_err();
+    assert!(error.to_string().contains("429"));
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(fake.requests().len(), 1);
+
+    // Later actions are not sent while the rate limit lasts
+    let started = Instant::now();
+    let error = fetch_labels(&state).await.unwrap_err();
+    assert!(error.to_string().contains("try again in"));
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(fake.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_search_results_use_q_and_paginate() {
+    let fake = start_with_messages(vec![
+        FakeMessage::new("a1", "Invoice").from("alice@example.com"),
+        FakeMessage::new("b1", "Hello").from("bob@example.com"),
+        FakeMessage::new("a2", "Lunch plans").from("Alice <alice@example.com>"),
+        FakeMessage::new("a3", "Archived")
+            .from("alice@example.com")
+            .labels(&[]),
+    ])
+    .await;
+    let mut state = state_for(&fake);
+    state.messages_per_screen = 1;
+
+    assert!(state.start_search("from:alice newer_than:7d"));
+    assert_eq!(state.labels[0].id.as_deref(), Some(SEARCH_LABEL_ID));
+    assert_eq!(
+        state.labels[0].name.as_deref(),
+        Some("SEARCH: from:alice newer_than:7d")
+    );
+    assert_eq!(state.selected_label, 0);
+
+    // newer_than: is not understood by the fake server, so drop it there
+    state.start_search("from:alice");
+    fetch_messages_for_label(&mut state).await;
+    assert_eq!(message_ids(&state), vec!["a1", "a2"]);
+    load_more_messages(&mut state).await;
+    assert_eq!(message_ids(&state), vec!["a1", "a2", "a3"]);
+
+    let list_request = fake
+        .requests()
+        .into_iter()
+        .find(|r| r.path.starts_with("messages?"))
+        .unwrap();
+    assert!(list_request.path.contains("q=from%3Aalice"));
+    assert!(!list_request.path.contains("labelIds"));
+
+    // Searching again replaces the previous results, an empty query removes them
+    assert!(state.start_search("subject:lunch"));
+    assert_eq!(
+        state
+            .labels
+            .iter()
+            .filter(|l| l.id.as_deref() == Some(SEARCH_LABEL_ID))
+            .count(),
+        1
+    );
+    assert!(!state.start_search("  "));
+    assert_eq!(state.labels[0].id.as_deref(), Some("INBOX"));
+    assert_eq!(state.search_query, None);
+}
+
+#[tokio::test]
+async fn test_search_falls_back_to_local_cache_when_offline() {
+    let db_path = "test_offline_search.db";
+    let _ = fs::remove_file(db_path);
+    let db = Arc::new(Database::new(&format!("sqlite:{}", db_path)).await.unwrap());
+    db.upsert_label(&Label {
+        id: Some("INBOX".to_string()),
+        name: Some("INBOX".to_string()),
+    })
+    .await
+    .unwrap();
+
+    // Cache the inbox and one full body while the server is reachable
+    let fake = start_with_messages(vec![
+        FakeMessage::new("trip", "Train tickets").body("Your seat reservation to Lyon"),
+        FakeMessage::new("other", "Newsletter"),
+    ])
+    .await;
+    let mut state = state_for(&fake);
+    state.set_database(db);
+    fetch_messages_for_label(&mut state).await;
+    fetch_full_message(&mut state, "trip").await.unwrap();
+
+    // Point at a port nothing listens on
+    state.api_base_url = "http://127.0.0.1:9".to_string();
+    assert!(state.start_search("reservation"));
+    fetch_messages_for_label(&mut state).await;
+
+    assert_eq!(message_ids(&state), vec!["trip"]);
+    assert!(state
+        .error_message
+        .as_deref()
+        .unwrap()
+        .contains("local cache"));
+    assert!(state.search_snippets["trip"].contains("reservation"));
+
+    let _ = fs::remove_file(db_path);
+}
@@ -0,0 +1,167 @@
+mod common;
+
+use common::fake_gmail::{FakeGmail, FakeMessage, Mailbox};
+use common::{inbox_state, press, type_text};
+use crossterm::event::KeyCode;
+use std::fs;
+use tuimail::state::{fuzzy_score, AppState, FocusedPane};
+
+fn label_names(state: &AppState) -> Vec<String> {
+    state.labels.iter().filter_map(|l| l.name.clone()).collect()
+}
+
+#[test]
+fn test_fuzzy_score_prefers_word_starts_and_runs() {
+    assert!(fuzzy_score("rcp", "Receipts").is_some());
+    assert!(fuzzy_score("xyz", "Receipts").is_none());
+    assert!(fuzzy_score("", "Receipts").is_some());
+
+    // Consecutive characters beat scattered ones
+    assert!(fuzzy_score("rec", "Receipts") > fuzzy_score("rec", "Rare/Ecology"));
+    // A match at a word start beats one inside a word
+    assert!(fuzzy_score("t", "Work/Travel") > fuzzy_score("t", "Receipts"));
+    assert_eq!(fuzzy_score("INB", "inbox"), fuzzy_score("inb", "INBOX"));
+}
+
+#[tokio::test]
+async fn test_create_rename_and_delete_label_from_folders_pane() {
+    let fake = FakeGmail::start().await;
+    let db_path = "test_label_management.db";
+    let (state_arc, db) = inbox_state(&fake, db_path).await;
+    state_arc.write().await.focused_pane = FocusedPane::Labels;
+
+    // Create
+    press(&state_arc, KeyCode::Char('n')).await;
+    type_text(&state_arc, "Travel").await;
+    press(&state_arc, KeyCode::Enter).await;
+
+    let travel_id = {
+        let state = state_arc.read().await;
+        assert!(state.label_prompt.is_none());
+        assert!(label_names(&state).contains(&"Travel".to_string()));
+        state
+            .labels
+            .iter()
+            .find(|l| l.name.as_deref() == Some("Travel"))
+            .and_then(|l| l.id.clone())
+            .unwrap()
+    };
+    assert!(fake.mailbox().labels.iter().any(|(_, n)| n == "Travel"));
+    assert!(db
+        .get_labels()
+        .await
+        .unwrap()
+        .iter()
+        .any(|l| l.id == travel_id));
+
+    // System labels cannot be renamed
+    press(&state_arc, KeyCode::Char('R')).await;
+    {
+        let mut state = state_arc.write().await;
+        assert!(state.label_prompt.is_none());
+        assert!(state.error_message.is_some());
+        state.clear_error_message();
+        state.selected_label = state
+            .labels
+            .iter()
+            .position(|l| l.id.as_deref() == Some(travel_id.as_str()))
+            .unwrap();
+    }
+
+    // Rename
+    press(&state_arc, KeyCode::Char('R')).await;
+    for _ in "Travel".chars() {
+        press(&state_arc, KeyCode::Backspace).await;
+    }
+    type_text(&state_arc, "Trips").await;
+    press(&state_arc, KeyCode::Enter).await;
+
+    assert!(label_names(&*state_arc.read().await).contains(&"Trips".to_string()));
+    assert!(fake.mailbox().labels.iter().any(|(_, n)| n == "Trips"));
+    let cached = db.get_labels().await.unwrap();
+    assert_eq!(
+        cached
+            .iter()
+            .find(|l| l.id == travel_id)
+            .map(|l| l.name.as_str()),
+        Some("Trips")
+    );
+
+    // Delete asks for confirmation first
+    press(&state_arc, KeyCode::Char('D')).await;
+    press(&state_arc, KeyCode::Char('n')).await;
+    assert!(label_names(&*state_arc.read().await).contains(&"Trips".to_string()));
+
+    press(&state_arc, KeyCode::Char('D')).await;
+    press(&state_arc, KeyCode::Char('y')).await;
+    {
+        let state = state_arc.read().await;
+        assert!(!label_names(&state).contains(&"Trips".to_string()));
+        assert!(state.selected_label < state.labels.len());
+    }
+    assert!(!fake.mailbox().labels.iter().any(|(id, _)| *id == travel_id));
+    assert!(!db
+        .get_labels()
+        .await
+        .unwrap()
+        .iter()
+        .any(|l| l.id == travel_id));
+
+    let _ = fs::remove_file(db_path);
+}
+
+#[tokio::test]
+async fn test_label_picker_applies_and_removes_labels() {
+    let fake = FakeGmail::start_with(Mailbox {
+        messages: vec![FakeMessage::new("m1", "Order confirmation")],
+        ..Default::default()
+    })
+    .await;
+    let db_path = "test_label_picker.db";
+    let (state_arc, db) = inbox_state(&fake, db_path).await;
+    state_arc.write().await.focused_pane = FocusedPane::Labels;
+    state_arc.write().await.focused_pane = FocusedPane::Messages;
+
+    // Fuzzy matching puts "Receipts" first, Enter applies it
+    press(&state_arc, KeyCode::Char('l')).await;
+    type_text(&state_arc, "rcp").await;
+    {
+        let state = state_arc.read().await;
+        let matches = state.label_picker_matches();
+        assert_eq!(matches[0].name.as_deref(), Some("Receipts"));
+        // Labels that cannot be applied are not offered
+        assert!(!matches.iter().any(|l| l.id.as_deref() == Some("SENT")));
+    }
+    press(&state_arc, KeyCode::Enter).await;
+
+    assert!(state_arc.read().await.label_picker.is_none());
+    assert!(state_arc.read().await.messages[0].has_label("Label_1"));
+    assert!(fake
+        .mailbox()
+        .message("m1")
+        .unwrap()
+        .label_ids
+        .contains(&"Label_1".to_string()));
+    let receipts = db.get_messages_for_label("Label_1", 10, 0).await.unwrap();
+    assert_eq!(receipts.len(), 1);
+
+    // Picking it again removes it
+    press(&state_arc, KeyCode::Char('l')).await;
+    type_text(&state_arc, "receipts").await;
+    press(&state_arc, KeyCode::Enter).await;
+
+    assert!(!state_arc.read().await.messages[0].has_label("Label_1"));
+    assert!(!fake
+        .mailbox()
+        .message("m1")
+        .unwrap()
+        .label_ids
+        .contains(&"Label_1".to_string()));
+    assert!(db
+        .get_messages_for_label("Label_1", 10, 0)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let _ = fs::remove_file(db_path);
+}
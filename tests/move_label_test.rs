@@ -0,0 +1,121 @@
+mod common;
+
+use common::fake_gmail::FakeMessage;
+use common::{
+    cached_ids, inbox_state, press, server_labels, start_with_inbox, type_text, visible_ids,
+};
+use crossterm::event::KeyCode;
+use std::fs;
+use tuimail::gmail_api::fetch_messages_for_label;
+use tuimail::state::FocusedPane;
+
+fn inbox_messages() -> Vec<FakeMessage> {
+    vec![
+        FakeMessage::new("newsletter", "Weekly news"),
+        FakeMessage::new("invoice-1", "Invoice March"),
+        FakeMessage::new("invoice-2", "Invoice April"),
+    ]
+}
+
+#[tokio::test]
+async fn test_message_is_moved_with_one_modify_call() {
+    let fake = start_with_inbox(inbox_messages()).await;
+    let db_path = "test_move_single.db";
+    let (state_arc, db) = inbox_state(&fake, db_path).await;
+
+    press(&state_arc, KeyCode::Down).await;
+    press(&state_arc, KeyCode::Char('m')).await;
+    {
+        let state = state_arc.read().await;
+        let picker = state.label_picker.as_ref().unwrap();
+        assert!(picker.moving);
+        // The folder the message is in is not offered
+        assert!(!state
+            .label_picker_matches()
+            .iter()
+            .any(|l| l.id.as_deref() == Some("INBOX")));
+    }
+    type_text(&state_arc, "rcp").await;
+    press(&state_arc, KeyCode::Enter).await;
+
+    {
+        let state = state_arc.read().await;
+        assert!(state.label_picker.is_none());
+        assert!(state.error_message.is_none());
+        assert_eq!(visible_ids(&state), ["newsletter", "invoice-2"]);
+    }
+    assert_eq!(server_labels(&fake, "invoice-1"), ["Label_1"]);
+
+    let modifies: Vec<serde_json::Value> = fake
+        .requests()
+        .into_iter()
+        .filter(|r| r.method == "POST")
+        .map(|r| {
+            assert!(r.path.ends_with("messages/invoice-1/modify"));
+            serde_json::from_str(&r.body).unwrap()
+        })
+        .collect();
+    assert_eq!(modifies.len(), 1);
+    assert_eq!(modifies[0]["addLabelIds"], serde_json::json!(["Label_1"]));
+    assert_eq!(modifies[0]["removeLabelIds"], serde_json::json!(["INBOX"]));
+
+    assert_eq!(cached_ids(&db, "INBOX").await, ["invoice-2", "newsletter"]);
+    assert_eq!(cached_ids(&db, "Label_1").await, ["invoice-1"]);
+
+    let _ = fs::remove_file(db_path);
+}
+
+#[tokio::test]
+async fn test_marked_messages_are_moved_together() {
+    let fake = start_with_inbox(inbox_messages()).await;
+    let db_path = "test_move_marked.db";
+    let (state_arc, db) = inbox_state(&fake, db_path).await;
+
+    press(&state_arc, KeyCode::Down).await;
+    press(&state_arc, KeyCode::Char('x')).await;
+    press(&state_arc, KeyCode::Down).await;
+    press(&state_arc, KeyCode::Char('x')).await;
+    press(&state_arc, KeyCode::Char('m')).await;
+    type_text(&state_arc, "Receipts").await;
+    press(&state_arc, KeyCode::Enter).await;
+
+    {
+        let state = state_arc.read().await;
+        assert_eq!(visible_ids(&state), ["newsletter"]);
+        assert!(!state.has_marks());
+    }
+    let batches: Vec<serde_json::Value> = fake
+        .requests()
+        .into_iter()
+        .filter(|r| r.method == "POST")
+        .map(|r| serde_json::from_str(&r.body).unwrap())
+        .collect();
+    assert_eq!(batches.len(), 1);
+    assert_eq!(
+        batches[0]["ids"],
+        serde_json::json!(["invoice-1", "invoice-2"])
+    );
+    assert_eq!(server_labels(&fake, "invoice-2"), ["Label_1"]);
+    assert_eq!(cached_ids(&db, "INBOX").await, ["newsletter"]);
+    assert_eq!(cached_ids(&db, "Label_1").await, ["invoice-1", "invoice-2"]);
+
+    // Nothing to move out of in a folder that is not a label
+    {
+        let mut state = state_arc.write().await;
+        state.selected_label = state
+            .labels
+            .iter()
+            .position(|l| l.id.as_deref() == Some("SENT"))
+            .unwrap();
+        fetch_messages_for_label(&mut state).await;
+        state.focused_pane = FocusedPane::Messages;
+    }
+    press(&state_arc, KeyCode::Char('m')).await;
+    {
+        let state = state_arc.read().await;
+        assert!(state.label_picker.is_none());
+        assert!(state.error_message.is_some());
+    }
+
+    let _ = fs::remove_file(db_path);
+}
@@ -0,0 +1,278 @@
+mod common;
+
+use common::fake_gmail::FakeMessage;
+use common::{cached_ids, inbox_state, press, start_with_inbox, visible_ids};
+use crossterm::event::KeyCode;
+use std::fs;
+use std::time::{Duration, Instant};
+use tuimail::gmail_api::fetch_messages_for_label;
+use tuimail::outbox::{replay_outbox, replay_outbox_in_background};
+use tuimail::state::{is_outbox_label, AppState, ComposeField, FocusedPane};
+
+const OFFLINE_URL: &str = "http://127.0.0.1:9";
+
+fn inbox_messages() -> Vec<FakeMessage> {
+    vec![
+        FakeMessage::new("newsletter", "Weekly news"),
+        FakeMessage::new("invoice", "Invoice"),
+        FakeMessage::new("lunch", "Lunch?"),
+    ]
+}
+
+async fn show_outbox(state: &mut AppState) {
+    state.selected_label = state
+        .labels
+        .iter()
+        .position(|l| is_outbox_label(l.id.as_deref().unwrap_or("")))
+        .unwrap();
+    fetch_messages_for_label(state).await;
+    state.focused_pane = FocusedPane::Messages;
+}
+
+#[tokio::test]
+async fn test_offline_actions_are_queued_and_replayed_in_order() {
+    let fake = start_with_inbox(inbox_messages()).await;
+    let db_path = "test_outbox_replay.db";
+    let (state_arc, db) = inbox_state(&fake, db_path).await;
+    assert_eq!(
+        visible_ids(&*state_arc.read().await),
+        ["newsletter", "invoice", "lunch"]
+    );
+
+    state_arc.write().await.api_base_url = OFFLINE_URL.to_string();
+    // Archive the newsletter, then delete the invoice
+    press(&state_arc, KeyCode::Char('a')).await;
+    {
+        let mut state = state_arc.write().await;
+        assert!(state.error_message.is_some());
+        state.clear_error_message();
+    }
+    press(&state_arc, KeyCode::Char('d')).await;
+
+    {
+        let state = state_arc.read().await;
+        assert_eq!(visible_ids(&state), ["lunch"]);
+        assert_eq!(state.outbox.len(), 2);
+        assert!(state
+            .labels
+            .iter()
+            .any(|l| is_outbox_label(l.id.as_deref().unwrap_or(""))));
+    }
+    assert_eq!(cached_ids(&db, "INBOX").await, ["lunch"]);
+    assert_eq!(db.get_outbox().await.unwrap().len(), 2);
+    // Nothing reached Gmail yet
+    assert!(fake
+        .mailbox()
+        .message("newsletter")
+        .unwrap()
+        .label_ids
+        .contains(&"INBOX".to_string()));
+
+    // Still offline: nothing is lost
+    assert_eq!(replay_outbox(&mut *state_arc.write().await).await, 0);
+    assert_eq!(state_arc.read().await.outbox.len(), 2);
+
+    let mut state = state_arc.write().await;
+    state.api_base_url = fake.base_url();
+    assert_eq!(replay_outbox(&mut state).await, 2);
+    assert!(state.outbox.is_empty());
+    assert!(!state
+        .labels
+        .iter()
+        .any(|l| is_outbox_label(l.id.as_deref().unwrap_or(""))));
+    assert!(db.get_outbox().await.unwrap().is_empty());
+    drop(state);
+
+    let modified: Vec<String> = fake
+        .requests()
+        .into_iter()
+        .filter(|r| r.method == "POST")
+        .map(|r| r.path)
+        .collect();
+    assert_eq!(modified.len(), 2);
+    assert!(modified[0].contains("newsletter"));
+    assert!(modified[1].contains("invoice"));
+
+    let mailbox = fake.mailbox();
+    assert!(!mailbox
+        .message("newsletter")
+        .unwrap()
+        .label_ids
+        .contains(&"INBOX".to_string()));
+    assert!(mailbox
+        .message("invoice")
+        .unwrap()
+        .label_ids
+        .contains(&"TRASH".to_string()));
+    drop(mailbox);
+
+    let _ = fs::remove_file(db_path);
+}
+
+#[tokio::test]
+async fn test_email_written_offline_is_sent_later() {
+    let fake = start_with_inbox(inbox_messages()).await;
+    let db_path = "test_outbox_send.db";
+    let (state_arc, db) = inbox_state(&fake, db_path).await;
+
+    {
+        let mut state = state_arc.write().await;
+        state.api_base_url = OFFLINE_URL.to_string();
+        state.start_composing(
+            Some("bob@example.com".to_string()),
+            None,
+            Some("Train times".to_string()),
+            Some("The 8:15 is cancelled.".to_string()),
+            Some(ComposeField::Send),
+        );
+    }
+    press(&state_arc, KeyCode::Enter).await;
+    {
+        let mut state = state_arc.write().await;
+        assert!(!state.composing);
+        assert!(state.error_message.is_some());
+        assert_eq!(state.outbox.len(), 1);
+        assert_eq!(state.outbox[0].subject, "Train times");
+        assert_eq!(state.outbox[0].description, "Send to bob@example.com");
+
+        // Listed in the Outbox folder as waiting
+        show_outbox(&mut state).await;
+        assert_eq!(state.messages.len(), 1);
+        assert_eq!(
+            state.messages[0].snippet.as_deref(),
+            Some("Waiting for a connection")
+        );
+        assert!(state.selected_outbox_entry().is_some());
+
+        state.api_base_url = fake.base_url();
+        assert_eq!(replay_outbox(&mut state).await, 1);
+        assert!(state.outbox.is_empty());
+        assert!(state.messages.is_empty());
+    }
+    assert!(db.get_outbox().await.unwrap().is_empty());
+
+    let mailbox = fake.mailbox();
+    assert_eq!(mailbox.sent.len(), 1);
+    assert!(mailbox.sent[0].contains("Subject: Train times\r\n"));
+    assert!(mailbox.sent[0].contains("The 8:15 is cancelled."));
+    drop(mailbox);
+
+    let _ = fs::remove_file(db_path);
+}
+
+#[tokio::test]
+async fn test_refused_entries_can_be_retried_or_cancelled() {
+    let fake = start_with_inbox(inbox_messages()).await;
+    let db_path = "test_outbox_refused.db";
+    let (state_arc, db) = inbox_state(&fake, db_path).await;
+
+    state_arc.write().await.api_base_url = OFFLINE_URL.to_string();
+    press(&state_arc, KeyCode::Char('a')).await;
+    state_arc.write().await.clear_error_message();
+    press(&state_arc, KeyCode::Char('a')).await;
+    state_arc.write().await.clear_error_message();
+
+    {
+        let mut state = state_arc.write().await;
+        state.api_base_url = fake.base_url();
+        fake.mailbox()
+            .respond_next(400, &[], r#"{"error": {"message": "Bad request"}}"#);
+        fake.mailbox()
+            .respond_next(400, &[], r#"{"error": {"message": "Bad request"}}"#);
+        assert_eq!(replay_outbox(&mut state).await, 0);
+        assert_eq!(state.outbox.len(), 2);
+        assert!(state.outbox.iter().all(|entry| entry.error.is_some()));
+        assert!(!state.has_pending_outbox());
+        // Failures are remembered across restarts
+        assert!(db
+            .get_outbox()
+            .await
+            .unwrap()
+            .iter()
+            .all(|entry| entry.error.is_some()));
+
+        show_outbox(&mut state).await;
+        assert_eq!(state.messages.len(), 2);
+        assert!(state.messages[0]
+            .snippet
+            .as_deref()
+            .unwrap()
+            .starts_with("Failed: "));
+    }
+
+    // Newest first: retry archiving the invoice
+    press(&state_arc, KeyCode::Char('r')).await;
+    {
+        let mut state = state_arc.write().await;
+        assert!(state.error_message.is_some());
+        state.clear_error_message();
+        assert_eq!(state.outbox.len(), 1);
+        assert_eq!(state.messages.len(), 1);
+    }
+    assert!(!fake
+        .mailbox()
+        .message("invoice")
+        .unwrap()
+        .label_ids
+        .contains(&"INBOX".to_string()));
+
+    // Cancel archiving the newsletter: it goes back to the inbox
+    press(&state_arc, KeyCode::Char('d')).await;
+    {
+        let state = state_arc.read().await;
+        assert!(state.outbox.is_empty());
+        assert!(state.messages.is_empty());
+    }
+    assert!(db.get_outbox().await.unwrap().is_empty());
+    assert_eq!(cached_ids(&db, "INBOX").await, ["lunch", "newsletter"]);
+    assert!(fake
+        .mailbox()
+        .message("newsletter")
+        .unwrap()
+        .label_ids
+        .contains(&"INBOX".to_string()));
+
+    let _ = fs::remove_file(db_path);
+}
+
+#[tokio::test]
+async fn test_background_replay_does_not_hold_the_state_lock() {
+    let fake = start_with_inbox(inbox_messages()).await;
+    let db_path = "test_outbox_replay_unlocked.db";
+    let (state_arc, _db) = inbox_state(&fake, db_path).await;
+
+    state_arc.write().await.api_base_url = OFFLINE_URL.to_string();
+    press(&state_arc, KeyCode::Char('a')).await;
+    assert_eq!(state_arc.read().await.outbox.len(), 1);
+
+    {
+        let mut state = state_arc.write().await;
+        state.api_base_url = fake.base_url();
+        state.clear_error_message();
+    }
+    fake.mailbox().response_delay = Duration::from_millis(100);
+    let request_count = fake.requests().len();
+    let replay = tokio::spawn({
+        let state_arc = state_arc.clone();
+        async move { replay_outbox_in_background(&state_arc).await }
+    });
+
+    // Wait until the archive request is in flight
+    while fake.requests().len() == request_count {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    let started = Instant::now();
+    drop(state_arc.write().await);
+    assert!(started.elapsed() < Duration::from_millis(50));
+
+    assert_eq!(replay.await.unwrap(), 1);
+    assert!(state_arc.read().await.outbox.is_empty());
+    assert!(!fake
+        .mailbox()
+        .message("newsletter")
+        .unwrap()
+        .label_ids
+        .contains(&"INBOX".to_string()));
+
+    let _ = fs::remove_file(db_path);
+}
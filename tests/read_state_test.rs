@@ -0,0 +1,122 @@
+mod common;
+
+use common::fake_gmail::{FakeGmail, FakeMessage, Mailbox};
+use common::press;
+use crossterm::event::KeyCode;
+use std::fs;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tuimail::database::Database;
+use tuimail::gmail_api::{fetch_messages_for_label, star, unstar};
+use tuimail::state::{AppState, FocusedPane};
+use tuimail::sync::sync_mailbox;
+use tuimail::types::Label;
+
+async fn inbox_state(fake: &FakeGmail, db_path: &str) -> (AppState, Arc<Database>) {
+    let _ = fs::remove_file(db_path);
+    let db = Arc::new(Database::new(&format!("sqlite:{}", db_path)).await.unwrap());
+    let inbox = Label {
+        id: Some("INBOX".to_string()),
+        name: Some("INBOX".to_string()),
+    };
+    db.upsert_label(&inbox).await.unwrap();
+
+    let mut state = AppState::new(reqwest::Client::new(), "test-token".to_string());
+    state.api_base_url = fake.base_url();
+    state.set_database(db.clone());
+    state.labels = vec![inbox];
+    fetch_messages_for_label(&mut state).await;
+    state.focused_pane = FocusedPane::Messages;
+    (state, db)
+}
+
+async fn cached_flags(db: &Database, id: &str) -> (bool, bool) {
+    let messages = db.get_messages_for_label("ALLMAIL", 100, 0).await.unwrap();
+    let message = messages.iter().find(|m| m.id == id).unwrap();
+    (message.is_unread, message.is_starred)
+}
+
+#[tokio::test]
+async fn test_flags_are_read_from_label_ids() {
+    let fake = FakeGmail::start_with(Mailbox {
+        messages: vec![
+            FakeMessage::new("new", "New").labels(&["INBOX", "UNREAD"]),
+            FakeMessage::new("starred", "Starred").labels(&["INBOX", "STARRED"]),
+        ],
+        ..Default::default()
+    })
+    .await;
+    let db_path = "test_read_state_flags.db";
+    let (state, db) = inbox_state(&fake, db_path).await;
+
+    assert!(state.messages[0].is_unread() && !state.messages[0].is_starred());
+    assert!(!state.messages[1].is_unread() && state.messages[1].is_starred());
+    assert_eq!(cached_flags(&db, "new").await, (true, false));
+    assert_eq!(cached_flags(&db, "starred").await, (false, true));
+
+    // The flags survive a reload from the cache
+    let mut state = state;
+    state.messages.clear();
+    state.load_messages_from_cache("INBOX").await.unwrap();
+    assert!(state.messages[0].is_unread());
+    assert!(state.messages[1].is_starred());
+
+    let _ = fs::remove_file(db_path);
+}
+
+#[tokio::test]
+async fn test_opening_marks_read_and_keys_toggle_flags() {
+    let fake = FakeGmail::start_with(Mailbox {
+        messages: vec![FakeMessage::new("m1", "Hello").labels(&["INBOX", "UNREAD"])],
+        ..Default::default()
+    })
+    .await;
+    let db_path = "test_read_state_keys.db";
+    let (state, db) = inbox_state(&fake, db_path).await;
+    let state_arc = Arc::new(RwLock::new(state));
+
+    press(&state_arc, KeyCode::Enter).await;
+    assert!(!state_arc.read().await.messages[0].is_unread());
+    assert_eq!(
+        fake.mailbox().message("m1").unwrap().label_ids,
+        vec!["INBOX"]
+    );
+    assert_eq!(cached_flags(&db, "m1").await, (false, false));
+
+    press(&state_arc, KeyCode::Char('U')).await;
+    press(&state_arc, KeyCode::Char('*')).await;
+    assert!(state_arc.read().await.messages[0].is_unread());
+    assert!(state_arc.read().await.messages[0].is_starred());
+    assert_eq!(
+        fake.mailbox().message("m1").unwrap().label_ids,
+        vec!["INBOX", "UNREAD", "STARRED"]
+    );
+    assert_eq!(cached_flags(&db, "m1").await, (true, true));
+
+    let _ = fs::remove_file(db_path);
+}
+
+#[tokio::test]
+async fn test_sync_applies_flag_changes_made_elsewhere() {
+    let fake = FakeGmail::start_with(Mailbox {
+        messages: vec![FakeMessage::new("m1", "Hello")],
+        ..Default::default()
+    })
+    .await;
+    let db_path = "test_read_state_sync.db";
+    let (mut state, db) = inbox_state(&fake, db_path).await;
+    sync_mailbox(&mut state).await.unwrap();
+
+    // Star it from "another client"
+    star(&state, "m1").await.unwrap();
+    sync_mailbox(&mut state).await.unwrap();
+    assert!(state.messages[0].is_starred());
+    assert_eq!(cached_flags(&db, "m1").await, (false, true));
+
+    unstar(&state, "m1").await.unwrap();
+    sync_mailbox(&mut state).await.unwrap();
+    assert!(!state.messages[0].is_starred());
+    assert_eq!(cached_flags(&db, "m1").await, (false, false));
+
+    let _ = fs::remove_file(db_path);
+}
@@ -0,0 +1,183 @@
+mod common;
+
+use common::fake_gmail::{FakeGmail, FakeMessage, Mailbox};
+use common::{press, press_with};
+use crossterm::event::{KeyCode, KeyModifiers};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tuimail::gmail_api::fetch_messages_for_label;
+use tuimail::state::{AppState, ComposeField, FocusedPane};
+use tuimail::types::Label;
+
+async fn state_for(fake: &FakeGmail) -> Arc<RwLock<AppState>> {
+    let mut state = AppState::new(reqwest::Client::new(), "test-token".to_string());
+    state.api_base_url = fake.base_url();
+    state.labels = vec![Label {
+        id: Some("INBOX".to_string()),
+        name: Some("INBOX".to_string()),
+    }];
+    fetch_messages_for_label(&mut state).await;
+    state.focused_pane = FocusedPane::Messages;
+    Arc::new(RwLock::new(state))
+}
+
+#[tokio::test]
+async fn test_reply_is_sent_into_the_thread() {
+    let fake = FakeGmail::start_with(Mailbox {
+        messages: vec![FakeMessage::new("m2", "Re: Lunch?")
+            .from("Ann <ann@example.com>")
+            .body("Noon works.\nSee you")
+            .thread("t1")
+            .header("References", "<m0@mail.example.com> <m1@mail.example.com>")
+            .header("In-Reply-To", "<m1@mail.example.com>")],
+        ..Default::default()
+    })
+    .await;
+    let state_arc = state_for(&fake).await;
+
+    press(&state_arc, KeyCode::Char('r')).await;
+    {
+        let mut state = state_arc.write().await;
+        assert!(state.composing);
+        let compose = &state.compose_state;
+        assert_eq!(compose.to, "Ann <ann@example.com>");
+        // The prefix is not stacked
+        assert_eq!(compose.subject, "Re: Lunch?");
+        assert_eq!(
+            compose.body,
+            "\n\nOn Tue, Jun 10, 2025 at 14:00, Ann <ann@example.com> wrote:\n> Noon works.\n> See you\n"
+        );
+        assert_eq!(compose.focused_field, ComposeField::Body);
+        state.compose_state.focused_field = ComposeField::Send;
+    }
+    press(&state_arc, KeyCode::Enter).await;
+    assert!(!state_arc.read().await.composing);
+
+    let mailbox = fake.mailbox();
+    // Unfold the long References header
+    let raw = mailbox.sent[0].replace("\r\n ", " ");
+    assert!(raw.contains("Subject: Re: Lunch?\r\n"));
+    assert!(raw.contains("In-Reply-To: <m2@mail.example.com>\r\n"));
+    assert!(raw.contains(
+        "References: <m0@mail.example.com> <m1@mail.example.com> <m2@mail.example.com>\r\n"
+    ));
+    assert_eq!(mailbox.message("sent-1").unwrap().thread_id, "t1");
+}
+
+#[tokio::test]
+async fn test_reply_to_first_message_of_a_thread() {
+    let fake = FakeGmail::start_with(Mailbox {
+        messages: vec![FakeMessage::new("m1", "Lunch?")],
+        ..Default::default()
+    })
+    .await;
+    let state_arc = state_for(&fake).await;
+
+    press(&state_arc, KeyCode::Char('r')).await;
+    {
+        let state = state_arc.read().await;
+        assert_eq!(state.compose_state.subject, "Re: Lunch?");
+        let threading = state.compose_state.threading.clone().unwrap();
+        assert_eq!(threading.thread_id.as_deref(), Some("thread-m1"));
+        assert_eq!(
+            threading.in_reply_to.as_deref(),
+            Some("<m1@mail.example.com>")
+        );
+        assert_eq!(threading.references, vec!["<m1@mail.example.com>"]);
+    }
+
+    // Cancelling drops the threading with the rest of the draft
+    press(&state_arc, KeyCode::Esc).await;
+    let state = state_arc.read().await;
+    assert!(!state.composing);
+    assert!(state.compose_state.threading.is_none());
+}
+
+#[tokio::test]
+async fn test_reply_all_leaves_out_our_own_address() {
+    let fake = FakeGmail::start_with(Mailbox {
+        messages: vec![FakeMessage::new("m1", "Plans")
+            .from("Ann <ann@example.com>")
+            .header("Cc", "bob@example.com, Me <ME@example.com>")],
+        ..Default::default()
+    })
+    .await;
+    let state_arc = state_for(&fake).await;
+
+    press(&state_arc, KeyCode::Char('r')).await;
+    {
+        let state = state_arc.read().await;
+        assert_eq!(state.compose_state.to, "Ann <ann@example.com>");
+        assert_eq!(state.compose_state.cc, "");
+    }
+    press(&state_arc, KeyCode::Esc).await;
+
+    press(&state_arc, KeyCode::Char('R')).await;
+    let state = state_arc.read().await;
+    assert_eq!(state.own_email.as_deref(), Some("me@example.com"));
+    assert_eq!(state.compose_state.to, "Ann <ann@example.com>");
+    // The original To was only us
+    assert_eq!(state.compose_state.cc, "bob@example.com");
+    assert!(state.compose_state.threading.is_some());
+}
+
+#[tokio::test]
+async fn test_forward_inline_keeps_attachments() {
+    let fake = FakeGmail::start_with(Mailbox {
+        messages: vec![FakeMessage::new("m1", "Invoice")
+            .from("Shop <shop@example.com>")
+            .body("Your invoice is attached.")
+            .attachment("invoice.pdf", "application/pdf", b"%PDF-1.4 invoice")],
+        ..Default::default()
+    })
+    .await;
+    let state_arc = state_for(&fake).await;
+
+    press(&state_arc, KeyCode::Char('F')).await;
+    let state = state_arc.read().await;
+    let compose = &state.compose_state;
+    assert!(state.composing);
+    assert_eq!(compose.to, "");
+    assert_eq!(compose.focused_field, ComposeField::To);
+    assert_eq!(compose.subject, "Fwd: Invoice");
+    assert!(compose.body.starts_with(
+        "\n\n---------- Forwarded message ---------\nFrom: Shop <shop@example.com>\n"
+    ));
+    assert!(compose.body.ends_with("\n\nYour invoice is attached."));
+    assert_eq!(compose.attachments.len(), 1);
+    assert_eq!(compose.attachments[0].filename, "invoice.pdf");
+    assert_eq!(compose.attachments[0].content, b"%PDF-1.4 invoice");
+    assert!(compose.threading.is_none());
+}
+
+#[tokio::test]
+async fn test_forward_as_attachment_sends_the_original() {
+    let fake = FakeGmail::start_with(Mailbox {
+        messages: vec![FakeMessage::new("m1", "Status: done").body("All green")],
+        ..Default::default()
+    })
+    .await;
+    let state_arc = state_for(&fake).await;
+
+    press_with(&state_arc, KeyCode::Char('f'), KeyModifiers::CONTROL).await;
+    {
+        let mut state = state_arc.write().await;
+        assert_eq!(state.compose_state.subject, "Fwd: Status: done");
+        let attachment = &state.compose_state.attachments[0];
+        assert_eq!(attachment.filename, "Status_ done.eml");
+        assert_eq!(attachment.content_type, "message/rfc822");
+        state.compose_state.to = "bob@example.com".to_string();
+        state.compose_state.focused_field = ComposeField::Send;
+    }
+    press(&state_arc, KeyCode::Enter).await;
+
+    let raw = fake.mailbox().sent[0].clone();
+    assert!(raw.contains("Content-Type: message/rfc822; name=\"Status_ done.eml\"\r\n"));
+    assert!(raw.contains("Content-Transfer-Encoding: 7bit\r\n\r\nFrom: sender@example.com\r\n"));
+    assert!(raw.contains("Subject: Status: done\r\n"));
+    assert!(raw.contains("\r\n\r\nAll green\r\n--"));
+    assert!(fake
+        .requests()
+        .iter()
+        .any(|r| r.path == "messages/m1?format=raw"));
+}
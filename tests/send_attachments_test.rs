@@ -0,0 +1,214 @@
+mod common;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::engine::Engine;
+use common::fake_gmail::FakeGmail;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::fs;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tuimail::event_handler::handle_key_event;
+use tuimail::gmail_api::operations::SIMPLE_SEND_LIMIT;
+use tuimail::gmail_api::send_email;
+use tuimail::mime::{detect_content_type, OutgoingAttachment, OutgoingMessage};
+use tuimail::state::{AppState, ComposeField};
+
+fn state_for(fake: &FakeGmail) -> AppState {
+    let mut state = AppState::new(reqwest::Client::new(), "test-token".to_string());
+    state.api_base_url = fake.base_url();
+    state
+}
+
+fn attachment(filename: &str, content: &[u8]) -> OutgoingAttachment {
+    OutgoingAttachment {
+        filename: filename.to_string(),
+        content_type: detect_content_type(filename, content).to_string(),
+        content: content.to_vec(),
+    }
+}
+
+// Decoded content of the base64 part that follows `header` in a raw message
+fn part_content(raw: &str, header: &str) -> Vec<u8> {
+    let start = raw.find(header).unwrap();
+    let part = &raw[start..];
+    let body_start = part.find("\r\n\r\n").unwrap() + 4;
+    let body_end = part[body_start..].find("--").unwrap() + body_start;
+    let encoded: String = part[body_start..body_end].lines().map(str::trim).collect();
+    STANDARD.decode(encoded).unwrap()
+}
+
+#[test]
+fn test_detect_content_type() {
+    assert_eq!(detect_content_type("report.PDF", b""), "application/pdf");
+    assert_eq!(detect_content_type("photo.jpeg", b""), "image/jpeg");
+    // Unknown extensions fall back to the content
+    assert_eq!(
+        detect_content_type("scan", b"\x89PNG\r\n\x1a\n...."),
+        "image/png"
+    );
+    assert_eq!(detect_content_type("README", b"plain words"), "text/plain");
+    assert_eq!(
+        detect_content_type("blob.bin", &[0xff, 0xfe, 0x00, 0x81]),
+        "application/octet-stream"
+    );
+}
+
+#[tokio::test]
+async fn test_send_email_with_attachments_is_multipart_mixed() {
+    let fake = FakeGmail::start().await;
+    let state = state_for(&fake);
+    let pdf = b"%PDF-1.4 fake document".to_vec();
+    let binary: Vec<u8> = (0..=255).collect();
+
+    let message = OutgoingMessage {
+        to: "bob@example.com".to_string(),
+        subject: "Files".to_string(),
+        body: "Both files attached.".to_string(),
+        attachments: vec![attachment("report.pdf", &pdf), attachment("data", &binary)],
+        ..Default::default()
+    };
+    send_email(&state, &message, None).await.unwrap();
+
+    let raw = fake.mailbox().sent[0].clone();
+    assert!(raw.contains("MIME-Version: 1.0\r\n"));
+    let boundary = raw
+        .split("boundary=\"")
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap()
+        .to_string();
+    assert_eq!(raw.matches(&format!("--{}\r\n", boundary)).count(), 3);
+    assert!(raw.trim_end().ends_with(&format!("--{}--", boundary)));
+    assert!(raw.contains("Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 7bit\r\n\r\nBoth files attached."));
+
+    assert!(raw.contains("Content-Disposition: attachment; filename=\"report.pdf\""));
+    assert_eq!(
+        part_content(&raw, "Content-Type: application/pdf; name=\"report.pdf\""),
+        pdf
+    );
+    assert_eq!(
+        part_content(
+            &raw,
+            "Content-Type: application/octet-stream; name=\"data\""
+        ),
+        binary
+    );
+    assert!(raw.lines().all(|line| line.len() <= 998));
+
+    // Small messages are sent with a plain messages.send
+    assert!(fake
+        .requests()
+        .iter()
+        .all(|r| !r.path.starts_with("upload/")));
+}
+
+#[tokio::test]
+async fn test_large_message_uses_resumable_upload() {
+    let fake = FakeGmail::start().await;
+    let state = state_for(&fake);
+    let video = vec![7u8; SIMPLE_SEND_LIMIT];
+
+    let message = OutgoingMessage {
+        to: "bob@example.com".to_string(),
+        subject: "Holiday video".to_string(),
+        body: "Too big for a plain send.".to_string(),
+        attachments: vec![attachment("holiday.mp4", &video)],
+        ..Default::default()
+    };
+    send_email(&state, &message, None).await.unwrap();
+
+    let paths: Vec<_> = fake.requests().into_iter().map(|r| r.path).collect();
+    assert_eq!(
+        paths,
+        vec![
+            "upload/messages/send?uploadType=resumable",
+            "upload/session/1"
+        ]
+    );
+    let mailbox = fake.mailbox();
+    assert_eq!(mailbox.sent.len(), 1);
+    assert!(mailbox.sent[0].contains("Subject: Holiday video\r\n"));
+    assert!(mailbox.sent[0].contains("Content-Type: video/mp4; name=\"holiday.mp4\""));
+}
+
+#[tokio::test]
+async fn test_message_over_the_limit_once_encoded_uses_resumable_upload() {
+    let fake = FakeGmail::start().await;
+    let state = state_for(&fake);
+    // The MIME message fits under the limit, its base64url `raw` does not
+    let photos = vec![7u8; SIMPLE_SEND_LIMIT * 7 / 10];
+
+    let message = OutgoingMessage {
+        to: "bob@example.com".to_string(),
+        subject: "Photos".to_string(),
+        attachments: vec![attachment("photos.zip", &photos)],
+        ..Default::default()
+    };
+    send_email(&state, &message, None).await.unwrap();
+
+    let mailbox = fake.mailbox();
+    assert!(mailbox.sent[0].len() < SIMPLE_SEND_LIMIT);
+    assert!(mailbox.requests[0].path.starts_with("upload/"));
+}
+
+#[tokio::test]
+async fn test_compose_window_attaches_files() {
+    let fake = FakeGmail::start().await;
+    let dir = std::env::temp_dir().join(format!("tuimail_compose_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("quote.txt");
+    fs::write(&file, "Quoted price: 42").unwrap();
+
+    let mut state = state_for(&fake);
+    state.start_composing(Some("bob@example.com".to_string()), None, None, None, None);
+    let state_arc = Arc::new(RwLock::new(state));
+    let press = |code: KeyCode, modifiers: KeyModifiers| {
+        let state_arc = state_arc.clone();
+        async move {
+            handle_key_event(KeyEvent::new(code, modifiers), state_arc)
+                .await
+                .unwrap();
+        }
+    };
+    let attach = |path: String| {
+        let press = &press;
+        async move {
+            press(KeyCode::Char('a'), KeyModifiers::CONTROL).await;
+            // The path is typed into the prompt, 'q' does not close the window
+            for c in path.chars() {
+                press(KeyCode::Char(c), KeyModifiers::NONE).await;
+            }
+            press(KeyCode::Enter, KeyModifiers::NONE).await;
+        }
+    };
+
+    attach(dir.join("missing-quote.txt").display().to_string()).await;
+    {
+        let state = state_arc.read().await;
+        assert!(state.composing);
+        assert!(state.compose_state.attachments.is_empty());
+        assert!(state.compose_state.attachment_error.is_some());
+    }
+
+    attach(file.display().to_string()).await;
+    {
+        let mut state = state_arc.write().await;
+        assert!(state.compose_state.attachment_error.is_none());
+        assert_eq!(state.compose_state.attachments.len(), 1);
+        assert_eq!(
+            state.compose_state.attachments[0].content_type,
+            "text/plain"
+        );
+        state.compose_state.focused_field = ComposeField::Send;
+    }
+
+    press(KeyCode::Enter, KeyModifiers::NONE).await;
+    assert!(!state_arc.read().await.composing);
+    let raw = fake.mailbox().sent[0].clone();
+    assert_eq!(
+        part_content(&raw, "name=\"quote.txt\""),
+        b"Quoted price: 42"
+    );
+
+    let _ = fs::remove_dir_all(&dir);
+}
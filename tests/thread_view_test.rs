@@ -0,0 +1,212 @@
+mod common;
+
+use common::fake_gmail::{FakeGmail, FakeMessage, Mailbox};
+use common::press;
+use crossterm::event::KeyCode;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tuimail::gmail_api::fetch_messages_for_label;
+use tuimail::state::{AppState, FocusedPane};
+use tuimail::types::Label;
+
+// Newest first: a three-message conversation, one message of which was sent
+// by us and is not in the inbox, and an unrelated single message in between
+async fn start_with_conversation() -> FakeGmail {
+    FakeGmail::start_with(Mailbox {
+        messages: vec![
+            FakeMessage::new("plans-3", "Re: Plans")
+                .thread("t-plans")
+                .from("carol@example.com")
+                .body("Saturday works for me."),
+            FakeMessage::new("invoice", "Invoice"),
+            FakeMessage::new("plans-2", "Re: Plans")
+                .thread("t-plans")
+                .from("me@example.com")
+                .labels(&["SENT"]),
+            FakeMessage::new("plans-1", "Plans")
+                .thread("t-plans")
+                .from("carol@example.com"),
+        ],
+        ..Default::default()
+    })
+    .await
+}
+
+async fn inbox_state(fake: &FakeGmail) -> AppState {
+    let mut state = AppState::new(reqwest::Client::new(), "test-token".to_string());
+    state.api_base_url = fake.base_url();
+    state.labels = vec![Label {
+        id: Some("INBOX".to_string()),
+        name: Some("INBOX".to_string()),
+    }];
+    fetch_messages_for_label(&mut state).await;
+    state.focused_pane = FocusedPane::Messages;
+    state
+}
+
+#[tokio::test]
+async fn test_thread_view_groups_messages_by_conversation() {
+    let fake = start_with_conversation().await;
+    let mut state = inbox_state(&fake).await;
+    assert_eq!(state.thread_rows(), vec![0, 1, 2]);
+
+    // Selecting an older message of a conversation moves to its newest one
+    state.selected_message = 2;
+    state.toggle_thread_view();
+    assert_eq!(state.thread_rows(), vec![0, 1]);
+    assert_eq!(state.selected_message, 0);
+    assert_eq!(state.message_state.selected(), Some(0));
+
+    // Only the inbox copies are loaded so far
+    let counts = state.thread_counts();
+    assert_eq!(counts.get("t-plans"), Some(&2));
+    assert_eq!(counts.get("thread-invoice"), Some(&1));
+
+    state.move_down();
+    assert_eq!(state.selected_message, 1);
+    state.move_down();
+    assert_eq!(
+        state.selected_message, 1,
+        "older thread messages have no row"
+    );
+
+    state.toggle_thread_view();
+    assert_eq!(state.thread_rows(), vec![0, 1, 2]);
+}
+
+#[tokio::test]
+async fn test_moving_up_skips_older_thread_messages() {
+    // The older message of the conversation sits between the invoice and lunch
+    let fake = FakeGmail::start_with(Mailbox {
+        messages: vec![
+            FakeMessage::new("plans-2", "Re: Plans").thread("t-plans"),
+            FakeMessage::new("invoice", "Invoice"),
+            FakeMessage::new("plans-1", "Plans").thread("t-plans"),
+            FakeMessage::new("lunch", "Lunch?"),
+        ],
+        ..Default::default()
+    })
+    .await;
+    let mut state = inbox_state(&fake).await;
+    state.toggle_thread_view();
+    assert_eq!(state.thread_rows(), vec![0, 1, 3]);
+
+    state.move_down();
+    state.move_down();
+    assert_eq!(state.selected_message, 3);
+    state.move_up();
+    assert_eq!(
+        state.selected_message, 1,
+        "older thread messages have no row"
+    );
+    assert_eq!(state.message_state.selected(), Some(1));
+    state.move_up();
+    assert_eq!(state.selected_message, 0);
+    state.move_up();
+    assert_eq!(state.selected_message, 0);
+}
+
+#[tokio::test]
+async fn test_open_conversation_and_act_on_whole_thread() {
+    let fake = start_with_conversation().await;
+    let mut state = inbox_state(&fake).await;
+    state.toggle_thread_view();
+    let state_arc = Arc::new(RwLock::new(state));
+
+    press(&state_arc, KeyCode::Enter).await;
+    {
+        let state = state_arc.read().await;
+        assert_eq!(state.focused_pane, FocusedPane::Content);
+        let thread = state.current_thread().expect("conversation should be open");
+        let ids: Vec<_> = thread.messages.iter().map(|m| m.id.as_str()).collect();
+        assert_eq!(ids, vec!["plans-1", "plans-2", "plans-3"]);
+        let expanded: Vec<_> = thread.messages.iter().map(|m| m.expanded).collect();
+        assert_eq!(expanded, vec![false, false, true]);
+        assert_eq!(state.thread_counts().get("t-plans"), Some(&3));
+        assert_eq!(
+            state.message_bodies.get("plans-3").map(String::as_str),
+            Some("Saturday works for me.")
+        );
+    }
+
+    // Expand the message we sent
+    press(&state_arc, KeyCode::Char('p')).await;
+    press(&state_arc, KeyCode::Enter).await;
+    {
+        let state = state_arc.read().await;
+        let thread = state.current_thread().unwrap();
+        assert_eq!(thread.selected, 1);
+        assert!(thread.messages[1].expanded);
+    }
+
+    press(&state_arc, KeyCode::Char('a')).await;
+    {
+        let state = state_arc.read().await;
+        let ids: Vec<_> = state.messages.iter().filter_map(|m| m.id.clone()).collect();
+        assert_eq!(ids, vec!["invoice"]);
+        assert!(state.open_thread.is_none());
+    }
+    {
+        let mailbox = fake.mailbox();
+        assert!(mailbox
+            .thread("t-plans")
+            .iter()
+            .all(|m| !m.label_ids.contains(&"INBOX".to_string())));
+    }
+    assert!(fake
+        .requests()
+        .iter()
+        .any(|r| r.method == "POST" && r.path == "threads/t-plans/modify"));
+
+    press(&state_arc, KeyCode::Char('d')).await;
+    assert!(state_arc.read().await.messages.is_empty());
+    assert_eq!(
+        fake.mailbox().message("invoice").unwrap().label_ids,
+        vec!["TRASH"]
+    );
+}
+
+#[tokio::test]
+async fn test_undo_restores_the_whole_thread() {
+    let fake = start_with_conversation().await;
+    let mut state = inbox_state(&fake).await;
+    state.toggle_thread_view();
+    let state_arc = Arc::new(RwLock::new(state));
+
+    // plans-2 is not in the inbox, so it is not loaded, but it is trashed
+    // along with the rest of the conversation
+    press(&state_arc, KeyCode::Char('d')).await;
+    assert!(fake
+        .mailbox()
+        .thread("t-plans")
+        .iter()
+        .all(|m| m.label_ids.contains(&"TRASH".to_string())));
+
+    press(&state_arc, KeyCode::Char('u')).await;
+    {
+        let state = state_arc.read().await;
+        let ids: Vec<_> = state.messages.iter().filter_map(|m| m.id.clone()).collect();
+        assert_eq!(ids, vec!["plans-3", "invoice", "plans-1"]);
+    }
+    {
+        let mailbox = fake.mailbox();
+        assert!(mailbox
+            .thread("t-plans")
+            .iter()
+            .all(|m| !m.label_ids.contains(&"TRASH".to_string())));
+        for id in ["plans-1", "plans-3"] {
+            assert!(mailbox
+                .message(id)
+                .unwrap()
+                .label_ids
+                .contains(&"INBOX".to_string()));
+        }
+    }
+    let requests = fake.requests();
+    assert!(requests
+        .iter()
+        .any(|r| r.method == "POST" && r.path == "threads/t-plans/untrash"));
+    assert!(!requests
+        .iter()
+        .any(|r| r.path.starts_with("messages/plans") && r.path.ends_with("/untrash")));
+}
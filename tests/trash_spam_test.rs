@@ -0,0 +1,121 @@
+mod common;
+
+use common::fake_gmail::{FakeGmail, FakeMessage, Mailbox};
+use common::{cached_ids, folder_state, press, visible_ids};
+use crossterm::event::KeyCode;
+use std::fs;
+use tuimail::state::PurgePrompt;
+
+#[tokio::test]
+async fn test_restore_delete_forever_and_empty_trash() {
+    let fake = FakeGmail::start_with(Mailbox {
+        messages: vec![
+            FakeMessage::new("old-1", "Old news").labels(&["TRASH"]),
+            FakeMessage::new("old-2", "Older news").labels(&["TRASH"]),
+            FakeMessage::new("old-3", "Oldest news").labels(&["TRASH", "Label_1"]),
+            FakeMessage::new("keep", "Keep me"),
+        ],
+        ..Default::default()
+    })
+    .await;
+    let db_path = "test_trash_management.db";
+    let (state_arc, db) = folder_state(&fake, db_path, "TRASH").await;
+    assert_eq!(cached_ids(&db, "TRASH").await, ["old-1", "old-2", "old-3"]);
+
+    // Restore the first one
+    press(&state_arc, KeyCode::Char('o')).await;
+    assert_eq!(visible_ids(&*state_arc.read().await), ["old-2", "old-3"]);
+    assert!(fake
+        .mailbox()
+        .message("old-1")
+        .unwrap()
+        .label_ids
+        .is_empty());
+    assert_eq!(cached_ids(&db, "TRASH").await, ["old-2", "old-3"]);
+    assert!(fake
+        .requests()
+        .iter()
+        .any(|r| r.method == "POST" && r.path.ends_with("messages/old-1/untrash")));
+
+    // Deleting forever asks first
+    press(&state_arc, KeyCode::Char('D')).await;
+    assert_eq!(
+        state_arc.read().await.purge_prompt,
+        Some(PurgePrompt::DeleteForever {
+            message_ids: vec!["old-2".to_string()]
+        })
+    );
+    press(&state_arc, KeyCode::Char('n')).await;
+    assert!(state_arc.read().await.purge_prompt.is_none());
+    assert!(fake.mailbox().message("old-2").is_some());
+
+    press(&state_arc, KeyCode::Char('D')).await;
+    press(&state_arc, KeyCode::Char('y')).await;
+    assert_eq!(visible_ids(&*state_arc.read().await), ["old-3"]);
+    assert!(fake.mailbox().message("old-2").is_none());
+    assert_eq!(cached_ids(&db, "TRASH").await, ["old-3"]);
+
+    // Emptying the trash removes the rest, also from other labels in the cache
+    press(&state_arc, KeyCode::Char('E')).await;
+    press(&state_arc, KeyCode::Char('y')).await;
+    {
+        let state = state_arc.read().await;
+        assert!(state.messages.is_empty());
+        assert!(state
+            .error_message
+            .as_deref()
+            .unwrap()
+            .starts_with("Trash emptied"));
+    }
+    assert!(fake.mailbox().message("old-3").is_none());
+    assert!(fake.mailbox().message("keep").is_some());
+    assert!(cached_ids(&db, "TRASH").await.is_empty());
+    assert!(cached_ids(&db, "Label_1").await.is_empty());
+    assert!(fake
+        .requests()
+        .iter()
+        .any(|r| r.path.ends_with("messages/batchDelete")));
+
+    let _ = fs::remove_file(db_path);
+}
+
+#[tokio::test]
+async fn test_marked_messages_are_not_spam_in_one_call() {
+    let fake = FakeGmail::start_with(Mailbox {
+        messages: vec![
+            FakeMessage::new("promo", "You won").labels(&["SPAM"]),
+            FakeMessage::new("friend", "Photos").labels(&["SPAM"]),
+            FakeMessage::new("real-spam", "Cheap pills").labels(&["SPAM"]),
+        ],
+        ..Default::default()
+    })
+    .await;
+    let db_path = "test_spam_management.db";
+    let (state_arc, db) = folder_state(&fake, db_path, "SPAM").await;
+
+    // Select the first two messages as a range
+    press(&state_arc, KeyCode::Char('v')).await;
+    press(&state_arc, KeyCode::Char('j')).await;
+    assert_eq!(
+        state_arc.read().await.marked_message_ids(),
+        ["promo", "friend"]
+    );
+    press(&state_arc, KeyCode::Char('o')).await;
+
+    assert_eq!(visible_ids(&*state_arc.read().await), ["real-spam"]);
+    {
+        let mailbox = fake.mailbox();
+        assert_eq!(mailbox.message("promo").unwrap().label_ids, ["INBOX"]);
+        assert_eq!(mailbox.message("friend").unwrap().label_ids, ["INBOX"]);
+    }
+    let batches: Vec<_> = fake
+        .requests()
+        .into_iter()
+        .filter(|r| r.path.ends_with("messages/batchModify"))
+        .collect();
+    assert_eq!(batches.len(), 1);
+    assert_eq!(cached_ids(&db, "SPAM").await, ["real-spam"]);
+    assert_eq!(cached_ids(&db, "INBOX").await, ["friend", "promo"]);
+
+    let _ = fs::remove_file(db_path);
+}
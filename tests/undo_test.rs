@@ -0,0 +1,135 @@
+mod common;
+
+use common::fake_gmail::{FakeGmail, FakeMessage, Mailbox};
+use common::{cached_ids, inbox_state, press, server_labels, start_with_inbox, visible_ids};
+use crossterm::event::KeyCode;
+use std::fs;
+use tuimail::gmail_api::fetch_messages_for_label;
+use tuimail::state::FocusedPane;
+
+fn inbox_messages() -> Vec<FakeMessage> {
+    vec![
+        FakeMessage::new("newsletter", "Weekly news"),
+        FakeMessage::new("invoice", "Invoice"),
+        FakeMessage::new("lunch", "Lunch?"),
+        FakeMessage::new("offer", "Great offer"),
+    ]
+}
+
+#[tokio::test]
+async fn test_undo_reverts_actions_newest_first() {
+    let fake = start_with_inbox(inbox_messages()).await;
+    let db_path = "test_undo_actions.db";
+    let (state_arc, db) = inbox_state(&fake, db_path).await;
+
+    press(&state_arc, KeyCode::Char('u')).await;
+    {
+        let mut state = state_arc.write().await;
+        assert_eq!(state.error_message.as_deref(), Some("Nothing to undo."));
+        state.clear_error_message();
+        state.selected_message = 1;
+    }
+
+    // Archive the invoice, delete the lunch message, report the offer as spam
+    press(&state_arc, KeyCode::Char('a')).await;
+    press(&state_arc, KeyCode::Char('d')).await;
+    press(&state_arc, KeyCode::Char('s')).await;
+    assert_eq!(visible_ids(&*state_arc.read().await), ["newsletter"]);
+    assert_eq!(cached_ids(&db, "INBOX").await.len(), 1);
+    assert_eq!(server_labels(&fake, "lunch"), ["TRASH"]);
+
+    press(&state_arc, KeyCode::Char('u')).await;
+    assert_eq!(server_labels(&fake, "offer"), ["INBOX"]);
+    press(&state_arc, KeyCode::Char('u')).await;
+    assert_eq!(server_labels(&fake, "lunch"), ["INBOX"]);
+    press(&state_arc, KeyCode::Char('u')).await;
+    assert_eq!(server_labels(&fake, "invoice"), ["INBOX"]);
+
+    {
+        let state = state_arc.read().await;
+        assert!(state.error_message.is_none());
+        assert_eq!(
+            visible_ids(&state),
+            ["newsletter", "invoice", "lunch", "offer"]
+        );
+        // The last message brought back is selected
+        assert_eq!(state.selected_message, 1);
+        assert!(state.undo_stack.is_empty());
+    }
+    assert_eq!(cached_ids(&db, "INBOX").await.len(), 4);
+    let untrashed = fake
+        .requests()
+        .iter()
+        .filter(|r| r.path.ends_with("lunch/untrash"))
+        .count();
+    assert_eq!(untrashed, 1);
+
+    let _ = fs::remove_file(db_path);
+}
+
+#[tokio::test]
+async fn test_undo_only_puts_back_labels_the_action_removed() {
+    let fake = FakeGmail::start_with(Mailbox {
+        messages: vec![
+            FakeMessage::new("receipt", "Receipt").labels(&["Label_1"]),
+            FakeMessage::new("invoice", "Invoice"),
+        ],
+        ..Default::default()
+    })
+    .await;
+    let db_path = "test_undo_labels.db";
+    let (state_arc, _db) = inbox_state(&fake, db_path).await;
+
+    // The receipt only carries the Receipts label, deleting it removes nothing
+    {
+        let mut state = state_arc.write().await;
+        state.selected_label = state
+            .labels
+            .iter()
+            .position(|l| l.id.as_deref() == Some("Label_1"))
+            .unwrap();
+        fetch_messages_for_label(&mut state).await;
+        state.focused_pane = FocusedPane::Messages;
+        assert_eq!(visible_ids(&state), ["receipt"]);
+    }
+
+    press(&state_arc, KeyCode::Char('d')).await;
+    assert!(state_arc.read().await.messages.is_empty());
+    press(&state_arc, KeyCode::Char('u')).await;
+
+    assert_eq!(visible_ids(&*state_arc.read().await), ["receipt"]);
+    assert_eq!(server_labels(&fake, "receipt"), ["Label_1"]);
+
+    let _ = fs::remove_file(db_path);
+}
+
+#[tokio::test]
+async fn test_undo_drops_an_action_still_in_the_outbox() {
+    let fake = start_with_inbox(inbox_messages()).await;
+    let db_path = "test_undo_outbox.db";
+    let (state_arc, db) = inbox_state(&fake, db_path).await;
+
+    state_arc.write().await.api_base_url = "http://127.0.0.1:9".to_string();
+    press(&state_arc, KeyCode::Char('a')).await;
+    {
+        let mut state = state_arc.write().await;
+        state.clear_error_message();
+        assert_eq!(state.outbox.len(), 1);
+    }
+
+    press(&state_arc, KeyCode::Char('u')).await;
+    {
+        let state = state_arc.read().await;
+        assert!(state.error_message.is_none());
+        assert!(state.outbox.is_empty());
+        assert_eq!(
+            visible_ids(&state),
+            ["newsletter", "invoice", "lunch", "offer"]
+        );
+    }
+    assert!(db.get_outbox().await.unwrap().is_empty());
+    assert_eq!(cached_ids(&db, "INBOX").await.len(), 4);
+    assert!(fake.requests().iter().all(|r| r.method == "GET"));
+
+    let _ = fs::remove_file(db_path);
+}